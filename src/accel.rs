@@ -0,0 +1,288 @@
+//! Cross-checking the crate's encoders and decoders against one another on
+//! the caller's own data, for a service that wants to confirm at startup that
+//! every path it might take agrees before it takes any of them.
+//! 
+//! The encoders all code with the tree `build_tree()` grows for the data, and
+//! must write the same bits as `HuffmanCodec::encode()`. The decoders all
+//! decode those bits, and must give back the data.
+//! 
+
+use std::error::Error;
+use std::fmt;
+
+use crate::HuffmanError;
+use crate::codebook::HuffmanCodebook;
+use crate::io::{encode, BitBuffer, DecodeTable, DecoderFsm, HuffmanReader};
+use crate::io::{HuffmanWriter, PushDecoder};
+use crate::tree::HuffmanCodec;
+
+
+/// The encoder the others are checked against.
+/// 
+const REFERENCE_ENCODER: &str = "codec";
+
+/// The name `AccelMismatch::Chars` gives the data itself, which the decoders
+/// are checked against.
+/// 
+const INPUT: &str = "input";
+
+
+/// The paths `validate_accelerations()` ran, all of which agreed.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccelReport {
+    /// The encoders, in the order they were run; the first is the reference.
+    pub encoders : Vec<&'static str>,
+    /// The decoders, in the order they were run.
+    pub decoders : Vec<&'static str>,
+    /// The length of the encoding, in bits.
+    pub bit_len  : usize,
+    /// The number of characters coded.
+    pub symbols  : usize,
+}
+
+/// The first disagreement `validate_accelerations()` found between two paths.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccelMismatch {
+    /// Two encoders wrote different bits.
+    Bits {
+        /// The reference encoder.
+        first      : &'static str,
+        /// The encoder that differs from it.
+        second     : &'static str,
+        /// The first bit that differs, or where the shorter output ends.
+        bit_offset : usize,
+    },
+    /// A decoder didn't give back the data. `first` is `"input"` for the
+    /// data itself.
+    Chars {
+        /// What the decoder was checked against.
+        first      : &'static str,
+        /// The decoder that differs from it.
+        second     : &'static str,
+        /// The first character that differs, or where the decoder's output
+        /// ended or it failed.
+        char_index : usize,
+    },
+}
+
+impl fmt::Display for AccelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccelMismatch::Bits { first, second, bit_offset } => {
+                write!(f, "encoders {:?} and {:?} diverge at bit {}",
+                       first, second, bit_offset)
+            },
+            AccelMismatch::Chars { first, second, char_index } => {
+                write!(f, "{:?} and decoder {:?} diverge at character {}",
+                       first, second, char_index)
+            },
+        }
+    }
+}
+
+impl Error for AccelMismatch {}
+
+
+/// Encodes and decodes `data` through every path the crate has, checking that
+/// each encoder writes the same bits as `HuffmanCodec::encode()` and each
+/// decoder gives back `data` from them. Reports the paths run, or fails with
+/// the first two that disagree and where.
+/// 
+pub fn validate_accelerations(data: &str) 
+
+    -> Result<AccelReport, AccelMismatch> 
+{
+    let codec    = HuffmanCodec::from_text(data)
+                       .expect("a tree holds every character");
+    let codebook = HuffmanCodebook::from_text(data);
+    let bits     = codec.encode(data)
+                        .expect("every character of the text has a code");
+
+    let encoders: [(&'static str, Encoded); 4] = [
+        (REFERENCE_ENCODER, Ok(bits.clone())),
+        ("encode",          Ok(encode(data))),
+        ("encode_iter",     encode_iter(&codebook, data)),
+        ("writer",          write(&codebook, data)),
+    ];
+    for (name, encoded) in &encoders[1..] {
+        if let Some(bit_offset) = bit_divergence(&bits, encoded) {
+            return Err(AccelMismatch::Bits {
+                first: REFERENCE_ENCODER, second: name, bit_offset
+            });
+        }
+    }
+
+    let (bytes, bit_len) = (bits.as_bytes(), bits.bit_len() as u64);
+    let table            = DecodeTable::from_codes(&codec.codes());
+
+    let tree_reader  = HuffmanReader::new(bytes, codec.tree(), bit_len);
+    let table_reader = |t: DecodeTable| {
+        HuffmanReader::with_table(bytes, &t, bit_len).collect()
+    };
+    let decoders: [(&'static str, Decoded); 6] = [
+        ("tree",         codec.tree().decode(&bits)),
+        ("fsm",          DecoderFsm::from_codebook(&codebook)
+                                    .and_then(|fsm| fsm.decode(&bits))),
+        ("table",        table.clone()
+                              .and_then(|t| t.decode(bytes, bits.bit_len()))),
+        ("reader",       tree_reader.collect()),
+        ("table_reader", table.and_then(table_reader)),
+        ("push",         push(&codebook, &bits)),
+    ];
+    let chars = data.chars().collect::<Vec<_>>();
+
+    for (name, decoded) in &decoders {
+        if let Some(char_index) = char_divergence(&chars, decoded) {
+            return Err(AccelMismatch::Chars {
+                first: INPUT, second: name, char_index
+            });
+        }
+    }
+    Ok(AccelReport {
+        encoders : encoders.iter().map(|&(name, _)| name).collect(),
+        decoders : decoders.iter().map(|&(name, _)| name).collect(),
+        bit_len  : bits.bit_len(),
+        symbols  : chars.len(),
+    })
+}
+
+/// What an encoder wrote.
+/// 
+type Encoded = Result<BitBuffer, HuffmanError>;
+
+/// What a decoder gave back.
+/// 
+type Decoded = Result<String, HuffmanError>;
+
+/// Encodes `data` with `HuffmanCodebook::encode_iter()`.
+/// 
+fn encode_iter(codebook: &HuffmanCodebook, data: &str) -> Encoded {
+    let mut iter  = codebook.encode_iter(data);
+    let bytes     = iter.by_ref().collect::<Vec<_>>();
+    let bit_len   = iter.bit_len() as usize;
+
+    iter.finalize()?;
+    BitBuffer::try_new(bytes, bit_len)
+}
+
+/// Encodes `data` with a `HuffmanWriter`.
+/// 
+fn write(codebook: &HuffmanCodebook, data: &str) -> Encoded {
+    let mut writer = HuffmanWriter::new(vec![], codebook);
+    let written    = writer.write_str(data).and_then(|_| writer.finish());
+
+    let (bytes, padding) = written.map_err(HuffmanError::from)?;
+    let bit_len          = bytes.len() * 8 - padding as usize;
+
+    BitBuffer::try_new(bytes, bit_len)
+}
+
+/// Decodes `bits` by pushing them to a `PushDecoder` a byte at a time.
+/// 
+fn push(codebook: &HuffmanCodebook, bits: &BitBuffer) -> Decoded {
+    let mut decoder = PushDecoder::new(codebook)?;
+
+    for byte in bits.as_bytes().chunks(1) {
+        decoder.push(byte)?;
+    }
+    decoder.finish(match bits.bit_len() % 8 {
+        0 if bits.bit_len() > 0 => 8,
+        n                       => n as u8,
+    })?;
+    Ok(decoder.drain_output().collect())
+}
+
+/// The first bit where `found` differs from `expected`, or where the shorter
+/// of them ends, or 0 if the encoder failed.
+/// 
+fn bit_divergence(expected: &BitBuffer, found: &Encoded) -> Option<usize> {
+    let Ok(found) = found else {
+        return Some(0);
+    };
+    let bit = |bits: &BitBuffer, i: usize| {
+        bits.as_bytes()[i / 8] >> (7 - i % 8) & 1
+    };
+    let len = expected.bit_len().min(found.bit_len());
+
+    (0..len).find(|&i| bit(expected, i) != bit(found, i))
+            .or((expected.bit_len() != found.bit_len()).then_some(len))
+}
+
+/// The first character where `found` differs from `expected`, or where the
+/// shorter of them ends. A decoder that failed is taken to diverge where it
+/// failed, as near as its error says.
+/// 
+fn char_divergence(expected: &[char], found: &Decoded) -> Option<usize> {
+    match found {
+        Ok(text) => {
+            let mut found = text.chars();
+            let mut index = 0;
+
+            loop {
+                match (expected.get(index), found.next()) {
+                    (None, None)                  => return None,
+                    (Some(a), Some(b)) if *a == b => index += 1,
+                    _                             => return Some(index),
+                }
+            }
+        },
+        Err(HuffmanError::InvalidCode { symbols, .. } |
+            HuffmanError::TruncatedBitstream { symbols, .. }) => Some(*symbols),
+        Err(_) => Some(0),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_agree() {
+        let moby = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        for text in [&moby[..], "", "a", "aaaa", "ab", "abracadabra",
+                     "é€🦀 mixed widths ✓"] {
+            let report = validate_accelerations(text).unwrap();
+
+            assert_eq!(report.encoders,
+                       ["codec", "encode", "encode_iter", "writer"]);
+            assert_eq!(report.decoders.len(), 6);
+            assert_eq!(report.symbols, text.chars().count());
+            assert_eq!(report.bit_len, encode(text).bit_len());
+        }
+    }
+
+    #[test]
+    fn divergences() {
+        let bits = BitBuffer::try_new(vec![0b1010_0000], 4).unwrap();
+        let flip = BitBuffer::try_new(vec![0b1000_0000], 4).unwrap();
+        let more = BitBuffer::try_new(vec![0b1010_1000], 5).unwrap();
+
+        assert_eq!(bit_divergence(&bits, &Ok(bits.clone())), None);
+        assert_eq!(bit_divergence(&bits, &Ok(flip)), Some(2));
+        assert_eq!(bit_divergence(&bits, &Ok(more)), Some(4));
+        assert_eq!(bit_divergence(&bits, &Err(HuffmanError::InvalidUtf8)),
+                   Some(0));
+
+        let chars = ['a', 'b', 'c'];
+
+        assert_eq!(char_divergence(&chars, &Ok("abc".into())), None);
+        assert_eq!(char_divergence(&chars, &Ok("abd".into())), Some(2));
+        assert_eq!(char_divergence(&chars, &Ok("ab".into())), Some(2));
+        assert_eq!(char_divergence(&chars, &Ok("abcd".into())), Some(3));
+        assert_eq!(char_divergence(&chars,
+                                   &Err(HuffmanError::InvalidCode {
+                                       bit_offset: 5, symbols: 1
+                                   })),
+                   Some(1));
+
+        let mismatch = AccelMismatch::Chars {
+            first: INPUT, second: "push", char_index: 7
+        };
+        assert_eq!(mismatch.to_string(),
+                   "\"input\" and decoder \"push\" diverge at character 7");
+    }
+}
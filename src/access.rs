@@ -0,0 +1,307 @@
+//! Reading ranges of a stream of blocks, as `compress_blocks()` writes, from
+//! many threads at once. The stream is held as a slice, such as a memory map
+//! of an archive, and indexed once; each thread then reads through a session
+//! of its own, which decodes only the blocks its reads touch.
+//! 
+
+use std::ops::Range;
+
+use crate::blocks::BLOCK_STORED;
+use crate::pipe::{read_flags, FLAG_BLOCKS};
+use crate::HuffmanError;
+use crate::codebook::HuffmanCodebook;
+use crate::io::DecodeTable;
+
+
+/// Where a block's bytes are, and how to get them back.
+/// 
+#[derive(Debug)]
+enum BlockData {
+    /// The bytes are at this range of the stream as they are.
+    Stored(Range<usize>),
+    /// The codes are at this range of the stream, this many bits of them, and
+    /// are decoded with the table.
+    Coded {
+        codes : Range<usize>,
+        bits  : usize,
+        table : DecodeTable,
+    },
+}
+
+/// A block of the stream, and where its bytes start in the data.
+/// 
+#[derive(Debug)]
+struct Block {
+    start : u64,
+    data  : BlockData,
+}
+
+/// The index of a stream of blocks, shared by the sessions that read it.
+/// 
+/// Opening the stream checks it throughout and decodes each coded block
+/// once, to learn where in the data the blocks start; after that, reads
+/// can't fail. The reader is `Sync` and holds nothing that changes, so any
+/// number of threads can read through it without locking, each with its own
+/// `DecodeSession`.
+/// 
+#[derive(Debug)]
+pub struct RandomAccessReader<'a> {
+    stream : &'a [u8],
+    blocks : Vec<Block>,
+    len    : u64,
+}
+
+impl<'a> RandomAccessReader<'a> {
+    /// Indexes `stream`, written by `compress_blocks()`. Fails as
+    /// `decompress_blocks()` would, and with `HuffmanError::MalformedData`
+    /// if a coded block decodes to characters that aren't bytes.
+    /// 
+    pub fn new(stream: &'a [u8]) -> Result<Self, HuffmanError> {
+        let truncated = HuffmanError::MalformedData("the data is truncated");
+        let mut rest  = stream;
+
+        if read_flags(&mut rest)? != FLAG_BLOCKS {
+            return Err(HuffmanError::MalformedData("the stream isn't in \
+                                                    blocks"));
+        }
+        let mut blocks = vec![];
+        let mut len    = 0;
+
+        // Takes the first `n` bytes of `rest`, giving where they are in
+        // `stream`.
+        let take = |rest: &mut &[u8], n: usize| {
+            let offset = stream.len() - rest.len();
+
+            *rest = rest.get(n..).ok_or(truncated.clone())?;
+            Ok::<_, HuffmanError>(offset..offset + n)
+        };
+        let take_u32 = |bytes: &[u8]| {
+            u32::from_le_bytes(bytes.try_into().unwrap()) as usize
+        };
+        while let Some(&flags) = rest.first() {
+            take(&mut rest, 1)?;
+
+            let (data, block_len) = match flags {
+                BLOCK_STORED => {
+                    let n     = take_u32(&stream[take(&mut rest, 4)?]);
+                    let bytes = take(&mut rest, n)?;
+
+                    (BlockData::Stored(bytes), n)
+                },
+                0            => {
+                    let bits  = take_u32(&stream[take(&mut rest, 4)?]);
+                    let n     = take_u32(&stream[take(&mut rest, 4)?]);
+                    let table = &stream[take(&mut rest, n)?];
+                    let codes = take(&mut rest, bits.div_ceil(8))?;
+
+                    let codebook = HuffmanCodebook::import(table)?;
+                    let table    = DecodeTable::from_codes(
+                                       &codebook.code_strings())?;
+                    let decoded  = table.decode(&stream[codes.clone()],
+                                                bits)?;
+
+                    if decoded.chars().any(|c| c > '\u{ff}') {
+                        return Err(HuffmanError::MalformedData(
+                                       "a code isn't for a byte"));
+                    }
+                    let n = decoded.chars().count();
+
+                    (BlockData::Coded { codes, bits, table }, n)
+                },
+                _            => {
+                    return Err(HuffmanError::MalformedData("unknown block \
+                                                            flags"));
+                },
+            };
+            blocks.push(Block { start: len, data });
+            len += block_len as u64;
+        }
+        Ok(Self { stream, blocks, len })
+    }
+    /// The length of the data, in bytes.
+    /// 
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The number of blocks in the stream.
+    /// 
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+    /// A session to read through, with room of its own to decode blocks in.
+    /// 
+    pub fn session(&self) -> DecodeSession<'_, 'a> {
+        DecodeSession { reader: self, scratch: vec![], cached: None }
+    }
+}
+
+/// One thread's reads of a `RandomAccessReader`. It holds the last coded
+/// block it decoded, so reads close together don't decode it again.
+/// 
+#[derive(Debug)]
+pub struct DecodeSession<'r, 'a> {
+    reader  : &'r RandomAccessReader<'a>,
+    scratch : Vec<u8>,
+    /// The index of the block decoded into `scratch`.
+    cached  : Option<usize>,
+}
+
+impl DecodeSession<'_, '_> {
+    /// Fills `buf` with the data from `offset`, returning how many bytes were
+    /// read: fewer than `buf` holds only at the end of the data, and 0 from
+    /// there on.
+    /// 
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> usize {
+        let reader = self.reader;
+        let mut at = offset;
+        let mut n  = 0;
+
+        while n < buf.len() && at < reader.len {
+            let index = reader.blocks.partition_point(|b| b.start <= at) - 1;
+            let block = &reader.blocks[index];
+            let bytes = match &block.data {
+                BlockData::Stored(range) => &reader.stream[range.clone()],
+                BlockData::Coded { codes, bits, table } => {
+                    if self.cached != Some(index) {
+                        let text = table.decode(&reader.stream[codes.clone()],
+                                                *bits)
+                                        .expect("blocks are checked on open");
+                        self.scratch.clear();
+                        self.scratch.extend(text.chars().map(|c| c as u8));
+                        self.cached = Some(index);
+                    }
+                    &self.scratch
+                },
+            };
+            let from  = (at - block.start) as usize;
+            let count = (bytes.len() - from).min(buf.len() - n);
+
+            buf[n..n + count].copy_from_slice(&bytes[from..from + count]);
+            n  += count;
+            at += count as u64;
+        }
+        n
+    }
+    /// The data in `range`, or as much of it as there is.
+    /// 
+    pub fn read_range(&mut self, range: Range<u64>) -> Vec<u8> {
+        let end     = range.end.min(self.reader.len);
+        let mut buf = vec![0; end.saturating_sub(range.start) as usize];
+        let n       = self.read_at(range.start, &mut buf);
+
+        buf.truncate(n);
+        buf
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{compress_auto, compress_blocks};
+    use crate::sample::SplitMix64;
+    use std::thread;
+
+    #[test]
+    fn concurrent_sessions() {
+        fn is_sync<T: Sync>() {}
+        is_sync::<RandomAccessReader>();
+
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text = text.as_bytes();
+        let mut stream = vec![];
+
+        compress_blocks(text, &mut stream, 4096).unwrap();
+
+        let reader = RandomAccessReader::new(&stream).unwrap();
+
+        assert_eq!(reader.len(), text.len() as u64);
+        assert_eq!(reader.blocks(), text.len().div_ceil(4096));
+
+        thread::scope(|s| {
+            for seed in 0..16 {
+                let reader = &reader;
+
+                s.spawn(move || {
+                    let mut rng     = SplitMix64(seed);
+                    let mut session = reader.session();
+
+                    for _ in 0..200 {
+                        let start = rng.below(text.len() + 10) as u64;
+                        let len   = rng.below(20_000) as u64;
+                        let end   = (start + len).min(text.len() as u64);
+
+                        let expected = text.get(start as usize..end as usize)
+                                           .unwrap_or(&[]);
+
+                        assert_eq!(session.read_range(start..start + len),
+                                   expected);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn stored_and_coded_blocks() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut rng  = SplitMix64(5);
+        let mut data = text.as_bytes()[..10_000].to_vec();
+
+        data.extend((0..10_000).map(|_| rng.next_u64() as u8));
+        data.extend(&text.as_bytes()[..10_000]);
+
+        let mut stream = vec![];
+        let stats = compress_blocks(&data[..], &mut stream, 5000).unwrap();
+
+        assert_eq!((stats.stored_blocks, stats.coded_blocks), (2, 4));
+
+        let reader      = RandomAccessReader::new(&stream).unwrap();
+        let mut session = reader.session();
+        let mut buf     = [0; 7000];
+
+        for offset in [0, 4999, 9000, 14_999, 19_999, 25_000, 29_000] {
+            let n   = session.read_at(offset, &mut buf);
+            let end = (offset as usize + buf.len()).min(data.len());
+
+            assert_eq!(&buf[..n], &data[offset as usize..end]);
+        }
+        assert_eq!(session.read_at(30_000, &mut buf), 0);
+        assert_eq!(session.read_range(29_990..40_000), &data[29_990..]);
+
+        let empty = {
+            let mut stream = vec![];
+            compress_blocks(&b""[..], &mut stream, 10).unwrap();
+            stream
+        };
+        let reader = RandomAccessReader::new(&empty).unwrap();
+
+        assert!(reader.is_empty());
+        assert_eq!(reader.session().read_range(0..10), b"");
+    }
+
+    #[test]
+    fn bad_streams() {
+        let mut stream = vec![];
+
+        compress_blocks(&[b'x'; 100][..], &mut stream, 100).unwrap();
+
+        let truncated = HuffmanError::MalformedData("the data is truncated");
+
+        for len in [3, 7, 10, stream.len() - 1] {
+            assert_eq!(RandomAccessReader::new(&stream[..len]).err(),
+                       Some(truncated.clone()));
+        }
+        let mut flagged = stream.clone();
+        flagged[6] = 2;
+
+        assert_eq!(RandomAccessReader::new(&flagged).err(),
+                   Some(HuffmanError::MalformedData("unknown block flags")));
+        assert_eq!(RandomAccessReader::new(&compress_auto("text")).err(),
+                   Some(HuffmanError::MalformedData(
+                            "the stream isn't in blocks")));
+    }
+}
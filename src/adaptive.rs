@@ -0,0 +1,785 @@
+//! Adaptive Huffman coding, by the FGK algorithm, for streams that can't be
+//! seen whole before coding starts. No code table is sent: the encoder and
+//! decoder each start from the same tree and update it the same way after
+//! every symbol, so their codes stay in step.
+//! 
+//! The tree starts as a lone escape node of weight 0. A symbol seen before is
+//! sent as the path to its leaf. A new one is sent as the path to the escape
+//! node, followed by its scalar value in 21 bits, and the escape node then
+//! splits into a branch over a new escape node and a leaf for the symbol.
+//! 
+//! After each symbol, the weights on the path from its leaf to the root are
+//! incremented. The nodes are kept numbered so that weights never increase
+//! with the number, and siblings are numbered one after the other; before
+//! a node's weight is incremented, it's swapped with the lowest numbered node
+//! of the same weight, which keeps the numbering so.
+//! 
+//! An encoder or decoder can be saved partway through a stream with
+//! `snapshot()` and carried on from there by `restore()`, in this layout,
+//! with integers little-endian:
+//! 
+//! | bytes  | contents                                                     |
+//! |--------|--------------------------------------------------------------|
+//! | 1      | format version, currently 1                                  |
+//! | 4      | the number of nodes                                          |
+//! | 4      | the index of the escape node                                 |
+//! | 28 ... | per node, in order of index: its weight in 8 bytes, then its |
+//! |        | parent, left and right children, number, and the scalar      |
+//! |        | value of its symbol, in 4 each, with 0xffffffff for none     |
+//! 
+//! The tree is the same in form on both sides of a stream, so a snapshot of
+//! an encoder restores a decoder in step with it, and the other way round.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::codebook::Code;
+use crate::collections::HashMap;
+use crate::io::{BitBuffer, BitReader, BitWriter};
+
+
+/// The number of bits a new symbol's scalar value is sent in.
+/// 
+pub(crate) const ESCAPE_BITS: u8 = 21;
+
+/// The index of no node.
+/// 
+const NONE: u32 = u32::MAX;
+
+/// The format version written by `snapshot()`.
+/// 
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// The bytes each node takes up in a snapshot.
+/// 
+const NODE_BYTES: usize = 28;
+
+
+/// A node of an adaptive tree. Nodes are indexed by `u32` rather than
+/// `Handle`, since a stream can bring in any number of symbols.
+/// 
+#[derive(Clone, Debug)]
+struct AdaptiveNode {
+    weight   : u64,
+    parent   : u32,
+    /// The children of a branch, and `NONE` for a leaf or the escape node.
+    children : [u32; 2],
+    /// The place of the node in `AdaptiveTree::order`.
+    number   : u32,
+}
+
+/// The tree shared in form by an encoder and its decoder.
+/// 
+#[derive(Clone, Debug)]
+struct AdaptiveTree {
+    nodes   : Vec<AdaptiveNode>,
+    /// The nodes in order of number: the root first, and weights never
+    /// increasing after it.
+    order   : Vec<u32>,
+    leaves  : HashMap<char, u32>,
+    /// The symbols of the leaves, by node.
+    symbols : HashMap<u32, char>,
+    escape  : u32,
+}
+
+impl AdaptiveTree {
+    fn new() -> Self {
+        let escape = AdaptiveNode {
+            weight   : 0,
+            parent   : NONE,
+            children : [NONE; 2],
+            number   : 0,
+        };
+        Self {
+            nodes   : vec![escape],
+            order   : vec![0],
+            leaves  : HashMap::new(),
+            symbols : HashMap::new(),
+            escape  : 0,
+        }
+    }
+    fn root(&self) -> u32 {
+        self.order[0]
+    }
+    fn node(&self, index: u32) -> &AdaptiveNode {
+        &self.nodes[index as usize]
+    }
+    fn node_mut(&mut self, index: u32) -> &mut AdaptiveNode {
+        &mut self.nodes[index as usize]
+    }
+    /// Adds a node, numbered last.
+    /// 
+    fn push(&mut self, parent: u32) -> u32 {
+        let index = self.nodes.len() as u32;
+
+        self.nodes.push(AdaptiveNode {
+            weight   : 0,
+            parent,
+            children : [NONE; 2],
+            number   : self.order.len() as u32,
+        });
+        self.order.push(index);
+        index
+    }
+    /// The path from the root to `node`, as bits taken right for 1.
+    /// 
+    fn path(&self, mut node: u32) -> Vec<bool> {
+        let mut path = vec![];
+
+        while self.node(node).parent != NONE {
+            let parent = self.node(node).parent;
+
+            path.push(self.node(parent).children[1] == node);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+    /// Counts one more of `c`, splitting the escape node if it's new.
+    /// 
+    fn update(&mut self, c: char) {
+        let mut node = match self.leaves.get(&c) {
+            Some(&leaf) => leaf,
+            None        => {
+                let branch = self.escape;
+                let leaf   = self.push(branch);
+                let escape = self.push(branch);
+
+                self.node_mut(branch).children = [escape, leaf];
+                self.leaves.insert(c, leaf);
+                self.symbols.insert(leaf, c);
+                self.escape = escape;
+                leaf
+            },
+        };
+        // The escape node's sibling weighs as much as its parent, so it's
+        // swapped with the lowest numbered leaf of its weight instead.
+        let parent = self.node(node).parent;
+
+        if parent != NONE && self.node(parent).children[0] == self.escape {
+            node = self.increment(node, true);
+        }
+        while node != NONE {
+            node = self.increment(node, false);
+        }
+    }
+    /// Swaps `node` with the lowest numbered node of its weight, or leaf if
+    /// `leaf` is set, and increments its weight. Gives its new parent.
+    /// 
+    fn increment(&mut self, node: u32, leaf: bool) -> u32 {
+        let weight = self.node(node).weight;
+        let number = self.node(node).number as usize;
+        let first  = self.order[..number].partition_point(|&n| {
+            self.node(n).weight > weight
+        });
+        let leader = self.order[first..=number].iter().copied().find(|&n| {
+            !leaf || self.node(n).children[0] == NONE
+        });
+        if let Some(leader) = leader
+            && leader != node
+        {
+            self.swap(node, leader);
+        }
+        self.node_mut(node).weight += 1;
+        self.node(node).parent
+    }
+    /// The tree in the layout given in the module docs.
+    /// 
+    fn snapshot(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + self.nodes.len() * NODE_BYTES);
+
+        bytes.push(SNAPSHOT_VERSION);
+        bytes.extend((self.nodes.len() as u32).to_le_bytes());
+        bytes.extend(self.escape.to_le_bytes());
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let symbol = self.symbols.get(&(index as u32))
+                                     .map_or(NONE, |&c| c as u32);
+
+            bytes.extend(node.weight.to_le_bytes());
+
+            for field in [node.parent, node.children[0], node.children[1],
+                          node.number, symbol] {
+                bytes.extend(field.to_le_bytes());
+            }
+        }
+        bytes
+    }
+    /// Reads a tree written by `snapshot()`, checking that it's one an
+    /// encoder could have built: its links agree, every node hangs from the
+    /// root, the escape node is an empty leaf, every other leaf has a
+    /// symbol of its own, and the numbering keeps the sibling property.
+    /// 
+    fn restore(bytes: &[u8]) -> Result<Self, HuffmanError> {
+        let malformed = |reason| Err(HuffmanError::MalformedData(reason));
+
+        let Some((&version, rest)) = bytes.split_first() else {
+            return malformed("the data is truncated");
+        };
+        if version != SNAPSHOT_VERSION {
+            return Err(HuffmanError::UnsupportedVersion(version));
+        }
+        let u32_at = |at: usize| {
+            u32::from_le_bytes(rest[at..at + 4].try_into().unwrap())
+        };
+        if rest.len() < 8 {
+            return malformed("the data is truncated");
+        }
+        let (len, escape) = (u32_at(0) as usize, u32_at(4));
+
+        let body = rest.len() - 8;
+
+        if body < len.saturating_mul(NODE_BYTES) {
+            return malformed("the data is truncated");
+        }
+        if body > len * NODE_BYTES {
+            return malformed("trailing bytes");
+        }
+        if len == 0 || escape as usize >= len {
+            return malformed("the escape node doesn't exist");
+        }
+        let mut tree = Self {
+            nodes   : Vec::with_capacity(len),
+            order   : vec![NONE; len],
+            leaves  : HashMap::new(),
+            symbols : HashMap::new(),
+            escape,
+        };
+        let exists = |link: u32| link == NONE || (link as usize) < len;
+
+        let records = rest[8..].chunks_exact(NODE_BYTES);
+
+        for (index, record) in records.enumerate() {
+            let field  = |i: usize| {
+                u32::from_le_bytes(record[8 + 4 * i..12 + 4 * i]
+                                       .try_into().unwrap())
+            };
+            let node   = AdaptiveNode {
+                weight   : u64::from_le_bytes(record[..8].try_into()
+                                                         .unwrap()),
+                parent   : field(0),
+                children : [field(1), field(2)],
+                number   : field(3),
+            };
+            let index  = index as u32;
+            let symbol = field(4);
+
+            if !exists(node.parent) || !node.children.iter().all(|&c| {
+                exists(c)
+            }) {
+                return malformed("a node links to one that doesn't exist");
+            }
+            match tree.order.get_mut(node.number as usize) {
+                Some(slot) if *slot == NONE => *slot = index,
+                _ => return malformed("the nodes aren't numbered in turn"),
+            }
+            let leaf = node.children == [NONE; 2];
+
+            match (leaf, index == escape, symbol) {
+                (true, true, NONE) if node.weight == 0 => {},
+                (true, false, symbol) if symbol != NONE => {
+                    let Some(c) = char::from_u32(symbol) else {
+                        return malformed("a symbol isn't a valid char");
+                    };
+                    if tree.leaves.insert(c, index).is_some() {
+                        return malformed("a symbol has two leaves");
+                    }
+                    tree.symbols.insert(index, c);
+                },
+                (false, false, NONE) if !node.children.contains(&NONE) => {},
+                _ => return malformed("a node isn't a branch, a leaf with a \
+                                       symbol, or the empty escape leaf"),
+            }
+            tree.nodes.push(node);
+        }
+        tree.check_links()?;
+        tree.check_sibling_property()?;
+        Ok(tree)
+    }
+    /// Checks that each node is the parent of its children, and that every
+    /// node is reached from the root, which is numbered first.
+    /// 
+    fn check_links(&self) -> Result<(), HuffmanError> {
+        let malformed = |reason| Err(HuffmanError::MalformedData(reason));
+
+        if self.node(self.root()).parent != NONE {
+            return malformed("the first node numbered isn't the root");
+        }
+        let mut stack   = vec![self.root()];
+        let mut reached = 0;
+
+        while let Some(index) = stack.pop() {
+            reached += 1;
+
+            for child in self.node(index).children {
+                if child == NONE {
+                    continue;
+                }
+                if self.node(child).parent != index {
+                    return malformed("a node isn't the parent of its \
+                                      children");
+                }
+                stack.push(child);
+            }
+        }
+        if reached != self.nodes.len() {
+            return malformed("a node isn't reached from the root");
+        }
+        Ok(())
+    }
+    /// Checks that weights never increase with the numbering, that each
+    /// branch weighs its children together, and that siblings are numbered
+    /// side by side.
+    /// 
+    fn check_sibling_property(&self) -> Result<(), HuffmanError> {
+        let in_order = self.order.windows(2).all(|pair| {
+            self.node(pair[0]).weight >= self.node(pair[1]).weight
+        });
+        let branches = self.nodes.iter().all(|node| match node.children {
+            [NONE, NONE]  => true,
+            [left, right] => {
+                let (l, r) = (self.node(left), self.node(right));
+
+                l.weight.checked_add(r.weight) == Some(node.weight)
+                    && l.number.abs_diff(r.number) == 1
+            },
+        });
+        match in_order && branches {
+            true  => Ok(()),
+            false => Err(HuffmanError::MalformedData("the weights break the \
+                                                      sibling property")),
+        }
+    }
+    /// Exchanges the places of two nodes, neither an ancestor of the other,
+    /// along with their subtrees.
+    /// 
+    fn swap(&mut self, a: u32, b: u32) {
+        let (pa, pb) = (self.node(a).parent, self.node(b).parent);
+        let side_a   = (self.node(pa).children[1] == a) as usize;
+        let side_b   = (self.node(pb).children[1] == b) as usize;
+
+        self.node_mut(pa).children[side_a] = b;
+        self.node_mut(pb).children[side_b] = a;
+        self.node_mut(a).parent = pb;
+        self.node_mut(b).parent = pa;
+
+        let (na, nb) = (self.node(a).number, self.node(b).number);
+
+        self.order.swap(na as usize, nb as usize);
+        self.node_mut(a).number = nb;
+        self.node_mut(b).number = na;
+    }
+}
+
+
+/// Encodes text a symbol at a time with adaptive Huffman codes, so the text
+/// needn't be seen in advance and no code table is sent. A stream is decoded
+/// by an `AdaptiveDecoder` fed the same bits from its start.
+/// 
+#[derive(Clone, Debug)]
+pub struct AdaptiveEncoder {
+    tree: AdaptiveTree,
+}
+
+impl Default for AdaptiveEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdaptiveEncoder {
+    pub fn new() -> Self {
+        Self { tree: AdaptiveTree::new() }
+    }
+    /// The bits for `c`, given the symbols encoded before it, after which
+    /// the codes are updated to count it.
+    /// 
+    pub fn encode_symbol(&mut self, c: char) -> BitBuffer {
+        let mut writer = BitWriter::new();
+
+        self.write_symbol(c, &mut writer);
+        writer.into_buffer()
+    }
+    /// Encodes each character of `text` in turn, as `encode_symbol()` does,
+    /// into one buffer.
+    /// 
+    pub fn encode(&mut self, text: &str) -> BitBuffer {
+        let mut writer = BitWriter::new();
+
+        for c in text.chars() {
+            self.write_symbol(c, &mut writer);
+        }
+        writer.into_buffer()
+    }
+    /// The number of distinct symbols encoded so far.
+    /// 
+    pub fn symbols(&self) -> usize {
+        self.tree.leaves.len()
+    }
+    /// Saves the codes as they stand, to carry on encoding from later with
+    /// `restore()`. See the module docs for the layout.
+    /// 
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.tree.snapshot()
+    }
+    /// An encoder that carries on from a `snapshot()`, of an encoder or of a
+    /// decoder, writing what a decoder at the same point in the stream
+    /// reads. Fails with `HuffmanError::UnsupportedVersion` for a snapshot
+    /// from another version of the format, and `HuffmanError::MalformedData`
+    /// if it's truncated, has trailing bytes, or isn't of a tree an encoder
+    /// could have built.
+    /// 
+    pub fn restore(bytes: &[u8]) -> Result<Self, HuffmanError> {
+        Ok(Self { tree: AdaptiveTree::restore(bytes)? })
+    }
+    /// Writes the bits for `c` to `writer`, as `encode_symbol()` gives them.
+    /// 
+    pub(crate) fn write_symbol(&mut self, c: char, writer: &mut BitWriter) {
+        let leaf     = self.tree.leaves.get(&c).copied();
+        let path     = self.tree.path(leaf.unwrap_or(self.tree.escape));
+        let mut code = Code::default();
+
+        for bit in path {
+            if code.len == 64 {
+                writer.write_code(code);
+                code = Code::default();
+            }
+            code = code.append(bit);
+        }
+        writer.write_code(code);
+
+        if leaf.is_none() {
+            writer.write_code(Code { bits: c as u64, len: ESCAPE_BITS });
+        }
+        self.tree.update(c);
+    }
+}
+
+
+/// Decodes the bits written by an `AdaptiveEncoder`, updating its codes after
+/// each symbol as the encoder did.
+/// 
+#[derive(Clone, Debug)]
+pub struct AdaptiveDecoder {
+    tree    : AdaptiveTree,
+    decoded : usize,
+}
+
+impl Default for AdaptiveDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdaptiveDecoder {
+    pub fn new() -> Self {
+        Self { tree: AdaptiveTree::new(), decoded: 0 }
+    }
+    /// Reads one symbol from `r`. Fails with
+    /// `HuffmanError::TruncatedBitstream` if `r` runs out partway through it,
+    /// and `HuffmanError::InvalidCode` if a new symbol's value isn't a
+    /// Unicode scalar value. Errors give the offset where the symbol started,
+    /// and leave the codes as they were.
+    /// 
+    pub fn decode_symbol(&mut self, r: &mut BitReader) 
+
+        -> Result<char, HuffmanError> 
+    {
+        let start    = r.position();
+        let mut node = self.tree.root();
+
+        let truncated = || HuffmanError::TruncatedBitstream {
+            bit_offset : start,
+            symbols    : self.decoded,
+        };
+        while let [left, right] = self.tree.node(node).children
+            && left != NONE
+        {
+            node = match r.read_bit().ok_or_else(truncated)? {
+                false => left,
+                true  => right,
+            };
+        }
+        let c = match self.tree.symbols.get(&node) {
+            Some(&c) => c,
+            None     => {
+                let mut value = 0;
+
+                for _ in 0..ESCAPE_BITS {
+                    let bit = r.read_bit().ok_or_else(truncated)?;
+
+                    value = value << 1 | bit as u32;
+                }
+                char::from_u32(value).ok_or(HuffmanError::InvalidCode {
+                    bit_offset : start,
+                    symbols    : self.decoded,
+                })?
+            },
+        };
+        self.tree.update(c);
+        self.decoded += 1;
+        Ok(c)
+    }
+    /// Decodes every symbol in `bits`, failing as `decode_symbol()` does.
+    /// 
+    pub fn decode(&mut self, bits: &BitBuffer) -> Result<String, HuffmanError> {
+        let mut r    = bits.reader();
+        let mut text = String::new();
+
+        while r.remaining() > 0 {
+            text.push(self.decode_symbol(&mut r)?);
+        }
+        Ok(text)
+    }
+    /// The number of symbols decoded so far.
+    /// 
+    pub fn decoded(&self) -> usize {
+        self.decoded
+    }
+    /// The number of distinct symbols decoded so far.
+    /// 
+    pub fn symbols(&self) -> usize {
+        self.tree.leaves.len()
+    }
+    /// Saves the codes as they stand, as `AdaptiveEncoder::snapshot()`
+    /// does.
+    /// 
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.tree.snapshot()
+    }
+    /// A decoder that carries on from a `snapshot()`, failing as
+    /// `AdaptiveEncoder::restore()` does. The symbols decoded are counted
+    /// from the start of the stream, which the root's weight gives.
+    /// 
+    pub fn restore(bytes: &[u8]) -> Result<Self, HuffmanError> {
+        let tree    = AdaptiveTree::restore(bytes)?;
+        let decoded = tree.node(tree.root()).weight as usize;
+
+        Ok(Self { tree, decoded })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::count_frequencies;
+    use crate::io::encode;
+
+    /// Encodes `text` a symbol at a time and decodes it, checking the trees
+    /// match after every symbol.
+    /// 
+    fn round_trip(text: &str) -> BitBuffer {
+        let mut encoder = AdaptiveEncoder::new();
+        let mut decoder = AdaptiveDecoder::new();
+
+        for c in text.chars() {
+            let bits = encoder.encode_symbol(c);
+            let mut r = bits.reader();
+
+            assert_eq!(decoder.decode_symbol(&mut r), Ok(c));
+            assert_eq!(r.remaining(), 0);
+            assert_eq!(decoder.tree.order, encoder.tree.order);
+        }
+        let bits = AdaptiveEncoder::new().encode(text);
+
+        assert_eq!(AdaptiveDecoder::new().decode(&bits).as_deref(), Ok(text));
+        bits
+    }
+
+    /// Checks the numbering: the root first, weights never increasing, each
+    /// branch weighing its children together, and siblings side by side.
+    /// 
+    fn check_sibling_property(tree: &AdaptiveTree) {
+        assert_eq!(tree.check_links(), Ok(()));
+        assert_eq!(tree.check_sibling_property(), Ok(()));
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text = text + "é€🦀\u{10ffff}";
+        let bits = round_trip(&text);
+
+        // It comes within a few percent of static codes, without their
+        // table.
+        assert!(bits.bit_len() < encode(&text).bit_len() * 104 / 100);
+
+        let mut encoder = AdaptiveEncoder::new();
+
+        encoder.encode(&text);
+        check_sibling_property(&encoder.tree);
+
+        assert_eq!(encoder.symbols(), count_frequencies(&text).len());
+    }
+
+    #[test]
+    fn adversarial_inputs() {
+        let same       = "a".repeat(5000);
+        let increasing = ('\u{20}'..).take(5000).collect::<String>();
+        let doubling   = ('a'..='t').enumerate()
+                                    .flat_map(|(i, c)| {
+                                        std::iter::repeat_n(c, 1 << (i / 2))
+                                    })
+                                    .collect::<String>();
+
+        for text in ["", "a", "ab", &same, &increasing, &doubling] {
+            round_trip(text);
+
+            let mut encoder = AdaptiveEncoder::new();
+
+            encoder.encode(text);
+            check_sibling_property(&encoder.tree);
+        }
+        // A repeated symbol costs a bit each once it's the only one.
+        assert_eq!(round_trip(&same).bit_len(), 21 + 4999);
+    }
+
+    #[test]
+    fn snapshots_resume() {
+        let text    = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text    = text + "é€🦀";
+        let half    = text.char_indices().nth(text.chars().count() / 2)
+                          .unwrap().0;
+        let (a, b)  = text.split_at(half);
+
+        // Encoded in two halves, with a fresh encoder restored between
+        // them, the stream is the one encoded in one go.
+        let mut encoder = AdaptiveEncoder::new();
+        let first       = encoder.encode(a);
+        let snapshot    = encoder.snapshot();
+        let mut resumed = AdaptiveEncoder::restore(&snapshot).unwrap();
+
+        assert_eq!(resumed.snapshot(), snapshot);
+        assert_eq!(resumed.symbols(), encoder.symbols());
+
+        let second    = resumed.encode(b);
+        let mut whole = BitWriter::new();
+
+        for bits in [&first, &second] {
+            let mut r = bits.reader();
+
+            while let Some(bit) = r.read_bit() {
+                whole.write_code(Code::default().append(bit));
+            }
+        }
+        let whole = whole.into_buffer();
+
+        assert_eq!(whole, AdaptiveEncoder::new().encode(&text));
+
+        // One decoder reads it all without restarting.
+        let mut decoder = AdaptiveDecoder::new();
+
+        assert_eq!(decoder.decode(&whole).as_deref(), Ok(text.as_str()));
+
+        // A decoder restored from the encoder's snapshot reads the rest, and
+        // one restored from a decoder's snapshot does too.
+        let mut decoder = AdaptiveDecoder::restore(&snapshot).unwrap();
+
+        assert_eq!(decoder.decoded(), a.chars().count());
+        assert_eq!(decoder.decode(&second).as_deref(), Ok(b));
+
+        let mut decoder = AdaptiveDecoder::new();
+
+        decoder.decode(&first).unwrap();
+
+        assert_eq!(decoder.snapshot(), snapshot);
+        assert_eq!(AdaptiveDecoder::restore(&decoder.snapshot()).unwrap()
+                                   .decode(&second).as_deref(),
+                   Ok(b));
+
+        // The empty tree round-trips too.
+        let empty = AdaptiveEncoder::new().snapshot();
+
+        assert_eq!(AdaptiveDecoder::restore(&empty).unwrap().snapshot(),
+                   empty);
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let mut encoder = AdaptiveEncoder::new();
+
+        encoder.encode("abracadabra");
+
+        let snapshot = encoder.snapshot();
+        let malformed = |bytes: &[u8]| match AdaptiveEncoder::restore(bytes) {
+            Err(HuffmanError::MalformedData(reason)) => reason,
+            other => panic!("{:?}", other.map(|e| e.snapshot())),
+        };
+        // The fields of the node at `index`: weight, parent, left, right,
+        // number and symbol.
+        let field = |index: usize, field: usize| match field {
+            0 => 9 + index * NODE_BYTES,
+            _ => 9 + index * NODE_BYTES + 4 + 4 * field,
+        };
+        let with = |at: usize, value: u32| {
+            let mut bytes = snapshot.clone();
+
+            bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            bytes
+        };
+        let mut version = snapshot.clone();
+
+        version[0] = 2;
+
+        assert_eq!(AdaptiveEncoder::restore(&version).err(),
+                   Some(HuffmanError::UnsupportedVersion(2)));
+        assert_eq!(malformed(&[]), "the data is truncated");
+        assert_eq!(malformed(&snapshot[..snapshot.len() - 1]),
+                   "the data is truncated");
+        assert_eq!(malformed(&[&snapshot[..], &[0]].concat()),
+                   "trailing bytes");
+        assert_eq!(malformed(&with(5, 99)), "the escape node doesn't exist");
+
+        let root   = encoder.tree.root() as usize;
+        let leaf   = encoder.tree.leaves[&'a'] as usize;
+        let escape = encoder.tree.escape as usize;
+
+        assert_eq!(malformed(&with(field(leaf, 1), 99)),
+                   "a node links to one that doesn't exist");
+        assert_eq!(malformed(&with(field(leaf, 4), 0)),
+                   "the nodes aren't numbered in turn");
+        assert_eq!(malformed(&with(field(leaf, 5), 0xd800)),
+                   "a symbol isn't a valid char");
+        assert_eq!(malformed(&with(field(leaf, 5), 'b' as u32)),
+                   "a symbol has two leaves");
+        assert!(malformed(&with(field(escape, 5), 'z' as u32))
+                    .starts_with("a node isn't a branch"));
+        let deep = encoder.tree.leaves[&'d'] as usize;
+
+        assert_ne!(encoder.tree.nodes[deep].parent, root as u32);
+        assert_eq!(malformed(&with(field(deep, 1), root as u32)),
+                   "a node isn't the parent of its children");
+        assert_eq!(malformed(&with(field(root, 1), leaf as u32)),
+                   "the first node numbered isn't the root");
+        assert_eq!(malformed(&with(field(leaf, 0),
+                                   encoder.tree.nodes[leaf].weight as u32 + 1)),
+                   "the weights break the sibling property");
+    }
+
+    #[test]
+    fn bad_streams() {
+        let bits = AdaptiveEncoder::new().encode("abcabc");
+        let cut  = BitBuffer::try_new_lenient(bits.as_bytes().to_vec(),
+                                              bits.bit_len() - 1)
+                             .unwrap();
+
+        assert!(matches!(AdaptiveDecoder::new().decode(&cut),
+                         Err(HuffmanError::TruncatedBitstream {
+                             symbols: 5, ..
+                         })));
+
+        // A surrogate isn't a scalar value.
+        let mut writer = BitWriter::new();
+
+        writer.write_code(Code { bits: 0xd800, len: ESCAPE_BITS });
+
+        assert_eq!(AdaptiveDecoder::new().decode(&writer.into_buffer()),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+    }
+}
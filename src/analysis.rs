@@ -0,0 +1,935 @@
+//! Reports that describe how well a set of Huffman codes fits a body of text.
+//! 
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use crate::{average_code_length, codes_from_table, codes_with_arena, tree_size};
+use crate::{Handle, Node, NodeMem};
+use crate::codebook::{predicted_bits, Code, FrequencyTable, HuffmanCodebook};
+use crate::collections::HashMap;
+use crate::io::Transition;
+use crate::escape::SymbolDisplay;
+
+
+/// The pseudo-count given to observed symbols the trained distribution never
+/// saw when computing KL divergence, so they don't make it infinite.
+/// 
+const SMOOTHING: f64 = 0.5;
+
+
+/// Describes how far an observed distribution has drifted from the one a set of
+/// codes was trained on.
+/// 
+#[derive(Clone, Debug, PartialEq)]
+pub struct DriftReport {
+    /// KL divergence, in bits, of the observed distribution from the trained
+    /// distribution, smoothed to cover symbols it never saw.
+    pub kl_divergence       : f64,
+    /// Total variation distance between the two distributions; 0 when they're
+    /// identical, 1 when they share no symbols.
+    pub total_variation     : f64,
+    /// Observed symbols the trained codes have no code for.
+    pub unseen_symbols      : usize,
+    /// Average bits per symbol when the observed text is encoded with the
+    /// trained codes, or `None` if it has unseen symbols.
+    pub stale_bits_per_char : Option<f64>,
+    /// Average bits per symbol when the codes are retrained on the observed
+    /// text.
+    pub fresh_bits_per_char : f64,
+}
+
+impl DriftReport {
+    /// The extra bits per symbol paid by not retraining, or `None` if the
+    /// trained codes can't encode the observed text at all.
+    /// 
+    pub fn penalty_bits_per_char(&self) -> Option<f64> {
+        self.stale_bits_per_char.map(|stale| stale - self.fresh_bits_per_char)
+    }
+}
+
+
+/// The share of `table`'s total that `char_` accounts for.
+/// 
+fn share(table: &FrequencyTable, char_: char) -> f64 {
+    match table.total() {
+        0     => 0.0,
+        total => table.get(char_) as f64 / total as f64,
+    }
+}
+
+/// Compares the distribution a set of codes was trained on with one observed
+/// later, to help decide whether the codes are still worth using. The penalty
+/// figure is the one to act on: it's what not retraining costs per symbol.
+/// 
+pub fn distribution_divergence(trained  : &FrequencyTable,
+                               observed : &FrequencyTable) -> DriftReport
+{
+    let unseen_symbols = observed.counts().keys()
+                                 .filter(|&&c| trained.get(c) == 0)
+                                 .count();
+    let smoothed = trained.total() as f64 + SMOOTHING * unseen_symbols as f64;
+
+    let mut kl_divergence   = 0.0;
+    let mut total_variation = 0.0;
+
+    for &c in observed.counts().keys() {
+        let p = share(observed, c);
+        let q = match trained.get(c) {
+            0 => SMOOTHING / smoothed,
+            f => f as f64 / smoothed,
+        };
+
+        kl_divergence   += p * log2(p / q);
+        total_variation += (p - share(trained, c)).abs();
+    }
+    for &c in trained.counts().keys() {
+        if observed.get(c) == 0 {
+            total_variation += share(trained, c);
+        }
+    }
+
+    let per_char = |bits: usize| match observed.total() {
+        0     => 0.0,
+        total => bits as f64 / total as f64,
+    };
+    let stale = predicted_bits(&codes_from_table(trained),  observed);
+    let fresh = predicted_bits(&codes_from_table(observed), observed);
+
+    DriftReport {
+        kl_divergence,
+        total_variation     : total_variation / 2.0,
+        unseen_symbols,
+        stale_bits_per_char : stale.map(per_char),
+        fresh_bits_per_char : fresh.map_or(0.0, per_char),
+    }
+}
+
+/// The Shannon entropy of the distribution in `freqs`, in bits per symbol.
+/// 
+pub(crate) fn entropy(freqs: &FrequencyTable) -> f64 {
+    let total = freqs.total() as f64;
+
+    freqs.counts().values()
+         .map(|&f| f as f64 / total)
+         .map(|p| -p * log2(p))
+         .sum()
+}
+
+/// The base-2 logarithm of `x`.
+/// 
+#[cfg(feature = "std")]
+fn log2(x: f64) -> f64 {
+    x.log2()
+}
+
+/// The base-2 logarithm of `x`. Without `std` there's no `f64::log2()`, so
+/// it's worked out from `x`'s exponent, and a series for the logarithm of its
+/// mantissa, which is between 1 and 2: ln m = 2 atanh z, for z = (m - 1) /
+/// (m + 1), which is under 1/3, so twenty terms of the series are plenty.
+/// 
+#[cfg(not(feature = "std"))]
+fn log2(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+    // Subnormals are scaled up into the normal range first.
+    let (x, shift) = match x < f64::MIN_POSITIVE {
+        true  => (x * (1_u64 << 54) as f64, 54),
+        false => (x, 0),
+    };
+    let bits     = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64 - 1023 - shift;
+    let mantissa = f64::from_bits(bits & !(0x7ff << 52) | 1023 << 52);
+
+    let z        = (mantissa - 1.0) / (mantissa + 1.0);
+    let mut term = z;
+    let mut sum  = 0.0;
+
+    for k in 0..20 {
+        sum  += term / (2 * k + 1) as f64;
+        term *= z * z;
+    }
+    exponent as f64 + 2.0 * sum * core::f64::consts::LOG2_E
+}
+
+/// The bits needed to store `codes` ahead of the encoded data: a 32-bit symbol
+/// count, then each symbol as UTF-8 followed by a byte holding its code length.
+/// That's enough for a decoder to rebuild canonical codes.
+/// 
+pub(crate) fn table_overhead_bits(codes: &HashMap<char, String>) -> usize {
+    32 + codes.keys().map(|c| c.len_utf8() * 8 + 8).sum::<usize>()
+}
+
+
+/// Sizes, in bits, of a text under several encodings, for judging what Huffman
+/// coding actually buys over simpler schemes.
+/// 
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaselineReport {
+    /// The number of characters in the text.
+    pub symbols          : usize,
+    /// The number of distinct characters in the text.
+    pub alphabet_size    : usize,
+    /// The text as UTF-8.
+    pub utf8_bits        : usize,
+    /// The text with every character given ⌈log2 k⌉ bits, where k is the size
+    /// of the alphabet.
+    pub fixed_width_bits : usize,
+    /// The Huffman coded text, not counting its code table.
+    pub huffman_bits     : usize,
+    /// The code table the Huffman coded text must be stored with.
+    pub table_bits       : usize,
+    /// The entropy lower bound for the text's distribution.
+    pub entropy_bits     : f64,
+}
+
+impl BaselineReport {
+    /// The Huffman coded text plus its code table.
+    /// 
+    pub fn huffman_total_bits(&self) -> usize {
+        self.huffman_bits + self.table_bits
+    }
+}
+
+impl fmt::Display for BaselineReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = [("utf-8",       self.utf8_bits as f64),
+                    ("fixed-width", self.fixed_width_bits as f64),
+                    ("huffman",     self.huffman_total_bits() as f64),
+                    ("entropy",     self.entropy_bits)];
+
+        writeln!(f, "{:<12} {:>12} {:>10}", "scheme", "bits", "bits/char")?;
+
+        for (name, bits) in rows {
+            let per_char = match self.symbols {
+                0 => 0.0,
+                n => bits / n as f64,
+            };
+            writeln!(f, "{:<12} {:>12.0} {:>10.3}", name, bits, per_char)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the size of `data` as UTF-8, as fixed-width codes, as Huffman codes
+/// with their table, and at its entropy bound. For short inputs the table can
+/// make Huffman coding the worst of the lot, and the report shows it.
+/// 
+pub fn baseline_report(data: &str) -> BaselineReport {
+    let freqs = FrequencyTable::from_text(data);
+    let codes = codes_from_table(&freqs);
+    let width = match freqs.len() {
+        0 => 0,
+        k => k.next_power_of_two().trailing_zeros() as usize,
+    };
+
+    BaselineReport {
+        symbols          : freqs.total(),
+        alphabet_size    : freqs.len(),
+        utf8_bits        : data.len() * 8,
+        fixed_width_bits : freqs.total() * width,
+        huffman_bits     : predicted_bits(&codes, &freqs).unwrap_or(0),
+        table_bits       : table_overhead_bits(&codes),
+        entropy_bits     : entropy(&freqs) * freqs.total() as f64,
+    }
+}
+
+
+/// How well the Huffman codes for a text compress it, from `code_stats()`.
+/// 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CodeStats {
+    /// The size of the text as UTF-8.
+    pub original_bits       : usize,
+    /// The size of the text as Huffman codes, not counting the code table.
+    pub compressed_bits     : usize,
+    /// The entropy of the text's distribution, in bits per character.
+    pub entropy             : f64,
+    /// The average length of the codes, weighted by frequency, in bits per
+    /// character. It's at least `entropy`, and less than `entropy + 1` for
+    /// any text of two or more distinct characters. A lone character's 1-bit
+    /// code is the exception, at exactly `entropy + 1`.
+    pub average_code_length : f64,
+    /// Each character's count and code length.
+    pub per_symbol          : HashMap<char, (usize, u8)>,
+}
+
+impl CodeStats {
+    /// `original_bits` over `compressed_bits`, or `None` for an empty text.
+    /// 
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.compressed_bits {
+            0    => None,
+            bits => Some(self.original_bits as f64 / bits as f64),
+        }
+    }
+}
+
+impl HuffmanCodebook {
+    /// The stats of the codes for the text their frequencies were counted
+    /// from. Like `frequencies()`, this isn't meaningful for a codebook made
+    /// by `from_static()`.
+    /// 
+    pub fn code_stats(&self) -> CodeStats {
+        let freqs = self.frequencies();
+
+        CodeStats {
+            original_bits       : freqs.counts()
+                                       .iter()
+                                       .map(|(c, &n)| c.len_utf8() * 8 * n)
+                                       .sum(),
+            compressed_bits     : self.codes()
+                                      .iter()
+                                      .map(|(&c, code)| freqs.get(c)
+                                                        * code.len as usize)
+                                      .sum(),
+            entropy             : entropy(freqs),
+            average_code_length : self.average_code_length(),
+            per_symbol          : self.codes()
+                                      .iter()
+                                      .map(|(&c, code)| {
+                                          (c, (freqs.get(c), code.len))
+                                      })
+                                      .collect(),
+        }
+    }
+}
+
+/// The stats of the Huffman codes for `data`, the ones
+/// `generate_huffman_codes()` gives.
+/// 
+pub fn code_stats(data: &str) -> CodeStats {
+    HuffmanCodebook::from_text(data).code_stats()
+}
+
+
+/// How a code table is sent ahead of the data it decodes.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableKind {
+    /// Each symbol and its code length, as `table_overhead_bits()` counts them.
+    Lengths,
+    /// The shape of the tree, a bit per node, with each leaf's symbol.
+    Tree,
+    /// Only a 64-bit fingerprint of a table the decoder already has.
+    External,
+}
+
+/// Where Huffman coding starts to pay for its table, from
+/// `break_even_length()`.
+/// 
+#[derive(Clone, Debug, PartialEq)]
+pub struct BreakEven {
+    /// The size of the table, in bits.
+    pub table_bits              : usize,
+    /// Average bits per symbol of Huffman coded text.
+    pub huffman_bits_per_symbol : f64,
+    /// Average bits per symbol of the text as UTF-8.
+    pub utf8_bits_per_symbol    : f64,
+    /// The shortest text, in symbols, for which the table and the coded text
+    /// together are smaller than the text as UTF-8; `None` if they never are.
+    pub symbols                 : Option<usize>,
+}
+
+/// Finds the length of text, with the distribution of `freqs`, beyond which
+/// Huffman coding it with its table sent as `table_kind` beats plain UTF-8.
+/// 
+pub fn break_even_length(freqs: &FrequencyTable, table_kind: TableKind) 
+
+    -> BreakEven 
+{
+    let codes = codes_from_table(freqs);
+    let total = freqs.total();
+
+    let table_bits = match table_kind {
+        TableKind::Lengths  => table_overhead_bits(&codes),
+        TableKind::Tree     => {
+            tree_size(codes.len()) 
+                + codes.keys().map(|c| c.len_utf8() * 8).sum::<usize>()
+        },
+        TableKind::External => 64,
+    };
+    let huffman_bits = predicted_bits(&codes, freqs).unwrap_or(0);
+    let utf8_bits    = freqs.counts().iter()
+                                     .map(|(c, &f)| c.len_utf8() * 8 * f)
+                                     .sum::<usize>();
+    let per_symbol   = |bits: usize| match total {
+        0 => 0.0,
+        n => bits as f64 / n as f64,
+    };
+    // Over n symbols, coding saves n * (utf8_bits - huffman_bits) / total bits,
+    // which must exceed the table.
+    let symbols = match utf8_bits.checked_sub(huffman_bits) {
+        Some(saved) if saved > 0 => Some(table_bits * total / saved + 1),
+        _                        => None,
+    };
+    BreakEven {
+        table_bits,
+        huffman_bits_per_symbol : per_symbol(huffman_bits),
+        utf8_bits_per_symbol    : per_symbol(utf8_bits),
+        symbols,
+    }
+}
+
+impl FrequencyTable {
+    /// The share of the total count taken by the `n` most frequent characters;
+    /// 1.0 once `n` covers the whole table, or if it's empty.
+    /// 
+    pub fn coverage_at(&self, n: usize) -> f64 {
+        let total = self.total();
+
+        match total {
+            0 => 1.0,
+            _ => self.ranked().iter().take(n).map(|&(_, f)| f).sum::<usize>() 
+                 as f64 / total as f64,
+        }
+    }
+    /// Tabulates the `top_n` most frequent characters, with their rank, count,
+    /// percentage of the total, and the cumulative percentage down to them.
+    /// Characters are shown as `SymbolDisplay` shows them.
+    /// Any beyond `top_n` are summed in a final OTHER row.
+    /// 
+    pub fn render(&self, top_n: usize) -> String {
+        let ranked = self.ranked();
+        let total  = self.total().max(1) as f64;
+        let mut table = format!("{:>5} {:<24} {:>10} {:>7} {:>7}\n", 
+                                "rank", "symbol", "count", "%", "cum %");
+        let mut cumulative = 0;
+
+        for (i, &(c, f)) in ranked.iter().take(top_n).enumerate() {
+            cumulative += f;
+            table += &format!("{:>5} {:<24} {:>10} {:>7.2} {:>7.2}\n", 
+                              i + 1, SymbolDisplay(c), f, 
+                              f as f64 * 100.0 / total, 
+                              cumulative as f64 * 100.0 / total);
+        }
+        if ranked.len() > top_n {
+            let other = self.total() - cumulative;
+
+            table += &format!("{:>5} {:<24} {:>10} {:>7.2} {:>7.2}\n", 
+                              "", "OTHER", other, 
+                              other as f64 * 100.0 / total, 100.0);
+        }
+        table
+    }
+}
+
+/// A hypothetical change to the alphabet of a codebook.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhatIf {
+    /// Add a symbol expected to occur with the given frequency. If the symbol
+    /// is already in the alphabet, the frequency is added to its count.
+    Add(char, usize),
+    /// Remove a symbol from the alphabet.
+    Remove(char),
+}
+
+/// How a hypothetical change to the alphabet would affect a codebook's codes.
+/// 
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhatIfReport {
+    /// The codebook's average code length, in bits.
+    pub average_length_before : f64,
+    /// The average code length after the change.
+    pub average_length_after  : f64,
+    /// The code length of each symbol in the codebook before and after the
+    /// change. The length after is `None` for a removed symbol.
+    pub lengths               : HashMap<char, (usize, Option<usize>)>,
+    /// The code length an added symbol would get, if it wasn't already in the
+    /// codebook.
+    pub added_length          : Option<usize>,
+}
+
+impl WhatIfReport {
+    /// The change in average code length; negative if the change would make
+    /// codes shorter on average.
+    /// 
+    pub fn average_length_delta(&self) -> f64 {
+        self.average_length_after - self.average_length_before
+    }
+    /// The existing symbols whose code length would change.
+    /// 
+    pub fn changed(&self) -> impl Iterator<Item = char> + '_ {
+        self.lengths.iter()
+            .filter(|(_, (before, after))| Some(*before) != *after)
+            .map(|(&c, _)| c)
+    }
+}
+
+impl HuffmanCodebook {
+    /// Reports how the codes would change if `symbol` were added to the
+    /// alphabet with the frequency `expected_freq`.
+    /// 
+    pub fn what_if_add(&self, symbol: char, expected_freq: usize) 
+
+        -> WhatIfReport 
+    {
+        self.what_if(&[WhatIf::Add(symbol, expected_freq)]).remove(0)
+    }
+    /// Reports how the codes would change if `symbol` were removed from the
+    /// alphabet.
+    /// 
+    pub fn what_if_remove(&self, symbol: char) -> WhatIfReport {
+        self.what_if(&[WhatIf::Remove(symbol)]).remove(0)
+    }
+    /// Reports on each of `changes`, each considered on its own against this
+    /// codebook. One node arena is reused for all the rebuilds.
+    /// 
+    pub fn what_if(&self, changes: &[WhatIf]) -> Vec<WhatIfReport> {
+        let mut nodes = NodeMem::new();
+
+        changes.iter().map(|change| {
+            let mut freqs = self.frequencies().clone();
+            let mut added = None;
+
+            match *change {
+                WhatIf::Add(c, f) => {
+                    if freqs.get(c) == 0 {
+                        added = Some(c);
+                    }
+                    freqs.add(c, f);
+                },
+                WhatIf::Remove(c) => {
+                    freqs.remove(c);
+                },
+            }
+            let codes   = codes_with_arena(&freqs, &mut nodes);
+            let lengths = self.codes().iter().map(|(c, code)| {
+                (*c, (code.len as usize, codes.get(c).map(String::len)))
+            }).collect();
+
+            WhatIfReport {
+                average_length_before : self.average_code_length(),
+                average_length_after  : average_code_length(&codes, &freqs),
+                lengths,
+                added_length          : added.and_then(|c| codes.get(&c))
+                                             .map(String::len),
+            }
+        }).collect()
+    }
+}
+
+
+/// Which structures are built for a set of codes, for `estimate_memory()`.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TreeOptions {
+    /// Codes are kept as strings, as `generate_huffman_codes()` returns them,
+    /// rather than packed in a `HuffmanCodebook`.
+    pub code_strings : bool,
+    /// A `DecoderFsm` is built for decoding.
+    pub decoder_fsm  : bool,
+}
+
+/// Expected peak memory use, in bytes, of building and using a set of codes.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// The arena holding the tree's nodes.
+    pub node_arena  : usize,
+    /// The heap used to build the tree.
+    pub heap        : usize,
+    /// The map of codes, including the strings' own buffers if codes are kept
+    /// as strings.
+    pub codebook    : usize,
+    /// The transition table of a `DecoderFsm`.
+    pub decoder_fsm : usize,
+}
+
+impl MemoryEstimate {
+    pub fn total(&self) -> usize {
+        self.node_arena + self.heap + self.codebook + self.decoder_fsm
+    }
+}
+
+/// The bytes a `HashMap` holding `len` entries of type `T` allocates: a power
+/// of two number of buckets, kept no more than 7/8 full, and a control byte
+/// per bucket.
+/// 
+fn map_size<T>(len: usize) -> usize {
+    let buckets = match len {
+        0     => return 0,
+        1..=3 => 4,
+        4..=7 => 8,
+        _     => (len * 8 / 7).next_power_of_two(),
+    };
+    buckets * (size_of::<T>() + 1)
+}
+
+/// Estimates the memory needed to build codes for an alphabet of
+/// `distinct_symbols` characters, and to hold the structures `options` asks
+/// for. It's computed from the sizes of the structures involved, so is best
+/// for large alphabets, where a code string's length is taken to be about the
+/// log of the alphabet's size.
+/// 
+pub fn estimate_memory(distinct_symbols: usize, options: &TreeOptions) 
+
+    -> MemoryEstimate 
+{
+    let n = distinct_symbols;
+
+    let codebook = if options.code_strings {
+        let string_len = (usize::BITS - n.leading_zeros()) as usize + 1;
+
+        map_size::<(char, String)>(n) + n * string_len
+    } else {
+        map_size::<(char, Code)>(n)
+    };
+    let decoder_fsm = match options.decoder_fsm {
+        true  => n.max(2).saturating_sub(1) * size_of::<[Transition; 2]>(),
+        false => 0,
+    };
+
+    MemoryEstimate {
+        node_arena : tree_size(n) * size_of::<Node>(),
+        heap       : n * size_of::<Handle>(),
+        codebook,
+        decoder_fsm,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use crate::sample::SplitMix64;
+
+    const TEXT: &str = "it was the best of times, it was the worst of times";
+
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn log2_without_std() {
+        let cases = [(1.0, 0.0), (8.0, 3.0), (0.5, -1.0), (0.75, -0.415037),
+                     (f64::MIN_POSITIVE / 4.0, -1024.0)];
+
+        for (x, expected) in cases {
+            assert!((log2(x) - expected).abs() < 1e-6, "{}", x);
+        }
+        assert!((log2(10.0) - core::f64::consts::LOG2_10).abs() < 1e-15);
+        assert_eq!(log2(0.0), f64::NEG_INFINITY);
+        assert_eq!(log2(f64::INFINITY), f64::INFINITY);
+        assert!(log2(-1.0).is_nan());
+    }
+
+    #[test]
+    fn identical() {
+        let freqs  = FrequencyTable::from_text(TEXT);
+        let report = distribution_divergence(&freqs, &freqs);
+
+        assert!(report.kl_divergence.abs() < 1e-9);
+        assert_eq!(report.total_variation, 0.0);
+        assert_eq!(report.unseen_symbols, 0);
+        assert_eq!(report.penalty_bits_per_char(), Some(0.0));
+    }
+
+    #[test]
+    fn perturbed() {
+        let trained  = FrequencyTable::from_text(TEXT);
+        let observed = FrequencyTable::from_text(&TEXT.replace("worst",
+                                                               "best"));
+        let report   = distribution_divergence(&trained, &observed);
+
+        assert!(report.kl_divergence > 0.0 && report.kl_divergence < 0.1);
+        assert!(report.total_variation > 0.0 && report.total_variation < 0.1);
+        assert_eq!(report.unseen_symbols, 0);
+
+        let penalty = report.penalty_bits_per_char().unwrap();
+
+        assert!((0.0..0.5).contains(&penalty));
+    }
+
+    #[test]
+    fn disjoint() {
+        let trained  = FrequencyTable::from_text("aaaabbc");
+        let observed = FrequencyTable::from_text("xxyz");
+        let report   = distribution_divergence(&trained, &observed);
+
+        assert!(report.kl_divergence > 1.0);
+        assert!((report.total_variation - 1.0).abs() < 1e-9);
+        assert_eq!(report.unseen_symbols, 3);
+        assert_eq!(report.stale_bits_per_char, None);
+        assert_eq!(report.penalty_bits_per_char(), None);
+        assert_eq!(report.fresh_bits_per_char, 1.5);
+    }
+
+    /// Frequencies without ties, so that every rebuild gives the same lengths.
+    /// 
+    fn untied() -> FrequencyTable {
+        [('a', 45), ('b', 13), ('c', 12), ('d', 16), ('e', 9), ('f', 5)]
+            .into_iter().collect()
+    }
+
+    #[test]
+    fn rendered_table() {
+        let freqs = untied();
+
+        assert_eq!(freqs.render(3).lines().collect::<Vec<_>>(), [
+            " rank symbol                        count       %   cum %",
+            "    1 'a'                              45   45.00   45.00",
+            "    2 'd'                              16   16.00   61.00",
+            "    3 'b'                              13   13.00   74.00",
+            "      OTHER                            26   26.00  100.00",
+        ]);
+        let mut escaped = FrequencyTable::default();
+        escaped.add('\n', 3);
+        escaped.add('"', 1);
+        escaped.add('\u{a0}', 2);
+        escaped.add('\u{200d}', 2);
+
+        assert_eq!(escaped.render(5).lines().collect::<Vec<_>>(), [
+            " rank symbol                        count       %   cum %",
+            "    1 U+000A (control)                  3   37.50   37.50",
+            "    2 U+00A0 (space separator)          2   25.00   62.50",
+            "    3 U+200D (format)                   2   25.00   87.50",
+            "    4 '\"'                               1   12.50  100.00",
+        ]);
+    }
+
+    #[test]
+    fn coverage() {
+        let freqs = untied();
+
+        assert_eq!(freqs.coverage_at(0), 0.0);
+        assert_eq!(freqs.coverage_at(2), 0.61);
+        assert_eq!(FrequencyTable::default().coverage_at(0), 1.0);
+
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        for len in [1, 7, 100, 1000, text.len()] {
+            let freqs = FrequencyTable::from_text(&text[..len]);
+            let mut last = 0.0;
+
+            for n in 0..=freqs.len() {
+                assert!(freqs.coverage_at(n) >= last);
+                last = freqs.coverage_at(n);
+            }
+            assert_eq!(freqs.coverage_at(freqs.len()), 1.0);
+            assert_eq!(freqs.coverage_at(usize::MAX), 1.0);
+        }
+    }
+
+    #[test]
+    fn what_if_add_matches_rebuild() {
+        let codebook = HuffmanCodebook::from_frequencies(untied());
+        let report   = codebook.what_if_add('g', 30);
+
+        let mut freqs = untied();
+        freqs.add('g', 30);
+        let rebuilt = HuffmanCodebook::from_frequencies(freqs);
+
+        assert_eq!(report.average_length_before,
+                   codebook.average_code_length());
+        assert_eq!(report.average_length_after, rebuilt.average_code_length());
+        assert_eq!(report.added_length,
+                   Some(rebuilt.codes()[&'g'].len as usize));
+
+        for (c, code) in codebook.codes() {
+            let lengths = (code.len as usize,
+                           Some(rebuilt.codes()[c].len as usize));
+
+            assert_eq!(report.lengths[c], lengths);
+        }
+    }
+
+    #[test]
+    fn what_if_remove_matches_rebuild() {
+        let codebook = HuffmanCodebook::from_frequencies(untied());
+        let report   = codebook.what_if_remove('a');
+
+        let mut freqs = untied();
+        freqs.remove('a');
+        let rebuilt = HuffmanCodebook::from_frequencies(freqs);
+
+        assert_eq!(report.average_length_after, rebuilt.average_code_length());
+        assert_eq!(report.lengths[&'a'], (1, None));
+        assert_eq!(report.added_length, None);
+        assert!(report.average_length_delta() > 0.0);
+
+        for c in report.changed().filter(|&c| c != 'a') {
+            assert_ne!(report.lengths[&c].1,
+                      Some(codebook.codes()[&c].len as usize));
+        }
+    }
+
+    #[test]
+    fn what_if_batch() {
+        let codebook = HuffmanCodebook::from_frequencies(untied());
+        let changes  = [WhatIf::Add('g', 30), WhatIf::Remove('a'),
+                        WhatIf::Add('a', 1)];
+        let reports  = codebook.what_if(&changes);
+
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0], codebook.what_if_add('g', 30));
+        assert_eq!(reports[1], codebook.what_if_remove('a'));
+        assert_eq!(reports[2].added_length, None);
+        assert_eq!(reports[2].changed().count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn memory_estimate() {
+        use crate::create_freq_nodes;
+        use crate::io::DecoderFsm;
+
+        // The arena's u16 handles limit alphabets to 32,768 symbols.
+        let freqs = (0x100..).filter_map(char::from_u32)
+                             .take(30_000)
+                             .enumerate()
+                             .map(|(i, c)| (c, i % 100 + 1))
+                             .collect::<FrequencyTable>();
+
+        let mut nodes = NodeMem::new();
+        create_freq_nodes(&freqs, &mut nodes);
+        let tree = crate::build_huffman_tree(&mut nodes);
+        assert!(tree.is_some());
+
+        let codebook = HuffmanCodebook::from_frequencies(freqs.clone());
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let strings  = codebook.code_strings();
+
+        // Buckets are kept no more than 7/8 full.
+        let map_bytes = |capacity: usize, entry: usize| {
+            (capacity * 8 / 7).next_power_of_two() * (entry + 1)
+        };
+        let actual = MemoryEstimate {
+            node_arena  : nodes.nodes.capacity() * size_of::<Node>(),
+            heap        : freqs.len() * size_of::<Handle>(),
+            codebook    : map_bytes(codebook.codes().capacity(), 
+                                    size_of::<(char, Code)>()),
+            decoder_fsm : std::mem::size_of_val(fsm.states()),
+        };
+        let string_bytes = map_bytes(strings.capacity(), 
+                                     size_of::<(char, String)>())
+                         + strings.values().map(String::capacity)
+                                           .sum::<usize>();
+
+        let close = |estimate: usize, actual: usize| {
+            (estimate as f64 - actual as f64).abs() <= 0.2 * actual as f64
+        };
+        let packed = estimate_memory(30_000, &TreeOptions { 
+            code_strings : false, 
+            decoder_fsm  : true,
+        });
+        let string = estimate_memory(30_000, &TreeOptions { 
+            code_strings : true, 
+            decoder_fsm  : false,
+        });
+
+        assert!(close(packed.node_arena,  actual.node_arena));
+        assert!(close(packed.heap,        actual.heap));
+        assert!(close(packed.codebook,    actual.codebook));
+        assert!(close(packed.decoder_fsm, actual.decoder_fsm));
+        assert!(close(packed.total(),     actual.total()));
+        assert!(close(string.codebook,    string_bytes));
+        assert_eq!(string.decoder_fsm, 0);
+    }
+
+    #[test]
+    fn baseline_short_input() {
+        let report = baseline_report("hello");
+
+        assert_eq!(report.utf8_bits, 40);
+        assert_eq!(report.fixed_width_bits, 10);
+        assert!(report.huffman_total_bits() > report.utf8_bits);
+        assert!(report.huffman_bits as f64 >= report.entropy_bits);
+        assert!(report.to_string().contains("huffman"));
+    }
+
+    #[test]
+    fn baseline_long_input() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let report = baseline_report(&text);
+
+        assert!(report.huffman_total_bits() < report.fixed_width_bits);
+        assert!(report.fixed_width_bits < report.utf8_bits);
+        assert!(report.huffman_bits as f64 >= report.entropy_bits);
+    }
+
+    #[test]
+    fn code_stats_bounds() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let stats = code_stats(&text);
+        let codes = crate::codebook::generate_huffman_codes(&text);
+        let freqs = FrequencyTable::from_text(&text);
+
+        assert_eq!(stats.original_bits, text.len() * 8);
+        assert_eq!(Some(stats.compressed_bits), predicted_bits(&codes, &freqs));
+        assert!(stats.compression_ratio().unwrap() > 1.5);
+        assert_eq!(stats.per_symbol.len(), codes.len());
+        assert_eq!(stats.per_symbol[&'e'], (freqs.get('e'),
+                                            codes[&'e'].len() as u8));
+
+        let mut rng = SplitMix64(7);
+
+        for _ in 0..200 {
+            let len  = 2 + rng.below(60);
+            let span = 2 + rng.below(20) as u32;
+            let text = (0..len).map(|_| {
+                char::from_u32('a' as u32 + rng.below(span as usize) as u32)
+                    .unwrap()
+            }).collect::<String>();
+            let stats = code_stats(&text);
+
+            if stats.per_symbol.len() > 1 {
+                assert!(stats.entropy <= stats.average_code_length + 1e-9);
+                assert!(stats.average_code_length < stats.entropy + 1.0);
+            }
+        }
+        // A lone character takes a bit for no information, and an empty text
+        // has nothing to compress.
+        let lone = code_stats("aaaa");
+
+        assert_eq!((lone.entropy, lone.average_code_length), (0.0, 1.0));
+        assert_eq!(lone.compression_ratio(), Some(8.0));
+        assert_eq!(code_stats("").compression_ratio(), None);
+        assert_eq!(code_stats("").average_code_length, 0.0);
+    }
+
+    #[test]
+    fn break_even() {
+        let even = [('a', 1), ('b', 1)].into_iter().collect();
+
+        // Each symbol saves 7 bits, so a 64-bit table is paid for after 10
+        // symbols, and a 19-bit tree (3 nodes and 2 bytes) after 3.
+        let lengths  = break_even_length(&even, TableKind::Lengths);
+        let tree     = break_even_length(&even, TableKind::Tree);
+        let external = break_even_length(&even, TableKind::External);
+
+        assert_eq!(lengths, BreakEven {
+            table_bits              : 64,
+            huffman_bits_per_symbol : 1.0,
+            utf8_bits_per_symbol    : 8.0,
+            symbols                 : Some(10),
+        });
+        assert_eq!((tree.table_bits, tree.symbols), (19, Some(3)));
+        assert_eq!((external.table_bits, external.symbols), (64, Some(10)));
+
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let n     = break_even_length(&freqs, TableKind::Lengths)
+                        .symbols.unwrap();
+
+        for (len, pays) in [(n - 1, false), (n, true)] {
+            let report = break_even_length(&freqs, TableKind::Lengths);
+            let coded  = report.table_bits as f64 
+                       + len as f64 * report.huffman_bits_per_symbol;
+
+            assert_eq!(coded < len as f64 * report.utf8_bits_per_symbol, pays);
+        }
+        assert_eq!(break_even_length(&FrequencyTable::default(), 
+                                     TableKind::Tree).symbols, None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Approximate counts of the most frequent characters in bounded memory, for
+//! a cheap first look at data too large to count exactly.
+//! 
+//! `ApproxCounter` runs the space-saving algorithm with `k` counters. A char
+//! that's being tracked is counted on its counter. An untracked char takes
+//! over the counter with the lowest count, `m`, and starts from `m + 1`,
+//! recording `m` as its possible overcount. After `N` chars:
+//! 
+//! - A tracked char's true count lies between its count less its overcount,
+//!   and its count.
+//! - No counter's overcount exceeds the lowest count, which is at most `N/k`.
+//! - An untracked char occurred at most as many times as the lowest count, so
+//!   every char that occurred more than `N/k` times is tracked.
+//! 
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::codebook::{FrequencyTable, ReservedId, TokenCodebook};
+use crate::collections::{map_with_capacity, HashMap};
+
+
+/// A bounded-memory counter of the most frequent characters of a stream. See
+/// the module docs for its error bounds.
+/// 
+#[derive(Clone, Debug)]
+pub struct ApproxCounter {
+    capacity : usize,
+    total    : u64,
+    counters : HashMap<char, (u64, u64)>,
+    by_count : BTreeSet<(u64, char)>,
+}
+
+impl ApproxCounter {
+    /// A counter that tracks up to `counters` characters at once, at least 1.
+    /// 
+    pub fn new(counters: usize) -> Self {
+        Self {
+            capacity : counters.max(1),
+            total    : 0,
+            counters : map_with_capacity(counters.max(1)),
+            by_count : BTreeSet::new(),
+        }
+    }
+    /// Counts an occurrence of `char_`.
+    /// 
+    pub fn add(&mut self, char_: char) {
+        self.total += 1;
+
+        let (count, error) = match self.counters.get(&char_) {
+            Some(&(count, error)) => {
+                self.by_count.remove(&(count, char_));
+                (count + 1, error)
+            },
+            None if self.counters.len() < self.capacity => (1, 0),
+            None => {
+                let (min, victim) = self.by_count.pop_first().unwrap();
+
+                self.counters.remove(&victim);
+                (min + 1, min)
+            },
+        };
+        self.counters.insert(char_, (count, error));
+        self.by_count.insert((count, char_));
+    }
+    /// Counts the characters of `text`.
+    /// 
+    pub fn add_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.add(c);
+        }
+    }
+    /// The number of characters counted.
+    /// 
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    /// The most any untracked character can have occurred, which is the
+    /// lowest count on a counter while they're all in use, and 0 before.
+    /// 
+    pub fn untracked_bound(&self) -> u64 {
+        match self.counters.len() < self.capacity {
+            true  => 0,
+            false => self.by_count.first().map_or(0, |&(count, _)| count),
+        }
+    }
+    /// Up to `n` of the tracked characters with the highest counts, each with
+    /// a lower and an upper bound on how many times it occurred. Ties are
+    /// broken in favor of the smaller char.
+    /// 
+    pub fn top(&self, n: usize) -> Vec<(char, u64, u64)> {
+        let mut top = self.counters.iter().map(|(&c, &(count, error))| {
+            (c, count - error, count)
+        }).collect::<Vec<_>>();
+
+        top.sort_unstable_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+    /// Codes for the tracked characters known to have occurred, weighted by
+    /// their lower bounds, and for `ReservedId::Escape`, weighted by the rest
+    /// of the total, to stand in for every other character. Fails as
+    /// `TokenCodebook::new()` does.
+    /// 
+    pub fn build_provisional_codebook(&self) 
+
+        -> Result<TokenCodebook, HuffmanError> 
+    {
+        let tracked = self.counters
+                          .iter()
+                          .map(|(&c, &(count, error))| (c, count - error))
+                          .filter(|&(_, lower)| lower > 0)
+                          .collect::<Vec<_>>();
+        let known   = tracked.iter().map(|&(_, lower)| lower).sum::<u64>();
+        let freqs   = tracked.into_iter()
+                             .map(|(c, lower)| (c, lower as usize))
+                             .collect::<FrequencyTable>();
+
+        TokenCodebook::new(&freqs, &[(ReservedId::Escape, self.total - known)])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::Token;
+
+    /// A skewed stream: the `i`th of 300 chars occurs 6000 / (i + 1) times,
+    /// in a shuffled order.
+    fn skewed() -> Vec<char> {
+        let mut stream = (0..300_u32).flat_map(|i| {
+            let c = char::from_u32(0x100 + i).unwrap();
+            std::iter::repeat_n(c, 6000 / (i as usize + 1))
+        }).collect::<Vec<_>>();
+        let mut state = 0x2545_f491_u64;
+
+        for i in (1..stream.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005)
+                         .wrapping_add(1442695040888963407);
+            stream.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        stream
+    }
+
+    #[test]
+    fn bounds_hold() {
+        let stream = skewed();
+        let exact  = FrequencyTable::from_chars(stream.iter().copied());
+
+        let mut approx = ApproxCounter::new(50);
+
+        stream.iter().for_each(|&c| approx.add(c));
+
+        let limit = approx.total() / 50;
+        let bound = approx.untracked_bound();
+        let top   = approx.top(usize::MAX);
+
+        assert_eq!(approx.total(), stream.len() as u64);
+        assert_eq!(top.len(), 50);
+        assert!(bound <= limit);
+
+        for &(c, lower, upper) in &top {
+            let count = exact.get(c) as u64;
+
+            assert!(lower <= count && count <= upper);
+            assert!(upper - lower <= bound);
+        }
+        for (&c, &count) in exact.counts() {
+            let tracked = top.iter().any(|&(t, ..)| t == c);
+
+            assert!(tracked || count as u64 <= bound);
+            assert!(tracked || count as u64 <= limit);
+        }
+        // The heaviest chars stand well clear of the noise, and come out on
+        // top in order.
+        let heaviest = approx.top(3).iter().map(|&(c, ..)| c)
+                                    .collect::<Vec<_>>();
+
+        assert_eq!(heaviest, ['\u{100}', '\u{101}', '\u{102}']);
+    }
+
+    #[test]
+    fn exact_until_full() {
+        let mut approx = ApproxCounter::new(3);
+
+        approx.add_text("abacab");
+
+        assert_eq!(approx.top(2), [('a', 3, 3), ('b', 2, 2)]);
+        assert_eq!(approx.untracked_bound(), 1);
+
+        // 'd' takes over the lowest counter, 'c''s.
+        approx.add('d');
+
+        assert_eq!(approx.top(3), [('a', 3, 3), ('b', 2, 2), ('d', 1, 2)]);
+        assert_eq!(ApproxCounter::new(3).untracked_bound(), 0);
+    }
+
+    #[test]
+    fn provisional_codebook() {
+        let stream = skewed();
+
+        let mut approx = ApproxCounter::new(20);
+
+        stream.iter().for_each(|&c| approx.add(c));
+
+        let codebook = approx.build_provisional_codebook().unwrap();
+        let escape   = codebook.reserved_code(ReservedId::Escape).unwrap();
+
+        for (c, lower, _) in approx.top(20) {
+            let code = codebook.code_for(Token::Char(c));
+
+            assert_eq!(code.is_some(), lower > 0);
+        }
+        // The chars left untracked make up much of the stream, so the escape
+        // is among the shortest codes.
+        assert!(escape.len <= codebook.code_for(Token::Char('\u{100}'))
+                                      .unwrap().len + 1);
+        assert!(codebook.code_for(Token::Char('\u{12b}')).is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! `huff`, compressing and decompressing text files from the shell.
+//! 
+//! ```text
+//! huff compress input.txt output.huf
+//! huff decompress output.huf roundtrip.txt
+//! huff compress --timeout 2.5 input.txt output.huf
+//! huff decompress --max-size 1000000 output.huf roundtrip.txt
+//! huff bench --iterations 20 input.txt
+//! huff inspect output.huf
+//! huff stats --build input.txt
+//! ```
+//! 
+//! Files are compressed with `pipe_compress_strict()`, so each carries its
+//! own code table and the number of characters it decodes to, and a damaged
+//! file fails to decompress rather than giving the wrong text. With
+//! `--timeout`, a run that takes longer than the given number of seconds is
+//! cancelled, and like any other failure leaves no output behind.
+//! 
+//! Decompressing stops once the text is over `--max-size` bytes, 1 GiB by
+//! default, so a small file crafted to decode to far more text than that
+//! can't fill the disk.
+//! 
+//! `bench` times counting, building the codes, encoding, and decoding a file
+//! with `measure()`, over `--iterations` rounds after two of warmup.
+//! 
+//! `inspect` describes a compressed file without writing its text: for a
+//! stream of ids, how often each occurs, by the name the stream gives it if
+//! it gives one, and for a stream of text, its sizes.
+//! 
+//! `stats` summarizes the characters of a text file, and with `--build`, the
+//! work of building its tree, from
+//! `HuffmanTree::from_frequencies_with_stats()`.
+//! 
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use huffman::HuffmanError;
+use huffman::codebook::{measure, FrequencyTable, MeasureConfig};
+use huffman::container::{decompress_ids_named, pipe_compress_strict};
+use huffman::container::{pipe_decompress_limited, CompressionStats};
+use huffman::container::{DecodeLimits, TableSource};
+use huffman::io::CancelToken;
+use huffman::tree::HuffmanTree;
+
+
+const USAGE: &str = "usage: huff compress [--timeout <secs>] <input> \
+                     <output>\n       \
+                     huff decompress [--timeout <secs>] [--max-size <bytes>] \
+                     <input> <output>\n       \
+                     huff bench [--iterations <n>] <input>\n       \
+                     huff inspect [--max-size <bytes>] <input>\n       \
+                     huff stats [--build] <input>";
+
+/// The most text decompressing writes, unless `--max-size` says otherwise.
+/// 
+const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+
+/// The options given ahead of, or among, the file names.
+/// 
+struct Options {
+    timeout  : Option<Duration>,
+    max_size : u64,
+    measure  : MeasureConfig,
+    build    : bool,
+}
+
+impl Options {
+    /// Splits the options out of `args`, leaving the rest in order.
+    /// 
+    fn parse(args: &[String]) -> Result<(Self, Vec<&str>), Failure> {
+        let mut options = Self {
+            timeout  : None,
+            max_size : DEFAULT_MAX_SIZE,
+            measure  : MeasureConfig::default(),
+            build    : false,
+        };
+        let mut rest    = vec![];
+        let mut args    = args.iter().map(String::as_str);
+
+        while let Some(arg) = args.next() {
+            match arg {
+                "--timeout"    => {
+                    let secs = args.next().and_then(|s| s.parse().ok());
+                    let secs = secs.and_then(|s| {
+                        Duration::try_from_secs_f64(s).ok()
+                    });
+                    options.timeout = Some(secs.ok_or(Failure::Usage)?);
+                },
+                "--max-size"   => {
+                    let bytes = args.next().and_then(|s| s.parse().ok());
+
+                    options.max_size = bytes.ok_or(Failure::Usage)?;
+                },
+                "--iterations" => {
+                    let n = args.next().and_then(|s| s.parse().ok());
+
+                    options.measure.iterations = n.ok_or(Failure::Usage)?;
+                },
+                "--build"      => options.build = true,
+                _              => rest.push(arg),
+            }
+        }
+        Ok((options, rest))
+    }
+
+    /// A token that's cancelled once the timeout, if any, runs out.
+    /// 
+    fn cancel_token(&self) -> CancelToken {
+        let token = CancelToken::new();
+
+        if let Some(timeout) = self.timeout {
+            let token = token.clone();
+
+            thread::spawn(move || {
+                thread::sleep(timeout);
+                token.cancel();
+            });
+        }
+        token
+    }
+}
+
+
+/// What went wrong, with the file it went wrong on.
+/// 
+enum Failure {
+    Usage,
+    Open(String, io::Error),
+    Create(String, io::Error),
+    Huffman(String, HuffmanError),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage             => write!(f, "{}", USAGE),
+            Failure::Open(path, e)     => {
+                write!(f, "huff: can't open {}: {}", path, e)
+            },
+            Failure::Create(path, e)   => {
+                write!(f, "huff: can't create {}: {}", path, e)
+            },
+            Failure::Huffman(path, e)  => write!(f, "huff: {}: {}", path, e),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    let result = Options::parse(&args).and_then(|(options, args)| {
+        match args[..] {
+            ["compress",   input, output] => compress(input, output, &options),
+            ["decompress", input, output] => {
+                decompress(input, output, &options)
+            },
+            ["bench",      input]         => bench(input, &options),
+            ["inspect",    input]         => inspect(input, &options),
+            ["stats",      input]         => stats(input, &options),
+            _                             => Err(Failure::Usage),
+        }
+    });
+    match result {
+        Ok(())                    => ExitCode::SUCCESS,
+        Err(e @ Failure::Usage)   => {
+            eprintln!("{}", e);
+            ExitCode::from(2)
+        },
+        Err(e)                    => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Compresses `input` to `output`, printing the sizes and the ratio.
+/// 
+fn compress(input: &str, output: &str, options: &Options) 
+
+    -> Result<(), Failure> 
+{
+    let token = options.cancel_token();
+    let stats = run(input, output, |r, w| {
+        pipe_compress_strict(r, w, TableSource::Train, Some(&token))
+    })?;
+    let ratio = match stats.text_bytes {
+        0 => 0.0,
+        n => stats.stream_bytes as f64 / n as f64,
+    };
+    println!("{}: {} bytes -> {} bytes ({:.1}%)", output, stats.text_bytes,
+             stats.stream_bytes, ratio * 100.0);
+    Ok(())
+}
+
+/// Decompresses `input` to `output`, up to the most text allowed.
+/// 
+fn decompress(input: &str, output: &str, options: &Options) 
+
+    -> Result<(), Failure> 
+{
+    let token  = options.cancel_token();
+    let limits = DecodeLimits {
+        max_output_bytes: Some(options.max_size), ..Default::default()
+    };
+    run(input, output, |r, w| {
+        pipe_decompress_limited(r, w, None, limits, Some(&token))
+    })?;
+    Ok(())
+}
+
+/// Times coding `input`, printing the time each phase took per round.
+/// 
+fn bench(input: &str, options: &Options) -> Result<(), Failure> {
+    let text   = read_text(input)?;
+    let report = measure(&text, options.measure);
+
+    println!("{}: {} bytes, {} chars, {:.2} bits/char, {} rounds", input,
+             report.bytes, report.chars, report.bits_per_char,
+             report.iterations);
+
+    for (phase, timing) in [("counting",    report.counting),
+                            ("table build", report.table_build),
+                            ("encode",      report.encode),
+                            ("decode",      report.decode)] {
+        println!("  {:<12} {:>12.1?} {:>10.1} MB/s", phase, timing.mean,
+                 timing.mb_per_sec);
+    }
+    Ok(())
+}
+
+/// Describes the compressed file `input`. The ids of a stream of ids are
+/// listed most frequent first, by name if the stream names them; a stream of
+/// text is decoded, up to the most text allowed, for its sizes.
+/// 
+fn inspect(input: &str, options: &Options) -> Result<(), Failure> {
+    let stream  = fs::read(input).map_err(|e| Failure::Open(input.into(), e))?;
+    let failure = |e| Failure::Huffman(input.into(), e);
+
+    let decoded = match decompress_ids_named(&stream) {
+        Ok(decoded) => decoded,
+        Err(HuffmanError::MalformedData("the stream holds text, not ids")) => {
+            let limits = DecodeLimits {
+                max_output_bytes: Some(options.max_size), ..Default::default()
+            };
+            let stats = pipe_decompress_limited(&stream[..], io::sink(), None,
+                                                limits, None)
+                            .map_err(failure)?;
+
+            println!("{}: text, {} chars, {} bytes", input, stats.symbols,
+                     stats.text_bytes);
+            println!("  {} bytes compressed, {} of table, {} bits of codes",
+                     stats.stream_bytes, stats.table_bytes,
+                     stats.payload_bits);
+            return Ok(());
+        },
+        Err(e) => return Err(failure(e)),
+    };
+    let mut counts = BTreeMap::new();
+
+    for &id in &decoded.ids {
+        *counts.entry(id).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+
+    counts.sort_by_key(|&(id, count)| (std::cmp::Reverse(count), id));
+
+    println!("{}: ids, {} of them, {} distinct{}", input, decoded.ids.len(),
+             counts.len(), match decoded.names {
+                 Some(_) => ", named",
+                 None    => "",
+             });
+    for (id, count) in counts {
+        match decoded.decoded_symbol_name(id) {
+            Some(name) => println!("  {:>10}  {}", count, name),
+            None       => println!("  {:>10}  #{}", count, id),
+        }
+    }
+    Ok(())
+}
+
+/// Summarizes the characters of `input`, and with `--build`, the work of
+/// building its tree.
+/// 
+fn stats(input: &str, options: &Options) -> Result<(), Failure> {
+    let failure = |e| Failure::Huffman(input.into(), e);
+    let r       = File::open(input).map_err(|e| {
+        Failure::Open(input.into(), e)
+    })?;
+    let freqs   = FrequencyTable::from_reader(BufReader::new(r))
+                      .map_err(|e| failure(e.into()))?;
+    let summary = freqs.summary();
+
+    println!("{}: {} chars, {} distinct, counts {} to {}, {} seen once",
+             input, summary.total, summary.symbols, summary.min_count,
+             summary.max_count, summary.singletons);
+
+    if options.build {
+        let (_, build) = HuffmanTree::from_frequencies_with_stats(&freqs)
+                             .map_err(failure)?;
+        let builder    = match build.two_queue {
+            true  => "two queues",
+            false => "heap",
+        };
+        println!("  built with a {}, {} heap ops, largest heap {}, {} \
+                  comparisons", builder, build.heap_ops, build.max_heap_len,
+                 build.comparisons);
+    }
+    Ok(())
+}
+
+/// Reads the whole of `input` as text.
+/// 
+fn read_text(input: &str) -> Result<String, Failure> {
+    let bytes = fs::read(input).map_err(|e| Failure::Open(input.into(), e))?;
+
+    String::from_utf8(bytes).map_err(|_| {
+        Failure::Huffman(input.into(), HuffmanError::InvalidUtf8)
+    })
+}
+
+/// Opens `input` and creates `output` for `f` to read and write. The output
+/// is removed if `f` fails or is cancelled, so a failure leaves no partial
+/// file behind.
+/// 
+fn run<F>(input: &str, output: &str, f: F) -> Result<CompressionStats, Failure>
+where
+    F: FnOnce(BufReader<File>, &mut BufWriter<File>)
+        -> Result<CompressionStats, HuffmanError>,
+{
+    let r = File::open(input).map_err(|e| Failure::Open(input.into(), e))?;
+    let w = File::create(output).map_err(|e| {
+        Failure::Create(output.into(), e)
+    })?;
+    let mut w = BufWriter::new(w);
+
+    f(BufReader::new(r), &mut w).map_err(|e| {
+        drop(fs::remove_file(output));
+        Failure::Huffman(input.into(), e)
+    })
+}
@@ -0,0 +1,384 @@
+//! Packing codes into bytes, and reading them back a bit at a time.
+//! 
+
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::codebook::Code;
+
+
+/// Packs codes into bytes, most significant bit first. Complete bytes can be
+/// taken as they fill, while the partial byte at the end is kept back until
+/// `finish()`.
+/// 
+#[derive(Clone, Debug, Default)]
+pub struct BitWriter {
+    bytes   : Vec<u8>,
+    bit_len : u64,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Appends the bits of `code`.
+    /// 
+    pub fn write_code(&mut self, code: Code) {
+        for i in (0..code.len).rev() {
+            let bit = (code.bits >> i & 1) as u8;
+            let pos = (self.bit_len % 8) as u32;
+
+            if pos == 0 {
+                self.bytes.push(0);
+            }
+            *self.bytes.last_mut().unwrap() |= bit << (7 - pos);
+            self.bit_len += 1;
+        }
+    }
+    /// Appends the first `nbits` bits of `bytes`, as packed by another writer,
+    /// whose padding must be zeros.
+    /// 
+    #[cfg(feature = "std")]
+    pub(crate) fn write_packed(&mut self, bytes: &[u8], nbits: u64) {
+        let shift = (self.bit_len % 8) as u32;
+        let len   = self.bytes.len() + ((self.bit_len + nbits).div_ceil(8)
+                                        - self.bit_len.div_ceil(8)) as usize;
+
+        for &byte in &bytes[..nbits.div_ceil(8) as usize] {
+            if shift == 0 {
+                self.bytes.push(byte);
+            } else {
+                *self.bytes.last_mut().unwrap() |= byte >> shift;
+                self.bytes.push(byte << (8 - shift));
+            }
+        }
+        self.bytes.truncate(len);
+        self.bit_len += nbits;
+    }
+    /// The number of bits written so far.
+    /// 
+    pub fn bit_len(&self) -> u64 {
+        self.bit_len
+    }
+    /// Pads the partial last byte, if there is one, with zero bits, so that
+    /// what's written next starts on a byte boundary. Returns the number of
+    /// padding bits. Readers skip the padding with `skip_to_byte_boundary()`.
+    /// 
+    pub fn align_to_byte(&mut self) -> u8 {
+        let pad = ((8 - self.bit_len % 8) % 8) as u8;
+
+        self.bit_len += pad as u64;
+        pad
+    }
+    /// The number of bytes waiting to be taken, counting a partial last byte.
+    /// 
+    pub(crate) fn buffered_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+    /// The number of complete bytes waiting to be taken.
+    /// 
+    pub(crate) fn complete_bytes(&self) -> usize {
+        match self.bit_len % 8 {
+            0 => self.bytes.len(),
+            _ => self.bytes.len() - 1,
+        }
+    }
+    /// Removes and returns the complete bytes written so far.
+    /// 
+    pub(crate) fn take_complete(&mut self) -> Vec<u8> {
+        let n = self.complete_bytes();
+        let partial = self.bytes.split_off(n);
+
+        core::mem::replace(&mut self.bytes, partial)
+    }
+    /// Removes and returns everything written so far, with the last byte padded
+    /// with zeros. The writer is left empty, to be written to afresh.
+    /// 
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.bit_len = 0;
+        core::mem::take(&mut self.bytes)
+    }
+    /// Everything written, with its bit length, counting any padding added
+    /// by `align_to_byte()`.
+    /// 
+    pub fn into_buffer(self) -> BitBuffer {
+        BitBuffer { bytes: self.bytes, bit_len: self.bit_len as usize }
+    }
+}
+
+/// Packed codes, most significant bit first, with the number of bits of them
+/// used. The bit length never runs past the end of the bytes, and every bit
+/// after it is zero, so the two can't be mismatched when the codes are
+/// decoded.
+/// 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BitBuffer {
+    bytes   : Vec<u8>,
+    bit_len : usize,
+}
+
+impl BitBuffer {
+    /// The first `bit_len` bits of `bytes`. Fails with
+    /// `HuffmanError::BitLengthOverrun` if `bytes` has fewer bits, and
+    /// `HuffmanError::NonzeroPadding` if any bit after them is set.
+    /// 
+    pub fn try_new(bytes: Vec<u8>, bit_len: usize) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        check_len(&bytes, bit_len)?;
+
+        match first_set_bit(&bytes, bit_len) {
+            Some(bit_offset) => {
+                Err(HuffmanError::NonzeroPadding { bit_offset })
+            },
+            None => Ok(Self { bytes, bit_len }),
+        }
+    }
+    /// Like `try_new()`, but takes any bits after the first `bit_len` as
+    /// padding, and clears them rather than failing.
+    /// 
+    pub fn try_new_lenient(mut bytes: Vec<u8>, bit_len: usize) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        check_len(&bytes, bit_len)?;
+
+        let used = bit_len.div_ceil(8);
+
+        bytes[used..].fill(0);
+
+        if !bit_len.is_multiple_of(8) {
+            bytes[used - 1] &= 0xff << (8 - bit_len % 8);
+        }
+        Ok(Self { bytes, bit_len })
+    }
+    /// The packed bytes, the last padded with zeros.
+    /// 
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+    /// The number of bits used.
+    /// 
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bit_len == 0
+    }
+    /// A reader over the bits used.
+    /// 
+    pub fn reader(&self) -> BitReader<'_> {
+        BitReader::new(&self.bytes, self.bit_len)
+    }
+    /// The packed bytes and the number of bits used.
+    /// 
+    pub fn into_parts(self) -> (Vec<u8>, usize) {
+        (self.bytes, self.bit_len)
+    }
+    /// An empty writer that reuses the buffer's allocation.
+    /// 
+    #[cfg(feature = "std")]
+    pub(crate) fn into_writer(mut self) -> BitWriter {
+        self.bytes.clear();
+
+        BitWriter { bytes: self.bytes, bit_len: 0 }
+    }
+}
+
+/// Fails with `HuffmanError::BitLengthOverrun` if `bytes` has fewer than
+/// `bit_len` bits.
+/// 
+fn check_len(bytes: &[u8], bit_len: usize) -> Result<(), HuffmanError> {
+    match bit_len <= bytes.len() * 8 {
+        true  => Ok(()),
+        false => Err(HuffmanError::BitLengthOverrun { 
+            bit_len, 
+            bytes: bytes.len() 
+        }),
+    }
+}
+
+/// The offset of the first set bit of `bytes` at or after `start`, if any.
+/// 
+fn first_set_bit(bytes: &[u8], start: usize) -> Option<usize> {
+    (start..bytes.len() * 8).find(|&i| bytes[i / 8] >> (7 - i % 8) & 1 == 1)
+}
+
+/// Reads bits from a byte slice, most significant bit first, up to a given
+/// number of bits.
+/// 
+#[derive(Clone, Debug)]
+pub struct BitReader<'a> {
+    bytes : &'a [u8],
+    nbits : usize,
+    pos   : usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// A reader over the first `nbits` bits of `bytes`.
+    /// 
+    /// # Panics
+    /// 
+    /// If `bytes` has fewer than `nbits` bits.
+    /// 
+    pub fn new(bytes: &'a [u8], nbits: usize) -> Self {
+        assert!(nbits <= bytes.len() * 8, "bit length past the end of bytes");
+        Self { bytes, nbits, pos: 0 }
+    }
+    /// The next bit, or `None` once all `nbits` have been read.
+    /// 
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.pos == self.nbits {
+            return None;
+        }
+        let bit = self.bytes[self.pos / 8] >> (7 - self.pos % 8) & 1 == 1;
+
+        self.pos += 1;
+        Some(bit)
+    }
+    /// The number of bits read so far.
+    /// 
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// Skips to the start of the next byte, unless already at the start of
+    /// one, and returns the number of bits skipped. Never skips past `nbits`.
+    /// 
+    pub fn skip_to_byte_boundary(&mut self) -> u8 {
+        let next = self.pos.next_multiple_of(8).min(self.nbits);
+        let skip = (next - self.pos) as u8;
+
+        self.pos = next;
+        skip
+    }
+    /// The number of bits left to read.
+    /// 
+    pub fn remaining(&self) -> usize {
+        self.nbits - self.pos
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::string::ToString;
+    use crate::codebook::{Codec, HuffmanCodebook};
+    use crate::io::{encode, DecoderFsm};
+
+    /// Writes three records, each aligned to a byte, and decodes each from
+    /// its own byte offset.
+    /// 
+    #[test]
+    fn aligned_records() {
+        let records  = ["call me ishmael", "some years ago", "never mind"];
+        let codebook = HuffmanCodebook::from_text(&records.concat());
+        let mut writer = BitWriter::new();
+        let mut index  = vec![];
+
+        for record in records {
+            let start = writer.bit_len();
+
+            for c in record.chars() {
+                writer.write_code(codebook.code_for(c).unwrap());
+            }
+            let len = writer.bit_len() - start;
+            let pad = writer.align_to_byte();
+
+            assert_eq!(start % 8, 0);
+            assert_eq!((len + pad as u64) % 8, 0);
+            assert!(pad < 8);
+            index.push(((start / 8) as usize, len as usize));
+        }
+        assert_eq!(writer.align_to_byte(), 0);
+
+        let bytes = writer.finish();
+
+        for ((offset, len), record) in index.into_iter().zip(records).rev() {
+            let mut r = BitReader::new(&bytes[offset..], len);
+
+            assert_eq!(codebook.decode(&mut r).unwrap(), record);
+        }
+
+        let mut r = BitReader::new(&[0xff, 0xff], 12);
+
+        assert_eq!(r.skip_to_byte_boundary(), 0);
+        r.read_bit();
+        assert_eq!(r.skip_to_byte_boundary(), 7);
+        assert_eq!(r.position(), 8);
+        r.read_bit();
+        assert_eq!(r.skip_to_byte_boundary(), 3);
+        assert_eq!(r.remaining(), 0);
+    }
+
+    #[test]
+    fn reusing_a_writer() {
+        let codebook   = HuffmanCodebook::from_text("call me ishmael");
+        let mut writer = BitWriter::new();
+
+        for record in ["call me", "ishmael", "", "me"] {
+            codebook.encode(record, &mut writer).unwrap();
+
+            let nbits = writer.bit_len() as usize;
+            let bytes = writer.finish();
+
+            assert_eq!(writer.bit_len(), 0);
+            assert_eq!(bytes.len(), nbits.div_ceil(8));
+            assert_eq!(codebook.decode(&mut BitReader::new(&bytes, nbits)),
+                       Ok(record.to_string()));
+        }
+        codebook.encode("mail", &mut writer).unwrap();
+        writer.finish();
+        codebook.encode("mail", &mut writer).unwrap();
+
+        let nbits  = writer.bit_len() as usize;
+        let buffer = writer.into_buffer();
+
+        assert_eq!(buffer.bit_len(), nbits);
+        assert_eq!(buffer.as_bytes().len(), nbits.div_ceil(8));
+    }
+
+    #[test]
+    fn buffer_validation() {
+        assert_eq!(BitBuffer::try_new(vec![0b1010_0000], 3).unwrap().reader()
+                                                           .remaining(), 3);
+        assert_eq!(BitBuffer::try_new(vec![0xff, 0x40], 9),
+                   Err(HuffmanError::NonzeroPadding { bit_offset: 9 }));
+        assert_eq!(BitBuffer::try_new(vec![0b1010_0000, 0, 4], 3),
+                   Err(HuffmanError::NonzeroPadding { bit_offset: 21 }));
+        assert_eq!(BitBuffer::try_new(vec![0], 9),
+                   Err(HuffmanError::BitLengthOverrun { 
+                       bit_len: 9, bytes: 1 
+                   }));
+        assert_eq!(BitBuffer::try_new_lenient(vec![0], 9),
+                   BitBuffer::try_new(vec![0], 9));
+
+        let lenient = BitBuffer::try_new_lenient(vec![0xff, 0xff], 9).unwrap();
+
+        assert_eq!(lenient.as_bytes(), [0xff, 0x80]);
+        assert_eq!(lenient, BitBuffer::try_new(vec![0xff, 0x80], 9).unwrap());
+        assert!(BitBuffer::try_new(vec![], 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn buffer_round_trip() {
+        let text     = "it is a truth universally acknowledged";
+        let codebook = HuffmanCodebook::from_text(text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let encoded  = encode(text);
+        let (bytes, bit_len) = encoded.clone().into_parts();
+        let rebuilt  = BitBuffer::try_new(bytes, bit_len).unwrap();
+
+        assert_eq!(rebuilt, encoded);
+        assert_eq!(fsm.decode(&rebuilt).unwrap(), text);
+        assert_eq!(codebook.decode(&mut rebuilt.reader()).unwrap(), text);
+
+        let mut writer = BitWriter::new();
+
+        codebook.encode(text, &mut writer).unwrap();
+
+        assert_eq!(writer.into_buffer(), encoded);
+    }
+}
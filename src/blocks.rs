@@ -0,0 +1,305 @@
+//! Compressing bytes in independent blocks, each coded with its own table or
+//! stored as it is, whichever is smaller, as DEFLATE falls back to stored
+//! blocks. Data that doesn't compress, such as random bytes, grows by no more
+//! than the header and a few bytes per block.
+//! 
+//! Each byte is coded as the character with its value, U+0000 to U+00FF. The
+//! stream starts with the header `pipe_compress()` writes, with only bit 5 of
+//! the flags set, and the blocks follow it, each starting on a byte boundary,
+//! with integers little-endian:
+//! 
+//! | bytes | contents                                                      |
+//! |-------|---------------------------------------------------------------|
+//! | 1     | flags; bit 0 is set if the block is stored                    |
+//! | 4 + n | if stored, the length of the block and its bytes              |
+//! | 4     | if coded, the number of bits of codes                         |
+//! | 4 + n | if coded, the table's length and the table, as written by     |
+//! |       | `HuffmanCodebook::export()`                                   |
+//! | ...   | if coded, the codes, packed most significant bit first and    |
+//! |       | padded to a whole byte                                        |
+//! 
+
+use std::io::{Read, Write};
+use std::time::Instant;
+
+use crate::bits::BitWriter;
+use crate::pipe::{read_exact, read_flags, read_table};
+use crate::pipe::{FLAG_BLOCKS, MAGIC, VERSION};
+use crate::HuffmanError;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+use crate::container::CompressionStats;
+use crate::io::{DecodeTable, READ_BUF_SIZE};
+
+
+/// Set if a block is stored as it is.
+/// 
+pub(crate) const BLOCK_STORED: u8 = 1;
+
+
+/// Compresses the bytes read from `r` in blocks of `block_size` bytes,
+/// writing the stream to `w`. Each block is stored as it is if coding it,
+/// with its table, would take more room. The stats count the blocks of each
+/// kind. The stream is read by `decompress_blocks()`.
+/// 
+/// # Panics
+/// 
+/// If `block_size` is 0, or doesn't fit in a `u32`.
+/// 
+pub fn compress_blocks(mut r      : impl Read,
+                       mut w      : impl Write,
+                       block_size : usize) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    assert!(block_size > 0, "blocks must have room for data");
+    assert!(u32::try_from(block_size).is_ok(), "blocks are at most 4 GiB");
+
+    let start     = Instant::now();
+    let mut stats = CompressionStats::default();
+    let mut block = Vec::with_capacity(block_size);
+    let mut out   = MAGIC.to_vec();
+
+    out.extend([VERSION, FLAG_BLOCKS]);
+
+    loop {
+        block.clear();
+        r.by_ref().take(block_size as u64).read_to_end(&mut block)?;
+
+        if block.is_empty() {
+            break;
+        }
+        write_block(&block, &mut out, &mut stats);
+
+        stats.symbols    += block.len() as u64;
+        stats.text_bytes += block.len() as u64;
+
+        stats.stream_bytes += out.len() as u64;
+        w.write_all(&out)?;
+        out.clear();
+    }
+    stats.stream_bytes += out.len() as u64;
+    w.write_all(&out)?;
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+/// Appends `block` to `out`, coded if that's smaller than storing it.
+/// 
+fn write_block(block: &[u8], out: &mut Vec<u8>, stats: &mut CompressionStats) {
+    let freqs    = FrequencyTable::from_chars(block.iter().map(|&b| b.into()));
+    let codebook = HuffmanCodebook::from_frequencies_capped(freqs);
+    let table    = codebook.export();
+
+    let table_bytes = 4 + table.len() as u64;
+
+    // The size is known from the counts, before anything is encoded.
+    let bits = codebook.frequencies().counts().iter().map(|(c, &n)| {
+        codebook.codes()[c].len as u64 * n as u64
+    }).sum::<u64>();
+    let coded = 4 + table_bytes + bits.div_ceil(8);
+
+    if coded >= 4 + block.len() as u64 || u32::try_from(bits).is_err() {
+        out.push(BLOCK_STORED);
+        out.extend((block.len() as u32).to_le_bytes());
+        out.extend(block);
+
+        stats.stored_blocks += 1;
+        return;
+    }
+    let mut writer = BitWriter::new();
+
+    for &b in block {
+        writer.write_code(codebook.codes()[&b.into()]);
+    }
+    out.push(0);
+    out.extend((bits as u32).to_le_bytes());
+    out.extend((table.len() as u32).to_le_bytes());
+    out.extend(table);
+    out.extend(writer.finish());
+
+    stats.payload_bits   += bits;
+    stats.predicted_bits += bits;
+    stats.table_bytes    += table_bytes;
+    stats.coded_blocks   += 1;
+}
+
+/// Decompresses a stream written by `compress_blocks()` from `r`, writing the
+/// bytes to `w` a block at a time. Fails with `HuffmanError::MalformedData`
+/// for any other stream.
+/// 
+pub fn decompress_blocks(mut r : impl Read,
+                         mut w : impl Write) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    if read_flags(&mut r)? != FLAG_BLOCKS {
+        return Err(HuffmanError::MalformedData("the stream isn't in blocks"));
+    }
+    let start     = Instant::now();
+    let mut stats = CompressionStats {
+        stream_bytes: (MAGIC.len() + 2) as u64, ..Default::default()
+    };
+    let mut flags = vec![];
+
+    loop {
+        flags.clear();
+        r.by_ref().take(1).read_to_end(&mut flags)?;
+
+        let block = match flags[..] {
+            []             => break,
+            [BLOCK_STORED] => {
+                let block = read_table(&mut r)?;
+
+                stats.stored_blocks += 1;
+                stats.stream_bytes  += 4 + block.len() as u64;
+                block
+            },
+            [0]            => {
+                let mut bits = [0; 4];
+                read_exact(&mut r, &mut bits)?;
+
+                let bits  = u32::from_le_bytes(bits) as usize;
+                let table = read_table(&mut r)?;
+                let len   = bits.div_ceil(8);
+                let mut codes = Vec::with_capacity(len.min(READ_BUF_SIZE));
+
+                r.by_ref().take(len as u64).read_to_end(&mut codes)?;
+
+                if codes.len() < len {
+                    return Err(HuffmanError::MalformedData("the data is \
+                                                            truncated"));
+                }
+                let codebook = HuffmanCodebook::import(&table)?;
+                let decoder  = DecodeTable::from_codes(
+                                   &codebook.code_strings())?;
+
+                stats.coded_blocks += 1;
+                stats.payload_bits += bits as u64;
+                stats.table_bytes  += 4 + table.len() as u64;
+                stats.stream_bytes += 8 + (table.len() + codes.len()) as u64;
+
+                decoder.decode(&codes, bits)?.chars().map(|c| {
+                    u8::try_from(c).map_err(|_| {
+                        HuffmanError::MalformedData("a code isn't for a byte")
+                    })
+                }).collect::<Result<Vec<_>, _>>()?
+            },
+            _              => {
+                return Err(HuffmanError::MalformedData("unknown block \
+                                                        flags"));
+            },
+        };
+        stats.stream_bytes += 1;
+        stats.symbols      += block.len() as u64;
+        stats.text_bytes   += block.len() as u64;
+        w.write_all(&block)?;
+    }
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{pipe_compress, pipe_decompress, TableSource};
+    use crate::sample::SplitMix64;
+
+    fn round_trip(data: &[u8], block_size: usize) -> CompressionStats {
+        let mut stream = vec![];
+        let mut output = vec![];
+        let stats = compress_blocks(data, &mut stream, block_size).unwrap();
+
+        assert_eq!(stats.stream_bytes, stream.len() as u64);
+        assert_eq!(stats.text_bytes, data.len() as u64);
+        assert_eq!(decompress_blocks(&stream[..], &mut output)
+                       .map(CompressionStats::sizes),
+                   Ok(stats.sizes()));
+        assert_eq!(output, data);
+
+        // Storing every block is the worst case.
+        let blocks = data.len().div_ceil(block_size);
+
+        assert!(stream.len() <= MAGIC.len() + 2 + data.len() + 5 * blocks);
+        stats
+    }
+
+    #[test]
+    fn random_bytes_are_stored() {
+        let mut rng  = SplitMix64(7);
+        let data     = (0..1 << 20).map(|_| rng.next_u64() as u8)
+                                   .collect::<Vec<_>>();
+        let stats    = round_trip(&data, 65536);
+
+        assert_eq!((stats.stored_blocks, stats.coded_blocks), (16, 0));
+        assert_eq!(stats.payload_bits, 0);
+    }
+
+    #[test]
+    fn text_is_coded() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let stats = round_trip(text.as_bytes(), 65536);
+        let total = text.len().div_ceil(65536) as u64;
+
+        assert_eq!((stats.stored_blocks, stats.coded_blocks), (0, total));
+        assert_eq!(stats.predicted_bits, stats.payload_bits);
+        assert!(stats.stream_bytes < stats.text_bytes * 2 / 3);
+    }
+
+    #[test]
+    fn mixed_and_small_inputs() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut rng  = SplitMix64(3);
+        let mut data = text.as_bytes()[..8192].to_vec();
+
+        data.extend((0..8192).map(|_| rng.next_u64() as u8));
+        data.extend(&text.as_bytes()[..8192]);
+
+        let stats = round_trip(&data, 8192);
+
+        assert_eq!((stats.stored_blocks, stats.coded_blocks), (1, 2));
+
+        for data in [&b""[..], b"a", b"aaaaaaaaaaaaaaaaaaaaaaaa", b"\0\xff"] {
+            round_trip(data, 4);
+        }
+        assert_eq!(round_trip(&[0xaa; 64], 64).coded_blocks, 1);
+    }
+
+    #[test]
+    fn bad_streams() {
+        let mut stream = vec![];
+
+        compress_blocks(&[b'x'; 100][..], &mut stream, 100).unwrap();
+
+        let decompress = |stream: &[u8]| {
+            decompress_blocks(stream, std::io::sink()).err()
+        };
+        let truncated = Some(HuffmanError::MalformedData(
+                                 "the data is truncated"));
+
+        assert_eq!(decompress(&stream[..stream.len() - 1]), truncated);
+        assert_eq!(decompress(&stream[..8]), truncated);
+
+        let mut flagged = stream.clone();
+        flagged[6] = 2;
+
+        assert_eq!(decompress(&flagged),
+                   Some(HuffmanError::MalformedData("unknown block flags")));
+
+        let mut other = vec![];
+        pipe_compress(&b"text"[..], &mut other, TableSource::Train, None)
+            .unwrap();
+
+        assert_eq!(decompress(&other),
+                   Some(HuffmanError::MalformedData(
+                            "the stream isn't in blocks")));
+        assert_eq!(pipe_decompress(&stream[..], std::io::sink(), None, None)
+                       .err(),
+                   Some(HuffmanError::MalformedData(
+                            "the stream is in blocks")));
+    }
+}
@@ -0,0 +1,190 @@
+//! Building a codebook step by step: counting text, or adding counts, then
+//! choosing how the codes are made, capped in length or kept stable against
+//! an earlier codebook, before generating them.
+//! 
+
+use crate::HuffmanError;
+use crate::canonical::LengthLimits;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+
+
+/// Gathers the counts and options for a `HuffmanCodebook`, which `build()`
+/// then generates:
+/// 
+/// ```
+/// use huffman::prelude::*;
+/// 
+/// let codebook = HuffmanBuilder::new().count("abracadabra")
+///                                     .max_length(3)
+///                                     .build()
+///                                     .unwrap();
+/// 
+/// assert!(codebook.codes().values().all(|code| code.len <= 3));
+/// ```
+/// 
+/// With no limits set, the codes are ordinary Huffman codes, as
+/// `HuffmanCodebook::try_from_frequencies()` gives them; with limits, they're
+/// the canonical codes of `HuffmanCodebook::from_frequencies_limited()`.
+/// 
+#[derive(Clone, Debug, Default)]
+pub struct HuffmanBuilder {
+    freqs    : FrequencyTable,
+    limits   : Option<LengthLimits>,
+    previous : Option<HuffmanCodebook>,
+}
+
+impl HuffmanBuilder {
+    /// A builder with no counts and no options.
+    /// 
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Counts the characters of `text`, on top of the counts so far.
+    /// 
+    pub fn count(&mut self, text: &str) -> &mut Self {
+        self.freqs.update(text);
+        self
+    }
+    /// Adds the counts of `freqs` to the counts so far, saturating as
+    /// `FrequencyTable::merge()` does.
+    /// 
+    pub fn counts(&mut self, freqs: FrequencyTable) -> &mut Self {
+        self.freqs.merge(freqs);
+        self
+    }
+    /// Limits every code to `max_len` bits, which is clamped to 64, as
+    /// `LengthLimits::new()` does.
+    /// 
+    pub fn max_length(&mut self, max_len: u8) -> &mut Self {
+        let limits = self.limits.get_or_insert_with(LengthLimits::default);
+
+        limits.max_len = max_len.min(64);
+        self
+    }
+    /// Limits the codes of `symbols` to `cap` bits, on top of the overall
+    /// limit, as `LengthLimits::max_length_for()` does.
+    /// 
+    pub fn max_length_for(&mut self, symbols: &[char], cap: u8) -> &mut Self {
+        self.limits.get_or_insert_with(LengthLimits::default)
+                   .max_length_for(symbols, cap);
+        self
+    }
+    /// Once the codes' lengths are chosen, reassigns the codes so that as
+    /// many symbols as possible keep the code they have in `previous`, such
+    /// as an earlier training of the same data, so retraining doesn't
+    /// needlessly change them. Each symbol keeps its code length, so the
+    /// codes are just as compact; a symbol keeps its old code when that has
+    /// the same length and doesn't rule out prefix-free codes for the rest.
+    /// The others are given the first free codes of their length.
+    /// 
+    pub fn stabilize_against(&mut self, previous: &HuffmanCodebook) 
+
+        -> &mut Self 
+    {
+        self.previous = Some(previous.clone());
+        self
+    }
+    /// The counts so far.
+    /// 
+    pub fn frequencies(&self) -> &FrequencyTable {
+        &self.freqs
+    }
+    /// Generates the codebook. Fails with `HuffmanError::CodeTooLong` if no
+    /// limits were set and a code would be longer than 64 bits, and with
+    /// `HuffmanError::LengthCapInfeasible` if the limits can't be met.
+    /// 
+    pub fn build(&self) -> Result<HuffmanCodebook, HuffmanError> {
+        self.build_counting_moves().map(|(codebook, _)| codebook)
+    }
+    /// Like `build()`, but also gives how many symbols ended up with a code
+    /// other than the one they have in the codebook given to
+    /// `stabilize_against()`, or 0 if there's none.
+    /// 
+    pub fn build_counting_moves(&self) 
+
+        -> Result<(HuffmanCodebook, usize), HuffmanError> 
+    {
+        let freqs        = self.freqs.clone();
+        let mut codebook = match &self.limits {
+            Some(limits) => {
+                HuffmanCodebook::from_frequencies_limited(freqs, limits)?
+            },
+            None         => HuffmanCodebook::try_from_frequencies(freqs)?,
+        };
+        let moved = match &self.previous {
+            Some(previous) => codebook.keep_codes_of(previous),
+            None           => 0,
+        };
+        Ok((codebook, moved))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_as_the_constructors_do() {
+        let text = "abracadabra, alakazam";
+
+        assert_eq!(HuffmanBuilder::new().count(text).build().unwrap().codes(),
+                   HuffmanCodebook::from_text(text).codes());
+
+        // Counts add up, whether counted or given.
+        let mut split = HuffmanBuilder::new();
+
+        split.count("abracadabra, ")
+             .counts(FrequencyTable::from_text("alakazam"));
+
+        assert_eq!(split.frequencies(), &FrequencyTable::from_text(text));
+        assert_eq!(split.build().unwrap().codes(),
+                   HuffmanCodebook::from_text(text).codes());
+        assert!(HuffmanBuilder::new().build().unwrap().codes().is_empty());
+    }
+
+    #[test]
+    fn limits() {
+        let freqs      = FrequencyTable::from_text("aaaaaaaabbbbccd e");
+        let mut limits = LengthLimits::new(3);
+
+        limits.max_length_for(&['a'], 2);
+
+        let built = HuffmanBuilder::new().counts(freqs.clone())
+                                         .max_length(3)
+                                         .max_length_for(&['a'], 2)
+                                         .build()
+                                         .unwrap();
+
+        assert_eq!(built.codes(),
+                   HuffmanCodebook::from_frequencies_limited(freqs.clone(),
+                                                             &limits)
+                       .unwrap()
+                       .codes());
+
+        // A cap alone keeps the overall limit at 64 bits.
+        let capped = HuffmanBuilder::new().counts(freqs.clone())
+                                          .max_length_for(&['e'], 2)
+                                          .build()
+                                          .unwrap();
+
+        assert!(capped.codes()[&'e'].len <= 2);
+        assert!(matches!(HuffmanBuilder::new().counts(freqs)
+                                              .max_length(2)
+                                              .build(),
+                         Err(HuffmanError::LengthCapInfeasible { .. })));
+    }
+
+    #[test]
+    fn stabilizes() {
+        let previous = HuffmanCodebook::from_text("abracadabra");
+        let (codebook, moved) = HuffmanBuilder::new()
+                                    .count("abracadabra")
+                                    .stabilize_against(&previous)
+                                    .build_counting_moves()
+                                    .unwrap();
+
+        assert_eq!(moved, 0);
+        assert_eq!(codebook.codes(), previous.codes());
+    }
+}
@@ -0,0 +1,368 @@
+//! Canonical codes, which follow from their lengths alone: generating them,
+//! limiting their lengths, and decoding them without a tree. Serializing and
+//! checking tables of codes are here too.
+//! 
+//! Canonical codes of a given length are consecutive values, and each length's
+//! first code follows from the counts of shorter codes. So a decoder only needs
+//! the first code and symbol count for each length, and the symbols in code
+//! order: memory in O(max_len + symbols), rather than a node per branch.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::collections::HashMap;
+use crate::io::BitReader;
+
+pub use crate::limit::{generate_huffman_codes_limited, LengthLimits};
+pub use crate::table::{deserialize_codes, serialize_codes};
+pub use crate::validate::{validate_codes, validate_complete_codes};
+pub use crate::validate::CodeTableError;
+
+
+/// A decoder for the canonical codes with the given lengths, using the
+/// first-code and offset tables for each length.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanonicalDecoder<S> {
+    /// The first code of each length, indexed by length.
+    first   : Vec<u64>,
+    /// The number of codes of each length.
+    count   : Vec<u32>,
+    /// The index in `symbols` of the first symbol with each length.
+    offset  : Vec<u32>,
+    /// The symbols in the order of their codes.
+    symbols : Vec<S>,
+}
+
+impl<S: Copy + Ord> CanonicalDecoder<S> {
+    /// A decoder for the canonical codes with the given `(symbol, length)`
+    /// pairs. Codes are assigned shortest first, and in order of symbol within
+    /// a length. Fails with `HuffmanError::MalformedData` if a length is 0 or
+    /// over 64, a symbol appears twice, or the lengths are too short for
+    /// every symbol to have a code. Lengths that leave codes unused are fine.
+    /// 
+    pub fn from_lengths(lengths: &[(S, u8)]) -> Result<Self, HuffmanError> {
+        let mut sorted = lengths.iter()
+                                .map(|&(s, len)| (len, s))
+                                .collect::<Vec<_>>();
+
+        sorted.sort_unstable();
+
+        let mut by_symbol = sorted.iter().map(|&(_, s)| s).collect::<Vec<_>>();
+
+        by_symbol.sort_unstable();
+
+        if by_symbol.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(HuffmanError::MalformedData("a symbol appears twice"));
+        }
+        if sorted.iter().any(|&(len, _)| len == 0 || len > 64) {
+            return Err(HuffmanError::MalformedData("a code length is out of \
+                                                    range"));
+        }
+        let max_len    = sorted.last().map_or(0, |&(len, _)| len as usize);
+        let mut count  = vec![0_u32; max_len + 1];
+        let mut first  = vec![0_u64; max_len + 1];
+        let mut offset = vec![0_u32; max_len + 1];
+
+        for &(len, _) in &sorted {
+            count[len as usize] += 1;
+        }
+        // Codes of each length start where the last length's codes end, one
+        // bit further down. Running out of room means they can't all fit.
+        let mut next  = 0_u128;
+        let mut index = 0;
+
+        for len in 1..=max_len {
+            next = (next + count[len - 1] as u128) << 1;
+
+            if next + count[len] as u128 > 1 << len {
+                return Err(HuffmanError::MalformedData("the code lengths are \
+                                                        oversubscribed"));
+            }
+            first[len]  = next as u64;
+            offset[len] = index;
+            index      += count[len];
+        }
+        let symbols = sorted.into_iter().map(|(_, s)| s).collect();
+
+        Ok(Self { first, count, offset, symbols })
+    }
+    /// Reads one code from `r` and returns its symbol. Fails with
+    /// `HuffmanError::InvalidCode` if the bits aren't the start of any code,
+    /// and `HuffmanError::TruncatedBitstream` if `r` runs out partway through
+    /// one. Errors give the offset where the code started; the decoder doesn't
+    /// count symbols, so their `symbols` is 0.
+    /// 
+    pub fn decode_symbol(&self, r: &mut BitReader) -> Result<S, HuffmanError> {
+        let start    = r.position();
+        let mut code = 0_u64;
+
+        for len in 1..self.first.len() {
+            let bit = r.read_bit().ok_or(HuffmanError::TruncatedBitstream {
+                bit_offset: start, symbols: 0
+            })?;
+            code = code << 1 | bit as u64;
+
+            let index = code.wrapping_sub(self.first[len]);
+
+            if index < self.count[len] as u64 {
+                return Ok(self.symbols[self.offset[len] as usize
+                                       + index as usize]);
+            }
+        }
+        Err(HuffmanError::InvalidCode { bit_offset: start, symbols: 0 })
+    }
+    /// The canonical codes, shortest first and in order of symbol within a
+    /// length.
+    /// 
+    pub fn codes(&self) -> impl Iterator<Item = (S, Code)> + '_ {
+        (1..self.first.len()).flat_map(move |len| {
+            let offset = self.offset[len] as usize;
+
+            (0..self.count[len] as usize).map(move |i| {
+                let bits = self.first[len] + i as u64;
+
+                (self.symbols[offset + i], Code { bits, len: len as u8 })
+            })
+        })
+    }
+    /// The number of symbols.
+    /// 
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+
+impl HuffmanCodebook {
+    /// The decoder for this codebook's code lengths, or `None` if they aren't
+    /// those of a prefix code, as an empty code's isn't.
+    /// 
+    fn canonical_decoder(&self) -> Option<CanonicalDecoder<char>> {
+        let lengths = self.codes()
+                          .iter()
+                          .map(|(&c, code)| (c, code.len))
+                          .collect::<Vec<_>>();
+
+        CanonicalDecoder::from_lengths(&lengths).ok()
+    }
+    /// A codebook with the same code lengths, and so the same compression, but
+    /// with the canonical codes for those lengths: shortest first, and in
+    /// order of symbol within a length. The frequencies are kept. A codebook
+    /// with an empty code has no canonical form, and is returned as it is.
+    /// 
+    pub fn to_canonical(&self) -> HuffmanCodebook {
+        match self.canonical_decoder() {
+            Some(decoder) => {
+                HuffmanCodebook::from_parts(self.freqs.clone(), 
+                                            decoder.codes().collect())
+            },
+            None => self.clone(),
+        }
+    }
+    /// Whether the codes are the canonical codes for their lengths, so that
+    /// a `CanonicalDecoder` built from the lengths alone decodes them.
+    /// 
+    pub fn is_canonical(&self) -> bool {
+        self.canonical_decoder().is_some_and(|decoder| {
+            decoder.codes().all(|(c, code)| self.codes()[&c] == code)
+        })
+    }
+}
+
+/// The length of the Huffman code for each character of `data`, which is all
+/// a reader needs to rebuild its canonical codes.
+/// 
+pub fn code_lengths(data: &str) -> HashMap<char, u8> {
+    HuffmanCodebook::from_text(data).codes()
+                                    .iter()
+                                    .map(|(&c, code)| (c, code.len))
+                                    .collect()
+}
+
+/// Like `generate_huffman_codes()`, but with the canonical codes for the same
+/// lengths: shortest first, and in order of character within a length, so
+/// the table can be rebuilt from `code_lengths()` alone.
+/// 
+pub fn generate_canonical_codes(data: &str) -> HashMap<char, String> {
+    HuffmanCodebook::from_text(data).to_canonical().code_strings()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::ToString;
+    use crate::codebook::{generate_huffman_codes, predicted_bits};
+    use crate::codebook::{FrequencyTable, HuffmanCodebook};
+    use crate::io::{BitWriter, DecoderFsm};
+    use crate::sample::SplitMix64;
+
+    #[test]
+    fn canonical_assignment() {
+        let decoder = CanonicalDecoder::from_lengths(&[('d', 2), ('a', 1),
+                                                       ('c', 3), ('b', 3)])
+                                       .unwrap();
+        let codes = decoder.codes()
+                           .map(|(c, code)| format!("{c}={code}"))
+                           .collect::<Vec<_>>();
+
+        assert_eq!(codes, ["a=0", "d=10", "b=110", "c=111"]);
+
+        let mut r = BitReader::new(&[0b1101_1101, 0b0100_0000], 11);
+        let mut decoded = String::new();
+
+        while r.remaining() > 0 {
+            decoded.push(decoder.decode_symbol(&mut r).unwrap());
+        }
+        assert_eq!(decoded, "bcadd");
+    }
+
+    #[test]
+    fn canonical_strings() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let codes = generate_canonical_codes(&text);
+        let lens  = code_lengths(&text);
+
+        let mut sorted = codes.iter()
+                              .map(|(&c, code)| (code.len(), c, code))
+                              .collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        // Each code is the one before it plus 1, shifted out to its length.
+        assert!(sorted[0].2.chars().all(|b| b == '0'));
+
+        for pair in sorted.windows(2) {
+            let (len, _, prev) = pair[0];
+            let (next_len, _, next) = pair[1];
+            let prev = u64::from_str_radix(prev, 2).unwrap();
+            let next = u64::from_str_radix(next, 2).unwrap();
+
+            assert_eq!(next, (prev + 1) << (next_len - len));
+        }
+        assert!(codes.iter().all(|(c, code)| code.len() == lens[c] as usize));
+        assert_eq!(predicted_bits(&codes, &freqs),
+                   predicted_bits(&generate_huffman_codes(&text), &freqs));
+        assert_eq!(generate_canonical_codes("aaa"),
+                   HashMap::from([('a', "0".to_string())]));
+        assert!(code_lengths("").is_empty());
+    }
+
+    #[test]
+    fn bad_inputs() {
+        let decoder = CanonicalDecoder::from_lengths(&[('a', 1), ('b', 3)])
+                                       .unwrap();
+
+        assert_eq!(decoder.decode_symbol(&mut BitReader::new(&[0xe0], 3)),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+        assert_eq!(decoder.decode_symbol(&mut BitReader::new(&[0x80], 2)),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 0, symbols: 0
+                   }));
+
+        let error = |lengths: &[(char, u8)]| {
+            CanonicalDecoder::from_lengths(lengths).err()
+        };
+        let malformed = |reason| Some(HuffmanError::MalformedData(reason));
+
+        assert_eq!(error(&[('a', 1), ('b', 1), ('c', 2)]),
+                   malformed("the code lengths are oversubscribed"));
+        assert_eq!(error(&[('a', 2), ('b', 1), ('c', 2), ('d', 3)]),
+                   malformed("the code lengths are oversubscribed"));
+        assert_eq!(error(&[('a', 1), ('b', 1), ('a', 2)]),
+                   malformed("a symbol appears twice"));
+        assert_eq!(error(&[('a', 1), ('b', 0)]),
+                   malformed("a code length is out of range"));
+        assert_eq!(error(&[('a', 1), ('b', 65)]),
+                   malformed("a code length is out of range"));
+        assert!(error(&[('a', 1), ('b', 64)]).is_none());
+    }
+
+    /// Decodes random text with random tables through both the canonical
+    /// decoder and the state machine built from the same codes.
+    /// 
+    #[test]
+    fn agrees_with_the_tree_decoder() {
+        let mut rng = SplitMix64(0x1234);
+
+        for seed in 0..20 {
+            let n     = 2 + rng.below(300);
+            let freqs = (0..n).map(|i| {
+                let c     = char::from_u32(0x20 + i as u32).unwrap();
+                let scale = 1 << rng.below(16);
+
+                (c, 1 + rng.below(scale))
+            }).collect::<FrequencyTable>();
+
+            let codebook = HuffmanCodebook::from_frequencies(freqs.clone());
+            let lengths  = codebook.codes()
+                                   .iter()
+                                   .map(|(&c, code)| (c, code.len))
+                                   .collect::<Vec<_>>();
+            let decoder  = CanonicalDecoder::from_lengths(&lengths).unwrap();
+            let table    = decoder.codes()
+                                  .map(|(c, code)| (c, code.bits, code.len))
+                                  .collect::<Vec<_>>();
+            let canonical = HuffmanCodebook::from_table(&table).unwrap();
+            let fsm       = DecoderFsm::from_codebook(&canonical).unwrap();
+
+            for (c, code) in codebook.codes() {
+                assert_eq!(canonical.code_for(*c).unwrap().len, code.len);
+            }
+            let text = freqs.sample(2000, seed);
+            let mut writer = BitWriter::new();
+
+            for c in text.chars() {
+                writer.write_code(canonical.code_for(c).unwrap());
+            }
+            let bits  = writer.into_buffer();
+            let mut r = bits.reader();
+            let mut decoded = String::new();
+
+            while r.remaining() > 0 {
+                decoded.push(decoder.decode_symbol(&mut r).unwrap());
+            }
+            assert_eq!(decoded, text);
+            assert_eq!(fsm.decode(&bits).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn converting_codebooks() {
+        let text      = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook  = HuffmanCodebook::from_text(&text);
+        let canonical = codebook.to_canonical();
+
+        assert!(!codebook.is_canonical());
+        assert!(canonical.is_canonical());
+        assert_eq!(canonical.frequencies(), codebook.frequencies());
+
+        for (c, code) in codebook.codes() {
+            assert_eq!(canonical.code_for(*c).unwrap().len, code.len);
+        }
+        let mut writer = BitWriter::new();
+
+        for c in text.chars() {
+            writer.write_code(canonical.code_for(c).unwrap());
+        }
+        let fsm = DecoderFsm::from_codebook(&canonical).unwrap();
+
+        assert_eq!(fsm.decode(&writer.into_buffer()).unwrap(), text);
+
+        // A lone symbol's code, "0", is the canonical code of length 1.
+        let lone = HuffmanCodebook::from_text("aaa");
+
+        assert!(lone.is_canonical());
+        assert_eq!(lone.to_canonical().codes(), lone.codes());
+    }
+}
@@ -0,0 +1,1015 @@
+//! Codebooks, and generating them: counting the characters of a text, and
+//! building codes for them, or for symbols of other types, from the counts,
+//! or from weights or probabilities. Codebooks for other alphabets, and the
+//! analysis of how well a codebook codes a text, are here too.
+//! 
+
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::cell::OnceCell as OnceLock;
+use core::cmp::{Ordering, Reverse};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+use crate::{build_huffman_tree, code_bits_from_table, codes_from_table};
+use crate::{grow_tree, tree_code_bits, tree_codes, HuffmanError, NodeMem};
+use crate::MAX_SYMBOLS;
+use crate::canonical::LengthLimits;
+use crate::collections::{map_with_capacity, Entry, HashMap};
+#[cfg(feature = "std")]
+use crate::io::{for_each_str_chunk, CancelToken};
+use crate::io::DecoderFsm;
+use crate::tree::Weight;
+
+#[cfg(feature = "std")]
+pub use crate::accel::{validate_accelerations, AccelMismatch, AccelReport};
+pub use crate::analysis::{baseline_report, BaselineReport};
+pub use crate::analysis::{code_stats, CodeStats};
+pub use crate::analysis::{break_even_length, BreakEven, TableKind};
+pub use crate::analysis::{distribution_divergence, DriftReport};
+pub use crate::analysis::{estimate_memory, MemoryEstimate, TreeOptions};
+pub use crate::analysis::{WhatIf, WhatIfReport};
+pub use crate::approx::ApproxCounter;
+pub use crate::builder::HuffmanBuilder;
+pub use crate::collections::MapKey;
+pub use crate::codec::{compare_models, Codec, ModelCost};
+pub use crate::escape::{escape_symbol, EscapeContext, SymbolDisplay};
+pub use crate::ids::IdCodebook;
+pub use crate::intern::{InternedCodebook, SymbolInterner};
+#[cfg(feature = "std")]
+pub use crate::measure::{measure, MeasureConfig, MeasureReport, PhaseTiming};
+#[cfg(feature = "parallel")]
+pub use crate::par::generate_huffman_codes_par;
+pub use crate::probability::codes_from_probabilities;
+pub use crate::reserved::{ReservedId, Token, TokenCodebook};
+pub use crate::shannon::ShannonFanoCodec;
+pub use crate::summary::{AlphabetSummary, CodeLookup};
+pub use crate::utf16::generate_huffman_codes_utf16;
+
+
+/// Holds the number of times each character occurs in a body of text. Counting
+/// is the first of the two passes described in the module docs.
+/// 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrequencyTable {
+    pub(crate) counts: HashMap<char, usize>,
+}
+
+impl FrequencyTable {
+    /// An empty table, to count text into a piece at a time with `update()`.
+    /// 
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Counts the characters of `data`.
+    /// 
+    pub fn from_text(data: &str) -> Self {
+        Self::from_chars(data.chars())
+    }
+    /// Counts the characters of `text` on top of those counted already.
+    /// 
+    pub fn update(&mut self, text: &str) {
+        for c in text.chars() {
+            *self.counts.entry(c).or_insert(0) += 1;
+        }
+    }
+    /// Counts the characters produced by `chars`, for input that doesn't exist
+    /// as a `String`.
+    /// 
+    pub fn from_chars(chars: impl Iterator<Item = char>) -> Self {
+        let mut counts = HashMap::new();
+
+        for c in chars {
+            *counts.entry(c).or_insert(0) += 1;
+        }
+        Self { counts }
+    }
+    /// Counts the characters of the UTF-8 text read from `r`, holding no more
+    /// than a fixed-size buffer of it in memory at a time. Only built with the
+    /// `std` feature.
+    /// 
+    #[cfg(feature = "std")]
+    pub fn from_reader(r: impl Read) -> io::Result<Self> {
+        Self::from_reader_with_cancel(r, None)
+    }
+    /// Like `from_reader()`, but gives up once `cancel` is cancelled. This is
+    /// checked once per buffer read, and reported as an `io::Error` wrapping
+    /// `HuffmanError::Cancelled`.
+    /// 
+    #[cfg(feature = "std")]
+    pub fn from_reader_with_cancel(r      : impl Read, 
+                                   cancel : Option<&CancelToken>) 
+
+        -> io::Result<Self> 
+    {
+        let mut counts = HashMap::new();
+
+        for_each_str_chunk(r, cancel, |chunk| {
+            for c in chunk.chars() {
+                *counts.entry(c).or_insert(0) += 1;
+            }
+            Ok(())
+        })?;
+        Ok(Self { counts })
+    }
+    /// The character counts.
+    /// 
+    pub fn counts(&self) -> &HashMap<char, usize> {
+        &self.counts
+    }
+    /// The count for `char_`, which is 0 if it was never seen.
+    /// 
+    pub fn get(&self, char_: char) -> usize {
+        self.counts.get(&char_).copied().unwrap_or(0)
+    }
+    /// The number of distinct characters.
+    /// 
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+    /// The number of characters counted, i.e. the sum of all counts, which
+    /// saturates at `usize::MAX` as counts do for `merge()`.
+    /// 
+    pub fn total(&self) -> usize {
+        self.counts.values().fold(0, |total, &n| total.saturating_add(n))
+    }
+    /// Adds `count` occurrences of `char_`. The count saturates at
+    /// `usize::MAX`, as for `merge()`.
+    /// 
+    pub fn add(&mut self, char_: char, count: usize) {
+        let total = self.counts.entry(char_).or_insert(0);
+
+        *total = total.saturating_add(count);
+    }
+    /// Generates the code strings for the characters counted, as
+    /// `generate_huffman_codes()` does for text with these counts.
+    /// 
+    pub fn build_codes(&self) -> HashMap<char, String> {
+        codes_from_table(self)
+    }
+    /// Removes `char_` from the table, returning its count.
+    /// 
+    pub fn remove(&mut self, char_: char) -> usize {
+        self.counts.remove(&char_).unwrap_or(0)
+    }
+    /// Splits the table into its `n` most frequent characters and the rest.
+    /// The tail is meant to be coded as a single OTHER symbol, whose frequency
+    /// is `TailStats::count`. Ties are broken in favor of the smaller char, so
+    /// the split doesn't depend on the table's iteration order.
+    /// `TokenCodebook::with_other()` builds codes for the split, and codes
+    /// the tail that way.
+    /// 
+    pub fn top_n_with_other(&self, n: usize) -> (FrequencyTable, TailStats) {
+        let mut ranked = self.ranked();
+
+        let tail  = ranked.split_off(n.min(ranked.len()));
+        let stats = TailStats {
+            count   : tail.iter().fold(0, |n, &(_, f)| n.saturating_add(f)),
+            symbols : tail.into_iter().map(|(c, _)| c).collect(),
+        };
+        (ranked.into_iter().collect(), stats)
+    }
+    /// The characters and their counts, most frequent first, with ties broken
+    /// in favor of the smaller char.
+    /// 
+    pub(crate) fn ranked(&self) -> Vec<(char, usize)> {
+        let mut ranked = self.counts.iter()
+                                    .map(|(&c, &f)| (c, f))
+                                    .collect::<Vec<_>>();
+
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+}
+
+/// Describes the characters `FrequencyTable::top_n_with_other` folded into the
+/// OTHER bucket.
+/// 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TailStats {
+    /// The folded characters, most frequent first.
+    pub symbols : Vec<char>,
+    /// Their combined count; the frequency of the OTHER bucket.
+    pub count   : usize,
+}
+
+impl FromIterator<(char, usize)> for FrequencyTable {
+    /// Collects `(char, count)` pairs; counts for repeated characters are
+    /// summed.
+    /// 
+    fn from_iter<I: IntoIterator<Item = (char, usize)>>(iter: I) -> Self {
+        let mut table = Self::default();
+
+        for (c, f) in iter {
+            table.add(c, f);
+        }
+        table
+    }
+}
+
+
+/// A Huffman code packed into an integer: the code is the low `len` bits of
+/// `bits`, its first bit being the most significant of them. Codes order
+/// canonically, by length and then by value, and format in binary as exactly
+/// `len` digits.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Code {
+    pub bits : u64,
+    pub len  : u8,
+}
+
+impl Code {
+    /// Packs a code string of '0's and '1's. Strings longer than 64 bits don't
+    /// fit and give `None`, as do strings with any other characters.
+    /// 
+    pub fn from_str_binary(code: &str) -> Option<Self> {
+        if code.len() > 64 {
+            return None;
+        }
+        let mut bits = 0;
+
+        for b in code.bytes() {
+            bits = match b {
+                b'0' => bits << 1,
+                b'1' => bits << 1 | 1,
+                _    => return None,
+            };
+        }
+        Some(Code { bits, len: code.len() as u8 })
+    }
+    /// The code with `bit` added to its end.
+    /// 
+    /// # Panics
+    /// 
+    /// If the code is already 64 bits long.
+    /// 
+    pub fn append(self, bit: bool) -> Code {
+        assert!(self.len < 64, "a code can't be longer than 64 bits");
+        Code { bits: self.bits << 1 | bit as u64, len: self.len + 1 }
+    }
+    /// The code followed by `other`, or `None` if together they're longer than
+    /// 64 bits.
+    /// 
+    pub fn concat(self, other: Code) -> Option<Code> {
+        let len = self.len.checked_add(other.len).filter(|&len| len <= 64)?;
+        let bits = self.bits.checked_shl(other.len as u32).unwrap_or(0) 
+                 | other.bits;
+        Some(Code { bits, len })
+    }
+    /// Whether the code has no bits beyond its length.
+    /// 
+    pub(crate) fn is_valid(&self) -> bool {
+        self.len <= 64 
+            && self.bits.checked_shr(self.len as u32).unwrap_or(0) == 0
+    }
+    /// The code's bits moved to the top of a `u64`, so that codes compare the
+    /// way their strings would.
+    /// 
+    pub(crate) fn left_aligned(&self) -> u64 {
+        self.bits.checked_shl(64 - self.len as u32).unwrap_or(0)
+    }
+    /// The code as a string of '0's and '1's.
+    /// 
+    pub(crate) fn to_code_string(self) -> String {
+        (0..self.len).rev()
+                     .map(|i| if self.bits >> i & 1 == 1 { '1' } else { '0' })
+                     .collect()
+    }
+}
+
+impl Ord for Code {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.len, self.bits).cmp(&(other.len, other.bits))
+    }
+}
+
+impl PartialOrd for Code {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Binary for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.to_code_string())
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Binary::fmt(self, f)
+    }
+}
+
+impl fmt::LowerHex for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.bits, f)
+    }
+}
+
+
+/// A set of Huffman codes together with the character frequencies they were
+/// generated from.
+/// 
+#[derive(Clone, Debug)]
+pub struct HuffmanCodebook {
+    pub(crate) freqs   : FrequencyTable,
+    pub(crate) codes   : HashMap<char, Code>,
+    /// A decoder for the codes, built the first time one's needed. Anything
+    /// that changes the codes clears it. Without `std`, there's no lock to
+    /// share it between threads with, and it's a plain `OnceCell`.
+    pub(crate) decoder : OnceLock<DecoderFsm>,
+}
+
+impl HuffmanCodebook {
+    /// Generates codes for the characters of `data`.
+    /// 
+    pub fn from_text(data: &str) -> Self {
+        Self::from_frequencies(FrequencyTable::from_text(data))
+    }
+    /// Generates codes for the characters counted in `freqs`.
+    /// 
+    /// Panics if a code would be longer than 64 bits. Counts skewed like the
+    /// Fibonacci numbers over more than 64 characters do that, and while a
+    /// text would have to be on the order of 10^13 characters long to have
+    /// them, a table read from CSV or collected from pairs needn't be.
+    /// `try_from_frequencies()` fails instead.
+    /// 
+    pub fn from_frequencies(freqs: FrequencyTable) -> Self {
+        Self::try_from_frequencies(freqs)
+            .expect("Huffman code longer than 64 bits")
+    }
+    /// Like `from_frequencies()`, but fails with `HuffmanError::CodeTooLong`
+    /// if a code would be longer than 64 bits.
+    /// 
+    pub fn try_from_frequencies(freqs: FrequencyTable) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut nodes = NodeMem::new();
+        let tree      = grow_tree(&freqs, &mut nodes);
+        let codes     = tree_code_bits(tree, &nodes)
+                            .map_err(HuffmanError::CodeTooLong)?;
+
+        Ok(Self::from_parts(freqs, codes))
+    }
+    /// Like `from_frequencies()`, but where a code would be longer than 64
+    /// bits, gives the canonical codes of `from_frequencies_limited()` with
+    /// the default limit of 64 bits instead, for callers with no way to fail.
+    /// 
+    pub(crate) fn from_frequencies_capped(freqs: FrequencyTable) -> Self {
+        let mut nodes = NodeMem::new();
+        let tree      = grow_tree(&freqs, &mut nodes);
+
+        match tree_code_bits(tree, &nodes) {
+            Ok(codes) => Self::from_parts(freqs, codes),
+            Err(_)    => {
+                Self::from_frequencies_limited(freqs, &LengthLimits::default())
+                    .expect("fewer than 2^64 characters fit in 64-bit codes")
+            },
+        }
+    }
+    /// Wraps `codes`, which are assumed to be prefix-free, with the
+    /// frequencies they were generated from.
+    /// 
+    pub(crate) fn from_parts(freqs : FrequencyTable, 
+                             codes : HashMap<char, Code>) 
+
+        -> Self 
+    {
+        Self { freqs, codes, decoder: OnceLock::new() }
+    }
+    /// Wraps a table of `(symbol, bits, len)` entries, such as one emitted by
+    /// `codegen::generate_rust_source()`. The table is checked for duplicate
+    /// symbols, codes with bits beyond their length, and codes that are a
+    /// prefix of another. Its codebook has an empty frequency table.
+    /// 
+    pub fn from_static(table: &'static [(char, u64, u8)]) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        Self::from_table(table)
+    }
+    /// Checks and wraps a table of `(symbol, bits, len)` entries, as for
+    /// `from_static()`.
+    /// 
+    pub(crate) fn from_table(table: &[(char, u64, u8)]) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut codes = map_with_capacity(table.len());
+
+        for &(c, bits, len) in table {
+            let code = Code { bits, len };
+
+            if !code.is_valid() {
+                return Err(HuffmanError::MalformedCode(c));
+            }
+            if codes.insert(c, code).is_some() {
+                return Err(HuffmanError::DuplicateSymbol(c));
+            }
+        }
+        let mut sorted = table.iter().map(|&(c, bits, len)| {
+            (Code { bits, len }.left_aligned(), len, c)
+        }).collect::<Vec<_>>();
+
+        sorted.sort_unstable();
+
+        // A code that's the prefix of another sorts immediately before one of
+        // the codes it's a prefix of.
+        for pair in sorted.windows(2) {
+            let (a_bits, a_len, a) = pair[0];
+            let (b_bits, _,     b) = pair[1];
+            let mask = u64::MAX.checked_shl(64 - a_len as u32).unwrap_or(0);
+
+            if a_bits == b_bits & mask {
+                return Err(HuffmanError::NotPrefixFree(a, b));
+            }
+        }
+        Ok(Self::from_parts(FrequencyTable::default(), codes))
+    }
+    /// The codes, keyed by character.
+    /// 
+    pub fn codes(&self) -> &HashMap<char, Code> {
+        &self.codes
+    }
+    /// The codes as strings of '0's and '1's, keyed by character.
+    /// 
+    pub fn code_strings(&self) -> HashMap<char, String> {
+        self.codes.iter().map(|(&c, code)| (c, code.to_code_string())).collect()
+    }
+    /// The frequencies the codes were generated from. What-if analysis needs
+    /// these, so it isn't meaningful for a codebook made by `from_static()`.
+    /// 
+    pub fn frequencies(&self) -> &FrequencyTable {
+        &self.freqs
+    }
+    /// The code for `char_`, if it has one.
+    /// 
+    pub fn code_for(&self, char_: char) -> Option<Code> {
+        self.codes.get(&char_).copied()
+    }
+    /// The average number of bits per character, weighted by frequency.
+    /// 
+    pub fn average_code_length(&self) -> f64 {
+        match self.freqs.total() {
+            0     => 0.0,
+            total => self.codes.iter()
+                                .map(|(&c, code)| self.freqs.get(c) 
+                                                  * code.len as usize)
+                                .sum::<usize>() as f64 / total as f64,
+        }
+    }
+}
+
+
+/// Generates a mapping of characters to string representations of their Huffman
+/// codes. A text with only one distinct character gives it the code "0", and
+/// an empty text gives no codes. The codes depend only on the text's counts,
+/// not on hash order, so any process regenerates the same table from them;
+/// `build_tree()` describes how ties are broken.
+/// 
+pub fn generate_huffman_codes(data: &str) -> HashMap<char, String> {
+    codes_from_table(&count_frequencies(data))
+}
+
+/// Like `generate_huffman_codes()`, but fails with `HuffmanError::EmptyInput`
+/// if `data` is empty, rather than giving no codes.
+/// 
+pub fn try_generate_huffman_codes(data: &str) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    let freqs = count_frequencies(data);
+
+    match freqs.is_empty() {
+        true  => Err(HuffmanError::EmptyInput),
+        false => Ok(codes_from_table(&freqs)),
+    }
+}
+
+/// Like `generate_huffman_codes()`, but gives each code as a `(bits, len)`
+/// pair, so it can be written out with shifts rather than a character at a
+/// time. The bits are LSB-aligned: the code's last bit is bit 0 of `bits`, and
+/// the bits above `len` are 0, so "110" is `(0b110, 3)`. Fails with
+/// `HuffmanError::MalformedCode` if a code would be longer than 64 bits, which
+/// takes counts skewed like the Fibonacci sequence over a total on the order
+/// of 10^13.
+/// 
+pub fn generate_huffman_code_bits(data: &str) 
+
+    -> Result<HashMap<char, (u64, u8)>, HuffmanError> 
+{
+    Ok(code_bits_from_table(&count_frequencies(data))?
+           .into_iter()
+           .map(|(c, code)| (c, (code.bits, code.len)))
+           .collect())
+}
+
+/// Counts the characters of `data`; the first of the two passes described in
+/// the module docs.
+/// 
+pub fn count_frequencies(data: &str) -> FrequencyTable {
+    FrequencyTable::from_text(data)
+}
+
+/// Like `generate_huffman_codes()`, but takes the symbols from an iterator,
+/// so characters needn't be collected into a `String` first, and symbols can
+/// be of any type: words or n-grams as `&str`, say, or enum variants. Symbols
+/// are counted in the order they first appear, and of equal counts, the one
+/// that appeared first is merged first, so the codes don't depend on hash
+/// order. Characters may tie differently than `generate_huffman_codes()`
+/// breaks their ties, but the codes are as short for the text.
+/// 
+/// # Panics
+/// 
+/// If there are more than 2^31 distinct symbols.
+/// 
+pub fn generate_huffman_codes_iter<T, I>(symbols: I) -> HashMap<T, String> 
+where
+    T: MapKey + Clone,
+    I: IntoIterator<Item = T>,
+{
+    let mut index  = HashMap::<T, usize>::new();
+    let mut counts = Vec::<(T, usize)>::new();
+
+    for symbol in symbols {
+        match index.entry(symbol) {
+            Entry::Occupied(e) => counts[*e.get()].1 += 1,
+            Entry::Vacant(e)   => {
+                counts.push((e.key().clone(), 1));
+                e.insert(counts.len() - 1);
+            },
+        }
+    }
+    assert!(counts.len() <= MAX_SYMBOLS, "too many symbols for a tree");
+
+    // Most frequent first; the sort is stable, so ties stay in order of
+    // first appearance.
+    counts.sort_by_key(|&(_, n)| Reverse(n));
+
+    generate_huffman_codes_weighted(counts)
+}
+
+/// Like `generate_huffman_codes()`, but for bytes, so data needn't be text.
+/// Each byte is coded as the char with the same value, U+0000 to U+00FF, so
+/// the codes for an ASCII text are the ones `generate_huffman_codes()` gives
+/// it.
+/// 
+pub fn generate_huffman_codes_bytes(data: &[u8]) -> HashMap<u8, String> {
+    let freqs = FrequencyTable::from_chars(data.iter().map(|&b| char::from(b)));
+
+    codes_from_table(&freqs).into_iter()
+                            .map(|(c, code)| (c as u8, code))
+                            .collect()
+}
+
+/// Like `generate_huffman_codes()`, but from counts made elsewhere, perhaps
+/// over a larger corpus than the text to be encoded, so the text needn't be
+/// counted. Counts of 0 are skipped, and their characters get no code. Codes
+/// for the counts of a text are the ones `generate_huffman_codes()` gives it.
+/// 
+pub fn generate_huffman_codes_from_freqs(freqs: &HashMap<char, usize>) 
+
+    -> HashMap<char, String> 
+{
+    codes_from_table(&freqs.iter()
+                           .filter(|&(_, &n)| n > 0)
+                           .map(|(&c, &n)| (c, n))
+                           .collect())
+}
+
+/// Like `generate_huffman_codes()`, but for characters, or symbols of any
+/// other type, with weights of any `Weight` type: counts as `u32` or `u64`,
+/// say, or probabilities as `f64`. Of equal weights, the symbol given first
+/// is merged first. Symbols given more than once are coded separately, so
+/// each should appear once.
+/// 
+/// # Panics
+/// 
+/// If there are more than 2^31 symbols.
+/// 
+pub fn generate_huffman_codes_weighted<T, W, I>(weights: I) 
+
+    -> HashMap<T, String> 
+where
+    T: MapKey + Clone,
+    W: Weight,
+    I: IntoIterator<Item = (T, W)>,
+{
+    let mut nodes = NodeMem::new();
+
+    for (c, w) in weights {
+        nodes.new_leaf(c, w);
+    }
+    let tree = build_huffman_tree(&mut nodes);
+
+    tree_codes(tree, &nodes)
+}
+
+/// The number of bits `codes` would produce for text with the character counts
+/// in `freqs`, or `None` if `freqs` has a character `codes` can't encode.
+/// 
+pub fn predicted_bits(codes: &HashMap<char, String>, freqs: &FrequencyTable) 
+
+    -> Option<usize> 
+{
+    freqs.counts().iter()
+         .map(|(c, &f)| codes.get(c).map(|code| code.len() * f))
+         .sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use super::*;
+    use alloc::{format, vec};
+    use alloc::string::ToString;
+    use crate::io::encode;
+    use crate::tree::build_tree;
+
+    /// Counts for `n` characters that follow the Fibonacci numbers, which
+    /// build a tree `n - 1` levels deep.
+    /// 
+    fn fibonacci_counts(n: u32) -> FrequencyTable {
+        let mut fib = (1, 1);
+
+        (0..n).map(|i| {
+            let count = fib.0;
+
+            fib = (fib.1, fib.0 + fib.1);
+            (char::from_u32(0x100 + i).unwrap(), count)
+        }).collect()
+    }
+
+    #[test]
+    fn codes_too_long() {
+        let freqs = fibonacci_counts(70);
+
+        assert!(matches!(HuffmanCodebook::try_from_frequencies(freqs.clone()),
+                         Err(HuffmanError::CodeTooLong(_))));
+
+        let capped = HuffmanCodebook::from_frequencies_capped(freqs);
+
+        assert_eq!(capped.codes().len(), 70);
+        assert!(capped.codes().values().all(|code| code.len <= 64));
+        assert!(capped.is_canonical());
+
+        // Short enough codes are the ordinary ones.
+        let freqs = fibonacci_counts(60);
+
+        assert_eq!(HuffmanCodebook::from_frequencies_capped(freqs.clone())
+                       .codes(),
+                   HuffmanCodebook::from_frequencies(freqs).codes());
+    }
+
+    #[test]
+    fn iterator_input() {
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let iter  = text.chars().filter(|_| true);
+
+        assert_eq!(FrequencyTable::from_chars(iter.clone()), freqs);
+
+        // Ties may be broken differently, but the codes are equally good.
+        let codes = generate_huffman_codes_iter(iter);
+
+        assert_eq!(codes.len(), freqs.len());
+        assert_eq!(predicted_bits(&codes, &freqs),
+                   predicted_bits(&generate_huffman_codes(&text), &freqs));
+    }
+
+    #[test]
+    fn codes_are_deterministic() {
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let codes = generate_huffman_codes(&text);
+
+        // Each table hashes with its own seed, so each iterates its counts in
+        // a different order.
+        for _ in 0..10 {
+            assert_eq!(generate_huffman_codes(&text), codes);
+        }
+        // Shuffle the text, which keeps its counts, and feed the counts in
+        // different orders.
+        let mut chars = text.chars().collect::<Vec<_>>();
+        let mut state = 0x9e37_79b9_u64;
+
+        for round in 0..5 {
+            for i in (1..chars.len()).rev() {
+                state = state.wrapping_mul(6364136223846793005)
+                             .wrapping_add(1442695040888963407);
+                chars.swap(i, (state >> 33) as usize % (i + 1));
+            }
+            let shuffled = chars.iter().collect::<String>();
+
+            assert_eq!(generate_huffman_codes(&shuffled), codes);
+
+            let mut counts = count_frequencies(&shuffled).counts()
+                                                         .iter()
+                                                         .map(|(&c, &n)| (c, n))
+                                                         .collect::<Vec<_>>();
+            counts.sort_unstable();
+
+            if round % 2 == 1 {
+                counts.reverse();
+            }
+            assert_eq!(codes_from_table(&counts.into_iter().collect()), codes);
+        }
+    }
+
+    #[test]
+    fn word_codes() {
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let codes = generate_huffman_codes_iter(words.iter());
+
+        let mut counts = HashMap::new();
+
+        for word in &words {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+        let most  = counts.iter().max_by_key(|&(_, &n)| n).unwrap().0;
+        let kraft = codes.values().map(|code| 0.5_f64.powi(code.len() as i32))
+                                  .sum::<f64>();
+
+        assert_eq!(codes.len(), counts.len());
+        assert_eq!(kraft, 1.0);
+        assert!(codes.values().all(|code| codes[most].len() <= code.len()));
+        assert_eq!(generate_huffman_codes_iter(words.iter()), codes);
+
+        // Of equal counts, the symbol seen first takes the left branch.
+        assert_eq!(generate_huffman_codes_iter(["whale", "ahab"]),
+                   HashMap::from([("whale", "0".to_string()),
+                                  ("ahab",  "1".to_string())]));
+    }
+
+    #[test]
+    fn byte_input() {
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let ascii = text.chars().filter(char::is_ascii).collect::<String>();
+        let codes = generate_huffman_codes_bytes(ascii.as_bytes());
+
+        assert_eq!(codes.into_iter().map(|(b, code)| (char::from(b), code))
+                                    .collect::<HashMap<_, _>>(),
+                   generate_huffman_codes(&ascii));
+
+        // The text has a few non-ASCII chars, which take two or three bytes
+        // each, so coding bytes costs a little more than coding chars.
+        let bytes = generate_huffman_codes_bytes(text.as_bytes());
+        let size  = text.bytes().map(|b| bytes[&b].len()).sum::<usize>();
+        let chars = predicted_bits(&generate_huffman_codes(&text),
+                                   &FrequencyTable::from_text(&text))
+                        .unwrap();
+
+        assert!(size > chars && size < chars + chars / 50);
+        assert!(size < text.len() * 8);
+
+        // Binary data, with every byte value.
+        let data  = (0..=255_u8).flat_map(|b| vec![b; 1 + b as usize % 7])
+                                .collect::<Vec<_>>();
+        let codes = generate_huffman_codes_bytes(&data);
+        let kraft = codes.values().map(|code| 0.5_f64.powi(code.len() as i32))
+                                  .sum::<f64>();
+
+        assert_eq!(codes.len(), 256);
+        assert_eq!(kraft, 1.0);
+        assert!(generate_huffman_codes_bytes(&[]).is_empty());
+    }
+
+    #[test]
+    fn precomputed_counts() {
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+
+        assert_eq!(generate_huffman_codes_from_freqs(freqs.counts()),
+                   generate_huffman_codes(&text));
+
+        let mut counts = HashMap::from([('a', 5), ('b', 2), ('c', 1)]);
+
+        counts.insert('z', 0);
+
+        assert_eq!(generate_huffman_codes_from_freqs(&counts),
+                   generate_huffman_codes("aaaaabbc"));
+        assert!(generate_huffman_codes_from_freqs(&HashMap::new()).is_empty());
+        assert!(generate_huffman_codes_from_freqs(&HashMap::from([('z', 0)]))
+                    .is_empty());
+    }
+
+    #[test]
+    fn code_bits() {
+        let text = read_to_string("data/moby_dick.txt").unwrap();
+
+        for text in [&text[..], "", "a", "ab", "abracadabra"] {
+            let format  = |(bits, len): (u64, u8)| {
+                format!("{:0w$b}", bits, w = len as usize)
+            };
+            let strings = generate_huffman_code_bits(text)
+                              .unwrap()
+                              .into_iter()
+                              .map(|(c, code)| (c, format(code)))
+                              .collect::<HashMap<_, _>>();
+
+            assert_eq!(strings, generate_huffman_codes(text));
+        }
+        // Fibonacci counts give codes as long as there are symbols less one.
+        let mut fib   = (1, 1);
+        let mut freqs = HashMap::new();
+
+        for c in ('\u{100}'..).take(70) {
+            freqs.insert(c, fib.0);
+            fib = (fib.1, fib.0 + fib.1);
+        }
+        let strings = generate_huffman_codes_from_freqs(&freqs);
+
+        assert_eq!(strings.values().map(String::len).max(), Some(69));
+        assert!(matches!(code_bits_from_table(&freqs.into_iter().collect()),
+                         Err(HuffmanError::MalformedCode(_))));
+    }
+
+    #[test]
+    fn degenerate_texts() {
+        // An empty text has an empty tree, and so no codes.
+        assert!(generate_huffman_codes("").is_empty());
+        assert!(HuffmanCodebook::from_text("").codes().is_empty());
+        assert!(encode("").is_empty());
+
+        for text in ["a", "aaaa"] {
+            let codes = HashMap::from([('a', "0".to_string())]);
+
+            assert_eq!(generate_huffman_codes(text), codes);
+            assert_eq!(generate_huffman_codes_from_freqs(
+                           count_frequencies(text).counts()
+                       ), codes);
+
+            let bits     = encode(text);
+            let codebook = HuffmanCodebook::from_text(text);
+            let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+            let tree     = build_tree(&count_frequencies(text)).unwrap();
+
+            assert_eq!(bits.clone().into_parts(), (vec![0], text.len()));
+            assert_eq!(fsm.decode(&bits).unwrap(), text);
+            assert_eq!(tree.decode(&bits).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn weight_types() {
+        const CLRS: [(char, u32); 6] = [('a', 45), ('b', 13), ('c', 12),
+                                        ('d', 16), ('e', 9),  ('f', 5)];
+        let lengths = |codes: HashMap<char, String>| {
+            let mut lengths = codes.into_iter()
+                                   .map(|(c, code)| (c, code.len()))
+                                   .collect::<Vec<_>>();
+            lengths.sort_unstable();
+            lengths
+        };
+        let counts = lengths(generate_huffman_codes_weighted(CLRS));
+
+        assert_eq!(counts, [('a', 1), ('b', 3), ('c', 3), ('d', 3), ('e', 4),
+                            ('f', 4)]);
+        assert_eq!(lengths(generate_huffman_codes_weighted(
+                       CLRS.map(|(c, w)| (c, w as u64 * 1_000_000_000_000))
+                   )), counts);
+        assert_eq!(lengths(generate_huffman_codes_weighted(
+                       CLRS.map(|(c, w)| (c, w as usize))
+                   )), counts);
+        assert_eq!(lengths(generate_huffman_codes_weighted(
+                       CLRS.map(|(c, w)| (c, w as f64 / 100.0))
+                   )), counts);
+
+        let text  = read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let probs = freqs.counts().iter().map(|(&c, &f)| {
+            (c, f as f64 / freqs.total() as f64)
+        });
+        let codes = generate_huffman_codes_weighted(probs);
+
+        // Ties may be broken differently, but the codes are equally good.
+        assert_eq!(predicted_bits(&codes, &freqs),
+                   predicted_bits(&generate_huffman_codes(&text), &freqs));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn counting_from_reader() {
+        // Multi-byte characters of every width, so some of them straddle the
+        // read buffer's edges.
+        let text = "ab\u{e9}\u{20ac}\u{1f600}".repeat(300_000);
+        let path = std::env::temp_dir()
+                       .join(format!("huffman-{}.txt", std::process::id()));
+
+        std::fs::write(&path, &text).unwrap();
+
+        let file  = std::fs::File::open(&path).unwrap();
+        let freqs = FrequencyTable::from_reader(file);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(freqs.unwrap(), FrequencyTable::from_text(&text));
+
+        let bad = FrequencyTable::from_reader(&[b'a', 0xe9, b'b'][..]);
+
+        assert_eq!(bad.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let cut = FrequencyTable::from_reader(&"\u{20ac}".as_bytes()[..2]);
+
+        assert_eq!(cut.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn cancel_counting() {
+        use std::{thread, time::{Duration, Instant}};
+
+        let cancel = CancelToken::new();
+        let remote = cancel.clone();
+        let start  = Instant::now();
+
+        // The reader never ends, so only cancelling can stop the count.
+        let worker = thread::spawn(move || {
+            FrequencyTable::from_reader_with_cancel(io::repeat(b'a'), 
+                                                    Some(&remote))
+        });
+        thread::sleep(Duration::from_millis(20));
+        cancel.cancel();
+
+        let err = worker.join().unwrap().unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(err.get_ref().unwrap().downcast_ref::<HuffmanError>(),
+                   Some(&HuffmanError::Cancelled));
+    }
+
+    #[test]
+    fn top_n_with_other() {
+        // A heavy tail: a handful of common symbols and many rare ones.
+        let freqs = ('a'..='z').enumerate()
+                               .map(|(i, c)| (c, 1024 >> i.min(10)))
+                               .collect::<FrequencyTable>();
+
+        let (head, tail) = freqs.top_n_with_other(4);
+
+        assert_eq!(head.len(), 4);
+        assert_eq!(head.get('a'), 1024);
+        assert_eq!(head.get('e'), 0);
+        assert_eq!(tail.symbols.len(), 22);
+        assert_eq!(tail.symbols[0], 'e');
+        assert_eq!(head.total() + tail.count, freqs.total());
+
+        let (head, tail) = freqs.top_n_with_other(100);
+
+        assert_eq!(head, freqs);
+        assert_eq!(tail, TailStats::default());
+    }
+
+    #[test]
+    fn code_helpers() {
+        let empty = Code::default();
+        let full  = Code { bits: u64::MAX, len: 64 };
+        let code  = Code::from_str_binary("0010").unwrap();
+
+        assert_eq!(code, Code { bits: 0b10, len: 4 });
+        assert_eq!(format!("{}", code), "0010");
+        assert_eq!(format!("{:b}", empty), "");
+        assert_eq!(format!("{:>3}", empty), "   ");
+        assert_eq!(format!("{:x}", full), "ffffffffffffffff");
+        assert_eq!(format!("{}", full), "1".repeat(64));
+        assert_eq!(Code::from_str_binary(""), Some(empty));
+        assert_eq!(Code::from_str_binary(&"0".repeat(65)), None);
+        assert_eq!(Code::from_str_binary("012"), None);
+
+        assert_eq!(code.append(true), Code { bits: 0b101, len: 5 });
+        assert_eq!(empty.append(false), Code { bits: 0, len: 1 });
+        assert_eq!(code.concat(empty), Some(code));
+        assert_eq!(empty.concat(full), Some(full));
+        assert_eq!(code.concat(code), Code::from_str_binary("00100010"));
+        assert_eq!(full.concat(empty.append(true)), None);
+
+        let mut codes = ["11", "0", "101", "100", "1"]
+            .map(|s| Code::from_str_binary(s).unwrap());
+        codes.sort();
+
+        assert_eq!(codes.map(|c| c.to_string()), ["0", "1", "11", "100", 
+                                                  "101"]);
+    }
+
+    #[test]
+    fn fallible_codes() {
+        let text = "abracadabra";
+
+        assert_eq!(try_generate_huffman_codes(text),
+                   Ok(generate_huffman_codes(text)));
+        assert_eq!(try_generate_huffman_codes(""),
+                   Err(HuffmanError::EmptyInput));
+        assert!(build_huffman_tree(&mut NodeMem::<usize>::new()).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+//! A common interface to coding strategies, so benchmarks and plumbing can be
+//! written once against `&dyn Codec`.
+//! 
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::analysis::table_overhead_bits;
+use crate::encode::encode_into;
+use crate::HuffmanError;
+use crate::codebook::HuffmanCodebook;
+use crate::io::{AdaptiveDecoder, AdaptiveEncoder, BitReader, BitWriter};
+use crate::io::{DecoderFsm, Transition};
+
+
+/// A way of coding text as bits.
+/// 
+pub trait Codec {
+    /// Fits the codec to `data`. Codecs that don't need training, such as
+    /// adaptive ones, do nothing.
+    /// 
+    fn train(&mut self, data: &str) -> Result<(), HuffmanError>;
+
+    /// Appends the coded bits for `data` to `out`.
+    /// 
+    fn encode(&self, data: &str, out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError>;
+
+    /// Decodes the text coded by the rest of `r`'s bits.
+    /// 
+    fn decode(&self, r: &mut BitReader) -> Result<String, HuffmanError>;
+
+    /// The bits needed to store whatever the decoder needs to know ahead of
+    /// the coded data.
+    /// 
+    fn table_overhead_bits(&self) -> u64;
+}
+
+impl Codec for HuffmanCodebook {
+    /// Replaces the codes with ones trained on `data`.
+    /// 
+    fn train(&mut self, data: &str) -> Result<(), HuffmanError> {
+        *self = HuffmanCodebook::from_text(data);
+        Ok(())
+    }
+    fn encode(&self, data: &str, out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError> 
+    {
+        encode_into(self, data, out, None)
+    }
+    fn decode(&self, r: &mut BitReader) -> Result<String, HuffmanError> {
+        let fsm       = DecoderFsm::from_codebook(self)?;
+        let mut text  = String::new();
+        let mut state = 0;
+        let mut start = r.position();
+        let symbols   = |text: &String| text.chars().count();
+
+        while let Some(bit) = r.read_bit() {
+            match fsm.step(state, bit) {
+                Transition::Next(next) => state = next,
+                Transition::Emit(c)    => {
+                    text.push(c);
+                    state = 0;
+                    start = r.position();
+                },
+                Transition::Invalid    => {
+                    return Err(HuffmanError::InvalidCode {
+                        bit_offset: start, symbols: symbols(&text)
+                    });
+                },
+            }
+        }
+        match state {
+            0 => Ok(text),
+            _ => Err(HuffmanError::TruncatedBitstream {
+                bit_offset: start, symbols: symbols(&text)
+            }),
+        }
+    }
+    /// The bits for a table of the symbols and their code lengths, from which
+    /// a decoder can rebuild canonical codes.
+    /// 
+    fn table_overhead_bits(&self) -> u64 {
+        table_overhead_bits(&self.code_strings()) as u64
+    }
+}
+
+/// Codes each text as a stream of its own, starting from the codes as they
+/// stand, which for a new encoder are those of an empty tree. The decoder
+/// starts from the same codes, restored from a snapshot, so nothing need be
+/// stored ahead of the coded text.
+/// 
+impl Codec for AdaptiveEncoder {
+    /// Does nothing, as the codes adapt to the text as it's coded.
+    /// 
+    fn train(&mut self, _: &str) -> Result<(), HuffmanError> {
+        Ok(())
+    }
+    fn encode(&self, data: &str, out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError> 
+    {
+        let mut encoder = self.clone();
+
+        for c in data.chars() {
+            encoder.write_symbol(c, out);
+        }
+        Ok(())
+    }
+    fn decode(&self, r: &mut BitReader) -> Result<String, HuffmanError> {
+        let mut decoder = AdaptiveDecoder::restore(&self.snapshot())?;
+        let mut text    = String::new();
+
+        while r.remaining() > 0 {
+            text.push(decoder.decode_symbol(r)?);
+        }
+        Ok(text)
+    }
+    fn table_overhead_bits(&self) -> u64 {
+        0
+    }
+}
+
+
+/// What coding a text cost one codec, as `compare_models()` reports it.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModelCost {
+    /// The number of characters in the text.
+    pub symbols      : usize,
+    /// The coded text.
+    pub payload_bits : u64,
+    /// What the decoder needs ahead of the coded text, as
+    /// `Codec::table_overhead_bits()` gives it.
+    pub table_bits   : u64,
+}
+
+impl ModelCost {
+    /// The coded text plus what's stored ahead of it.
+    /// 
+    pub fn total_bits(&self) -> u64 {
+        self.payload_bits + self.table_bits
+    }
+    /// The total bits per character, or 0 for an empty text.
+    /// 
+    pub fn bits_per_char(&self) -> f64 {
+        match self.symbols {
+            0 => 0.0,
+            n => self.total_bits() as f64 / n as f64,
+        }
+    }
+}
+
+/// Codes `data` with each of `codecs`, trained already, giving what it cost
+/// each, in the same order. Fails with the first error a codec gives.
+/// 
+pub fn compare_models(codecs: &[&dyn Codec], data: &str) 
+
+    -> Result<Vec<ModelCost>, HuffmanError> 
+{
+    let symbols = data.chars().count();
+
+    codecs.iter().map(|codec| {
+        let mut out = BitWriter::new();
+
+        codec.encode(data, &mut out)?;
+
+        Ok(ModelCost {
+            symbols,
+            payload_bits : out.bit_len(),
+            table_bits   : codec.table_overhead_bits(),
+        })
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::boxed::Box;
+    use crate::codebook::ShannonFanoCodec;
+
+    /// Trains, encodes, and decodes through the trait object alone.
+    /// 
+    fn round_trip(codec: &mut dyn Codec, text: &str) -> (String, u64) {
+        codec.train(text).unwrap();
+
+        let mut out = BitWriter::new();
+        codec.encode(text, &mut out).unwrap();
+
+        let nbits   = out.bit_len();
+        let bytes   = out.finish();
+        let mut r   = BitReader::new(&bytes, nbits as usize);
+        let decoded = codec.decode(&mut r).unwrap();
+
+        assert_eq!(r.remaining(), 0);
+        (decoded, nbits)
+    }
+
+    #[test]
+    fn through_trait_objects() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut codecs: Vec<Box<dyn Codec>> = vec![
+            Box::new(HuffmanCodebook::from_text("")),
+            Box::new(ShannonFanoCodec::default()),
+            Box::new(AdaptiveEncoder::new()),
+        ];
+        for codec in &mut codecs {
+            let (decoded, nbits) = round_trip(codec.as_mut(), &text);
+
+            assert_eq!(decoded, text);
+            assert!(nbits + codec.table_overhead_bits()
+                    < text.len() as u64 * 8);
+        }
+    }
+
+    #[test]
+    fn adaptive_codec() {
+        let mut warm = AdaptiveEncoder::new();
+
+        AdaptiveEncoder::encode(&mut warm, "call me ishmael");
+
+        // Each text starts from the codes as they stand, so encoding the
+        // same text twice gives the same bits.
+        for codec in [&AdaptiveEncoder::new() as &dyn Codec, &warm] {
+            let mut out = BitWriter::new();
+
+            codec.encode("ishmael", &mut out).unwrap();
+            codec.encode("ishmael", &mut out).unwrap();
+
+            let nbits = out.bit_len() as usize;
+            let bytes = out.finish();
+            let mut r = BitReader::new(&bytes, nbits / 2);
+
+            assert_eq!(codec.decode(&mut r).as_deref(), Ok("ishmael"));
+
+            let mut r = BitReader::new(&bytes, nbits);
+
+            assert!(codec.decode(&mut r).is_err());
+        }
+        let (fresh, seen) = (AdaptiveEncoder::new(), &warm);
+        let costs = compare_models(&[&fresh, seen], "ishmael").unwrap();
+
+        // Symbols seen before cost no escapes.
+        assert!(costs[1].payload_bits < costs[0].payload_bits);
+        assert_eq!(costs[0].table_bits, 0);
+    }
+
+    #[test]
+    fn comparing_models() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let huffman  = HuffmanCodebook::from_text(&text);
+        let fano     = ShannonFanoCodec::from_text(&text).unwrap();
+        let adaptive = AdaptiveEncoder::new();
+        let costs    = compare_models(&[&huffman, &adaptive, &fano], &text)
+                           .unwrap();
+
+        assert_eq!(costs[0], ModelCost {
+            symbols      : text.chars().count(),
+            payload_bits : crate::io::encode(&text).bit_len() as u64,
+            table_bits   : Codec::table_overhead_bits(&huffman),
+        });
+        assert_eq!(costs[1].table_bits, 0);
+        assert!(costs[1].payload_bits < costs[0].payload_bits * 104 / 100);
+        assert!(costs.iter().all(|cost| cost.bits_per_char() < 8.0));
+
+        // Huffman codes are optimal, Shannon-Fano codes only near it, and
+        // both store the same kind of table.
+        assert!(costs[0].payload_bits <= costs[2].payload_bits);
+        assert!(costs[2].payload_bits < costs[0].payload_bits * 105 / 100);
+        assert_eq!(costs[2].table_bits, 
+                   Codec::table_overhead_bits(fano.codebook()));
+
+        // On a short text the table costs more than adapting does.
+        let short = "a".repeat(40) + "b";
+        let costs = compare_models(&[&HuffmanCodebook::from_text(&short),
+                                     &adaptive], &short).unwrap();
+
+        assert!(costs[1].total_bits() < costs[0].total_bits());
+        assert_eq!(compare_models(&[&huffman], "\0").err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\0', char_index: 0, byte_offset: 0
+                   }));
+        assert_eq!(compare_models(&[], &text), Ok(vec![]));
+    }
+
+    #[test]
+    fn huffman_errors() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0, 1), ('b', 0b10, 2)];
+        let codec: &dyn Codec = &HuffmanCodebook::from_static(CODES).unwrap();
+
+        assert_eq!(codec.table_overhead_bits(), 32 + 2 * 16);
+        assert_eq!(codec.encode("abc", &mut BitWriter::new()),
+                   Err(HuffmanError::SymbolNotInCodebook {
+                       symbol: 'c', char_index: 2, byte_offset: 2
+                   }));
+        assert_eq!(codec.decode(&mut BitReader::new(&[0b0110_0000], 3)),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 1, symbols: 1
+                   }));
+        assert_eq!(codec.decode(&mut BitReader::new(&[0b0101_0000], 4)),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 3, symbols: 2
+                   }));
+    }
+}
@@ -0,0 +1,752 @@
+//! Generates Rust source that embeds codes in a program, so it needn't train
+//! them at runtime. It's meant to be called from a build script:
+//! 
+//! ```ignore
+//! // build.rs
+//! let codebook = HuffmanCodebook::from_text(&corpus);
+//! let source   = codegen::generate_rust_source(&codebook, "CODES");
+//! 
+//! std::fs::write(Path::new(&out_dir).join("codes.rs"), source)?;
+//! 
+//! // lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/codes.rs"));
+//! 
+//! let codebook = HuffmanCodebook::from_static(CODES)?;
+//! ```
+//! 
+
+use alloc::{format, vec};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::escape::Quoted;
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::io::{DecoderFsm, Transition};
+
+
+/// The codes of `codebook` sorted by symbol.
+/// 
+pub(crate) fn sorted_codes(codebook: &HuffmanCodebook) -> Vec<(char, Code)> {
+    let mut codes = codebook.codes().iter()
+                                    .map(|(&c, &code)| (c, code))
+                                    .collect::<Vec<_>>();
+    codes.sort_unstable_by_key(|&(c, _)| c);
+    codes
+}
+
+/// A Rust binary literal for the bits of `code`, with a digit for each bit.
+/// 
+fn binary_literal(code: Code) -> String {
+    match code.len {
+        0 => "0".to_string(),
+        _ => format!("0b{}", code.to_code_string()),
+    }
+}
+
+/// Generates the source for a `static` named `ident` holding the codes of
+/// `codebook` as `(symbol, bits, len)` entries sorted by symbol, which is the
+/// form `HuffmanCodebook::from_static()` takes.
+/// 
+pub fn generate_rust_source(codebook: &HuffmanCodebook, ident: &str) -> String {
+    let mut src = String::new();
+
+    src.push_str("// Generated by huffman::codegen; do not edit.\n");
+    src.push_str(&format!("pub static {}: &[(char, u64, u8)] = &[\n", ident));
+
+    for (c, code) in sorted_codes(codebook) {
+        src.push_str(&format!("    ({}, {}, {}),\n", 
+                              Quoted(c), binary_literal(code), code.len));
+    }
+    src.push_str("];\n");
+    src
+}
+
+/// The most arms a generated `match` gets before it's split into nested
+/// matches over ranges of symbols, to keep compile times reasonable.
+/// 
+const MAX_ARMS: usize = 256;
+
+/// Generates the source for a function named `fn_name` that maps a `char` to
+/// its code in `codebook` as a `(bits, len)` pair, or to `None` if it has no
+/// code. The code is looked up by a `match`, which for large alphabets is
+/// nested by symbol range.
+/// 
+pub fn emit_encoder_fn(codebook: &HuffmanCodebook, fn_name: &str) -> String {
+    emit_encoder_fn_with_arms(codebook, fn_name, MAX_ARMS)
+}
+
+fn emit_encoder_fn_with_arms(codebook : &HuffmanCodebook, 
+                             fn_name  : &str, 
+                             max_arms : usize) -> String 
+{
+    let mut src = String::new();
+
+    src.push_str("// Generated by huffman::codegen; do not edit.\n");
+    src.push_str(&format!("pub fn {}(c: char) -> Option<(u64, u8)> {{\n", 
+                          fn_name));
+    src.push_str("    match c {\n");
+
+    emit_arms(&mut src, &sorted_codes(codebook), max_arms, 8);
+
+    src.push_str("        _ => None,\n");
+    src.push_str("    }\n");
+    src.push_str("}\n");
+    src
+}
+
+/// Emits a match arm for each of `codes`, or if there are more than `max_arms`
+/// of them, arms for ranges of them that each hold a nested match.
+/// 
+fn emit_arms(src      : &mut String, 
+             codes    : &[(char, Code)], 
+             max_arms : usize, 
+             indent   : usize) 
+{
+    let pad = " ".repeat(indent);
+
+    if codes.len() <= max_arms {
+        for &(c, code) in codes {
+            src.push_str(&format!("{}{} => Some(({}, {})),\n", pad, 
+                                  Quoted(c), binary_literal(code), code.len));
+        }
+        return;
+    }
+    for group in codes.chunks(codes.len().div_ceil(max_arms)) {
+        let first = group[0].0;
+        let last  = group[group.len() - 1].0;
+
+        src.push_str(&format!("{}{}..={} => match c {{\n", 
+                              pad, Quoted(first), Quoted(last)));
+
+        emit_arms(src, group, max_arms, indent + 4);
+
+        src.push_str(&format!("{}    _ => None,\n", pad));
+        src.push_str(&format!("{}}},\n", pad));
+    }
+}
+
+/// Generates a C header declaring the codes of `codebook` as an array of
+/// `huff_code_t` sorted by symbol, with `HUFF_CODE_COUNT` defined as its length.
+/// Symbols are Unicode scalar values and codes are the low `len` bits of
+/// `bits`. `guard` names the include guard; characters that can't appear in a
+/// C identifier are replaced with underscores. An empty codebook gets a single
+/// zeroed placeholder entry, since C has no empty arrays.
+/// 
+/// Fails with `HuffmanError::CodeTooLong` if a code doesn't fit in 32 bits.
+/// 
+pub fn emit_c_header(codebook: &HuffmanCodebook, guard: &str) 
+
+    -> Result<String, HuffmanError> 
+{
+    let codes = sorted_codes(codebook);
+
+    if let Some(&(c, _)) = codes.iter().find(|(_, code)| code.len > 32) {
+        return Err(HuffmanError::CodeTooLong(c));
+    }
+    let mut guard = guard.chars()
+                         .map(|c| if c.is_ascii_alphanumeric() { c } 
+                                  else                         { '_' })
+                         .collect::<String>();
+
+    if !guard.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        guard.insert(0, '_');
+    }
+    let mut src = String::new();
+
+    src.push_str("/* Generated by huffman::codegen; do not edit. */\n");
+    src.push_str(&format!("#ifndef {}\n#define {}\n\n", guard, guard));
+    src.push_str("#include <stdint.h>\n\n");
+    src.push_str("typedef struct {\n");
+    src.push_str("    uint32_t symbol;\n");
+    src.push_str("    uint32_t bits;\n");
+    src.push_str("    uint8_t  len;\n");
+    src.push_str("} huff_code_t;\n\n");
+    src.push_str(&format!("#define HUFF_CODE_COUNT {}\n\n", codes.len()));
+    src.push_str(&format!("static const huff_code_t huff_codes[{}] = {{\n", 
+                          codes.len().max(1)));
+
+    for (c, code) in &codes {
+        src.push_str(&format!("    {{ 0x{:08x}u, 0x{:08x}u, {:2} }}, \
+                               /* U+{:04X} */\n", 
+                              *c as u32, code.bits, code.len, *c as u32));
+    }
+    if codes.is_empty() {
+        src.push_str("    { 0x00000000u, 0x00000000u,  0 },\n");
+    }
+    src.push_str("};\n\n");
+    src.push_str(&format!("#endif /* {} */\n", guard));
+    Ok(src)
+}
+
+/// The languages `emit_decoder_fsm()` can generate.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lang {
+    Rust,
+    C,
+}
+
+/// Packs a transition into the `u32` the generated tables hold: a state index,
+/// a symbol to emit with the top bit set, or `u32::MAX` for invalid bits.
+/// 
+fn packed_transition(transition: Transition) -> u32 {
+    match transition {
+        Transition::Next(state) => state,
+        Transition::Emit(c)     => 0x8000_0000 | c as u32,
+        Transition::Invalid     => u32::MAX,
+    }
+}
+
+const RUST_FSM_DRIVER: &str = "
+pub fn huff_decode(bytes: &[u8], nbits: usize) -> Option<String> {
+    if nbits > bytes.len() * 8 {
+        return None;
+    }
+    let mut text  = String::new();
+    let mut state = 0;
+
+    for i in 0..nbits {
+        let bit  = (bytes[i / 8] >> (7 - i % 8) & 1) as usize;
+        let next = HUFF_FSM[state][bit];
+
+        if next == u32::MAX {
+            return None;
+        }
+        if next & 0x8000_0000 == 0 {
+            state = next as usize;
+        } else {
+            text.push(char::from_u32(next & 0x7fff_ffff)?);
+            state = 0;
+        }
+    }
+    if state == 0 { Some(text) } else { None }
+}
+";
+
+const C_FSM_DRIVER: &str = "
+/* Decodes the first nbits bits of bytes into out, which must have room for
+   nbits symbols. Returns the number of symbols decoded, or -1 if the bits
+   aren't a sequence of whole codes. */
+static long huff_decode(const uint8_t *bytes, size_t nbits, uint32_t *out)
+{
+    size_t   i;
+    long     n     = 0;
+    uint32_t state = 0;
+
+    for (i = 0; i < nbits; i++) {
+        uint32_t bit  = (bytes[i / 8] >> (7 - i % 8)) & 1u;
+        uint32_t next = huff_fsm[state][bit];
+
+        if (next == 0xffffffffu)
+            return -1;
+        if (next & 0x80000000u) {
+            out[n++] = next & 0x7fffffffu;
+            state    = 0;
+        } else {
+            state    = next;
+        }
+    }
+    return state == 0 ? n : -1;
+}
+";
+
+/// Generates source for decoding the codes of `codebook` without building any
+/// tables at runtime: the transitions of its `DecoderFsm` as a static array,
+/// `HUFF_FSM` in Rust or `huff_fsm` in C, and a `huff_decode` function that
+/// runs them over a bitstream. Each transition is a state index, a symbol to
+/// emit with the top bit set, or all ones for bits that aren't a code. Bits
+/// are read from the most significant bit of each byte down.
+/// 
+/// In Rust, `huff_decode(bytes, nbits)` returns the decoded `String`, or `None`
+/// if the bits aren't a sequence of whole codes. In C, it writes Unicode
+/// scalars to a caller-provided buffer and returns their count, or -1.
+/// 
+pub fn emit_decoder_fsm(codebook: &HuffmanCodebook, lang: Lang) 
+
+    -> Result<String, HuffmanError> 
+{
+    let fsm = DecoderFsm::from_codebook(codebook)?;
+    let len = fsm.states().len();
+    let mut src = String::new();
+
+    match lang {
+        Lang::Rust => {
+            src.push_str("// Generated by huffman::codegen; do not edit.\n");
+            src.push_str(&format!("pub static HUFF_FSM: [[u32; 2]; {}] = [\n", 
+                                  len));
+        },
+        Lang::C => {
+            src.push_str("/* Generated by huffman::codegen; do not edit. */\n");
+            src.push_str("#include <stddef.h>\n#include <stdint.h>\n\n");
+            src.push_str(&format!("static const uint32_t huff_fsm[{}][2] = \
+                                   {{\n", len));
+        },
+    }
+    for &[zero, one] in fsm.states() {
+        let (zero, one) = (packed_transition(zero), packed_transition(one));
+
+        src.push_str(&match lang {
+            Lang::Rust => format!("    [0x{:08x}, 0x{:08x}],\n", zero, one),
+            Lang::C    => format!("    {{ 0x{:08x}u, 0x{:08x}u }},\n", 
+                                  zero, one),
+        });
+    }
+    match lang {
+        Lang::Rust => {
+            src.push_str("];\n");
+            src.push_str(RUST_FSM_DRIVER);
+        },
+        Lang::C => {
+            src.push_str("};\n");
+            src.push_str(C_FSM_DRIVER);
+        },
+    }
+    Ok(src)
+}
+
+
+/// The forms `emit_static_array()` can lay a table out in.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaticStyle {
+    /// A slice of `(symbol, bits, len)` sorted by symbol, `HUFF_CODES`, for
+    /// `lookup_sorted()` or `binary_search_by_key()`.
+    SortedArray,
+    /// A perfect hash table built by hash and displace: `HUFF_DISPLACEMENTS`
+    /// gives each bucket of symbols a displacement that moves them to free
+    /// slots of `HUFF_SLOTS`, and `huff_lookup()` finds a symbol's code with
+    /// two hashes and no search.
+    PhfLikeHash,
+}
+
+/// Generates the source for a static table of the codes of `codebook` in the
+/// given `style`. The source uses only core types, so the crate embedding it
+/// needn't depend on this one.
+/// 
+pub fn emit_static_array(codebook: &HuffmanCodebook, style: StaticStyle) 
+
+    -> String 
+{
+    match style {
+        StaticStyle::SortedArray => {
+            generate_rust_source(codebook, "HUFF_CODES")
+        },
+        StaticStyle::PhfLikeHash => {
+            emit_displacement_table(&sorted_codes(codebook))
+        },
+    }
+}
+
+/// Looks up `c` in a table of `(symbol, bits, len)` sorted by symbol, such as
+/// one emitted by `emit_static_array()` or `generate_rust_source()`, and
+/// returns its `(bits, len)`.
+/// 
+pub fn lookup_sorted(table: &[(char, u64, u8)], c: char) -> Option<(u64, u8)> {
+    table.binary_search_by_key(&c, |&(s, _, _)| s)
+         .ok()
+         .map(|i| (table[i].1, table[i].2))
+}
+
+/// The hash the displacement table is built with. `HASH_SOURCE` must be kept
+/// the same as this.
+/// 
+fn displacement_hash(c: char, k: u64) -> u64 {
+    let mut x = c as u64 ^ k.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x ^= x >> 33;
+    x  = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x
+}
+
+const HASH_SOURCE: &str = "
+pub fn huff_hash(c: char, k: u64) -> u64 {
+    let mut x = c as u64 ^ k.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x ^= x >> 33;
+    x  = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x
+}
+
+pub fn huff_lookup(c: char) -> Option<(u64, u8)> {
+    let bucket = (huff_hash(c, 0) % HUFF_BUCKETS as u64) as usize;
+    let k      = HUFF_DISPLACEMENTS[bucket] as u64 + 1;
+    let slot   = (huff_hash(c, k) % HUFF_SLOT_COUNT as u64) as usize;
+
+    match HUFF_SLOTS[slot] {
+        Some((s, bits, len)) if s == c => Some((bits, len)),
+        _                              => None,
+    }
+}
+";
+
+/// A hash-and-displace table for `codes`: the displacement of each bucket,
+/// and the slots.
+/// 
+struct DisplacementTable {
+    displacements : Vec<u32>,
+    slots         : Vec<Option<(char, Code)>>,
+}
+
+impl DisplacementTable {
+    /// The average number of symbols in a bucket.
+    /// 
+    const BUCKET_SIZE: usize = 4;
+
+    /// How many displacements a bucket tries before the table is rebuilt with
+    /// more slots.
+    /// 
+    const MAX_TRIES: u32 = 1 << 16;
+
+    fn new(codes: &[(char, Code)]) -> Self {
+        let buckets   = codes.len().div_ceil(Self::BUCKET_SIZE).max(1);
+        let mut slots = codes.len() + codes.len() / 4 + 1;
+
+        loop {
+            if let Some(table) = Self::build(codes, buckets, slots) {
+                return table;
+            }
+            slots += slots / 4 + 1;
+        }
+    }
+    /// Places the largest buckets first, while there's the most room, or
+    /// gives up if a bucket can't be placed.
+    /// 
+    fn build(codes: &[(char, Code)], nbuckets: usize, nslots: usize) 
+
+        -> Option<Self> 
+    {
+        let mut buckets = vec![vec![]; nbuckets];
+
+        for &(c, code) in codes {
+            buckets[(displacement_hash(c, 0) % nbuckets as u64) as usize]
+                .push((c, code));
+        }
+        let mut order = (0..nbuckets).collect::<Vec<_>>();
+
+        order.sort_by_key(|&b| core::cmp::Reverse(buckets[b].len()));
+
+        let mut table = Self {
+            displacements : vec![0; nbuckets],
+            slots         : vec![None; nslots],
+        };
+        let mut taken = vec![];
+
+        for b in order.into_iter().filter(|&b| !buckets[b].is_empty()) {
+            let d = (0..Self::MAX_TRIES).find(|&d| {
+                taken.clear();
+
+                buckets[b].iter().all(|&(c, _)| {
+                    let slot = table.slot(c, d);
+                    let free = table.slots[slot].is_none()
+                               && !taken.contains(&slot);
+                    taken.push(slot);
+                    free
+                })
+            })?;
+            for &(c, code) in &buckets[b] {
+                let slot = table.slot(c, d);
+                table.slots[slot] = Some((c, code));
+            }
+            table.displacements[b] = d;
+        }
+        Some(table)
+    }
+    fn slot(&self, c: char, displacement: u32) -> usize {
+        let k = displacement as u64 + 1;
+        (displacement_hash(c, k) % self.slots.len() as u64) as usize
+    }
+    #[cfg(test)]
+    fn lookup(&self, c: char) -> Option<Code> {
+        let nbuckets = self.displacements.len() as u64;
+        let d        = self.displacements[(displacement_hash(c, 0) 
+                                           % nbuckets) as usize];
+
+        self.slots[self.slot(c, d)].filter(|&(s, _)| s == c)
+                                   .map(|(_, code)| code)
+    }
+}
+
+/// Generates the source for the hash-and-displace form of `codes`.
+/// 
+fn emit_displacement_table(codes: &[(char, Code)]) -> String {
+    let table = DisplacementTable::new(codes);
+    let mut src = String::new();
+
+    src.push_str("// Generated by huffman::codegen; do not edit.\n");
+    src.push_str(&format!("pub const HUFF_BUCKETS: usize = {};\n", 
+                          table.displacements.len()));
+    src.push_str(&format!("pub const HUFF_SLOT_COUNT: usize = {};\n\n", 
+                          table.slots.len()));
+    src.push_str("pub static HUFF_DISPLACEMENTS: [u32; HUFF_BUCKETS] = [\n");
+
+    for row in table.displacements.chunks(8) {
+        let row = row.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        src.push_str(&format!("    {},\n", row.join(", ")));
+    }
+    src.push_str("];\n\n");
+    src.push_str("pub static HUFF_SLOTS: [Option<(char, u64, u8)>; \
+                  HUFF_SLOT_COUNT] = [\n");
+
+    for slot in &table.slots {
+        src.push_str(&match slot {
+            Some((c, code)) => format!("    Some(({}, {}, {})),\n", 
+                                       Quoted(*c), binary_literal(*code), 
+                                       code.len),
+            None            => "    None,\n".to_string(),
+        });
+    }
+    src.push_str("];\n");
+    src.push_str(HASH_SOURCE);
+    src
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::FrequencyTable;
+    use crate::io::BitBuffer;
+
+    mod fixture {
+        use alloc::string::String;
+
+        include!("../tests/fixtures/codebook.rs");
+        include!("../tests/fixtures/encoder_fn.rs");
+        include!("../tests/fixtures/encoder_fn_nested.rs");
+        include!("../tests/fixtures/decoder_fsm.rs");
+        include!("../tests/fixtures/static_sorted.rs");
+        include!("../tests/fixtures/static_phf.rs");
+    }
+
+    /// Frequencies without ties, so the codes don't depend on hash order.
+    /// 
+    fn codebook() -> HuffmanCodebook {
+        let freqs = [('a', 45), ('b', 13), ('c', 12), ('d', 16), ('e', 9), 
+                     ('f', 5)];
+        HuffmanCodebook::from_frequencies(freqs.into_iter()
+                                               .collect::<FrequencyTable>())
+    }
+
+    #[test]
+    fn rust_source_matches_fixture() {
+        let src = generate_rust_source(&codebook(), "CODEBOOK");
+
+        assert_eq!(src, include_str!("../tests/fixtures/codebook.rs"));
+    }
+
+    #[test]
+    fn encode_with_static_table() {
+        let source   = codebook();
+        let embedded = HuffmanCodebook::from_static(fixture::CODEBOOK).unwrap();
+
+        assert_eq!(embedded.codes(), source.codes());
+
+        let encode = |codebook: &HuffmanCodebook| {
+            "deadbeef".chars()
+                      .map(|c| codebook.code_for(c).unwrap().to_code_string())
+                      .collect::<String>()
+        };
+        assert_eq!(encode(&embedded), encode(&source));
+    }
+
+    #[test]
+    fn encoder_fn_matches_fixture() {
+        let codebook = codebook();
+
+        assert_eq!(emit_encoder_fn(&codebook, "encode_char"),
+                   include_str!("../tests/fixtures/encoder_fn.rs"));
+        assert_eq!(emit_encoder_fn_with_arms(&codebook, "encode_nested", 2),
+                   include_str!("../tests/fixtures/encoder_fn_nested.rs"));
+    }
+
+    #[test]
+    fn encoder_fn_agrees_with_codebook() {
+        let codebook = codebook();
+
+        for c in ['a', 'b', 'c', 'd', 'e', 'f', 'g', '\0', 'z'] {
+            let expected = codebook.code_for(c).map(|code| (code.bits, 
+                                                            code.len));
+
+            assert_eq!(fixture::encode_char(c),   expected);
+            assert_eq!(fixture::encode_nested(c), expected);
+        }
+    }
+
+    #[test]
+    fn encoder_fn_nests_large_alphabets() {
+        let text     = ('\u{3000}'..'\u{4000}').collect::<String>();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let src      = emit_encoder_fn(&codebook, "f");
+
+        // 4096 symbols become 256 ranges of 16.
+        assert_eq!(src.matches("match c").count(), 1 + 256);
+        assert_eq!(src.matches(" => Some(").count(), 4096);
+    }
+
+    #[test]
+    fn c_header_structure() {
+        let text     = "a\"b*/c\\d\n\u{e9}\u{1f600}\u{1f600}";
+        let codebook = HuffmanCodebook::from_text(text);
+        let header   = emit_c_header(&codebook, "my-codes.h").unwrap();
+
+        assert!(header.starts_with("/* Generated"));
+        assert!(header.contains("#ifndef my_codes_h\n#define my_codes_h\n"));
+        assert!(header.contains("#define HUFF_CODE_COUNT 11\n"));
+        assert!(header.ends_with("#endif /* my_codes_h */\n"));
+
+        let rows = header.lines()
+                         .filter(|line| line.starts_with("    { 0x"))
+                         .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 11);
+
+        let mut symbols = vec![];
+
+        for row in rows {
+            let fields = row.trim_start_matches("    { ")
+                            .split([',', ' ', '}'])
+                            .filter(|f| !f.is_empty())
+                            .take(3)
+                            .collect::<Vec<_>>();
+            let hex = |f: &str| {
+                let digits = f.strip_prefix("0x").unwrap()
+                              .strip_suffix('u').unwrap();
+                assert_eq!(digits.len(), 8);
+                u32::from_str_radix(digits, 16).unwrap()
+            };
+            let symbol = char::from_u32(hex(fields[0])).unwrap();
+            let code   = codebook.code_for(symbol).unwrap();
+
+            assert_eq!(hex(fields[1]) as u64, code.bits);
+            assert_eq!(fields[2].parse::<u8>().unwrap(), code.len);
+
+            symbols.push(symbol);
+        }
+        // Sorted, with no duplicates.
+        assert!(symbols.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn c_header_limits() {
+        static LONG: &[(char, u64, u8)] = &[('a', 0, 33)];
+
+        let codebook = HuffmanCodebook::from_static(LONG).unwrap();
+
+        assert_eq!(emit_c_header(&codebook, "G"), 
+                   Err(HuffmanError::CodeTooLong('a')));
+
+        let empty  = HuffmanCodebook::from_text("");
+        let header = emit_c_header(&empty, "0g").unwrap();
+
+        assert!(header.contains("#define _0g\n"));
+        assert!(header.contains("#define HUFF_CODE_COUNT 0\n"));
+        assert!(header.contains("huff_codes[1]"));
+    }
+
+    #[test]
+    fn decoder_fsm_matches_runtime() {
+        let codebook = codebook();
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let src      = emit_decoder_fsm(&codebook, Lang::Rust).unwrap();
+
+        assert_eq!(src, include_str!("../tests/fixtures/decoder_fsm.rs"));
+
+        let text  = "deadbeefcafeface";
+        let bits  = text.chars()
+                        .map(|c| codebook.code_for(c).unwrap().to_code_string())
+                        .collect::<String>();
+        let bytes = bits.as_bytes().chunks(8).map(|byte| {
+            byte.iter().enumerate()
+                .map(|(i, b)| (b - b'0') << (7 - i))
+                .sum::<u8>()
+        }).collect::<Vec<_>>();
+
+        let packed = BitBuffer::try_new(bytes.clone(), bits.len()).unwrap();
+        let cut    = BitBuffer::try_new_lenient(bytes.clone(), bits.len() - 1)
+                               .unwrap();
+
+        assert_eq!(fsm.decode(&packed).unwrap(), text);
+        assert_eq!(fixture::huff_decode(&bytes, bits.len()).unwrap(), text);
+        assert!(fsm.decode(&cut).is_err());
+
+        for nbits in [bits.len() - 1, bytes.len() * 8 + 1] {
+            assert!(fixture::huff_decode(&bytes, nbits).is_none());
+        }
+    }
+
+    #[test]
+    fn decoder_fsm_c() {
+        let src = emit_decoder_fsm(&codebook(), Lang::C).unwrap();
+
+        assert!(src.contains("static const uint32_t huff_fsm[5][2] = {\n"));
+        assert!(src.contains("    { 0x80000061u, 0x00000001u },\n"));
+        assert!(src.contains("static long huff_decode("));
+    }
+
+    #[test]
+    fn escapes_symbols() {
+        let src = generate_rust_source(&HuffmanCodebook::from_text("'\n\\\"é"),
+                                       "T");
+        for literal in [r"'\''", r"'\n'", r"'\\'", r#"'"'"#, "'é'"] {
+            assert!(src.contains(literal), "{} missing from {}", literal, src);
+        }
+    }
+
+    #[test]
+    fn from_static_rejects_bad_tables() {
+        use crate::HuffmanError::*;
+
+        static DUPLICATE  : &[(char, u64, u8)] = &[('a', 0, 1), ('a', 1, 1)];
+        static OVERLONG   : &[(char, u64, u8)] = &[('a', 0b10, 1)];
+        static PREFIXED   : &[(char, u64, u8)] = &[('a', 0b1, 1), 
+                                                   ('b', 0b10, 2),
+                                                   ('c', 0b0, 1)];
+
+        let err = |t| HuffmanCodebook::from_static(t).unwrap_err();
+
+        assert_eq!(err(DUPLICATE), DuplicateSymbol('a'));
+        assert_eq!(err(OVERLONG),  MalformedCode('a'));
+        assert_eq!(err(PREFIXED),  NotPrefixFree('a', 'b'));
+    }
+
+    #[test]
+    fn static_arrays_match_fixtures() {
+        let codebook = codebook();
+
+        assert_eq!(emit_static_array(&codebook, StaticStyle::SortedArray),
+                   include_str!("../tests/fixtures/static_sorted.rs"));
+        assert_eq!(emit_static_array(&codebook, StaticStyle::PhfLikeHash),
+                   include_str!("../tests/fixtures/static_phf.rs"));
+    }
+
+    #[test]
+    fn static_arrays_agree_with_codebook() {
+        let codebook = codebook();
+
+        for c in ['a', 'b', 'c', 'd', 'e', 'f', 'g', '\0', 'z', '\u{1f600}'] {
+            let expected = codebook.code_for(c).map(|code| (code.bits, 
+                                                            code.len));
+
+            assert_eq!(lookup_sorted(fixture::HUFF_CODES, c), expected);
+            assert_eq!(fixture::huff_lookup(c), expected);
+        }
+        assert_eq!(fixture::huff_hash('x', 7), displacement_hash('x', 7));
+    }
+
+    #[test]
+    fn displacement_tables_for_large_alphabets() {
+        let text     = ('\u{3000}'..'\u{4000}').collect::<String>();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let table    = DisplacementTable::new(&sorted_codes(&codebook));
+
+        assert!(table.slots.len() < 4096 * 2);
+
+        for (&c, &code) in codebook.codes() {
+            assert_eq!(table.lookup(c), Some(code));
+        }
+        assert_eq!(table.lookup('a'), None);
+
+        let empty = DisplacementTable::new(&[]);
+
+        assert_eq!(empty.lookup('a'), None);
+    }
+}
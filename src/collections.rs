@@ -0,0 +1,56 @@
+//! The maps and sets the crate keys by symbol. With the `std` feature they're
+//! the standard hashed ones; without it, there's no hasher to seed, and
+//! they're B-trees instead, so their keys are ordered rather than hashed.
+//! Nothing depends on the order they iterate in either way.
+//! 
+
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::hash_map::Entry;
+
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
+#[cfg(not(feature = "std"))]
+pub(crate) use alloc::collections::btree_map::Entry;
+
+
+/// What symbols of other types than `char` must be to key the maps of codes
+/// generated for them: `Eq` and `Hash` with the `std` feature, or `Ord`
+/// without it. Every such type is one already.
+/// 
+#[cfg(feature = "std")]
+pub trait MapKey: Eq + core::hash::Hash {}
+
+#[cfg(feature = "std")]
+impl<T: Eq + core::hash::Hash> MapKey for T {}
+
+/// What symbols of other types than `char` must be to key the maps of codes
+/// generated for them: `Eq` and `Hash` with the `std` feature, or `Ord`
+/// without it. Every such type is one already.
+/// 
+#[cfg(not(feature = "std"))]
+pub trait MapKey: Ord {}
+
+#[cfg(not(feature = "std"))]
+impl<T: Ord> MapKey for T {}
+
+/// An empty map with room for `n` entries, where it has a capacity at all.
+/// 
+pub(crate) fn map_with_capacity<K: MapKey, V>(n: usize) -> HashMap<K, V> {
+    #[cfg(feature = "std")]
+    return HashMap::with_capacity(n);
+
+    #[cfg(not(feature = "std"))]
+    return { let _ = n; HashMap::new() };
+}
+
+/// An empty set with room for `n` entries, where it has a capacity at all.
+/// 
+pub(crate) fn set_with_capacity<T: MapKey>(n: usize) -> HashSet<T> {
+    #[cfg(feature = "std")]
+    return HashSet::with_capacity(n);
+
+    #[cfg(not(feature = "std"))]
+    return { let _ = n; HashSet::new() };
+}
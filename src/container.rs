@@ -0,0 +1,32 @@
+//! Compressed streams: text, or ids, compressed into a self-describing
+//! stream and back again, split into blocks, frames, or lines, or several
+//! streams multiplexed into one.
+//! 
+
+#[cfg(feature = "std")]
+pub use crate::blocks::{compress_blocks, decompress_blocks};
+#[cfg(feature = "std")]
+pub use crate::frame::{FrameReader, FrameWriter};
+#[cfg(feature = "std")]
+pub use crate::lines::compress_lines;
+#[cfg(all(feature = "std", feature = "test-support"))]
+pub use crate::mutate::{arbitrary_container, mutate_container, Mutation};
+#[cfg(feature = "std")]
+pub use crate::mux::{MuxReader, MuxWriter};
+pub use crate::names::{DecodedIds, SymbolMap};
+#[cfg(feature = "std")]
+pub use crate::pipe::{pipe_compress, pipe_compress_strict, pipe_decompress};
+#[cfg(feature = "std")]
+pub use crate::pipe::pipe_compress_redacted;
+#[cfg(feature = "std")]
+pub use crate::pipe::{pipe_decompress_limited, CompressionStats, DecodeLimits};
+#[cfg(feature = "std")]
+pub use crate::pipe::{compress_auto, decompress_auto, TableSource};
+#[cfg(feature = "std")]
+pub use crate::pipe::compress_chars;
+#[cfg(feature = "std")]
+pub use crate::pipe::{compress_ids, decompress_ids};
+#[cfg(feature = "std")]
+pub use crate::pipe::{compress_ids_named, decompress_ids_named};
+#[cfg(feature = "std")]
+pub use crate::selfcheck::{self_check, SelfCheckFailure};
@@ -0,0 +1,295 @@
+//! Reading and writing frequency tables as CSV, one `symbol,count` row per
+//! character.
+//! 
+//! Fields may be quoted, which is needed for symbols like `,` and `"`. Inside
+//! quotes, `""` is a quote and a backslash starts an escape: `\n`, `\r`, `\t`,
+//! `\\`, `\"`, or `\u{200d}` for any character by its code point in hex.
+//! Symbols are written as `escape_symbol()` shows them in CSV. A header row is
+//! optional, and blank lines are skipped.
+//! 
+
+use std::io::{self, Read, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::HuffmanError;
+use crate::codebook::{escape_symbol, EscapeContext, FrequencyTable};
+
+
+/// A field of a CSV row.
+/// 
+struct Field {
+    text   : String,
+    quoted : bool,
+}
+
+/// A row of CSV fields, and the line it starts on.
+/// 
+struct Row {
+    line   : usize,
+    fields : Vec<Field>,
+}
+
+/// Splits CSV text into rows of fields.
+/// 
+struct RowParser<'a> {
+    chars : Peekable<Chars<'a>>,
+    line  : usize,
+}
+
+impl<'a> RowParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { chars: text.chars().peekable(), line: 1 }
+    }
+    fn error(&self, reason: &'static str) -> HuffmanError {
+        HuffmanError::InvalidCsv { line: self.line, reason }
+    }
+    /// Parses the next row, or gives `None` at the end of the text.
+    /// 
+    fn next_row(&mut self) -> Result<Option<Row>, HuffmanError> {
+        if self.chars.peek().is_none() {
+            return Ok(None);
+        }
+        let line       = self.line;
+        let mut fields = vec![];
+
+        loop {
+            fields.push(match self.chars.peek() {
+                Some('"') => self.quoted_field()?,
+                _         => self.plain_field(),
+            });
+            match self.chars.next() {
+                Some(',')  => {},
+                Some('\n') => {
+                    self.line += 1;
+                    break;
+                },
+                _          => break,
+            }
+        }
+        Ok(Some(Row { line, fields }))
+    }
+    /// Reads a field up to the next comma or line break, which is left to be
+    /// read.
+    /// 
+    fn plain_field(&mut self) -> Field {
+        let mut text = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            if c == ',' || c == '\n' {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        if text.ends_with('\r') {
+            text.pop();
+        }
+        Field { text, quoted: false }
+    }
+    /// Reads the `{200d}` of a `\u{200d}` escape, giving the character.
+    /// 
+    fn code_point(&mut self) -> Result<char, HuffmanError> {
+        let unknown = self.error("unknown escape sequence");
+        let mut hex = String::new();
+
+        if self.chars.next() != Some('{') {
+            return Err(unknown);
+        }
+        loop {
+            match self.chars.next() {
+                Some('}') => break,
+                Some(c) if c.is_ascii_hexdigit() && hex.len() < 6 => {
+                    hex.push(c);
+                },
+                _ => return Err(unknown),
+            }
+        }
+        u32::from_str_radix(&hex, 16).ok()
+                                     .and_then(char::from_u32)
+                                     .ok_or(unknown)
+    }
+    /// Reads a field from its opening quote to its closing quote, leaving the
+    /// comma or line break after it to be read.
+    /// 
+    fn quoted_field(&mut self) -> Result<Field, HuffmanError> {
+        let start    = self.line;
+        let mut text = String::new();
+
+        self.chars.next();
+
+        loop {
+            let Some(c) = self.chars.next() else {
+                return Err(HuffmanError::InvalidCsv {
+                    line   : start,
+                    reason : "a quoted field is never closed",
+                });
+            };
+            match c {
+                '"' if self.chars.peek() == Some(&'"') => {
+                    self.chars.next();
+                    text.push('"');
+                },
+                '"' => break,
+                '\\' => {
+                    text.push(match self.chars.next() {
+                        Some('n')  => '\n',
+                        Some('r')  => '\r',
+                        Some('t')  => '\t',
+                        Some('\\') => '\\',
+                        Some('"')  => '"',
+                        Some('u')  => self.code_point()?,
+                        _ => return Err(self.error("unknown escape sequence")),
+                    });
+                },
+                '\n' => {
+                    self.line += 1;
+                    text.push(c);
+                },
+                _ => text.push(c),
+            }
+        }
+        while let Some(' ' | '\t' | '\r') = self.chars.peek() {
+            self.chars.next();
+        }
+        match self.chars.peek() {
+            None | Some(',' | '\n') => Ok(Field { text, quoted: true }),
+            _ => Err(self.error("unexpected text after a quoted field")),
+        }
+    }
+}
+
+/// Writes `c` as a CSV field, quoting and escaping it if it needs to be.
+/// 
+fn write_symbol(w: &mut impl Write, c: char) -> io::Result<()> {
+    write!(w, "{}", escape_symbol(c, EscapeContext::Csv))
+}
+
+
+impl FrequencyTable {
+    /// Reads a table from CSV with a `symbol,count` row per character. Counts
+    /// for repeated symbols are summed. The first row is taken as a header if
+    /// its count has no digits. See the module docs for quoting.
+    /// 
+    pub fn from_csv_reader(mut r: impl Read) 
+
+        -> Result<FrequencyTable, HuffmanError> 
+    {
+        let mut text = String::new();
+
+        r.read_to_string(&mut text).map_err(|e| {
+            match e.kind() {
+                io::ErrorKind::InvalidData => HuffmanError::InvalidUtf8,
+                _                          => HuffmanError::from(e),
+            }
+        })?;
+        let mut parser = RowParser::new(&text);
+        let mut table  = FrequencyTable::default();
+        let mut first  = true;
+
+        while let Some(Row { line, fields }) = parser.next_row()? {
+            let error = |reason| HuffmanError::InvalidCsv { line, reason };
+
+            if let [Field { text, quoted: false }] = &fields[..]
+                && text.trim().is_empty() {
+                continue;
+            }
+            let [symbol, count] = &fields[..] else {
+                return Err(error("expected two fields, symbol and count"));
+            };
+            let Ok(count) = count.text.trim().parse() else {
+                let digits = count.text.contains(|c: char| c.is_ascii_digit());
+
+                if first && !digits {
+                    first = false;
+                    continue;
+                }
+                return Err(error("the count isn't a non-negative integer"));
+            };
+            let mut chars = symbol.text.chars();
+
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(error("the symbol isn't a single character"));
+            };
+            table.add(c, count);
+            first = false;
+        }
+        Ok(table)
+    }
+    /// Writes the table as CSV that `from_csv_reader()` reads back: a
+    /// `symbol,count` header, then a row per character, most frequent first.
+    /// 
+    pub fn to_csv_writer(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "symbol,count")?;
+
+        for (c, count) in self.ranked() {
+            write_symbol(&mut w, c)?;
+            writeln!(w, ",{}", count)?;
+        }
+        w.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<FrequencyTable, HuffmanError> {
+        FrequencyTable::from_csv_reader(text.as_bytes())
+    }
+
+    #[test]
+    fn fixture() {
+        let file  = std::fs::File::open("tests/fixtures/frequencies.csv");
+        let table = FrequencyTable::from_csv_reader(file.unwrap()).unwrap();
+        let expected = [('a', 12), ('\n', 4), (',', 3), ('"', 2), ('\\', 1),
+                        ('é', 7), ('🦀', 5), (' ', 9), ('\t', 1), ('b', 6)];
+
+        assert_eq!(table, expected.into_iter().collect());
+    }
+
+    #[test]
+    fn round_trip() {
+        let text  = "\"Quoted\", said\tthe \\ man,\r\nin 🦀 Zürich.\n\
+                     \u{0}\u{a0}\u{200d}\u{ffff}\u{10ffff}";
+        let table = FrequencyTable::from_text(text);
+        let mut csv = vec![];
+
+        table.to_csv_writer(&mut csv).unwrap();
+
+        assert!(csv.starts_with(b"symbol,count\n"));
+        assert_eq!(FrequencyTable::from_csv_reader(&csv[..]).unwrap(), table);
+        assert_eq!(parse("").unwrap(), FrequencyTable::default());
+        assert_eq!(parse("x,1\r\ny,2\r\n").unwrap(),
+                   [('x', 1), ('y', 2)].into_iter().collect());
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let error = |line, reason| Err(HuffmanError::InvalidCsv { line,
+                                                                  reason });
+        assert_eq!(parse("a,1\n\nbc,2\n"),
+                   error(3, "the symbol isn't a single character"));
+        assert_eq!(parse("symbol,count\na,1\nb,many\n"),
+                   error(3, "the count isn't a non-negative integer"));
+        assert_eq!(parse("a,1\nb,2,3\n"),
+                   error(2, "expected two fields, symbol and count"));
+        assert_eq!(parse("a,1\n\"b,2\n"),
+                   error(2, "a quoted field is never closed"));
+        assert_eq!(parse("\"\n\",1\n\"\\q\",2\n"),
+                   error(3, "unknown escape sequence"));
+        assert_eq!(parse("\"\\u{d800}\",1\n"),
+                   error(1, "unknown escape sequence"));
+        assert_eq!(parse("\"\\u{1234567}\",1\n"),
+                   error(1, "unknown escape sequence"));
+        assert_eq!(parse("\"\\u{e9}\",1\n\"\\u200d\",1\n"),
+                   error(2, "unknown escape sequence"));
+        assert_eq!(parse("\"a\"b,1\n"),
+                   error(1, "unexpected text after a quoted field"));
+        assert_eq!(parse("a,-1\n"),
+                   error(1, "the count isn't a non-negative integer"));
+        assert_eq!(FrequencyTable::from_csv_reader(&b"a,1\n\xff,2\n"[..]),
+                   Err(HuffmanError::InvalidUtf8));
+    }
+}
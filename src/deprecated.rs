@@ -0,0 +1,825 @@
+//! The crate's items at the paths they had in its root, before they were
+//! grouped into modules, kept for a release so code using them has time to
+//! move. Each is an alias or a wrapper of the item at its new path, deprecated
+//! with a note naming that path.
+//! 
+//! Traits can't be aliased, nor tuple structs' constructors, so those few are
+//! re-exported as they are, without a deprecation warning.
+//! 
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::borrow::Cow;
+use core::cmp::Ordering;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Read, Write};
+
+use crate::HuffmanError;
+use crate::{canonical, codebook, container, heapq, io, tree};
+use crate::collections::HashMap;
+
+pub use crate::codebook::{Codec, SymbolDisplay};
+pub use crate::tree::Weight;
+
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::validate_accelerations`")]
+pub fn validate_accelerations(data: &str) 
+
+    -> Result<codebook::AccelReport, codebook::AccelMismatch> 
+{
+    codebook::validate_accelerations(data)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::AccelMismatch`")]
+pub type AccelMismatch = codebook::AccelMismatch;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::AccelReport`")]
+pub type AccelReport = codebook::AccelReport;
+
+#[deprecated(note = "use `huffman::codebook::baseline_report`")]
+pub fn baseline_report(data: &str) -> codebook::BaselineReport {
+    codebook::baseline_report(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::BaselineReport`")]
+pub type BaselineReport = codebook::BaselineReport;
+
+#[deprecated(note = "use `huffman::codebook::code_stats`")]
+pub fn code_stats(data: &str) -> codebook::CodeStats {
+    codebook::code_stats(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::CodeStats`")]
+pub type CodeStats = codebook::CodeStats;
+
+#[deprecated(note = "use `huffman::codebook::break_even_length`")]
+pub fn break_even_length(freqs      : &codebook::FrequencyTable,
+                         table_kind : codebook::TableKind) 
+
+    -> codebook::BreakEven 
+{
+    codebook::break_even_length(freqs, table_kind)
+}
+
+#[deprecated(note = "use `huffman::codebook::BreakEven`")]
+pub type BreakEven = codebook::BreakEven;
+
+#[deprecated(note = "use `huffman::codebook::TableKind`")]
+pub type TableKind = codebook::TableKind;
+
+#[deprecated(note = "use `huffman::codebook::distribution_divergence`")]
+pub fn distribution_divergence(trained  : &codebook::FrequencyTable,
+                               observed : &codebook::FrequencyTable) 
+
+    -> codebook::DriftReport 
+{
+    codebook::distribution_divergence(trained, observed)
+}
+
+#[deprecated(note = "use `huffman::codebook::DriftReport`")]
+pub type DriftReport = codebook::DriftReport;
+
+#[deprecated(note = "use `huffman::codebook::estimate_memory`")]
+pub fn estimate_memory(distinct_symbols : usize,
+                       options          : &codebook::TreeOptions) 
+
+    -> codebook::MemoryEstimate 
+{
+    codebook::estimate_memory(distinct_symbols, options)
+}
+
+#[deprecated(note = "use `huffman::codebook::MemoryEstimate`")]
+pub type MemoryEstimate = codebook::MemoryEstimate;
+
+#[deprecated(note = "use `huffman::codebook::TreeOptions`")]
+pub type TreeOptions = codebook::TreeOptions;
+
+#[deprecated(note = "use `huffman::codebook::WhatIf`")]
+pub type WhatIf = codebook::WhatIf;
+
+#[deprecated(note = "use `huffman::codebook::WhatIfReport`")]
+pub type WhatIfReport = codebook::WhatIfReport;
+
+#[deprecated(note = "use `huffman::codebook::ApproxCounter`")]
+pub type ApproxCounter = codebook::ApproxCounter;
+
+#[deprecated(note = "use `huffman::codebook::compare_models`")]
+pub fn compare_models(codecs: &[&dyn codebook::Codec], data: &str) 
+
+    -> Result<Vec<codebook::ModelCost>, HuffmanError> 
+{
+    codebook::compare_models(codecs, data)
+}
+
+#[deprecated(note = "use `huffman::codebook::ModelCost`")]
+pub type ModelCost = codebook::ModelCost;
+
+#[deprecated(note = "use `huffman::codebook::escape_symbol`")]
+pub fn escape_symbol(c: char, context: codebook::EscapeContext) 
+
+    -> Cow<'static, str> 
+{
+    codebook::escape_symbol(c, context)
+}
+
+#[deprecated(note = "use `huffman::codebook::EscapeContext`")]
+pub type EscapeContext = codebook::EscapeContext;
+
+#[deprecated(note = "use `huffman::codebook::IdCodebook`")]
+pub type IdCodebook = codebook::IdCodebook;
+
+#[deprecated(note = "use `huffman::codebook::InternedCodebook`")]
+pub type InternedCodebook = codebook::InternedCodebook;
+
+#[deprecated(note = "use `huffman::codebook::SymbolInterner`")]
+pub type SymbolInterner = codebook::SymbolInterner;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::measure`")]
+pub fn measure(data: &str, config: codebook::MeasureConfig) 
+
+    -> codebook::MeasureReport 
+{
+    codebook::measure(data, config)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::MeasureConfig`")]
+pub type MeasureConfig = codebook::MeasureConfig;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::MeasureReport`")]
+pub type MeasureReport = codebook::MeasureReport;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::codebook::PhaseTiming`")]
+pub type PhaseTiming = codebook::PhaseTiming;
+
+#[cfg(feature = "parallel")]
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes_par`")]
+pub fn generate_huffman_codes_par(data: &str) -> HashMap<char, String> {
+    codebook::generate_huffman_codes_par(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::codes_from_probabilities`")]
+pub fn codes_from_probabilities(p: &[f64], tolerance: f64) 
+
+    -> Result<Vec<codebook::Code>, HuffmanError> 
+{
+    codebook::codes_from_probabilities(p, tolerance)
+}
+
+#[deprecated(note = "use `huffman::codebook::ReservedId`")]
+pub type ReservedId = codebook::ReservedId;
+
+#[deprecated(note = "use `huffman::codebook::Token`")]
+pub type Token = codebook::Token;
+
+#[deprecated(note = "use `huffman::codebook::TokenCodebook`")]
+pub type TokenCodebook = codebook::TokenCodebook;
+
+#[deprecated(note = "use `huffman::codebook::AlphabetSummary`")]
+pub type AlphabetSummary = codebook::AlphabetSummary;
+
+#[deprecated(note = "use `huffman::codebook::CodeLookup`")]
+pub type CodeLookup = codebook::CodeLookup;
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes_utf16`")]
+pub fn generate_huffman_codes_utf16(units: &[u16]) 
+
+    -> HashMap<u16, codebook::Code> 
+{
+    codebook::generate_huffman_codes_utf16(units)
+}
+
+#[deprecated(note = "use `huffman::codebook::FrequencyTable`")]
+pub type FrequencyTable = codebook::FrequencyTable;
+
+#[deprecated(note = "use `huffman::codebook::TailStats`")]
+pub type TailStats = codebook::TailStats;
+
+#[deprecated(note = "use `huffman::codebook::Code`")]
+pub type Code = codebook::Code;
+
+#[deprecated(note = "use `huffman::codebook::HuffmanCodebook`")]
+pub type HuffmanCodebook = codebook::HuffmanCodebook;
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes`")]
+pub fn generate_huffman_codes(data: &str) -> HashMap<char, String> {
+    codebook::generate_huffman_codes(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::try_generate_huffman_codes`")]
+pub fn try_generate_huffman_codes(data: &str) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    codebook::try_generate_huffman_codes(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_code_bits`")]
+pub fn generate_huffman_code_bits(data: &str) 
+
+    -> Result<HashMap<char, (u64, u8)>, HuffmanError> 
+{
+    codebook::generate_huffman_code_bits(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::count_frequencies`")]
+pub fn count_frequencies(data: &str) -> codebook::FrequencyTable {
+    codebook::count_frequencies(data)
+}
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes_iter`")]
+pub fn generate_huffman_codes_iter<T, I>(symbols: I) 
+
+    -> HashMap<T, String> 
+where
+    T: codebook::MapKey + Clone,
+    I: IntoIterator<Item = T>,
+{
+    codebook::generate_huffman_codes_iter(symbols)
+}
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes_bytes`")]
+pub fn generate_huffman_codes_bytes(data: &[u8]) -> HashMap<u8, String> {
+    codebook::generate_huffman_codes_bytes(data)
+}
+
+#[deprecated(
+    note = "use `huffman::codebook::generate_huffman_codes_from_freqs`"
+)]
+pub fn generate_huffman_codes_from_freqs(freqs: &HashMap<char, usize>) 
+
+    -> HashMap<char, String> 
+{
+    codebook::generate_huffman_codes_from_freqs(freqs)
+}
+
+#[deprecated(note = "use `huffman::codebook::generate_huffman_codes_weighted`")]
+pub fn generate_huffman_codes_weighted<T, W, I>(weights: I) 
+
+    -> HashMap<T, String> 
+where
+    T: codebook::MapKey + Clone,
+    W: tree::Weight,
+    I: IntoIterator<Item = (T, W)>,
+{
+    codebook::generate_huffman_codes_weighted(weights)
+}
+
+#[deprecated(note = "use `huffman::codebook::predicted_bits`")]
+pub fn predicted_bits(codes : &HashMap<char, String>,
+                      freqs : &codebook::FrequencyTable) 
+
+    -> Option<usize> 
+{
+    codebook::predicted_bits(codes, freqs)
+}
+
+#[deprecated(note = "use `huffman::tree::BuildStats`")]
+pub type BuildStats = tree::BuildStats;
+
+#[deprecated(note = "use `huffman::tree::TreeBuild`")]
+pub type TreeBuild = tree::TreeBuild;
+
+#[deprecated(note = "use `huffman::tree::build_huffman_tree_transcripted`")]
+pub fn build_huffman_tree_transcripted(freqs: &codebook::FrequencyTable) 
+
+    -> Result<(tree::HuffmanTree, Vec<(u32, u32)>), HuffmanError> 
+{
+    tree::build_huffman_tree_transcripted(freqs)
+}
+
+#[deprecated(note = "use `huffman::tree::replay_transcript`")]
+pub fn replay_transcript(freqs      : &codebook::FrequencyTable,
+                         transcript : &[(u32, u32)]) 
+
+    -> Result<tree::HuffmanTree, tree::ReplayError> 
+{
+    tree::replay_transcript(freqs, transcript)
+}
+
+#[deprecated(note = "use `huffman::tree::build_tree`")]
+pub fn build_tree(freqs: &codebook::FrequencyTable) 
+
+    -> Result<tree::HuffmanTree, HuffmanError> 
+{
+    tree::build_tree(freqs)
+}
+
+#[deprecated(note = "use `huffman::tree::HuffmanCodec`")]
+pub type HuffmanCodec = tree::HuffmanCodec;
+
+#[deprecated(note = "use `huffman::tree::HuffmanTree`")]
+pub type HuffmanTree = tree::HuffmanTree;
+
+#[deprecated(note = "use `huffman::tree::ReplayError`")]
+pub type ReplayError = tree::ReplayError;
+
+#[deprecated(note = "use `huffman::tree::tree_to_dot`")]
+pub fn tree_to_dot(data: &str) -> String {
+    tree::tree_to_dot(data)
+}
+
+#[deprecated(note = "use `huffman::tree::SplitCodec`")]
+pub type SplitCodec = tree::SplitCodec;
+
+#[deprecated(note = "use `huffman::tree::CodeTrie`")]
+pub type CodeTrie = tree::CodeTrie;
+
+#[deprecated(note = "use `huffman::tree::Conflict`")]
+pub type Conflict = tree::Conflict;
+
+#[deprecated(note = "use `huffman::tree::Walk`")]
+pub type Walk = tree::Walk;
+
+#[deprecated(note = "use `huffman::canonical::code_lengths`")]
+pub fn code_lengths(data: &str) -> HashMap<char, u8> {
+    canonical::code_lengths(data)
+}
+
+#[deprecated(note = "use `huffman::canonical::generate_canonical_codes`")]
+pub fn generate_canonical_codes(data: &str) -> HashMap<char, String> {
+    canonical::generate_canonical_codes(data)
+}
+
+#[deprecated(note = "use `huffman::canonical::CanonicalDecoder`")]
+pub type CanonicalDecoder<S> = canonical::CanonicalDecoder<S>;
+
+#[deprecated(note = "use `huffman::canonical::generate_huffman_codes_limited`")]
+pub fn generate_huffman_codes_limited(data: &str, max_len: u8) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    canonical::generate_huffman_codes_limited(data, max_len)
+}
+
+#[deprecated(note = "use `huffman::canonical::LengthLimits`")]
+pub type LengthLimits = canonical::LengthLimits;
+
+#[deprecated(note = "use `huffman::canonical::deserialize_codes`")]
+pub fn deserialize_codes(bytes: &[u8]) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    canonical::deserialize_codes(bytes)
+}
+
+#[deprecated(note = "use `huffman::canonical::serialize_codes`")]
+pub fn serialize_codes(codes: &HashMap<char, String>) -> Vec<u8> {
+    canonical::serialize_codes(codes)
+}
+
+#[deprecated(note = "use `huffman::canonical::validate_codes`")]
+pub fn validate_codes(codes: &HashMap<char, String>) 
+
+    -> Result<(), canonical::CodeTableError> 
+{
+    canonical::validate_codes(codes)
+}
+
+#[deprecated(note = "use `huffman::canonical::validate_complete_codes`")]
+pub fn validate_complete_codes(codes: &HashMap<char, String>) 
+
+    -> Result<(), canonical::CodeTableError> 
+{
+    canonical::validate_complete_codes(codes)
+}
+
+#[deprecated(note = "use `huffman::canonical::CodeTableError`")]
+pub type CodeTableError = canonical::CodeTableError;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::DecodeSession`")]
+pub type DecodeSession<'r, 'a> = io::DecodeSession<'r, 'a>;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::RandomAccessReader`")]
+pub type RandomAccessReader<'a> = io::RandomAccessReader<'a>;
+
+#[deprecated(note = "use `huffman::io::AdaptiveDecoder`")]
+pub type AdaptiveDecoder = io::AdaptiveDecoder;
+
+#[deprecated(note = "use `huffman::io::AdaptiveEncoder`")]
+pub type AdaptiveEncoder = io::AdaptiveEncoder;
+
+#[deprecated(note = "use `huffman::io::BitBuffer`")]
+pub type BitBuffer = io::BitBuffer;
+
+#[deprecated(note = "use `huffman::io::BitReader`")]
+pub type BitReader<'a> = io::BitReader<'a>;
+
+#[deprecated(note = "use `huffman::io::BitWriter`")]
+pub type BitWriter = io::BitWriter;
+
+#[deprecated(note = "use `huffman::io::decode_annotated`")]
+pub fn decode_annotated(bits     : &io::BitBuffer,
+                        codebook : &codebook::HuffmanCodebook) 
+
+    -> Result<Vec<(char, Range<u64>)>, HuffmanError> 
+{
+    io::decode_annotated(bits, codebook)
+}
+
+#[deprecated(note = "use `huffman::io::encode`")]
+pub fn encode(data: &str) -> io::BitBuffer {
+    io::encode(data)
+}
+
+#[deprecated(note = "use `huffman::io::encode_annotated`")]
+pub fn encode_annotated(text: &str, codebook: &codebook::HuffmanCodebook) 
+
+    -> Result<(io::BitBuffer, Vec<io::AnnotatedStep>), HuffmanError> 
+{
+    io::encode_annotated(text, codebook)
+}
+
+#[deprecated(note = "use `huffman::io::AnnotatedStep`")]
+pub type AnnotatedStep = io::AnnotatedStep;
+
+#[deprecated(note = "use `huffman::io::EncodeIter`")]
+pub type EncodeIter<'a> = io::EncodeIter<'a>;
+
+#[deprecated(note = "use `huffman::io::DecodeIter`")]
+pub type DecodeIter<'a> = io::DecodeIter<'a>;
+
+#[deprecated(note = "use `huffman::io::DecodeSummary`")]
+pub type DecodeSummary = io::DecodeSummary;
+
+#[deprecated(note = "use `huffman::io::DecoderFsm`")]
+pub type DecoderFsm = io::DecoderFsm;
+
+#[deprecated(note = "use `huffman::io::Transition`")]
+pub type Transition = io::Transition;
+
+#[deprecated(note = "use `huffman::io::IncrementalCompressor`")]
+pub type IncrementalCompressor = io::IncrementalCompressor;
+
+#[deprecated(note = "use `huffman::io::Step`")]
+pub type Step = io::Step;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::LineReader`")]
+pub type LineReader<R> = io::LineReader<R>;
+
+#[deprecated(note = "use `huffman::io::DecodeTable`")]
+pub type DecodeTable = io::DecodeTable;
+
+#[cfg(feature = "parallel")]
+#[deprecated(note = "use `huffman::io::encode_par`")]
+pub fn encode_par(data: &str) -> io::BitBuffer {
+    io::encode_par(data)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::PipelineCompressor`")]
+pub type PipelineCompressor = io::PipelineCompressor;
+
+#[deprecated(note = "use `huffman::io::PushDecoder`")]
+pub type PushDecoder = io::PushDecoder;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::HuffmanReader`")]
+pub type HuffmanReader<'a, R> = io::HuffmanReader<'a, R>;
+
+#[deprecated(note = "use `huffman::io::decode_utf16`")]
+pub fn decode_utf16(bits: &io::BitBuffer, codes: &HashMap<u16, codebook::Code>) 
+
+    -> Result<Vec<u16>, HuffmanError> 
+{
+    io::decode_utf16(bits, codes)
+}
+
+#[deprecated(note = "use `huffman::io::encode_utf16`")]
+pub fn encode_utf16(units: &[u16], codes: &HashMap<u16, codebook::Code>) 
+
+    -> Result<io::BitBuffer, HuffmanError> 
+{
+    io::encode_utf16(units, codes)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::io::HuffmanWriter`")]
+pub type HuffmanWriter<W> = io::HuffmanWriter<W>;
+
+#[deprecated(note = "use `huffman::io::CancelToken`")]
+pub type CancelToken = io::CancelToken;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::compress_blocks`")]
+pub fn compress_blocks(r: impl Read, w: impl Write, block_size: usize) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::compress_blocks(r, w, block_size)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::decompress_blocks`")]
+pub fn decompress_blocks(r: impl Read, w: impl Write) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::decompress_blocks(r, w)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::FrameReader`")]
+pub type FrameReader<R> = container::FrameReader<R>;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::FrameWriter`")]
+pub type FrameWriter<W> = container::FrameWriter<W>;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::compress_lines`")]
+pub fn compress_lines(r: impl BufRead, w: impl Write) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::compress_lines(r, w)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::MuxReader`")]
+pub type MuxReader<R> = container::MuxReader<R>;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::MuxWriter`")]
+pub type MuxWriter<W> = container::MuxWriter<W>;
+
+#[deprecated(note = "use `huffman::container::DecodedIds`")]
+pub type DecodedIds = container::DecodedIds;
+
+#[deprecated(note = "use `huffman::container::SymbolMap`")]
+pub type SymbolMap = container::SymbolMap;
+
+#[cfg(all(feature = "std", feature = "test-support"))]
+#[deprecated(note = "use `huffman::container::arbitrary_container`")]
+pub fn arbitrary_container(seed: u64) -> Vec<u8> {
+    container::arbitrary_container(seed)
+}
+
+#[cfg(all(feature = "std", feature = "test-support"))]
+#[deprecated(note = "use `huffman::container::mutate_container`")]
+pub fn mutate_container(bytes: &[u8], strategy: container::Mutation, seed: u64) 
+
+    -> Vec<u8> 
+{
+    container::mutate_container(bytes, strategy, seed)
+}
+
+#[cfg(all(feature = "std", feature = "test-support"))]
+#[deprecated(note = "use `huffman::container::Mutation`")]
+pub type Mutation = container::Mutation;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::pipe_compress`")]
+pub fn pipe_compress(r      : impl Read,
+                     w      : impl Write,
+                     table  : container::TableSource,
+                     cancel : Option<&io::CancelToken>) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::pipe_compress(r, w, table, cancel)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::pipe_compress_strict`")]
+pub fn pipe_compress_strict(r      : impl Read,
+                            w      : impl Write,
+                            table  : container::TableSource,
+                            cancel : Option<&io::CancelToken>) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::pipe_compress_strict(r, w, table, cancel)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::pipe_decompress`")]
+pub fn pipe_decompress(r        : impl Read,
+                       w        : impl Write,
+                       codebook : Option<&codebook::HuffmanCodebook>,
+                       cancel   : Option<&io::CancelToken>) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::pipe_decompress(r, w, codebook, cancel)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::pipe_compress_redacted`")]
+pub fn pipe_compress_redacted(r      : impl Read,
+                              w      : impl Write,
+                              table  : container::TableSource,
+                              redact : &HashMap<char, char>,
+                              cancel : Option<&io::CancelToken>) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::pipe_compress_redacted(r, w, table, redact, cancel)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::pipe_decompress_limited`")]
+pub fn pipe_decompress_limited(r        : impl Read,
+                               w        : impl Write,
+                               codebook : Option<&codebook::HuffmanCodebook>,
+                               limits   : container::DecodeLimits,
+                               cancel   : Option<&io::CancelToken>) 
+
+    -> Result<container::CompressionStats, HuffmanError> 
+{
+    container::pipe_decompress_limited(r, w, codebook, limits, cancel)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::CompressionStats`")]
+pub type CompressionStats = container::CompressionStats;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::DecodeLimits`")]
+pub type DecodeLimits = container::DecodeLimits;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::compress_auto`")]
+pub fn compress_auto(data: &str) -> Vec<u8> {
+    container::compress_auto(data)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::decompress_auto`")]
+pub fn decompress_auto(stream: &[u8]) -> Result<String, HuffmanError> {
+    container::decompress_auto(stream)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::TableSource`")]
+pub type TableSource<'a> = container::TableSource<'a>;
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::compress_ids`")]
+pub fn compress_ids(ids: &[u32]) -> Result<Vec<u8>, HuffmanError> {
+    container::compress_ids(ids)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::decompress_ids`")]
+pub fn decompress_ids(stream: &[u8]) -> Result<Vec<u32>, HuffmanError> {
+    container::decompress_ids(stream)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::compress_ids_named`")]
+pub fn compress_ids_named(ids: &[u32], names: &container::SymbolMap) 
+
+    -> Result<Vec<u8>, HuffmanError> 
+{
+    container::compress_ids_named(ids, names)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::decompress_ids_named`")]
+pub fn decompress_ids_named(stream: &[u8]) 
+
+    -> Result<container::DecodedIds, HuffmanError> 
+{
+    container::decompress_ids_named(stream)
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::self_check`")]
+pub fn self_check() -> Result<(), container::SelfCheckFailure> {
+    container::self_check()
+}
+
+#[cfg(feature = "std")]
+#[deprecated(note = "use `huffman::container::SelfCheckFailure`")]
+pub type SelfCheckFailure = container::SelfCheckFailure;
+
+#[deprecated(note = "use `huffman::heapq::heapify_max_with_aux`")]
+pub fn heapify_max_with_aux<T, C, A>(heap: &mut [T], cmp: C, aux: &A)
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heapify_max_with_aux(heap, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::heap_pop_max_with_aux`")]
+pub fn heap_pop_max_with_aux<T, C, A>(heap: &mut Vec<T>, cmp: C, aux: &A) 
+
+    -> Option<T> 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heap_pop_max_with_aux(heap, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::heap_append_with_aux`")]
+pub fn heap_append_with_aux<T, I, C, A>(heap  : &mut Vec<T>,
+                                        items : I,
+                                        cmp   : C,
+                                        aux   : &A)
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heap_append_with_aux(heap, items, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::IncrementalHeapify`")]
+pub type IncrementalHeapify<T, C, A> = heapq::IncrementalHeapify<T, C, A>;
+
+#[deprecated(note = "use `huffman::heapq::Status`")]
+pub type Status = heapq::Status;
+
+#[deprecated(note = "use `huffman::heapq::heap_push_max_with_aux`")]
+pub fn heap_push_max_with_aux<T, C, A>(heap : &mut Vec<T>,
+                                       item : T,
+                                       cmp  : C,
+                                       aux  : &A)
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heap_push_max_with_aux(heap, item, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::merge_with_aux`")]
+pub fn merge_with_aux<'a, T, I, C, A>(iters: Vec<I>, cmp: &'a C, aux: &'a A) 
+
+    -> impl Iterator<Item = T> + 'a 
+where
+    T: 'a,
+    I: Iterator<Item = T> + 'a,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::merge_with_aux(iters, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::Heap`")]
+pub type Heap<T, C, A> = heapq::Heap<T, C, A>;
+
+#[deprecated(note = "use `huffman::heapq::heap_pushpop_with_aux`")]
+pub fn heap_pushpop_with_aux<T, C, A>(heap: &mut [T], item: T, cmp: C, aux: &A) 
+
+    -> T 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heap_pushpop_with_aux(heap, item, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::heap_replace_with_aux`")]
+pub fn heap_replace_with_aux<T, C, A>(heap : &mut Vec<T>,
+                                      item : T,
+                                      cmp  : C,
+                                      aux  : &A) 
+
+    -> Option<T> 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::heap_replace_with_aux(heap, item, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::is_heap_with_aux`")]
+pub fn is_heap_with_aux<T, C, A>(heap: &[T], cmp: C, aux: &A) 
+
+    -> bool 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::is_heap_with_aux(heap, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::nlargest_with_aux`")]
+pub fn nlargest_with_aux<T, I, C, A>(n: usize, items: I, cmp: C, aux: &A) 
+
+    -> Vec<T> 
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::nlargest_with_aux(n, items, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::nsmallest_with_aux`")]
+pub fn nsmallest_with_aux<T, I, C, A>(n: usize, items: I, cmp: C, aux: &A) 
+
+    -> Vec<T> 
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapq::nsmallest_with_aux(n, items, cmp, aux)
+}
+
+#[deprecated(note = "use `huffman::heapq::RunningMedian`")]
+pub type RunningMedian = heapq::RunningMedian;
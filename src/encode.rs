@@ -0,0 +1,558 @@
+//! Encoding text as packed codes.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::str::CharIndices;
+
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::io::{BitBuffer, BitWriter, DecoderFsm, Transition};
+
+
+/// An iterator over the bytes of encoded text, made by
+/// `HuffmanCodebook::encode_iter()`. Codes are packed most significant bit
+/// first, and bytes are produced as they fill, so the output is never held in
+/// memory. The last byte is padded with zeros; `finalize()` says how many of
+/// its bits are used.
+/// 
+#[derive(Clone, Debug)]
+pub struct EncodeIter<'a> {
+    codebook : &'a HuffmanCodebook,
+    chars    : CharIndices<'a>,
+    index    : usize,
+    acc      : u128,
+    acc_len  : u32,
+    bit_len  : u64,
+    error    : Option<HuffmanError>,
+}
+
+impl<'a> EncodeIter<'a> {
+    /// The number of bits of output encoded so far; once the iterator is
+    /// exhausted, the length of the whole output.
+    /// 
+    pub fn bit_len(&self) -> u64 {
+        self.bit_len
+    }
+    /// Once the iterator is exhausted, gives the number of bits of the last
+    /// byte that are part of the output: 1 to 8, or 0 if there was no output.
+    /// If the iterator stopped at a character with no code, fails with
+    /// `HuffmanError::SymbolNotInCodebook` instead, and the bytes produced
+    /// encode the text before it.
+    /// 
+    pub fn finalize(self) -> Result<u8, HuffmanError> {
+        match self.error {
+            Some(error) => Err(error),
+            None        => Ok(match self.bit_len % 8 {
+                0 if self.bit_len > 0 => 8,
+                n                     => n as u8,
+            }),
+        }
+    }
+}
+
+impl Iterator for EncodeIter<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        while self.acc_len < 8 && self.error.is_none() {
+            let Some((offset, c)) = self.chars.next() else { break };
+
+            match self.codebook.code_for(c) {
+                Some(code) => {
+                    self.acc      = self.acc << code.len | code.bits as u128;
+                    self.acc_len += code.len as u32;
+                    self.bit_len += code.len as u64;
+                },
+                None => {
+                    self.error = Some(HuffmanError::SymbolNotInCodebook { 
+                        symbol      : c, 
+                        char_index  : self.index, 
+                        byte_offset : offset,
+                    });
+                },
+            }
+            self.index += 1;
+        }
+        match self.acc_len {
+            0 => None,
+            n if n >= 8 => {
+                self.acc_len -= 8;
+                Some((self.acc >> self.acc_len) as u8)
+            },
+            n => {
+                self.acc_len = 0;
+                Some((self.acc << (8 - n)) as u8)
+            },
+        }
+    }
+}
+
+impl HuffmanCodebook {
+    /// Encodes `text` lazily, producing the packed output a byte at a time.
+    /// 
+    pub fn encode_iter<'a>(&'a self, text: &'a str) -> EncodeIter<'a> {
+        EncodeIter {
+            codebook : self,
+            chars    : text.char_indices(),
+            index    : 0,
+            acc      : 0,
+            acc_len  : 0,
+            bit_len  : 0,
+            error    : None,
+        }
+    }
+    /// Writes the codes for `text` into `out` starting at bit `start_bit`,
+    /// counting from the most significant bit of `out[0]`, and returns the
+    /// position of the bit after the last code, where the next field can
+    /// start. `out` is extended with zeros to hold the codes. Only the bits
+    /// the codes cover are written, so the data around them, even in the
+    /// same byte, is left as it was. Fails with
+    /// `HuffmanError::SymbolNotInCodebook`, before writing anything, if
+    /// `text` has a character with no code.
+    /// 
+    pub fn encode_into(&self, text: &str, out: &mut Vec<u8>, start_bit: usize) 
+
+        -> Result<usize, HuffmanError> 
+    {
+        let mut end = start_bit;
+
+        for (char_index, (byte_offset, c)) in text.char_indices().enumerate() {
+            let code = self.code_for(c).ok_or(
+                HuffmanError::SymbolNotInCodebook {
+                    symbol: c, char_index, byte_offset
+                }
+            )?;
+            end += code.len as usize;
+        }
+        if out.len() < end.div_ceil(8) {
+            out.resize(end.div_ceil(8), 0);
+        }
+        let mut pos = start_bit;
+
+        // Each code is written as many bits at a time as fit in the byte
+        // they go in.
+        for c in text.chars() {
+            let code     = self.codes[&c];
+            let mut left = code.len as u32;
+
+            while left > 0 {
+                let free  = 8 - (pos % 8) as u32;
+                let n     = free.min(left);
+                let shift = free - n;
+                let mask  = ((1_u16 << n) - 1) as u8;
+                let bits  = (code.bits >> (left - n)) as u8 & mask;
+                let byte  = &mut out[pos / 8];
+
+                *byte  = *byte & !(mask << shift) | bits << shift;
+                left  -= n;
+                pos   += n as usize;
+            }
+        }
+        Ok(end)
+    }
+    /// Decodes `nsymbols` characters from `buf` starting at bit `start_bit`,
+    /// as `DecoderFsm::decode_from()` does. The decoder is built on the first
+    /// call and kept with the codebook, so decoding many short fields with
+    /// the same codebook pays for it once.
+    /// 
+    pub fn decode_from(&self, buf: &[u8], start_bit: usize, nsymbols: usize) 
+
+        -> Result<(String, usize), HuffmanError> 
+    {
+        self.decoder()?.decode_from(buf, start_bit, nsymbols)
+    }
+    /// The decoder for the codes, built if this is the first time it's
+    /// needed.
+    /// 
+    pub(crate) fn decoder(&self) -> Result<&DecoderFsm, HuffmanError> {
+        if let Some(fsm) = self.decoder.get() {
+            return Ok(fsm);
+        }
+        let fsm = DecoderFsm::from_codebook(self)?;
+
+        Ok(self.decoder.get_or_init(|| fsm))
+    }
+}
+
+/// Encodes `data` with codes generated from it, the ones given as strings by
+/// `generate_huffman_codes()`, returning the packed codes. A text with only
+/// one distinct character, whose code is "0", is coded as a 0 bit for each
+/// character.
+/// 
+pub fn encode(data: &str) -> BitBuffer {
+    let codebook   = HuffmanCodebook::from_text(data);
+    let mut writer = BitWriter::new();
+
+    encode_into(&codebook, data, &mut writer, None)
+        .expect("every character of the text has a code");
+
+    writer.into_buffer()
+}
+
+/// One character's part of an encoding, as recorded by `encode_annotated()`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnnotatedStep {
+    /// The character.
+    pub char_      : char,
+    /// Its code.
+    pub code       : Code,
+    /// The offset in the output of the code's first bit.
+    pub bit_offset : u64,
+}
+
+/// Appends the codes for `text` to `writer`, recording each one in `steps` if
+/// given. Positions in errors are from the start of `text`.
+/// 
+pub(crate) fn encode_into(codebook  : &HuffmanCodebook,
+                          text      : &str,
+                          writer    : &mut BitWriter,
+                          mut steps : Option<&mut Vec<AnnotatedStep>>) 
+
+    -> Result<(), HuffmanError> 
+{
+    for (char_index, (byte_offset, c)) in text.char_indices().enumerate() {
+        let code = codebook.code_for(c).ok_or(
+            HuffmanError::SymbolNotInCodebook {
+                symbol: c, char_index, byte_offset
+            }
+        )?;
+        if let Some(steps) = steps.as_deref_mut() {
+            steps.push(AnnotatedStep {
+                char_      : c,
+                code,
+                bit_offset : writer.bit_len(),
+            });
+        }
+        writer.write_code(code);
+    }
+    Ok(())
+}
+
+/// Encodes `text`, returning the packed output along with the code each
+/// character contributed and where it starts. The steps tile the output: each
+/// code starts where the one before it ends, and the last ends at the end of
+/// the output.
+/// 
+pub fn encode_annotated(text: &str, codebook: &HuffmanCodebook) 
+
+    -> Result<(BitBuffer, Vec<AnnotatedStep>), HuffmanError> 
+{
+    let mut writer = BitWriter::new();
+    let mut steps  = Vec::with_capacity(text.len());
+
+    encode_into(codebook, text, &mut writer, Some(&mut steps))?;
+
+    Ok((writer.into_buffer(), steps))
+}
+
+/// Decodes `bits`, giving each symbol with the range of bits its code spans.
+/// Fails as `Codec::decode()` does.
+/// 
+pub fn decode_annotated(bits: &BitBuffer, codebook: &HuffmanCodebook) 
+
+    -> Result<Vec<(char, Range<u64>)>, HuffmanError> 
+{
+    let fsm       = DecoderFsm::from_codebook(codebook)?;
+    let mut r     = bits.reader();
+    let mut steps = vec![];
+    let mut state = 0;
+    let mut start = 0;
+
+    while let Some(bit) = r.read_bit() {
+        match fsm.step(state, bit) {
+            Transition::Next(next) => state = next,
+            Transition::Emit(c)    => {
+                steps.push((c, start as u64..r.position() as u64));
+                state = 0;
+                start = r.position();
+            },
+            Transition::Invalid    => {
+                return Err(HuffmanError::InvalidCode {
+                    bit_offset: start, symbols: steps.len()
+                });
+            },
+        }
+    }
+    match state {
+        0 => Ok(steps),
+        _ => Err(HuffmanError::TruncatedBitstream {
+            bit_offset: start, symbols: steps.len()
+        }),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_eager_packing() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+
+        let mut writer = BitWriter::new();
+
+        for c in text.chars() {
+            writer.write_code(codebook.code_for(c).unwrap());
+        }
+        let bit_len = writer.bit_len();
+        let eager   = writer.finish();
+
+        let mut iter = codebook.encode_iter(&text);
+        let lazy     = iter.by_ref().collect::<Vec<_>>();
+
+        assert_eq!(lazy, eager);
+        assert_eq!(iter.bit_len(), bit_len);
+        assert_eq!(iter.finalize(), Ok(match bit_len % 8 { 0 => 8, 
+                                                           n => n as u8 }));
+
+        let fsm = DecoderFsm::from_codebook(&codebook).unwrap();
+
+        let lazy = BitBuffer::try_new(lazy, bit_len as usize).unwrap();
+
+        assert_eq!(fsm.decode(&lazy).unwrap(), text);
+    }
+
+    #[test]
+    fn long_codes_and_edge_cases() {
+        // Codes up to 64 bits long still pack exactly.
+        static LONG: &[(char, u64, u8)] = &[('a', 1, 64), ('b', 1, 1), 
+                                            ('c', 0, 64)];
+        let codebook = HuffmanCodebook::from_static(LONG).unwrap();
+        let bytes    = codebook.encode_iter("bab").collect::<Vec<_>>();
+
+        assert_eq!(bytes, [0x80, 0, 0, 0, 0, 0, 0, 0, 0xc0]);
+
+        let mut iter = codebook.encode_iter("");
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.finalize(), Ok(0));
+
+        let mut iter = codebook.encode_iter("bbbx");
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), [0xe0]);
+        assert_eq!(iter.finalize(), 
+                   Err(HuffmanError::SymbolNotInCodebook { 
+                       symbol: 'x', char_index: 3, byte_offset: 3 
+                   }));
+    }
+
+    #[test]
+    fn unknown_symbol_positions() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0, 1), ('é', 0b10, 2), 
+                                             ('€', 0b11, 2)];
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+        let unknown  = |text: &str| {
+            let mut iter = codebook.encode_iter(text);
+            iter.by_ref().for_each(drop);
+            match iter.finalize() {
+                Err(HuffmanError::SymbolNotInCodebook { 
+                    symbol, char_index, byte_offset 
+                }) => Some((symbol, char_index, byte_offset)),
+                _  => None,
+            }
+        };
+        assert_eq!(unknown("xaé€"), Some(('x', 0, 0)));
+        assert_eq!(unknown("aé€ω€a"), Some(('ω', 3, 6)));
+        assert_eq!(unknown("€€é🦀"), Some(('🦀', 3, 8)));
+        assert_eq!(unknown("aé€a"), None);
+    }
+
+    #[test]
+    fn annotations_tile_the_output() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+
+        let (bits, steps) = encode_annotated(&text, &codebook).unwrap();
+        let mut iter = codebook.encode_iter(&text);
+
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), bits.as_bytes());
+        assert_eq!(steps.iter().map(|s| s.char_).collect::<String>(), text);
+
+        let mut end = 0;
+
+        for step in &steps {
+            assert_eq!(step.bit_offset, end);
+            assert_eq!(Some(step.code), codebook.code_for(step.char_));
+            end += step.code.len as u64;
+        }
+        assert_eq!(end, iter.bit_len());
+
+        let decoded = decode_annotated(&bits, &codebook).unwrap();
+
+        assert_eq!(decoded.len(), steps.len());
+
+        for ((c, range), step) in decoded.into_iter().zip(&steps) {
+            assert_eq!(c, step.char_);
+            assert_eq!(range, step.bit_offset..step.bit_offset
+                                                + step.code.len as u64);
+        }
+        assert_eq!(encode_annotated("ab\0", &codebook).err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\0', char_index: 2, byte_offset: 2
+                   }));
+        let (bytes, _) = bits.into_parts();
+        let truncated  = BitBuffer::try_new_lenient(bytes, end as usize - 1)
+                                   .unwrap();
+
+        assert_eq!(decode_annotated(&truncated, &codebook).err(),
+                   Some(HuffmanError::TruncatedBitstream {
+                       bit_offset : steps.last().unwrap().bit_offset as usize,
+                       symbols    : steps.len() - 1,
+                   }));
+    }
+
+    #[test]
+    fn fields_at_bit_offsets() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0,   1), ('b', 0b100, 3),
+                                             ('c', 0b101, 3), ('d', 0b11,  2)];
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+
+        // "abcd" is 0 100 101 11, written from bit 5 over set bits, which
+        // are kept on either side.
+        let mut out = vec![0xff, 0xff];
+
+        assert_eq!(codebook.encode_into("abcd", &mut out, 5), Ok(14));
+        assert_eq!(out, [0b1111_1010, 0b0101_1111]);
+        assert_eq!(codebook.decode_from(&out, 5, 4), Ok(("abcd".into(), 14)));
+        assert_eq!(codebook.decode_from(&out, 5, 2), Ok(("ab".into(), 9)));
+
+        // Past the end of the buffer, which grows to hold the codes.
+        let mut out = vec![];
+
+        assert_eq!(codebook.encode_into("abcd", &mut out, 12), Ok(21));
+        assert_eq!(out.len(), 3);
+        assert_eq!(codebook.decode_from(&out, 12, 4),
+                   Ok(("abcd".into(), 21)));
+        assert_eq!(codebook.encode_into("", &mut out, 21), Ok(21));
+        assert_eq!(codebook.decode_from(&out, 21, 0), Ok((String::new(), 21)));
+
+        // Asking for more symbols than there are bits. The padding after
+        // the codes reads as three more 'a's.
+        assert_eq!(codebook.decode_from(&out, 12, 8),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 24, symbols: 7
+                   }));
+        assert_eq!(codebook.decode_from(&out, 12, usize::MAX),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 24, symbols: 7
+                   }));
+        assert_eq!(codebook.decode_from(&out, 30, 1),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 30, symbols: 0
+                   }));
+
+        // A symbol with no code writes nothing.
+        let before = out.clone();
+
+        assert_eq!(codebook.encode_into("abx", &mut out, 0),
+                   Err(HuffmanError::SymbolNotInCodebook {
+                       symbol: 'x', char_index: 2, byte_offset: 2
+                   }));
+        assert_eq!(codebook.encode_into("ab", &mut out, 100), Ok(104));
+        assert_eq!(out[..3], before);
+
+        static INCOMPLETE: &[(char, u64, u8)] = &[('a', 0b0, 1),
+                                                  ('b', 0b10, 2)];
+        let codebook = HuffmanCodebook::from_static(INCOMPLETE).unwrap();
+
+        assert_eq!(codebook.decode_from(&[0b0110_0000], 0, 2),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 1, symbols: 1
+                   }));
+    }
+
+    #[test]
+    fn fields_between_raw_bytes() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fields   = text.lines().filter(|l| !l.is_empty())
+                                   .take(50)
+                                   .collect::<Vec<_>>();
+
+        // Each field is its length as a raw byte, then its codes starting
+        // partway into the byte after, then a raw marker byte.
+        let mut out = vec![];
+
+        for (i, field) in fields.iter().enumerate() {
+            out.push(field.chars().count() as u8);
+
+            let start = out.len() * 8 + i % 8;
+
+            codebook.encode_into(field, &mut out, start).unwrap();
+            out.push(0xa5);
+        }
+        let mut pos = 0;
+
+        for (i, field) in fields.iter().enumerate() {
+            let len = out[pos / 8] as usize;
+
+            let (decoded, end) = codebook.decode_from(&out, pos + 8 + i % 8,
+                                                      len).unwrap();
+            assert_eq!(decoded, *field);
+            assert_eq!(out[end.div_ceil(8)], 0xa5);
+
+            pos = (end.div_ceil(8) + 1) * 8;
+        }
+        assert_eq!(pos, out.len() * 8);
+
+        // The decoder was built once and kept, and gives what one built
+        // separately does.
+        let fsm = DecoderFsm::from_codebook(&codebook).unwrap();
+
+        assert_eq!(codebook.decoder.get(), Some(&fsm));
+        assert_eq!(fsm.decode_from(&out, 9, 3),
+                   codebook.decode_from(&out, 9, 3));
+
+        // A byte at a time, the codes are packed as a bit at a time.
+        for field in &fields {
+            let mut writer = BitWriter::new();
+            let mut packed = vec![];
+
+            encode_into(&codebook, field, &mut writer, None).unwrap();
+
+            let nbits = writer.bit_len() as usize;
+
+            assert_eq!(codebook.encode_into(field, &mut packed, 0), Ok(nbits));
+            assert_eq!(packed, writer.finish());
+        }
+
+        // Codes up to 64 bits long, spanning nine bytes from mid-byte.
+        static LONG: &[(char, u64, u8)] = &[('a', 1, 64), ('b', 1, 1),
+                                            ('c', 0, 64)];
+        let codebook = HuffmanCodebook::from_static(LONG).unwrap();
+        let mut out  = vec![0xff];
+        let end      = codebook.encode_into("acb", &mut out, 7).unwrap();
+
+        assert_eq!(end, 7 + 64 + 64 + 1);
+        assert_eq!(out.len(), 17);
+        assert_eq!(out[0], 0xfe);
+        assert_eq!(codebook.decode_from(&out, 7, 3),
+                   Ok(("acb".into(), end)));
+    }
+
+    #[test]
+    fn encode_from_text() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = crate::codebook::count_frequencies(&text);
+        let codes = crate::codebook::generate_huffman_codes(&text);
+
+        let bits     = encode(&text);
+        let expected = codes.iter()
+                            .map(|(&c, code)| code.len() * freqs.get(c))
+                            .sum::<usize>();
+
+        assert_eq!(bits.bit_len(), expected);
+        assert_eq!(bits.as_bytes().len(), expected.div_ceil(8));
+
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+
+        assert_eq!(fsm.decode(&bits).unwrap(), text);
+        assert_eq!(encode("").into_parts(), (vec![], 0));
+        assert_eq!(encode("aaaaaaaaa").into_parts(), (vec![0, 0], 9));
+    }
+}
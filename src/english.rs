@@ -0,0 +1,124 @@
+//! A built-in table of codes for English text, for inputs too short to be
+//! worth carrying a table of their own.
+//! 
+
+use crate::codebook::HuffmanCodebook;
+
+
+impl HuffmanCodebook {
+    /// Canonical codes for English text, trained on a sample of English prose.
+    /// Every printable ASCII character has a code, as do tab, newline, and
+    /// carriage return, along with the few typographic marks in the sample.
+    /// The codebook has an empty frequency table.
+    /// 
+    pub fn english() -> HuffmanCodebook {
+        HuffmanCodebook::from_static(ENGLISH).expect("the table is valid")
+    }
+}
+
+
+// Generated by huffman::codegen; do not edit.
+pub static ENGLISH: &[(char, u64, u8)] = &[
+    ('\t', 0b11111111011010, 14),
+    ('\n', 0b110010, 6),
+    ('\r', 0b11111111011011, 14),
+    (' ', 0b000, 3),
+    ('!', 0b11111101100, 11),
+    ('"', 0b11111111011100, 14),
+    ('#', 0b11111111011101, 14),
+    ('$', 0b11111111011110, 14),
+    ('%', 0b11111111011111, 14),
+    ('&', 0b11111111100000, 14),
+    ('\'', 0b11111111100001, 14),
+    ('(', 0b1111111101000, 13),
+    (')', 0b1111111101001, 13),
+    ('*', 0b11111111100010, 14),
+    ('+', 0b11111111100011, 14),
+    (',', 0b110011, 6),
+    ('-', 0b111110000, 9),
+    ('.', 0b1111000, 7),
+    ('/', 0b11111111100100, 14),
+    ('0', 0b11111111100101, 14),
+    ('1', 0b1111111101010, 13),
+    ('2', 0b11111111100110, 14),
+    ('3', 0b11111111100111, 14),
+    ('4', 0b11111111101000, 14),
+    ('5', 0b11111111101001, 14),
+    ('6', 0b11111111101010, 14),
+    ('7', 0b11111111101011, 14),
+    ('8', 0b11111111101100, 14),
+    ('9', 0b11111111101101, 14),
+    (':', 0b1111111101011, 13),
+    (';', 0b111110001, 9),
+    ('<', 0b11111111101110, 14),
+    ('=', 0b11111111101111, 14),
+    ('>', 0b11111111110000, 14),
+    ('?', 0b111110010, 9),
+    ('@', 0b11111111110001, 14),
+    ('A', 0b111110011, 9),
+    ('B', 0b1111101110, 10),
+    ('C', 0b1111101111, 10),
+    ('D', 0b111111101110, 12),
+    ('E', 0b11111101101, 11),
+    ('F', 0b11111101110, 11),
+    ('G', 0b11111101111, 11),
+    ('H', 0b1111110000, 10),
+    ('I', 0b11110110, 8),
+    ('J', 0b111111101111, 12),
+    ('K', 0b11111111110010, 14),
+    ('L', 0b11111110000, 11),
+    ('M', 0b111111110000, 12),
+    ('N', 0b1111110001, 10),
+    ('O', 0b11111110001, 11),
+    ('P', 0b11111110010, 11),
+    ('Q', 0b11111111110011, 14),
+    ('R', 0b11111110011, 11),
+    ('S', 0b1111110010, 10),
+    ('T', 0b111110100, 9),
+    ('U', 0b1111111101100, 13),
+    ('V', 0b111111110001, 12),
+    ('W', 0b111110101, 9),
+    ('X', 0b11111111110100, 14),
+    ('Y', 0b11111110100, 11),
+    ('Z', 0b11111111110101, 14),
+    ('[', 0b11111111110110, 14),
+    ('\\', 0b11111111110111, 14),
+    (']', 0b11111111111000, 14),
+    ('^', 0b11111111111001, 14),
+    ('_', 0b111111110010, 12),
+    ('`', 0b11111111111010, 14),
+    ('a', 0b0100, 4),
+    ('b', 0b1111001, 7),
+    ('c', 0b110100, 6),
+    ('d', 0b10110, 5),
+    ('e', 0b001, 3),
+    ('f', 0b110101, 6),
+    ('g', 0b110110, 6),
+    ('h', 0b0101, 4),
+    ('i', 0b0110, 4),
+    ('j', 0b1111110011, 10),
+    ('k', 0b11110111, 8),
+    ('l', 0b10111, 5),
+    ('m', 0b110111, 6),
+    ('n', 0b0111, 4),
+    ('o', 0b1000, 4),
+    ('p', 0b111000, 6),
+    ('q', 0b1111110100, 10),
+    ('r', 0b11000, 5),
+    ('s', 0b1001, 4),
+    ('t', 0b1010, 4),
+    ('u', 0b111001, 6),
+    ('v', 0b1111010, 7),
+    ('w', 0b111010, 6),
+    ('x', 0b1111110101, 10),
+    ('y', 0b111011, 6),
+    ('z', 0b11111110101, 11),
+    ('{', 0b11111111111011, 14),
+    ('|', 0b11111111111100, 14),
+    ('}', 0b11111111111101, 14),
+    ('~', 0b11111111111110, 14),
+    ('—', 0b111110110, 9),
+    ('’', 0b11111110110, 11),
+    ('“', 0b111111110011, 12),
+    ('”', 0b11111111111111, 14),
+];
@@ -0,0 +1,288 @@
+//! The errors reported by the crate.
+//! 
+
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::escape::SymbolDisplay;
+
+
+/// The ways the operations of this crate can fail.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HuffmanError {
+    /// A code table lists the same symbol more than once.
+    DuplicateSymbol(char),
+    /// The code for a symbol is longer than 64 bits, or has bits set beyond
+    /// its length.
+    MalformedCode(char),
+    /// The code for a symbol is longer than the format it's being written in
+    /// allows.
+    CodeTooLong(char),
+    /// The code for the first symbol is a prefix of the code for the second,
+    /// so a decoder couldn't tell them apart.
+    NotPrefixFree(char, char),
+    /// A bitstream has a run of bits that isn't any symbol's code.
+    InvalidCode {
+        /// The offset of the first bit of the run.
+        bit_offset : usize,
+        /// The number of symbols decoded before it.
+        symbols    : usize,
+    },
+    /// A bitstream ends partway through a code, or is shorter than its stated
+    /// bit count.
+    TruncatedBitstream {
+        /// The offset of the first bit of the unfinished code.
+        bit_offset : usize,
+        /// The number of symbols decoded before it.
+        symbols    : usize,
+    },
+    /// The operation was cancelled through its `CancelToken`.
+    Cancelled,
+    /// The text to encode has a symbol the codes don't cover.
+    SymbolNotInCodebook {
+        /// The symbol.
+        symbol      : char,
+        /// Its position in the text, counted in characters.
+        char_index  : usize,
+        /// Its position in the text, counted in bytes of UTF-8.
+        byte_offset : usize,
+    },
+    /// Input that should be UTF-8 text isn't.
+    InvalidUtf8,
+    /// The input fed to the encoding pass of a two-pass operation isn't the
+    /// input fed to its counting pass.
+    InputMismatch,
+    /// A line of CSV couldn't be parsed.
+    InvalidCsv {
+        /// The line number, counting from 1.
+        line   : usize,
+        /// What's wrong with it.
+        reason : &'static str,
+    },
+    /// Reading or writing failed with an I/O error of this kind. Only built
+    /// with the `std` feature.
+    #[cfg(feature = "std")]
+    Io(io::ErrorKind),
+    /// Serialized data was written by an unknown version of its format.
+    UnsupportedVersion(u8),
+    /// Serialized data is corrupt, for the given reason.
+    MalformedData(&'static str),
+    /// A compressed stream doesn't carry its code table, and none was given.
+    MissingCodebook,
+    /// The UTF-16 input to encode has a code unit the codes don't cover.
+    UnitNotInCodebook {
+        /// The code unit.
+        unit  : u16,
+        /// Its position in the input.
+        index : usize,
+    },
+    /// The ids to encode include one the codes don't cover.
+    IdNotInCodebook {
+        /// The id.
+        id    : u32,
+        /// Its position in the input.
+        index : usize,
+    },
+    /// An alphabet has more symbols than a tree can hold.
+    TooManySymbols {
+        /// The number of symbols.
+        symbols : usize,
+        /// The most a tree can hold.
+        max     : usize,
+    },
+    /// A probability distribution can't be coded, for the given reason.
+    InvalidProbabilities {
+        /// The index of the entry at fault, if the fault is with one entry.
+        index  : Option<usize>,
+        /// What's wrong.
+        reason : &'static str,
+    },
+    /// A thread doing part of the work panicked.
+    WorkerPanicked,
+    /// There aren't enough codes within the length caps for every symbol.
+    LengthCapInfeasible {
+        /// The symbol left without a code.
+        symbol : char,
+        /// Its cap.
+        cap    : u8,
+    },
+    /// The codebook given for a strict stream has a different number of
+    /// symbols than the stream was encoded with.
+    AlphabetMismatch {
+        /// The number the stream declares.
+        expected : usize,
+        /// The number the codebook has.
+        found    : usize,
+    },
+    /// A strict stream decodes to a different number of characters than it
+    /// declares.
+    LengthMismatch {
+        /// The number the stream declares.
+        expected : u64,
+        /// The number decoded, which may be partial if there were too many.
+        found    : u64,
+    },
+    /// A record is for a channel the stream doesn't have.
+    UnknownChannel {
+        /// The channel.
+        channel  : usize,
+        /// The number of channels the stream has.
+        channels : usize,
+    },
+    /// A bit length runs past the end of the bytes given with it.
+    BitLengthOverrun {
+        /// The bit length.
+        bit_len : usize,
+        /// The number of bytes.
+        bytes   : usize,
+    },
+    /// Packed bits have a bit set after their bit length.
+    NonzeroPadding {
+        /// The offset of the first such bit.
+        bit_offset : usize,
+    },
+    /// There's nothing to build codes from: no symbols, or no text.
+    EmptyInput,
+    /// A symbol map names the same id more than once.
+    DuplicateId(u32),
+    /// Decoding produced more output than a `DecodeLimits` allows, and was
+    /// stopped.
+    OutputLimitExceeded {
+        /// The characters or bytes produced when it was stopped, whichever
+        /// the limit was on.
+        produced : u64,
+        /// The limit.
+        limit    : u64,
+    },
+}
+
+impl fmt::Display for HuffmanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use HuffmanError::*;
+        match self {
+            DuplicateSymbol(c) => {
+                write!(f, "symbol {} appears more than once", SymbolDisplay(*c))
+            },
+            MalformedCode(c) => {
+                write!(f, "the code for {} is malformed", SymbolDisplay(*c))
+            },
+            CodeTooLong(c) => {
+                write!(f, "the code for {} is too long for the format", 
+                       SymbolDisplay(*c))
+            },
+            NotPrefixFree(a, b) => {
+                write!(f, "the code for {} is a prefix of the code for {}",
+                       SymbolDisplay(*a), SymbolDisplay(*b))
+            },
+            InvalidCode { bit_offset, symbols } => {
+                write!(f, "the bitstream holds bits that aren't a valid code \
+                           at bit {} (after {} symbols)", bit_offset, symbols)
+            },
+            TruncatedBitstream { bit_offset, symbols } => {
+                write!(f, "the bitstream ends partway through the code at bit \
+                           {} (after {} symbols)", bit_offset, symbols)
+            },
+            Cancelled => {
+                write!(f, "the operation was cancelled")
+            },
+            SymbolNotInCodebook { symbol, char_index, byte_offset } => {
+                write!(f, "there's no code for {} at character {} (byte {})",
+                       SymbolDisplay(*symbol), char_index, byte_offset)
+            },
+            InvalidUtf8 => {
+                write!(f, "the input isn't valid UTF-8")
+            },
+            InputMismatch => {
+                write!(f, "the input to encode differs from the input counted")
+            },
+            InvalidCsv { line, reason } => {
+                write!(f, "invalid CSV on line {}: {}", line, reason)
+            },
+            #[cfg(feature = "std")]
+            Io(kind) => {
+                write!(f, "I/O error: {}", kind)
+            },
+            UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            },
+            MalformedData(reason) => {
+                write!(f, "malformed data: {}", reason)
+            },
+            MissingCodebook => {
+                write!(f, "the stream has no code table and none was given")
+            },
+            UnitNotInCodebook { unit, index } => {
+                write!(f, "there's no code for the unit {:#06x} at index {}",
+                       unit, index)
+            },
+            IdNotInCodebook { id, index } => {
+                write!(f, "there's no code for the id {} at index {}", id,
+                       index)
+            },
+            TooManySymbols { symbols, max } => {
+                write!(f, "{} symbols is more than a tree can hold ({})",
+                       symbols, max)
+            },
+            InvalidProbabilities { index: Some(index), reason } => {
+                write!(f, "invalid probability at index {}: {}", index, reason)
+            },
+            InvalidProbabilities { index: None, reason } => {
+                write!(f, "invalid probabilities: {}", reason)
+            },
+            WorkerPanicked => {
+                write!(f, "a worker thread panicked")
+            },
+            LengthCapInfeasible { symbol, cap } => {
+                write!(f, "there's no room for a code of at most {} bits for \
+                           {}", cap, SymbolDisplay(*symbol))
+            },
+            AlphabetMismatch { expected, found } => {
+                write!(f, "the stream was coded with {} symbols, but the \
+                           codebook has {}", expected, found)
+            },
+            LengthMismatch { expected, found } => {
+                write!(f, "the stream declares {} characters, but {} were \
+                           decoded", expected, found)
+            },
+            UnknownChannel { channel, channels } => {
+                write!(f, "there's no channel {}; the stream has {}",
+                       channel, channels)
+            },
+            BitLengthOverrun { bit_len, bytes } => {
+                write!(f, "a bit length of {} runs past the end of {} bytes",
+                       bit_len, bytes)
+            },
+            NonzeroPadding { bit_offset } => {
+                write!(f, "the padding bit at {} is set", bit_offset)
+            },
+            EmptyInput => {
+                write!(f, "there are no symbols to build codes from")
+            },
+            DuplicateId(id) => {
+                write!(f, "id {} is named more than once", id)
+            },
+            OutputLimitExceeded { produced, limit } => {
+                write!(f, "decoding stopped at {} of output, over the limit \
+                           of {}", produced, limit)
+            },
+        }
+    }
+}
+
+impl Error for HuffmanError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for HuffmanError {
+    /// Unwraps a `HuffmanError` carried by `error`, such as
+    /// `HuffmanError::Cancelled`; otherwise keeps just its kind.
+    /// 
+    fn from(error: io::Error) -> Self {
+        match error.get_ref().and_then(|e| e.downcast_ref::<HuffmanError>()) {
+            Some(inner) => inner.clone(),
+            None        => HuffmanError::Io(error.kind()),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! The one escaping scheme for showing symbols in text, so that every export
+//! shows a given character the same way and they can be cross-referenced.
+//! 
+//! A character is shown as itself unless it's hidden, or has a meaning of its
+//! own in the format. Hidden characters are the control characters,
+//! whitespace other than a space, and those `char::escape_debug()` won't show
+//! as themselves: unassigned code points, noncharacters such as U+FFFF, and
+//! combining marks and joiners such as U+200D, which would otherwise attach
+//! to whatever they were printed next to. Each format writes a hidden
+//! character by its code point, in the format's own escape syntax.
+//! 
+//! Diagnostics, which are read rather than parsed, show characters with
+//! `SymbolDisplay` instead.
+//! 
+
+use alloc::format;
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+
+/// The format a symbol is shown in, which decides how `escape_symbol()`
+/// escapes it.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EscapeContext {
+    /// The body of a Rust char literal, to be shown between single quotes:
+    /// `\\`, `\'`, `\n`, `\r`, `\t` and `\0`, and `\u{200d}` for other hidden
+    /// characters.
+    Plain,
+    /// A whole CSV field, as `FrequencyTable::from_csv_reader()` reads it
+    /// back: quoted if it's a comma, a quote, whitespace, or escaped, with
+    /// `""`, `\\`, `\n`, `\r`, `\t`, and `\u{200d}` for other hidden
+    /// characters.
+    Csv,
+    /// The body of a JSON string: `\"`, `\\`, `\n`, `\r`, `\t`, `\b` and
+    /// `\f`, and `\u200d` for other hidden characters, with characters beyond
+    /// U+FFFF as a surrogate pair.
+    Json,
+    /// The body of a quoted Graphviz DOT string: `\"`, `\\` and `&amp;`, and
+    /// `&#x200d;` for hidden characters.
+    Dot,
+    /// Markdown text, such as a table cell: a backslash before any ASCII
+    /// punctuation that Markdown gives a meaning to, `&amp;`, and `&#x200d;`
+    /// for hidden characters.
+    Markdown,
+}
+
+/// Whether `c` is written by its code point rather than as itself. See the
+/// module docs.
+/// 
+fn is_hidden(c: char) -> bool {
+    match c {
+        ' ' | '"' | '\'' | '\\' => false,
+        _ => c.is_whitespace() || c.escape_debug().nth(1).is_some(),
+    }
+}
+
+/// `c` as it's shown in `context`, escaped by the scheme described for each
+/// context and in the module docs. The same character always gives the same
+/// text, and different characters give different text.
+/// 
+pub fn escape_symbol(c: char, context: EscapeContext) -> Cow<'static, str> {
+    use EscapeContext::*;
+
+    let hex = c as u32;
+
+    match (context, c) {
+        (Plain,    '\\') => "\\\\".into(),
+        (Plain,    '\'') => "\\'".into(),
+        (Plain,    '\n') => "\\n".into(),
+        (Plain,    '\r') => "\\r".into(),
+        (Plain,    '\t') => "\\t".into(),
+        (Plain,    '\0') => "\\0".into(),
+        (Plain,    _) if is_hidden(c) => format!("\\u{{{:x}}}", hex).into(),
+
+        (Csv,      '"')  => "\"\"\"\"".into(),
+        (Csv,      '\\') => "\"\\\\\"".into(),
+        (Csv,      '\n') => "\"\\n\"".into(),
+        (Csv,      '\r') => "\"\\r\"".into(),
+        (Csv,      '\t') => "\"\\t\"".into(),
+        (Csv,      ',')  => "\",\"".into(),
+        (Csv,      ' ')  => "\" \"".into(),
+        (Csv,      _) if is_hidden(c) => format!("\"\\u{{{:x}}}\"", hex).into(),
+
+        (Json,     '"')  => "\\\"".into(),
+        (Json,     '\\') => "\\\\".into(),
+        (Json,     '\n') => "\\n".into(),
+        (Json,     '\r') => "\\r".into(),
+        (Json,     '\t') => "\\t".into(),
+        (Json,     '\u{8}') => "\\b".into(),
+        (Json,     '\u{c}') => "\\f".into(),
+        (Json,     _) if is_hidden(c) => {
+            c.encode_utf16(&mut [0; 2])
+             .iter()
+             .map(|unit| format!("\\u{:04x}", unit))
+             .collect::<String>()
+             .into()
+        },
+        (Dot,      '"')  => "\\\"".into(),
+        (Dot,      '\\') => "\\\\".into(),
+
+        (Markdown, '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '<' | '>'
+                 | '(' | ')' | '#' | '+' | '-' | '.' | '!' | '|' | '~') => {
+            format!("\\{}", c).into()
+        },
+        (Dot | Markdown, '&') => "&amp;".into(),
+        (Dot | Markdown, _) if is_hidden(c) => format!("&#x{:x};", hex).into(),
+
+        _ => c.to_string().into(),
+    }
+}
+
+/// Shows a character between single quotes, escaped as for
+/// `EscapeContext::Plain`.
+/// 
+pub(crate) struct Quoted(pub(crate) char);
+
+impl fmt::Display for Quoted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("'{}'", escape_symbol(self.0, EscapeContext::Plain)))
+    }
+}
+
+/// Shows a character in a diagnostic, such as an error message or a report,
+/// so that characters that look alike, or like nothing, can't be mistaken
+/// for one another. Printable ASCII is shown between single quotes, and
+/// anything else by its code point and a rough category from a small table
+/// of ranges: `U+00A0 (space separator)`, `U+200D (format)`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolDisplay(pub char);
+
+impl SymbolDisplay {
+    /// The rough category of a character that isn't printable ASCII.
+    /// 
+    fn category(c: char) -> &'static str {
+        match c as u32 {
+            0x00..=0x1f | 0x7f..=0x9f => "control",
+            0xa0 | 0x1680 | 0x2000..=0x200a | 0x202f | 0x205f | 0x3000 => {
+                "space separator"
+            },
+            0x2028 => "line separator",
+            0x2029 => "paragraph separator",
+            0xad | 0x61c | 0x180e | 0x200b..=0x200f | 0x202a..=0x202e
+                 | 0x2060..=0x2064 | 0x2066..=0x206f | 0xfeff => "format",
+            0xfe00..=0xfe0f | 0xe0100..=0xe01ef => "variation selector",
+            0x300..=0x36f | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff
+                 | 0x20d0..=0x20ff | 0xfe20..=0xfe2f => "combining mark",
+            0xe0000..=0xe007f => "tag",
+            0xfdd0..=0xfdef => "noncharacter",
+            n if n & 0xfffe == 0xfffe => "noncharacter",
+            0xe000..=0xf8ff | 0xf0000..=0x10ffff => "private use",
+            _ if c.is_alphabetic() => "letter",
+            _ if c.is_numeric()    => "number",
+            _ if c.is_whitespace() => "whitespace",
+            _ => "symbol",
+        }
+    }
+}
+
+impl fmt::Display for SymbolDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            c @ ' '..='~' => f.pad(&format!("'{}'", c)),
+            c => f.pad(&format!("U+{:04X} ({})", c as u32,
+                                Self::category(c))),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use EscapeContext::*;
+
+    /// Characters that need care somewhere: controls, quotes, separators,
+    /// a noncharacter, the parts of an emoji ZWJ sequence, a combining mark,
+    /// unusual whitespace, and the last code point.
+    /// 
+    const NASTY: &[char] = &['\0', '\u{1}', '\u{8}', '\t', '\n', '\u{c}',
+                             '\r', '\u{1b}', '\u{7f}', '\u{85}', ' ', '"',
+                             '\'', '|', ',', '\\', '&', '<', '*', 'a', 'é',
+                             '\u{a0}', '\u{301}', '\u{200d}', '\u{2028}',
+                             '\u{feff}', '\u{ffff}', '👩', '\u{10ffff}'];
+
+    #[test]
+    fn every_context() {
+        let expected: &[(char, [&str; 5])] = &[
+            // Plain, Csv, Json, Dot, Markdown
+            ('\0',   ["\\0", "\"\\u{0}\"", "\\u0000", "&#x0;", "&#x0;"]),
+            ('\u{1}', ["\\u{1}", "\"\\u{1}\"", "\\u0001", "&#x1;", "&#x1;"]),
+            ('\u{8}', ["\\u{8}", "\"\\u{8}\"", "\\b", "&#x8;", "&#x8;"]),
+            ('\t',   ["\\t", "\"\\t\"", "\\t", "&#x9;", "&#x9;"]),
+            ('\n',   ["\\n", "\"\\n\"", "\\n", "&#xa;", "&#xa;"]),
+            ('\u{c}', ["\\u{c}", "\"\\u{c}\"", "\\f", "&#xc;", "&#xc;"]),
+            ('\r',   ["\\r", "\"\\r\"", "\\r", "&#xd;", "&#xd;"]),
+            ('\u{1b}', ["\\u{1b}", "\"\\u{1b}\"", "\\u001b", "&#x1b;",
+                        "&#x1b;"]),
+            ('\u{7f}', ["\\u{7f}", "\"\\u{7f}\"", "\\u007f", "&#x7f;",
+                        "&#x7f;"]),
+            ('\u{85}', ["\\u{85}", "\"\\u{85}\"", "\\u0085", "&#x85;",
+                        "&#x85;"]),
+            (' ',    [" ", "\" \"", " ", " ", " "]),
+            ('"',    ["\"", "\"\"\"\"", "\\\"", "\\\"", "\""]),
+            ('\'',   ["\\'", "'", "'", "'", "'"]),
+            ('|',    ["|", "|", "|", "|", "\\|"]),
+            (',',    [",", "\",\"", ",", ",", ","]),
+            ('\\',   ["\\\\", "\"\\\\\"", "\\\\", "\\\\", "\\\\"]),
+            ('&',    ["&", "&", "&", "&amp;", "&amp;"]),
+            ('<',    ["<", "<", "<", "<", "\\<"]),
+            ('*',    ["*", "*", "*", "*", "\\*"]),
+            ('a',    ["a", "a", "a", "a", "a"]),
+            ('é',    ["é", "é", "é", "é", "é"]),
+            ('\u{a0}', ["\\u{a0}", "\"\\u{a0}\"", "\\u00a0", "&#xa0;",
+                        "&#xa0;"]),
+            ('\u{301}', ["\\u{301}", "\"\\u{301}\"", "\\u0301", "&#x301;",
+                         "&#x301;"]),
+            ('\u{200d}', ["\\u{200d}", "\"\\u{200d}\"", "\\u200d",
+                          "&#x200d;", "&#x200d;"]),
+            ('\u{2028}', ["\\u{2028}", "\"\\u{2028}\"", "\\u2028",
+                          "&#x2028;", "&#x2028;"]),
+            ('\u{feff}', ["\\u{feff}", "\"\\u{feff}\"", "\\ufeff",
+                          "&#xfeff;", "&#xfeff;"]),
+            ('\u{ffff}', ["\\u{ffff}", "\"\\u{ffff}\"", "\\uffff",
+                          "&#xffff;", "&#xffff;"]),
+            ('👩',   ["👩", "👩", "👩", "👩", "👩"]),
+            ('\u{10ffff}', ["\\u{10ffff}", "\"\\u{10ffff}\"",
+                            "\\udbff\\udfff", "&#x10ffff;", "&#x10ffff;"]),
+        ];
+        assert_eq!(expected.iter().map(|&(c, _)| c).collect::<Vec<_>>(),
+                   NASTY);
+
+        for &(c, escaped) in expected {
+            for (context, escaped) in [Plain, Csv, Json, Dot, Markdown]
+                                          .into_iter()
+                                          .zip(escaped) {
+                assert_eq!(escape_symbol(c, context), escaped,
+                           "{:?} in {:?}", c, context);
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_hidden_left() {
+        for context in [Plain, Csv, Json, Dot, Markdown] {
+            let mut seen = std::collections::HashSet::new();
+
+            for &c in NASTY {
+                let escaped = escape_symbol(c, context);
+
+                assert!(!escaped.chars().any(|c| c != ' ' && is_hidden(c)));
+                assert!(seen.insert(escaped), "{:?} in {:?}", c, context);
+            }
+        }
+        // An emoji ZWJ sequence comes apart into visible pieces.
+        let family = "👩\u{200d}👧".chars()
+                                    .map(|c| escape_symbol(c, Plain))
+                                    .collect::<Vec<_>>();
+
+        assert_eq!(family, ["👩", "\\u{200d}", "👧"]);
+        assert_eq!(Quoted('\n').to_string(), "'\\n'");
+        assert_eq!(format!("{:<5}|", Quoted('a')), "'a'  |");
+    }
+
+    #[test]
+    fn symbol_display() {
+        let shown = ['a', ' ', '\'', '\\', '~', '\0', '\n', '\u{7f}', '\u{85}',
+                     '\u{a0}', '\u{200d}', '\u{202e}', '\u{2028}', '\u{301}',
+                     '\u{fe0f}', '\u{feff}', '\u{ffff}', '\u{e000}', 'é', '٣',
+                     '€', '🦀', '\u{10ffff}']
+            .map(|c| SymbolDisplay(c).to_string());
+
+        assert_eq!(shown, ["'a'", "' '", "'''", "'\\'", "'~'",
+                           "U+0000 (control)", "U+000A (control)",
+                           "U+007F (control)", "U+0085 (control)",
+                           "U+00A0 (space separator)", "U+200D (format)",
+                           "U+202E (format)", "U+2028 (line separator)",
+                           "U+0301 (combining mark)",
+                           "U+FE0F (variation selector)", "U+FEFF (format)",
+                           "U+FFFF (noncharacter)", "U+E000 (private use)",
+                           "U+00E9 (letter)", "U+0663 (number)",
+                           "U+20AC (symbol)", "U+1F980 (symbol)",
+                           "U+10FFFF (noncharacter)"]);
+        assert_eq!(format!("{:<8}|", SymbolDisplay('a')), "'a'     |");
+
+        // Error messages show invisible characters by code point.
+        let missing = |symbol| crate::HuffmanError::SymbolNotInCodebook {
+            symbol, char_index: 3, byte_offset: 5
+        }.to_string();
+
+        assert_eq!(missing('\u{a0}'),
+                   "there's no code for U+00A0 (space separator) at \
+                    character 3 (byte 5)");
+        assert_eq!(missing('\u{200d}'),
+                   "there's no code for U+200D (format) at character 3 \
+                    (byte 5)");
+        assert_eq!(missing(' '),
+                   "there's no code for ' ' at character 3 (byte 5)");
+        assert_eq!(crate::HuffmanError::NotPrefixFree('\u{1b}', 'x')
+                       .to_string(),
+                   "the code for U+001B (control) is a prefix of the code \
+                    for 'x'");
+    }
+}
@@ -0,0 +1,343 @@
+//! Saving a codebook as a single versioned blob, and loading it back.
+//! 
+//! The blob is laid out as follows, with integers little-endian:
+//! 
+//! | bytes       | contents                                               |
+//! |-------------|--------------------------------------------------------|
+//! | 1           | format version, currently 1                            |
+//! | 1           | option flags: bit 0 is set if the codes are canonical  |
+//! | 4           | the number of codes                                    |
+//! | 4 + 1 + ... | per code: the symbol's scalar value, the code length,  |
+//! |             | then the code in ⌈len / 8⌉ bytes, most significant     |
+//! |             | first                                                  |
+//! 
+//! Codes are written in order of symbol, so a codebook always exports to the
+//! same bytes.
+//! 
+//! A codebook can also be exported as flat arrays sorted by code, for
+//! decoders elsewhere to binary search, by `export_sorted_codes()`.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codegen::sorted_codes;
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+
+
+/// The format version written by `export()`.
+/// 
+const VERSION: u8 = 1;
+
+/// The flag set when the codes are canonical for their lengths, so a reader
+/// can decode them with a `CanonicalDecoder` without checking.
+/// 
+const CANONICAL: u8 = 1;
+
+/// The option flags `import()` understands.
+/// 
+const KNOWN_FLAGS: u8 = CANONICAL;
+
+
+/// Reads the fields of an exported blob in order.
+/// 
+struct Cursor<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], HuffmanError> {
+        if self.bytes.len() < n {
+            return Err(HuffmanError::MalformedData("the data is truncated"));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+
+        self.bytes = tail;
+        Ok(head)
+    }
+    fn u8(&mut self) -> Result<u8, HuffmanError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, HuffmanError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+
+impl HuffmanCodebook {
+    /// Serializes the codes as a versioned blob that `import()` reads back.
+    /// The frequencies aren't included. See the module docs for the layout.
+    /// 
+    pub fn export(&self) -> Vec<u8> {
+        let codes     = sorted_codes(self);
+        let flags     = if self.is_canonical() { CANONICAL } else { 0 };
+        let mut bytes = vec![VERSION, flags];
+
+        bytes.extend((codes.len() as u32).to_le_bytes());
+
+        for (c, code) in codes {
+            let width = code.len.div_ceil(8) as usize;
+
+            bytes.extend((c as u32).to_le_bytes());
+            bytes.push(code.len);
+            bytes.extend(&code.bits.to_be_bytes()[8 - width..]);
+        }
+        bytes
+    }
+    /// Loads codes saved by `export()`. The codes are checked as for
+    /// `from_static()`, and the codebook has an empty frequency table. Fails
+    /// with `HuffmanError::UnsupportedVersion` for blobs from another version
+    /// of the format, and with `HuffmanError::MalformedData` if the blob is
+    /// truncated, has trailing bytes, sets unknown option flags, has a symbol
+    /// that isn't a Unicode scalar value, or is flagged canonical but isn't.
+    /// The codes of a blob flagged canonical can be decoded from their
+    /// lengths alone.
+    /// 
+    pub fn import(bytes: &[u8]) -> Result<Self, HuffmanError> {
+        let mut cursor = Cursor { bytes };
+        let version    = cursor.u8()?;
+
+        if version != VERSION {
+            return Err(HuffmanError::UnsupportedVersion(version));
+        }
+        let flags = cursor.u8()?;
+
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(HuffmanError::MalformedData("unknown option flags"));
+        }
+        let count     = cursor.u32()? as usize;
+        let mut table = Vec::with_capacity(count.min(cursor.bytes.len() / 5));
+
+        for _ in 0..count {
+            let c = char::from_u32(cursor.u32()?).ok_or(
+                HuffmanError::MalformedData("a symbol isn't a valid char")
+            )?;
+            let len = cursor.u8()?;
+
+            if len > 64 {
+                return Err(HuffmanError::MalformedCode(c));
+            }
+            let bits = cursor.take(len.div_ceil(8) as usize)?
+                             .iter()
+                             .fold(0, |bits, &b| bits << 8 | b as u64);
+            table.push((c, bits, len));
+        }
+        if !cursor.bytes.is_empty() {
+            return Err(HuffmanError::MalformedData("trailing bytes"));
+        }
+        let codebook = Self::from_table(&table)?;
+
+        if flags & CANONICAL != 0 && !codebook.is_canonical() {
+            return Err(HuffmanError::MalformedData("the codes aren't \
+                                                    canonical"));
+        }
+        Ok(codebook)
+    }
+    /// The codes as three arrays of the same length: the codes left-aligned,
+    /// their lengths, and their symbols, sorted by code.
+    /// 
+    /// A left-aligned code has its first bit in the most significant bit of
+    /// the `u64`, its last bit `len` bits down, and zeros below that: code
+    /// "101" is `0b101 << 61`. Left-aligned codes compare the way the strings
+    /// of their bits do, so for a window of the next 64 bits of a stream,
+    /// most significant bit first and zero-padded past its end, the code it
+    /// starts with is the last one that's no greater than the window. For
+    /// canonical codes, as `to_canonical()` gives, the codes of each length
+    /// are also a contiguous run, ordered shortest first.
+    /// 
+    pub fn export_sorted_codes(&self) -> (Vec<u64>, Vec<u8>, Vec<char>) {
+        let mut sorted = self.codes()
+                             .iter()
+                             .map(|(&c, code)| {
+                                 (code.left_aligned(), code.len, c)
+                             })
+                             .collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let mut codes   = Vec::with_capacity(sorted.len());
+        let mut lengths = Vec::with_capacity(sorted.len());
+        let mut symbols = Vec::with_capacity(sorted.len());
+
+        for (code, len, c) in sorted {
+            codes.push(code);
+            lengths.push(len);
+            symbols.push(c);
+        }
+        (codes, lengths, symbols)
+    }
+    /// The codebook for arrays given by `export_sorted_codes()`. The codes are
+    /// checked as for `from_static()`, and the codebook has an empty frequency
+    /// table. Fails with `HuffmanError::MalformedData` if the arrays differ
+    /// in length or aren't sorted, and with `HuffmanError::MalformedCode` for
+    /// a code longer than 64 bits or with bits set after its length.
+    /// 
+    pub fn from_sorted_codes(codes   : &[u64], 
+                             lengths : &[u8], 
+                             symbols : &[char]) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        if codes.len() != lengths.len() || codes.len() != symbols.len() {
+            return Err(HuffmanError::MalformedData("the arrays differ in \
+                                                    length"));
+        }
+        let mut table = Vec::with_capacity(codes.len());
+
+        for ((&code, &len), &c) in codes.iter().zip(lengths).zip(symbols) {
+            let bits = match len {
+                0..=64 => code.checked_shr(64 - len as u32).unwrap_or(0),
+                _      => return Err(HuffmanError::MalformedCode(c)),
+            };
+            if (Code { bits, len }).left_aligned() != code {
+                return Err(HuffmanError::MalformedCode(c));
+            }
+            table.push((c, bits, len));
+        }
+        let sorted = codes.iter().zip(lengths).collect::<Vec<_>>();
+
+        if !sorted.is_sorted() {
+            return Err(HuffmanError::MalformedData("the codes aren't sorted"));
+        }
+        Self::from_table(&table)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let bytes    = codebook.export();
+        let imported = HuffmanCodebook::import(&bytes).unwrap();
+
+        assert_eq!(imported.codes(), codebook.codes());
+        assert_eq!(imported.export(), bytes);
+
+        static LONG: &[(char, u64, u8)] = &[('a', u64::MAX, 64), ('🦀', 1, 2),
+                                            ('b', 0b10, 64)];
+        let codebook = HuffmanCodebook::from_static(LONG).unwrap();
+        let imported = HuffmanCodebook::import(&codebook.export()).unwrap();
+
+        assert_eq!(imported.codes(), codebook.codes());
+
+        let empty = HuffmanCodebook::from_text("");
+
+        assert_eq!(empty.export(), [1, 1, 0, 0, 0, 0]);
+        assert!(HuffmanCodebook::import(&empty.export()).unwrap()
+                                .codes().is_empty());
+    }
+
+    #[test]
+    fn bad_blobs() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0, 1), ('b', 0b1, 1)];
+        let bytes = HuffmanCodebook::from_static(CODES).unwrap().export();
+        let with  = |i: usize, b: u8| {
+            let mut bytes = bytes.clone();
+            bytes[i] = b;
+            HuffmanCodebook::import(&bytes).map(|_| ())
+        };
+        assert_eq!(bytes, [1, 1, 2, 0, 0, 0, 
+                           b'a', 0, 0, 0, 1, 0x00,
+                           b'b', 0, 0, 0, 1, 0x01]);
+        assert_eq!(with(0, 2), Err(HuffmanError::UnsupportedVersion(2)));
+        assert_eq!(with(1, 4),
+                   Err(HuffmanError::MalformedData("unknown option flags")));
+        assert_eq!(with(2, 3),
+                   Err(HuffmanError::MalformedData("the data is truncated")));
+        assert_eq!(with(2, 1),
+                   Err(HuffmanError::MalformedData("trailing bytes")));
+        assert_eq!(with(9, 0xd8),
+                   Err(HuffmanError::MalformedData("a symbol isn't a valid \
+                                                    char")));
+        assert_eq!(with(12, b'a'), Err(HuffmanError::DuplicateSymbol('a')));
+        assert_eq!(with(17, 2), Err(HuffmanError::MalformedCode('b')));
+        assert_eq!(with(16, 65), Err(HuffmanError::MalformedCode('b')));
+        assert_eq!(with(17, 0x00), Err(HuffmanError::NotPrefixFree('a', 'b')));
+        assert_eq!(HuffmanCodebook::import(&[]).err(),
+                   Some(HuffmanError::MalformedData("the data is truncated")));
+    }
+
+    #[test]
+    fn sorted_codes() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text).to_canonical();
+
+        let (codes, lengths, symbols) = codebook.export_sorted_codes();
+        let back = HuffmanCodebook::from_sorted_codes(&codes, &lengths,
+                                                      &symbols).unwrap();
+        assert_eq!(back.codes(), codebook.codes());
+        assert!(lengths.is_sorted());
+
+        // The code a payload starts with is found by a binary search on a
+        // window of its first bits.
+        let starts = text.char_indices().map(|(i, _)| i).step_by(997);
+
+        for start in starts.take(500) {
+            let mut window = [0; 8];
+
+            for (w, b) in window.iter_mut()
+                                .zip(codebook.encode_iter(&text[start..])) {
+                *w = b;
+            }
+            let window = u64::from_be_bytes(window);
+            let i      = codes.partition_point(|&code| code <= window) - 1;
+
+            assert_eq!(Some(symbols[i]), text[start..].chars().next());
+            assert_eq!(window >> (64 - lengths[i]), 
+                       codes[i] >> (64 - lengths[i]));
+        }
+        let empty = HuffmanCodebook::from_text("").export_sorted_codes();
+
+        assert_eq!(empty, (vec![], vec![], vec![]));
+    }
+
+    #[test]
+    fn bad_sorted_codes() {
+        use HuffmanError::*;
+
+        let from = |codes: &[u64], lengths: &[u8], symbols: &[char]| {
+            HuffmanCodebook::from_sorted_codes(codes, lengths, symbols)
+                .map(|_| ())
+        };
+        let one = 1 << 63;
+
+        assert_eq!(from(&[0, one], &[1, 1], &['a', 'b']), Ok(()));
+        assert_eq!(from(&[0, one], &[1], &['a', 'b']),
+                   Err(MalformedData("the arrays differ in length")));
+        assert_eq!(from(&[one, 0], &[1, 1], &['a', 'b']),
+                   Err(MalformedData("the codes aren't sorted")));
+        assert_eq!(from(&[0, one | 1], &[1, 1], &['a', 'b']),
+                   Err(MalformedCode('b')));
+        assert_eq!(from(&[0, one], &[1, 65], &['a', 'b']),
+                   Err(MalformedCode('b')));
+        assert_eq!(from(&[0, 0], &[1, 2], &['a', 'b']),
+                   Err(NotPrefixFree('a', 'b')));
+        assert_eq!(from(&[0, one], &[1, 1], &['a', 'a']),
+                   Err(DuplicateSymbol('a')));
+    }
+
+    #[test]
+    fn canonical_flag() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+
+        assert_eq!(codebook.export()[1], 0);
+
+        let bytes = codebook.to_canonical().export();
+
+        assert_eq!(bytes[1], CANONICAL);
+        assert!(HuffmanCodebook::import(&bytes).unwrap().is_canonical());
+
+        let mut bytes = codebook.export();
+        bytes[1] = CANONICAL;
+
+        assert_eq!(HuffmanCodebook::import(&bytes).err(),
+                   Some(HuffmanError::MalformedData("the codes aren't \
+                                                     canonical")));
+    }
+}
@@ -0,0 +1,246 @@
+//! Splitting a compressed stream into self-delimiting frames of a fixed size
+//! for a packet-oriented transport, and joining them back up.
+//! 
+//! Each frame is a 2-byte little-endian payload length, a flags byte, and the
+//! payload. The payloads together are the original stream, cut wherever the
+//! frame size falls, so codes are free to straddle frames. Every frame but the
+//! last is full.
+//! 
+
+use std::io::{self, Read, Write};
+
+use crate::HuffmanError;
+
+
+/// Set on the first frame of a stream.
+/// 
+const FIRST: u8 = 1;
+
+/// Set on the last frame of a stream.
+/// 
+const LAST: u8 = 2;
+
+
+/// Writes the bytes written to it as frames on an inner writer. A frame is
+/// only written once it's known whether it's the last, so up to a frame of
+/// data is held back until more arrives or `finish()` is called.
+/// 
+#[derive(Debug)]
+pub struct FrameWriter<W: Write> {
+    inner      : W,
+    frame_size : usize,
+    buf        : Vec<u8>,
+    first      : bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// A writer of frames with payloads of `frame_payload_size` bytes.
+    /// 
+    /// # Panics
+    /// 
+    /// If `frame_payload_size` is 0.
+    /// 
+    pub fn new(inner: W, frame_payload_size: u16) -> Self {
+        assert!(frame_payload_size > 0, "frames must have room for data");
+
+        Self {
+            inner,
+            frame_size : frame_payload_size as usize,
+            buf        : Vec::with_capacity(frame_payload_size as usize * 2),
+            first      : true,
+        }
+    }
+    /// Writes the first `len` bytes held back as a frame.
+    /// 
+    fn emit(&mut self, len: usize, last: bool) -> io::Result<()> {
+        let mut flags = 0;
+
+        if self.first {
+            flags |= FIRST;
+        }
+        if last {
+            flags |= LAST;
+        }
+        let [lo, hi] = (len as u16).to_le_bytes();
+
+        self.inner.write_all(&[lo, hi, flags])?;
+        self.inner.write_all(&self.buf[..len])?;
+        self.buf.drain(..len);
+        self.first = false;
+        Ok(())
+    }
+    /// Writes what's held back as the last frame, which may be short or even
+    /// empty, and returns the inner writer. A stream whose writer is dropped
+    /// without this has no last frame.
+    /// 
+    pub fn finish(mut self) -> io::Result<W> {
+        self.emit(self.buf.len(), true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for FrameWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+
+        while self.buf.len() > self.frame_size {
+            self.emit(self.frame_size, false)?;
+        }
+        Ok(data.len())
+    }
+    /// Flushes the inner writer. Data held back for the next frame stays held
+    /// back.
+    /// 
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+/// Reads the payloads of the frames written by a `FrameWriter` as one
+/// continuous stream, ending after the last frame.
+/// 
+#[derive(Debug)]
+pub struct FrameReader<R: Read> {
+    inner     : R,
+    remaining : usize,
+    first     : bool,
+    done      : bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, remaining: 0, first: true, done: false }
+    }
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+    /// Reads the next frame's header.
+    /// 
+    fn next_frame(&mut self) -> io::Result<()> {
+        let mut header = [0; 3];
+
+        self.inner.read_exact(&mut header).map_err(|e| {
+            match e.kind() {
+                io::ErrorKind::UnexpectedEof => malformed("no last frame"),
+                _                            => e,
+            }
+        })?;
+        let flags = header[2];
+
+        if flags & !(FIRST | LAST) != 0 {
+            return Err(malformed("unknown frame flags"));
+        }
+        if (flags & FIRST != 0) != self.first {
+            return Err(malformed("frames out of order"));
+        }
+        self.remaining = u16::from_le_bytes([header[0], header[1]]) as usize;
+        self.first     = false;
+        self.done      = flags & LAST != 0;
+        Ok(())
+    }
+}
+
+fn malformed(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   HuffmanError::MalformedData(reason))
+}
+
+impl<R: Read> Read for FrameReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.next_frame()?;
+        }
+        let len = buf.len().min(self.remaining);
+        let n   = self.inner.read(&mut buf[..len])?;
+
+        if n == 0 {
+            return Err(malformed("a frame is cut short"));
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::container::{pipe_compress, pipe_decompress, TableSource};
+
+    fn frames(data: &[u8], size: u16) -> Vec<u8> {
+        let mut writer = FrameWriter::new(vec![], size);
+
+        for chunk in data.chunks(5) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn framing() {
+        assert_eq!(frames(b"abcde", 2),
+                   b"\x02\x00\x01ab\x02\x00\x00cd\x01\x00\x02e");
+        assert_eq!(frames(b"abcd", 2), b"\x02\x00\x01ab\x02\x00\x02cd");
+        assert_eq!(frames(b"", 2), b"\x00\x00\x03");
+
+        let mut data = vec![];
+
+        FrameReader::new(&b"\x02\x00\x01ab\x00\x00\x00\x01\x00\x02e"[..])
+            .read_to_end(&mut data).unwrap();
+
+        assert_eq!(data, b"abe");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn compressed_round_trip() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut stream = vec![];
+
+        pipe_compress(text.as_bytes(), &mut stream, TableSource::Train, None)
+            .unwrap();
+
+        for size in [2, 3, 7, 1200] {
+            let mut writer = FrameWriter::new(vec![], size);
+
+            pipe_compress(text.as_bytes(), &mut writer, TableSource::Train,
+                          None).unwrap();
+
+            let framed = writer.finish().unwrap();
+            let frames = stream.len().div_ceil(size as usize);
+
+            assert_eq!(framed.len(), stream.len() + frames * 3);
+
+            let mut output = vec![];
+
+            pipe_decompress(FrameReader::new(&framed[..]), &mut output, None,
+                            None).unwrap();
+
+            assert_eq!(String::from_utf8(output).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn bad_frames() {
+        let error = |framed: &[u8]| {
+            let mut data = vec![];
+            let error = FrameReader::new(framed).read_to_end(&mut data)
+                                                .unwrap_err();
+            HuffmanError::from(error)
+        };
+        let malformed = HuffmanError::MalformedData;
+
+        assert_eq!(error(b"\x02\x00\x01ab"), malformed("no last frame"));
+        assert_eq!(error(b"\x02\x00\x01a"), malformed("a frame is cut short"));
+        assert_eq!(error(b"\x02\x00\x00ab"), malformed("frames out of order"));
+        assert_eq!(error(b"\x02\x00\x09ab"), malformed("unknown frame flags"));
+        assert_eq!(error(b"\x02\x00\x01ab\x01\x00\x03e"),
+                   malformed("frames out of order"));
+    }
+}
@@ -0,0 +1,576 @@
+//! A state machine that decodes a bitstream of Huffman codes. Each state is a
+//! position within a partly read code, and each bit read either moves to
+//! another state, or completes a code and emits its symbol.
+//! 
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::HuffmanError;
+use crate::canonical::validate_codes;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::collections::HashMap;
+use crate::io::BitBuffer;
+use crate::tree::CodeTrie;
+
+
+/// What a `DecoderFsm` does on reading a bit in a given state.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// Move to the state with this index.
+    Next(u32),
+    /// Emit this symbol and return to the start state.
+    Emit(char),
+    /// The bits read so far aren't the start of any code.
+    Invalid,
+}
+
+/// A decoder for the codes of a codebook, as a table of transitions indexed by
+/// state and bit. State 0 is the start state, where each code begins.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecoderFsm {
+    states: Vec<[Transition; 2]>,
+}
+
+/// The state machine whose states are the nodes of `trie`.
+/// 
+impl From<CodeTrie> for DecoderFsm {
+    fn from(trie: CodeTrie) -> Self {
+        Self { states: trie.into_transitions() }
+    }
+}
+
+impl DecoderFsm {
+    /// Builds the state machine for the codes of `codebook`. States are
+    /// numbered in the order the codes sort in, so the same codes always give
+    /// the same table.
+    /// 
+    pub fn from_codebook(codebook: &HuffmanCodebook) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        Ok(CodeTrie::from_codebook(codebook)?.into())
+    }
+    /// Builds the state machine for a map of code strings, such as the one
+    /// `generate_huffman_codes()` returns, without a codebook or frequencies.
+    /// The table is checked with `validate_codes()` first. Fails with
+    /// `HuffmanError::MalformedCode` for empty codes or ones with characters
+    /// other than '0' and '1', `HuffmanError::NotPrefixFree` if a code is the
+    /// same as or a prefix of another, and `HuffmanError::CodeTooLong` for
+    /// codes over 64 bits.
+    /// 
+    pub fn from_code_map(codes: &HashMap<char, String>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        validate_codes(codes)?;
+
+        let codes = codes.iter().map(|(&c, code)| {
+            let packed = Code::from_str_binary(code).ok_or(match code.len() {
+                0..=64 => HuffmanError::MalformedCode(c),
+                _      => HuffmanError::CodeTooLong(c),
+            })?;
+            Ok((packed, c))
+        }).collect::<Result<_, HuffmanError>>()?;
+
+        Ok(CodeTrie::from_codes(codes)?.into())
+    }
+    /// The transitions, indexed by state and then by bit.
+    /// 
+    pub fn states(&self) -> &[[Transition; 2]] {
+        &self.states
+    }
+    /// The transition taken on reading `bit` in `state`.
+    /// 
+    pub fn step(&self, state: u32, bit: bool) -> Transition {
+        self.states[state as usize][bit as usize]
+    }
+    /// Decodes `bits`.
+    /// 
+    pub fn decode(&self, bits: &BitBuffer) -> Result<String, HuffmanError> {
+        let mut text = String::new();
+
+        self.decode_into(bits, &mut text)?;
+        Ok(text)
+    }
+    /// Like `decode()`, but decodes into `out`, which is cleared first. Its
+    /// capacity is kept and grown as needed, never shrunk, so a buffer reused
+    /// across calls stops allocating once it's big enough. On error, `out`
+    /// holds the symbols decoded before it.
+    /// 
+    pub fn decode_into(&self, bits: &BitBuffer, out: &mut String) 
+
+        -> Result<DecodeSummary, HuffmanError> 
+    {
+        out.clear();
+
+        self.decode_with(bits, |c| {
+            out.push(c);
+            ControlFlow::Continue(())
+        })
+    }
+    /// Like `decode_into()`, for a codebook over bytes, as `ByteCodec` and
+    /// the block format build, whose symbols are the chars U+0000 to U+00FF.
+    /// Decodes into `out`, cleared first and never shrunk. Fails with
+    /// `HuffmanError::MalformedData` at a symbol past U+00FF, leaving `out`
+    /// with the bytes before it.
+    /// 
+    pub fn decode_bytes_into(&self, bits: &BitBuffer, out: &mut Vec<u8>) 
+
+        -> Result<DecodeSummary, HuffmanError> 
+    {
+        let mut wide = false;
+
+        out.clear();
+
+        let summary = self.decode_with(bits, |c| match u8::try_from(c) {
+            Ok(b)  => { out.push(b); ControlFlow::Continue(()) },
+            Err(_) => { wide = true; ControlFlow::Break(()) },
+        })?;
+        if wide {
+            return Err(HuffmanError::MalformedData("a code isn't for a byte"));
+        }
+        Ok(summary)
+    }
+    /// Decodes `bits`, passing each symbol to `sink` as it's decoded, without
+    /// allocating. Decoding stops early if `sink` returns
+    /// `ControlFlow::Break`.
+    /// 
+    pub fn decode_with<F>(&self, bits: &BitBuffer, mut sink: F) 
+
+        -> Result<DecodeSummary, HuffmanError> 
+    where
+        F: FnMut(char) -> ControlFlow<()>,
+    {
+        let (bytes, len) = (bits.as_bytes(), bits.bit_len());
+        let mut pos      = 0;
+        let mut symbols  = 0;
+
+        while let Some(c) = self.read_symbol(bytes, len, &mut pos, symbols)? {
+            symbols += 1;
+
+            if sink(c).is_break() {
+                break;
+            }
+        }
+        Ok(DecodeSummary { symbols, bits: pos })
+    }
+    /// Decodes `nsymbols` characters from the codes in `buf` starting at bit
+    /// `start_bit`, as written by `HuffmanCodebook::encode_into()`, and
+    /// returns them with the position of the bit after the last code. Fails
+    /// with `HuffmanError::TruncatedBitstream` if `buf` ends before the last
+    /// code does, as it does if `start_bit` is past its end, and with
+    /// `HuffmanError::InvalidCode` at a run of bits that isn't a code. Bit
+    /// offsets in errors count from the start of `buf`.
+    /// 
+    pub fn decode_from(&self, buf: &[u8], start_bit: usize, nsymbols: usize) 
+
+        -> Result<(String, usize), HuffmanError> 
+    {
+        let nbits    = buf.len() * 8;
+        let mut text = String::with_capacity(
+                           nsymbols.min(nbits.saturating_sub(start_bit)));
+        let mut pos  = start_bit;
+
+        for symbols in 0..nsymbols {
+            match self.read_symbol(buf, nbits, &mut pos, symbols)? {
+                Some(c) => text.push(c),
+                None    => {
+                    return Err(HuffmanError::TruncatedBitstream {
+                        bit_offset: pos, symbols
+                    });
+                },
+            }
+        }
+        Ok((text, pos))
+    }
+    /// Decodes `bits` lazily, a symbol at a time, so decoding can stop early.
+    /// If the bits aren't a sequence of whole codes, the last item is the
+    /// error.
+    /// 
+    pub fn decode_iter<'a>(&'a self, bits: &'a BitBuffer) -> DecodeIter<'a> {
+        DecodeIter { 
+            fsm     : self, 
+            bits    : bits.as_bytes(), 
+            nbits   : bits.bit_len(), 
+            pos     : 0, 
+            symbols : 0, 
+            done    : false,
+        }
+    }
+    /// Reads the code starting at bit `pos` of the first `nbits` bits of
+    /// `bits`, advancing `pos` past it. Returns `None` if `pos` is already at
+    /// `nbits`. `symbols`, the number decoded so far, is only used to report
+    /// errors.
+    /// 
+    fn read_symbol(&self, 
+                   bits    : &[u8], 
+                   nbits   : usize, 
+                   pos     : &mut usize, 
+                   symbols : usize) 
+
+        -> Result<Option<char>, HuffmanError> 
+    {
+        let start = *pos;
+        let mut state = 0;
+
+        while *pos < nbits {
+            let i   = *pos;
+            let bit = bits[i / 8] >> (7 - i % 8) & 1 == 1;
+
+            *pos += 1;
+
+            match self.step(state, bit) {
+                Transition::Next(next) => state = next,
+                Transition::Emit(c)    => return Ok(Some(c)),
+                Transition::Invalid    => {
+                    return Err(HuffmanError::InvalidCode { 
+                        bit_offset: start, symbols 
+                    });
+                },
+            }
+        }
+        if state == 0 {
+            Ok(None)
+        } else {
+            Err(HuffmanError::TruncatedBitstream { bit_offset: start, symbols })
+        }
+    }
+}
+
+/// What `DecoderFsm::decode_with()` got through before finishing or being
+/// stopped by its sink.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeSummary {
+    /// The number of symbols passed to the sink.
+    pub symbols : usize,
+    /// The number of bits read.
+    pub bits    : usize,
+}
+
+/// An iterator over the symbols decoded from a bitstream, made by
+/// `DecoderFsm::decode_iter()`.
+/// 
+#[derive(Clone, Debug)]
+pub struct DecodeIter<'a> {
+    fsm     : &'a DecoderFsm,
+    bits    : &'a [u8],
+    nbits   : usize,
+    pos     : usize,
+    symbols : usize,
+    done    : bool,
+}
+
+impl DecodeIter<'_> {
+    /// The number of bits read so far.
+    /// 
+    pub fn bit_pos(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Iterator for DecodeIter<'_> {
+    type Item = Result<char, HuffmanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let symbol = self.fsm.read_symbol(self.bits, 
+                                          self.nbits, 
+                                          &mut self.pos, 
+                                          self.symbols);
+        match symbol {
+            Ok(Some(_)) => self.symbols += 1,
+            _           => self.done = true,
+        }
+        symbol.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::string::ToString;
+
+    /// Packs the codes for `text` most significant bit first.
+    /// 
+    pub(crate) fn pack(codebook: &HuffmanCodebook, text: &str) -> BitBuffer {
+        let mut bytes = vec![];
+        let mut nbits = 0;
+
+        for c in text.chars() {
+            let code = codebook.code_for(c).unwrap();
+
+            for i in (0..code.len).rev() {
+                if nbits % 8 == 0 {
+                    bytes.push(0);
+                }
+                bytes[nbits / 8] |= ((code.bits >> i & 1) as u8) 
+                                    << (7 - nbits % 8);
+                nbits += 1;
+            }
+        }
+        BitBuffer::try_new(bytes, nbits).unwrap()
+    }
+
+    /// The first `nbits` bits of `bits`.
+    /// 
+    pub(crate) fn cut(bits: &BitBuffer, nbits: usize) -> BitBuffer {
+        BitBuffer::try_new_lenient(bits.as_bytes().to_vec(), nbits).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let bits     = pack(&codebook, &text);
+
+        // A complete code over n symbols has n - 1 internal nodes.
+        assert_eq!(fsm.states().len(), codebook.codes().len() - 1);
+        assert_eq!(fsm.decode(&bits).unwrap(), text);
+    }
+
+    #[test]
+    fn from_a_code_map() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codes = crate::codebook::generate_huffman_codes(&text);
+        let fsm   = DecoderFsm::from_code_map(&codes).unwrap();
+        let bits  = text.chars().map(|c| codes[&c].as_str())
+                                .collect::<String>();
+        let bytes = bits.as_bytes().chunks(8).map(|chunk| {
+            chunk.iter().enumerate().fold(0, |b, (i, &d)| {
+                b | ((d - b'0') << (7 - i))
+            })
+        }).collect::<Vec<u8>>();
+        let bits  = BitBuffer::try_new(bytes, bits.len()).unwrap();
+
+        assert_eq!(fsm.decode(&bits).unwrap(), text);
+
+        let map = |codes: &[(char, &str)]| {
+            codes.iter().map(|&(c, code)| (c, code.to_string())).collect()
+        };
+        assert_eq!(DecoderFsm::from_code_map(&map(&[('a', "0"), ('b', "01")])),
+                   Err(HuffmanError::NotPrefixFree('a', 'b')));
+        assert_eq!(DecoderFsm::from_code_map(&map(&[('a', "0"), ('b', "2")])),
+                   Err(HuffmanError::MalformedCode('b')));
+        assert_eq!(DecoderFsm::from_code_map(&map(&[('a', "0"), ('b', "")])),
+                   Err(HuffmanError::MalformedCode('b')));
+        assert_eq!(DecoderFsm::from_code_map(&map(&[('a', &"1".repeat(65))])),
+                   Err(HuffmanError::CodeTooLong('a')));
+    }
+
+    #[test]
+    fn lazy_decoding() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let bits     = pack(&codebook, &text);
+        let nbits    = bits.bit_len();
+
+        let mut iter = fsm.decode_iter(&bits);
+        let head     = iter.by_ref().take(5).collect::<Result<String, _>>();
+
+        assert_eq!(head.unwrap(), text.chars().take(5).collect::<String>());
+        assert!(iter.bit_pos() < 64);
+
+        let all = fsm.decode_iter(&bits).collect::<Result<String, _>>();
+
+        assert_eq!(all.unwrap(), fsm.decode(&bits).unwrap());
+
+        let cut       = cut(&bits, nbits - 1);
+        let mut items = fsm.decode_iter(&cut).collect::<Vec<_>>();
+
+        let last      = text.chars().last().unwrap();
+        let last_code = codebook.code_for(last).unwrap();
+        let symbols   = text.chars().count() - 1;
+
+        assert_eq!(items.pop(), Some(Err(HuffmanError::TruncatedBitstream { 
+            bit_offset: nbits - last_code.len as usize, symbols 
+        })));
+        assert!(items.iter().all(Result::is_ok));
+        assert_eq!(items.len(), symbols);
+    }
+
+    /// A fixed-capacity buffer, standing in for something like
+    /// `heapless::String` on a target without an allocator.
+    /// 
+    struct FixedBuf<const N: usize> {
+        chars : [char; N],
+        len   : usize,
+    }
+
+    impl<const N: usize> FixedBuf<N> {
+        fn push(&mut self, c: char) -> ControlFlow<()> {
+            self.chars[self.len] = c;
+            self.len += 1;
+
+            if self.len == N {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }
+    }
+
+    #[test]
+    fn decoding_with_a_sink() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let packed   = pack(&codebook, &text);
+
+        let mut buf = FixedBuf::<32> { chars: ['\0'; 32], len: 0 };
+        let summary = fsm.decode_with(&packed, |c| buf.push(c)).unwrap();
+        let head    = text.chars().take(32).collect::<Vec<_>>();
+        let bits    = head.iter()
+                          .map(|&c| codebook.code_for(c).unwrap().len as usize)
+                          .sum();
+
+        assert_eq!(&buf.chars[..], &head[..]);
+        assert_eq!(summary, DecodeSummary { symbols: 32, bits });
+
+        let mut count = 0;
+        let summary   = fsm.decode_with(&packed, |_| {
+            count += 1;
+            ControlFlow::Continue(())
+        }).unwrap();
+
+        assert_eq!(summary.symbols, text.chars().count());
+        assert_eq!(summary.symbols, count);
+        assert_eq!(summary.bits, packed.bit_len());
+    }
+
+    #[test]
+    fn bad_bitstreams() {
+        static INCOMPLETE: &[(char, u64, u8)] = &[('a', 0b0, 1), 
+                                                  ('b', 0b10, 2)];
+        let codebook = HuffmanCodebook::from_static(INCOMPLETE).unwrap();
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let bits     = |bytes: &[u8], nbits| {
+            BitBuffer::try_new(bytes.to_vec(), nbits).unwrap()
+        };
+        assert_eq!(fsm.decode(&bits(&[0b0100_0000], 3)).unwrap(), "ab");
+        assert_eq!(fsm.decode(&bits(&[0b0110_0000], 3)), 
+                   Err(HuffmanError::InvalidCode { 
+                       bit_offset: 1, symbols: 1 
+                   }));
+        assert_eq!(fsm.decode(&bits(&[0b0100_0000], 2)),
+                   Err(HuffmanError::TruncatedBitstream { 
+                       bit_offset: 1, symbols: 1 
+                   }));
+        assert_eq!(fsm.decode(&bits(&[0b0100_0000, 0], 9)).unwrap(), 
+                   "abaaaaaa");
+        assert_eq!(fsm.decode(&bits(&[0b0101_0000, 0b1000_0000], 9)),
+                   Err(HuffmanError::TruncatedBitstream { 
+                       bit_offset: 8, symbols: 6 
+                   }));
+    }
+
+    #[test]
+    fn error_offsets() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let bits     = pack(&codebook, &text);
+
+        // Cut the stream one bit into the code for each of a few symbols, and
+        // check the error points at where that code starts.
+        let mut bit_offset = 0;
+
+        for (symbols, c) in text.chars().enumerate().take(500) {
+            let len = codebook.code_for(c).unwrap().len as usize;
+
+            if symbols % 97 == 0 && len > 1 {
+                let err = HuffmanError::TruncatedBitstream { bit_offset, 
+                                                             symbols };
+                let cut = cut(&bits, bit_offset + 1);
+
+                assert_eq!(fsm.decode(&cut), Err(err.clone()));
+
+                let mut sent = 0;
+                let result   = fsm.decode_with(&cut, |_| {
+                    sent += 1;
+                    ControlFlow::Continue(())
+                });
+                assert_eq!(result, Err(err));
+                assert_eq!(sent, symbols);
+            }
+            bit_offset += len;
+        }
+    }
+
+    #[test]
+    fn decode_into_reuses_the_buffer() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let payloads = text.split_whitespace()
+                           .take(1000)
+                           .map(|word| (word, pack(&codebook, word)))
+                           .collect::<Vec<_>>();
+
+        assert_eq!(payloads.len(), 1000);
+
+        let mut out = String::with_capacity(4096);
+
+        for (word, bits) in &payloads {
+            let summary = fsm.decode_into(bits, &mut out).unwrap();
+
+            assert_eq!(out, *word);
+            assert_eq!(summary, DecodeSummary {
+                symbols: word.chars().count(), bits: bits.bit_len()
+            });
+            assert_eq!(out.capacity(), 4096);
+        }
+        let (word, bits) = &payloads[0];
+
+        assert!(fsm.decode_into(&cut(bits, bits.bit_len() - 1), &mut out)
+                   .is_err());
+        assert!(out.len() < word.len());
+    }
+
+    #[test]
+    fn decode_bytes_into_reuses_the_buffer() {
+        let data     = std::fs::read("data/moby_dick.txt").unwrap();
+        let latin1   = data.iter().map(|&b| b as char).collect::<String>();
+        let codebook = HuffmanCodebook::from_text(&latin1);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+        let payloads = data.chunks(data.len() / 1000)
+                           .take(1000)
+                           .map(|chunk| {
+                               let text = chunk.iter()
+                                               .map(|&b| b as char)
+                                               .collect::<String>();
+                               (chunk, pack(&codebook, &text))
+                           })
+                           .collect::<Vec<_>>();
+
+        assert_eq!(payloads.len(), 1000);
+
+        let mut out = Vec::with_capacity(4096);
+
+        for (chunk, bits) in &payloads {
+            let summary = fsm.decode_bytes_into(bits, &mut out).unwrap();
+
+            assert_eq!(out, *chunk);
+            assert_eq!(summary, DecodeSummary {
+                symbols: chunk.len(), bits: bits.bit_len()
+            });
+            assert_eq!(out.capacity(), 4096);
+        }
+        // A symbol that isn't a byte stops the decode after the ones before.
+        let codebook = HuffmanCodebook::from_text("ab€");
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+
+        assert_eq!(fsm.decode_bytes_into(&pack(&codebook, "ab€a"), &mut out),
+                   Err(HuffmanError::MalformedData("a code isn't for a byte")));
+        assert_eq!(out, b"ab");
+    }
+}
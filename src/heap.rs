@@ -0,0 +1,931 @@
+//! Heap operations to go with the `heapq` crate's push and pop: fused ones,
+//! each doing in one sift what a pop and a push would do in two, the
+//! selection of the smallest or largest few of many items, and the merging of
+//! sorted runs. They take the same comparison with an auxiliary argument as
+//! `heapq`'s `_with_aux` functions, and keep the same min-heap order, so the
+//! heaps can be mixed freely. The `_max_with_aux` functions keep max-heaps
+//! with the same comparisons, and `Heap` keeps a heap with its comparison.
+//! `IncrementalHeapify` builds or appends to a heap a few steps at a time.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::iter;
+use core::mem;
+
+use heapq::{heapify_with_aux, heap_pop_with_aux, heap_push_with_aux};
+
+
+/// Pops the smallest item of `heap` and pushes `item`, in one sift. Gives the
+/// popped item, or `None` if `heap` was empty, in which case `item` is just
+/// pushed. `item` is pushed even if it's smaller than the one popped.
+/// 
+pub fn heap_replace_with_aux<T, C, A>(heap : &mut Vec<T>,
+                                      item : T,
+                                      cmp  : C,
+                                      aux  : &A) 
+
+    -> Option<T> 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    if heap.is_empty() {
+        heap.push(item);
+        return None;
+    }
+    let top = mem::replace(&mut heap[0], item);
+
+    sift_down(heap, &cmp, aux);
+    Some(top)
+}
+
+/// Pushes `item` onto `heap` and pops the smallest item, in one sift, or none
+/// if `item` is the smallest already, in which case it's given straight back.
+/// 
+pub fn heap_pushpop_with_aux<T, C, A>(heap : &mut [T],
+                                      item : T,
+                                      cmp  : C,
+                                      aux  : &A) 
+
+    -> T 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    match heap.first() {
+        Some(top) if cmp(top, &item, aux) == Ordering::Less => {
+            let top = mem::replace(&mut heap[0], item);
+
+            sift_down(heap, &cmp, aux);
+            top
+        },
+        _ => item,
+    }
+}
+
+/// Whether `heap` is in heap order by `cmp`: no item smaller than its
+/// parent, so the smallest is first. A max-heap of the `_max_with_aux`
+/// functions is in heap order by the reverse of its comparison.
+/// 
+pub fn is_heap_with_aux<T, C, A>(heap: &[T], cmp: C, aux: &A) -> bool
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    (1..heap.len()).all(|i| cmp(&heap[(i - 1) / 2], &heap[i], aux).is_le())
+}
+
+/// The `n` smallest items of `items` by `cmp`, smallest first, as sorting
+/// them all and keeping the first `n` would give, ties in the order they
+/// came. Only the `n` smallest so far are held, on a heap, as the items are
+/// read.
+/// 
+pub fn nsmallest_with_aux<T, I, C, A>(n     : usize,
+                                      items : I,
+                                      cmp   : C,
+                                      aux   : &A) 
+
+    -> Vec<T> 
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let mut kept = bounded_heap(n, items, &cmp, aux);
+
+    kept.sort_by(|a, b| cmp(&a.1, &b.1, aux).then(a.0.cmp(&b.0)));
+    kept.into_iter().map(|(_, item)| item).collect()
+}
+
+/// The `n` largest items of `items` by `cmp`, largest first, ties in the
+/// order they came. See `nsmallest_with_aux()`.
+/// 
+pub fn nlargest_with_aux<T, I, C, A>(n     : usize,
+                                     items : I,
+                                     cmp   : C,
+                                     aux   : &A) 
+
+    -> Vec<T> 
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    nsmallest_with_aux(n, items, |a, b, aux| cmp(b, a, aux), aux)
+}
+
+/// Like `heapq`'s `heapify_with_aux()`, but puts `heap` in max-heap order by
+/// `cmp`, with the largest item on top, for the other `_max_with_aux`
+/// functions. `cmp` is the ordinary comparison, not a reversed one.
+/// 
+pub fn heapify_max_with_aux<T, C, A>(heap: &mut [T], cmp: C, aux: &A)
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heapify_with_aux(heap, |a, b, aux| cmp(b, a, aux), aux);
+}
+
+/// Like `heapq`'s `heap_push_with_aux()`, for a max-heap.
+/// 
+pub fn heap_push_max_with_aux<T, C, A>(heap : &mut Vec<T>,
+                                       item : T,
+                                       cmp  : C,
+                                       aux  : &A)
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heap_push_with_aux(heap, item, |a, b, aux| cmp(b, a, aux), aux);
+}
+
+/// Like `heapq`'s `heap_pop_with_aux()`, for a max-heap: removes the largest
+/// item and gives it, or `None` if the heap is empty.
+/// 
+pub fn heap_pop_max_with_aux<T, C, A>(heap: &mut Vec<T>, cmp: C, aux: &A) 
+
+    -> Option<T> 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    heap_pop_with_aux(heap, |a, b, aux| cmp(b, a, aux), aux)
+}
+
+/// Merges `iters`, each sorted by `cmp`, into one sorted run, lazily: each
+/// source is read only when its next item is needed to tell which comes next.
+/// Items that tie come out in the order of their sources in `iters`, and a
+/// source's own items in its order, as a stable sort of them all would give.
+/// Only the head of each source is held, on a heap.
+/// 
+pub fn merge_with_aux<'a, T, I, C, A>(iters : Vec<I>,
+                                      cmp   : &'a C,
+                                      aux   : &'a A) 
+
+    -> impl Iterator<Item = T> + 'a 
+where
+    T: 'a,
+    I: Iterator<Item = T> + 'a,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let by_head = move |a: &(T, usize), b: &(T, usize), aux: &A| {
+        cmp(&a.0, &b.0, aux).then(a.1.cmp(&b.1))
+    };
+    let mut iters = iters;
+    let mut heads = None;
+    // The source of the item given last, whose next item isn't read yet.
+    let mut taken = None;
+
+    iter::from_fn(move || {
+        let heads = heads.get_or_insert_with(|| {
+            let mut heads = iters.iter_mut().enumerate().filter_map(|(i, it)| {
+                it.next().map(|item| (item, i))
+            }).collect::<Vec<_>>();
+
+            heapify_with_aux(&mut heads, by_head, aux);
+            heads
+        });
+        let next = taken.and_then(|i: usize| {
+            iters[i].next().map(|item| (item, i))
+        });
+        let (item, i) = match next {
+            Some(next) => heap_pushpop_with_aux(heads, next, by_head, aux),
+            None       => heap_pop_with_aux(heads, by_head, aux)?,
+        };
+        taken = Some(i);
+        Some(item)
+    })
+}
+
+/// A min-heap that owns its items with the comparison and the auxiliary
+/// value it orders them by, so every operation uses the same ones. The free
+/// functions leave that to the caller, and a heap pushed with one comparison
+/// and popped with another silently loses its order.
+/// 
+pub struct Heap<T, C, A> {
+    items : Vec<T>,
+    cmp   : C,
+    aux   : A,
+}
+
+impl<T, C, A> Heap<T, C, A>
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    /// An empty heap ordered by `cmp` with `aux`.
+    /// 
+    pub fn new(cmp: C, aux: A) -> Self {
+        Self { items: vec![], cmp, aux }
+    }
+    /// A heap of `items`, put in heap order in linear time.
+    /// 
+    pub fn from_vec(mut items: Vec<T>, cmp: C, aux: A) -> Self {
+        heapify_with_aux(&mut items, &cmp, &aux);
+
+        Self { items, cmp, aux }
+    }
+    pub fn push(&mut self, item: T) {
+        heap_push_with_aux(&mut self.items, item, &self.cmp, &self.aux);
+
+        debug_assert!(settled(&self.items, 0, &self.cmp, &self.aux));
+    }
+    /// Removes the smallest item and gives it, or `None` if the heap is
+    /// empty.
+    /// 
+    pub fn pop(&mut self) -> Option<T> {
+        let top = heap_pop_with_aux(&mut self.items, &self.cmp, &self.aux);
+
+        debug_assert!(settled(&self.items, 0, &self.cmp, &self.aux));
+        top
+    }
+    /// The smallest item, if any.
+    /// 
+    pub fn peek(&self) -> Option<&T> {
+        self.items.first()
+    }
+    /// Pops the smallest item and pushes `item`, in one sift, as
+    /// `heap_replace_with_aux()` does.
+    /// 
+    pub fn replace(&mut self, item: T) -> Option<T> {
+        heap_replace_with_aux(&mut self.items, item, &self.cmp, &self.aux)
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    /// The auxiliary value the items are ordered by.
+    /// 
+    pub fn aux(&self) -> &A {
+        &self.aux
+    }
+    /// The auxiliary value, to change between operations, such as to add
+    /// nodes to an arena the items index. A change mustn't reorder the items
+    /// held.
+    /// 
+    pub fn aux_mut(&mut self) -> &mut A {
+        &mut self.aux
+    }
+    /// The items, smallest first.
+    /// 
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut sorted = Vec::with_capacity(self.len());
+
+        while let Some(item) = self.pop() {
+            sorted.push(item);
+        }
+        sorted
+    }
+}
+
+/// Whether an `IncrementalHeapify` has more to do.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    InProgress,
+    Done,
+}
+
+/// Puts a vector in heap order a few steps at a time, for callers with a
+/// bound on how long each call may take, such as an audio callback. It's
+/// Floyd's heapify, which sifts each parent down from the last to the first,
+/// broken off after a budget of sift steps and resumed where it left off.
+/// 
+/// The items can be read between calls, with `items()`, but they aren't in
+/// heap order until `run_for()` gives `Status::Done`, so they're only handed
+/// back to be pushed and popped then, or by `into_vec()` or `into_heap()`,
+/// which finish the work first.
+/// 
+pub struct IncrementalHeapify<T, C, A> {
+    items : Vec<T>,
+    cmp   : C,
+    aux   : A,
+    floyd : Floyd,
+}
+
+impl<T, C, A> IncrementalHeapify<T, C, A>
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    /// Starts putting `items` in heap order by `cmp` with `aux`. Nothing is
+    /// moved until `run_for()` is called.
+    /// 
+    pub fn new(items: Vec<T>, cmp: C, aux: A) -> Self {
+        Self::append(vec![], items, cmp, aux)
+    }
+    /// Starts appending `items` to `heap`, which must be in heap order by
+    /// `cmp` already. Only the parents of the items appended and their
+    /// ancestors are sifted, as `heap_append_with_aux()` does in one go.
+    /// 
+    pub fn append<I>(mut heap: Vec<T>, items: I, cmp: C, aux: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let start = heap.len();
+
+        heap.extend(items);
+
+        let floyd = Floyd::after(start, heap.len());
+
+        Self { items: heap, cmp, aux, floyd }
+    }
+    /// Carries on for at most `budget` sift steps, each comparing an item
+    /// with its children and swapping it with the smaller, and gives whether
+    /// the items are in heap order yet.
+    /// 
+    pub fn run_for(&mut self, budget: usize) -> Status {
+        self.floyd.run_for(&mut self.items, budget, &self.cmp, &self.aux)
+    }
+    /// Whether the items are in heap order yet.
+    /// 
+    pub fn status(&self) -> Status {
+        self.floyd.status()
+    }
+    /// The items, in whatever order they're in so far.
+    /// 
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+    /// The items in heap order, finishing the work left to do.
+    /// 
+    pub fn into_vec(mut self) -> Vec<T> {
+        self.run_for(usize::MAX);
+        self.items
+    }
+    /// A `Heap` of the items, with the comparison and auxiliary value they
+    /// were ordered by, finishing the work left to do.
+    /// 
+    pub fn into_heap(mut self) -> Heap<T, C, A> {
+        self.run_for(usize::MAX);
+
+        Heap { items: self.items, cmp: self.cmp, aux: self.aux }
+    }
+}
+
+/// Appends `items` to `heap`, which is in heap order by `cmp`, and restores
+/// the order by sifting down the parents of the items appended and their
+/// ancestors, last first, leaving the rest of `heap` as it was.
+/// 
+pub fn heap_append_with_aux<T, I, C, A>(heap  : &mut Vec<T>,
+                                        items : I,
+                                        cmp   : C,
+                                        aux   : &A)
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let start = heap.len();
+
+    heap.extend(items);
+    Floyd::after(start, heap.len()).run_for(heap, usize::MAX, &cmp, aux);
+}
+
+/// How far a Floyd heapify has got. The parents to sift are the ancestors of
+/// the items from some index on, which at each level up are a range of
+/// indices. They're sifted a range at a time, from `next` down to `lo`, and
+/// the range above is from the parent of `lo` to that of `hi`, less any of
+/// it already sifted.
+/// 
+#[derive(Clone, Copy, Debug)]
+struct Floyd {
+    lo   : usize,
+    hi   : usize,
+    /// The next parent to sift, or `None` once the last has been started.
+    next : Option<usize>,
+    /// Where the item being sifted is, while a sift is under way.
+    at   : Option<usize>,
+}
+
+impl Floyd {
+    /// The heapify of a vector of `len` items whose first `start` are in heap
+    /// order already.
+    /// 
+    fn after(start: usize, len: usize) -> Self {
+        let mut floyd = Self { lo: 0, hi: 0, next: None, at: None };
+
+        if len >= 2 && start < len {
+            floyd.lo   = (start.max(1) - 1) / 2;
+            floyd.hi   = (len - 2) / 2;
+            floyd.next = Some(floyd.hi);
+        }
+        floyd
+    }
+    fn status(&self) -> Status {
+        match (self.next, self.at) {
+            (None, None) => Status::Done,
+            _            => Status::InProgress,
+        }
+    }
+    fn run_for<T, C, A>(&mut self, 
+                        heap   : &mut [T], 
+                        budget : usize, 
+                        cmp    : &C, 
+                        aux    : &A) 
+
+        -> Status 
+    where
+        C: Fn(&T, &T, &A) -> Ordering,
+    {
+        for _ in 0..budget {
+            let Some(i) = self.at.or_else(|| self.start_sift()) else {
+                break;
+            };
+            self.at = sift_step(heap, i, cmp, aux);
+        }
+        self.status()
+    }
+    /// Gives the next parent to sift, moving on to the range above once the
+    /// one being sifted is done.
+    /// 
+    fn start_sift(&mut self) -> Option<usize> {
+        let i = self.next?;
+
+        self.next = if i > self.lo {
+            Some(i - 1)
+        } else if self.lo > 0 {
+            self.hi = (self.hi - 1) / 2;
+            self.lo = (self.lo - 1) / 2;
+            Some(self.hi.min(i - 1))
+        } else {
+            None
+        };
+        Some(i)
+    }
+}
+
+/// The `n` smallest items of `items` by `cmp`, each with its index, on a heap
+/// with the largest on top. It never holds more than `n` items.
+/// 
+fn bounded_heap<T, I, C, A>(n: usize, items: I, cmp: &C, aux: &A) 
+
+    -> Vec<(usize, T)> 
+where
+    I: IntoIterator<Item = T>,
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    // Later items count as larger than earlier ones they tie with, so an item
+    // that ties with the largest kept is dropped.
+    let largest_first = |a: &(usize, T), b: &(usize, T), aux: &A| {
+        cmp(&b.1, &a.1, aux).then(b.0.cmp(&a.0))
+    };
+    let mut heap = Vec::with_capacity(n);
+
+    if n == 0 {
+        return heap;
+    }
+    for item in items.into_iter().enumerate() {
+        if heap.len() < n {
+            heap_push_with_aux(&mut heap, item, largest_first, aux);
+        } else {
+            heap_pushpop_with_aux(&mut heap, item, largest_first, aux);
+        }
+    }
+    heap
+}
+
+/// Moves the item at the top of `heap` down, swapping it with the smaller of
+/// its children while that's smaller than it, toward the leaves. The items
+/// below the top must be in heap order already; the whole of `heap` is on
+/// return. `heapq` sifts the same way after a pop, and sifts a pushed item
+/// the other way, up from the leaves toward the top.
+/// 
+fn sift_down<T, C, A>(heap: &mut [T], cmp: &C, aux: &A)
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let mut i = 0;
+
+    while let Some(child) = sift_step(heap, i, cmp, aux) {
+        i = child;
+    }
+    debug_assert!(settled(heap, i, cmp, aux));
+}
+
+/// One level of a sift down: swaps the item at `i` with the smaller of its
+/// children if that's smaller than it, giving where it moved to, or `None`
+/// if it's in order with its children.
+/// 
+fn sift_step<T, C, A>(heap: &mut [T], i: usize, cmp: &C, aux: &A) 
+
+    -> Option<usize> 
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let mut least = i;
+
+    for child in [2 * i + 1, 2 * i + 2] {
+        if child < heap.len()
+            && cmp(&heap[child], &heap[least], aux) == Ordering::Less
+        {
+            least = child;
+        }
+    }
+    (least != i).then(|| {
+        heap.swap(i, least);
+        least
+    })
+}
+
+/// Whether the item at `i` is in heap order with its parent and children.
+/// Checking the whole heap after every operation would make debug builds
+/// take quadratic time, so the operations check the items they moved.
+/// 
+fn settled<T, C, A>(heap: &[T], i: usize, cmp: &C, aux: &A) -> bool
+where
+    C: Fn(&T, &T, &A) -> Ordering,
+{
+    let parent   = i.checked_sub(1).map(|p| p / 2);
+    let in_order = |a: usize, b: usize| cmp(&heap[a], &heap[b], aux).is_le();
+
+    parent.is_none_or(|p| in_order(p, i))
+        && [2 * i + 1, 2 * i + 2].iter()
+                                 .all(|&c| c >= heap.len() || in_order(i, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SplitMix64;
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    fn by_value(a: &u64, b: &u64, _: &()) -> Ordering {
+        a.cmp(b)
+    }
+
+    fn assert_heap(heap: &[u64]) {
+        assert!(is_heap_with_aux(heap, by_value, &()), "{:?}", heap);
+    }
+
+    #[test]
+    fn checks_heap_order() {
+        assert!(is_heap_with_aux(&[], by_value, &()));
+        assert!(is_heap_with_aux(&[5], by_value, &()));
+        assert!(is_heap_with_aux(&[1, 3, 2, 3, 4, 2], by_value, &()));
+        assert!(!is_heap_with_aux(&[2, 1], by_value, &()));
+        assert!(!is_heap_with_aux(&[1, 3, 2, 3, 4, 1], by_value, &()));
+
+        let mut max = vec![1, 5, 3, 4];
+
+        heapify_max_with_aux(&mut max, by_value, &());
+
+        assert!(is_heap_with_aux(&max, |a, b, aux| by_value(b, a, aux), &()));
+        assert!(!is_heap_with_aux(&max, by_value, &()));
+    }
+
+    #[test]
+    fn fuzzed_operations() {
+        let mut rng       = SplitMix64(280);
+        let mut heap      = vec![];
+        let mut reference = BinaryHeap::new();
+
+        for _ in 0..20_000 {
+            let item = rng.below(1000) as u64;
+
+            match rng.below(4) {
+                0 | 1 => {
+                    heap_push_with_aux(&mut heap, item, by_value, &());
+                    reference.push(Reverse(item));
+                },
+                2 => {
+                    assert_eq!(heap_pop_with_aux(&mut heap, by_value, &()),
+                               reference.pop().map(|Reverse(x)| x));
+                },
+                _ => {
+                    let top = heap_replace_with_aux(&mut heap, item, by_value,
+                                                    &());
+
+                    assert_eq!(top, reference.pop().map(|Reverse(x)| x));
+                    reference.push(Reverse(item));
+                },
+            }
+            assert_heap(&heap);
+        }
+        // Whatever is left pops in the order the reference does.
+        let popped = iter::from_fn(|| heap_pop_with_aux(&mut heap, by_value,
+                                                        &()));
+
+        assert!(popped.eq(iter::from_fn(|| reference.pop().map(|r| r.0))));
+    }
+
+    #[test]
+    fn keeps_heap_order() {
+        let mut rng  = SplitMix64(275);
+        let mut heap = vec![];
+
+        for _ in 0..5000 {
+            let item = rng.below(100) as u64;
+            let min  = heap.iter().copied().min();
+
+            match rng.below(4) {
+                0 => {
+                    heap_push_with_aux(&mut heap, item, by_value, &());
+                },
+                1 => {
+                    assert_eq!(heap_pop_with_aux(&mut heap, by_value, &()),
+                               min);
+                },
+                2 => {
+                    let len = heap.len();
+
+                    assert_eq!(heap_replace_with_aux(&mut heap, item, by_value,
+                                                     &()),
+                               min);
+                    assert_eq!(heap.len(), len.max(1));
+                },
+                _ => {
+                    let popped = heap_pushpop_with_aux(&mut heap, item,
+                                                       by_value, &());
+
+                    assert_eq!(popped, min.map_or(item, |min| min.min(item)));
+                },
+            }
+            assert_heap(&heap);
+        }
+    }
+
+    #[test]
+    fn incremental_heapify() {
+        let mut rng = SplitMix64(267);
+
+        for len in [0, 1, 2, 3, 7, 8, 100, 3000] {
+            let items = (0..len).map(|_| rng.below(1000) as u64)
+                                .collect::<Vec<_>>();
+            let whole = IncrementalHeapify::new(items.clone(), by_value, ())
+                                           .into_vec();
+            let mut sorted = items.clone();
+
+            sorted.sort_unstable();
+            assert_heap(&whole);
+
+            // A step at a time gives the same heap, and between steps the
+            // items are all there to read.
+            let mut heapify = IncrementalHeapify::new(items, by_value, ());
+            let mut calls   = 0;
+
+            while heapify.run_for(1) == Status::InProgress {
+                let mut seen = heapify.items().to_vec();
+
+                seen.sort_unstable();
+                assert_eq!(seen, sorted);
+                calls += 1;
+            }
+            assert!(calls <= 2 * len);
+            assert_eq!(heapify.status(), Status::Done);
+            assert_eq!(heapify.run_for(1), Status::Done);
+            assert_eq!(heapify.items(), whole);
+
+            let heap = heapify.into_heap();
+
+            assert_eq!(heap.into_sorted_vec(), sorted);
+        }
+    }
+
+    #[test]
+    fn incremental_append() {
+        let mut rng = SplitMix64(268);
+
+        for (len, more) in [(0, 10), (1, 1), (5, 0), (10, 3), (100, 1000),
+                            (1000, 7)] {
+            let mut heap = (0..len).map(|_| rng.below(1000) as u64)
+                                   .collect::<Vec<_>>();
+            let items    = (0..more).map(|_| rng.below(1000) as u64)
+                                    .collect::<Vec<_>>();
+
+            heapify_with_aux(&mut heap, by_value, &());
+
+            let mut whole = heap.clone();
+
+            heap_append_with_aux(&mut whole, items.clone(), by_value, &());
+            assert_heap(&whole);
+
+            let mut append = IncrementalHeapify::append(heap.clone(),
+                                                        items.clone(),
+                                                        by_value, ());
+
+            while append.run_for(1) == Status::InProgress {}
+
+            assert_eq!(append.items(), whole);
+
+            let mut sorted = [heap, items].concat();
+
+            sorted.sort_unstable();
+            assert_eq!(append.into_heap().into_sorted_vec(), sorted);
+        }
+    }
+
+    #[test]
+    fn max_heaps() {
+        let mut heap = (0..100).map(|i| i * 7919 % 100).collect::<Vec<u64>>();
+
+        heapify_max_with_aux(&mut heap, by_value, &());
+
+        let popped = iter::from_fn(|| {
+            heap_pop_max_with_aux(&mut heap, by_value, &())
+        });
+        assert!(popped.eq((0..100).rev()));
+
+        for i in (0..100).map(|i| i * 31 % 100) {
+            heap_push_max_with_aux(&mut heap, i, by_value, &());
+        }
+        let popped = iter::from_fn(|| {
+            heap_pop_max_with_aux(&mut heap, by_value, &())
+        });
+        assert!(popped.eq((0..100).rev()));
+
+        let mut rng       = SplitMix64(279);
+        let mut reference = BinaryHeap::new();
+
+        for _ in 0..5000 {
+            match rng.below(3) {
+                0 => {
+                    assert_eq!(heap_pop_max_with_aux(&mut heap, by_value, &()),
+                               reference.pop());
+                },
+                _ => {
+                    let item = rng.below(100) as u64;
+
+                    heap_push_max_with_aux(&mut heap, item, by_value, &());
+                    reference.push(item);
+                },
+            }
+            assert_eq!(heap.first(), reference.peek());
+        }
+    }
+
+    #[test]
+    fn heap_type() {
+        let mut rng   = SplitMix64(278);
+        let mut heap  = Heap::new(by_value, ());
+        let mut model = vec![];
+
+        assert_eq!((heap.pop(), heap.peek()), (None, None));
+
+        for _ in 0..5000 {
+            let item = rng.below(100) as u64;
+
+            model.sort_unstable();
+
+            match rng.below(3) {
+                0 => {
+                    heap.push(item);
+                    model.push(item);
+                },
+                1 => {
+                    let min = (!model.is_empty()).then(|| model.remove(0));
+
+                    assert_eq!(heap.pop(), min);
+                },
+                _ => {
+                    let min = (!model.is_empty()).then(|| model.remove(0));
+
+                    assert_eq!(heap.replace(item), min);
+                    model.push(item);
+                },
+            }
+            assert_eq!(heap.peek(), model.iter().min());
+            assert_eq!(heap.len(), model.len());
+            assert_heap(&heap.items);
+        }
+        model.sort_unstable();
+
+        assert_eq!(heap.into_sorted_vec(), model);
+
+        // Ordered through the auxiliary value, which grows between
+        // operations, as the arena of a Huffman tree's nodes does.
+        let by_weight = |a: &usize, b: &usize, weights: &Vec<u64>| {
+            weights[*a].cmp(&weights[*b]).then(a.cmp(b))
+        };
+        let weights  = vec![5, 1, 4, 1];
+        let mut heap = Heap::from_vec(vec![0, 1, 2, 3], by_weight, weights);
+
+        assert_eq!(heap.peek(), Some(&1));
+
+        heap.aux_mut().push(0);
+        heap.push(4);
+
+        assert!(!heap.is_empty());
+        assert_eq!(heap.aux().len(), 5);
+        assert_eq!(heap.into_sorted_vec(), [4, 1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn fused_merges_match_pop_pop_push() {
+        // Merges the two smallest weights until one is left, as a Huffman
+        // tree is built, recording each pair merged. Ties are broken by id,
+        // so the order is total and both ways must merge the same pairs.
+        fn cmp(a: &(u64, usize), b: &(u64, usize), _: &()) -> Ordering {
+            a.cmp(b)
+        }
+        let mut rng = SplitMix64(2752);
+
+        for len in [1, 2, 3, 10, 257, 1000] {
+            let weights = (0..len).map(|id| (1 + rng.below(20) as u64, id))
+                                  .collect::<Vec<_>>();
+
+            let (mut slow, mut fast) = (weights.clone(), weights);
+            let (mut slow_pairs, mut fast_pairs) = (vec![], vec![]);
+            let mut next = len;
+
+            heapq::heapify_with_aux(&mut slow, cmp, &());
+            heapq::heapify_with_aux(&mut fast, cmp, &());
+
+            while slow.len() > 1 {
+                let a = heap_pop_with_aux(&mut slow, cmp, &()).unwrap();
+                let b = heap_pop_with_aux(&mut slow, cmp, &()).unwrap();
+
+                heap_push_with_aux(&mut slow, (a.0 + b.0, next), cmp, &());
+                slow_pairs.push((a, b));
+                next += 1;
+            }
+            next = len;
+
+            while fast.len() > 1 {
+                let a      = heap_pop_with_aux(&mut fast, cmp, &()).unwrap();
+                let merged = (a.0 + fast[0].0, next);
+                let b      = heap_replace_with_aux(&mut fast, merged, cmp, &());
+
+                fast_pairs.push((a, b.unwrap()));
+                next += 1;
+            }
+            assert_eq!(fast_pairs, slow_pairs);
+            assert_eq!(fast, slow);
+        }
+    }
+
+    #[test]
+    fn selection() {
+        fn by_key(a: &(u64, usize), b: &(u64, usize), _: &()) -> Ordering {
+            a.0.cmp(&b.0)
+        }
+        let mut rng = SplitMix64(2762);
+
+        for len in [0, 1, 2, 5, 100, 1000] {
+            // Few distinct keys, so there are ties, told apart by the index.
+            let items = (0..len).map(|i| (rng.below(10) as u64, i))
+                                .collect::<Vec<_>>();
+
+            let mut sorted = items.clone();
+            sorted.sort_by_key(|&(key, _)| key);
+
+            let mut reversed = items.clone();
+            reversed.sort_by_key(|&(key, _)| std::cmp::Reverse(key));
+
+            for n in [0, 1, 3, len / 2, len, len + 1] {
+                assert_eq!(nsmallest_with_aux(n, items.clone(), by_key, &()),
+                           sorted[..n.min(len)]);
+                assert_eq!(nlargest_with_aux(n, items.clone(), by_key, &()),
+                           reversed[..n.min(len)]);
+            }
+        }
+        // The heap holds no more than `n` items however many are read.
+        let heap = bounded_heap(3, 0..100_000_u64, &by_value, &());
+
+        assert_eq!((heap.len(), heap.capacity()), (3, 3));
+        assert!(bounded_heap(0, 0..10_u64, &by_value, &()).is_empty());
+    }
+
+    #[test]
+    fn merges_sorted_runs() {
+        fn by_key(a: &(u64, usize), b: &(u64, usize), _: &()) -> Ordering {
+            a.0.cmp(&b.0)
+        }
+        let mut rng = SplitMix64(2772);
+
+        for runs in [0, 1, 2, 5, 20] {
+            // Few distinct keys, so there are ties, told apart by the index.
+            let mut next = 0;
+            let runs = (0..runs).map(|_| {
+                let mut run = (0..rng.below(50)).map(|_| {
+                    next += 1;
+                    (rng.below(10) as u64, next)
+                }).collect::<Vec<_>>();
+
+                run.sort_by_key(|&(key, _)| key);
+                run
+            }).collect::<Vec<_>>();
+
+            let mut sorted = runs.concat();
+            sorted.sort_by_key(|&(key, _)| key);
+
+            let iters = runs.into_iter().map(Vec::into_iter).collect();
+
+            assert!(merge_with_aux(iters, &by_key, &()).eq(sorted));
+        }
+        let empty = vec![vec![], vec![3, 4], vec![]];
+        let iters = empty.into_iter().map(Vec::into_iter).collect();
+
+        assert!(merge_with_aux(iters, &by_value, &()).eq([3, 4]));
+    }
+
+    #[test]
+    fn merges_lazily() {
+        fn guarded(items: Vec<u64>) -> impl Iterator<Item = u64> {
+            items.into_iter().chain(iter::from_fn(|| {
+                panic!("read past what the merge needed")
+            }))
+        }
+        let iters      = vec![guarded(vec![1, 2]), guarded(vec![3])];
+        let mut merged = merge_with_aux(iters, &by_value, &());
+
+        // The first source is read for 2 only once 1 is given, and neither
+        // is read again until 2 is.
+        assert_eq!(merged.next(), Some(1));
+        assert_eq!(merged.next(), Some(2));
+    }
+}
@@ -0,0 +1,13 @@
+//! Heaps ordered by a comparison that takes an auxiliary argument, such as
+//! the arena a heap of handles indexes into: the `heapq` crate's push, pop,
+//! and heapify, and the rest of the operations built on them here.
+//! 
+
+pub use ::heapq::{heap_pop_with_aux, heap_push_with_aux, heapify_with_aux};
+
+pub use crate::heap::{heapify_max_with_aux, heap_pop_max_with_aux};
+pub use crate::heap::{heap_append_with_aux, IncrementalHeapify, Status};
+pub use crate::heap::{heap_push_max_with_aux, merge_with_aux, Heap};
+pub use crate::heap::{heap_pushpop_with_aux, heap_replace_with_aux};
+pub use crate::heap::{is_heap_with_aux, nlargest_with_aux, nsmallest_with_aux};
+pub use crate::median::RunningMedian;
@@ -0,0 +1,294 @@
+//! Coding sequences of `u32` token ids, such as the ids a BPE tokenizer gives,
+//! without squeezing them through chars or `u16`s.
+//! 
+//! Ids are counted in an array indexed by id when the largest is small, and in
+//! a map otherwise, and a codebook likewise keeps its codes in an array or a
+//! map depending on how densely its ids fill their range. The codes are
+//! canonical, so a codebook is saved as its ids and code lengths alone.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::probability::{char_to_id, id_to_char};
+use crate::{HuffmanError, MAX_SYMBOLS};
+use crate::canonical::CanonicalDecoder;
+use crate::codebook::{generate_huffman_codes_weighted, Code};
+use crate::io::{BitBuffer, BitWriter};
+
+
+/// Ids below this are counted in an array.
+/// 
+const DENSE_COUNT_LIMIT: usize = 1 << 16;
+
+/// A codebook keeps its codes in an array if it has at least one id for every
+/// this many slots the array would need.
+/// 
+const DENSE_CODES_RATIO: usize = 4;
+
+
+/// The number of times each id in `ids` occurs, in order of id.
+/// 
+fn count_ids(ids: &[u32]) -> Vec<(u32, u64)> {
+    let max = ids.iter().copied().max().map_or(0, |max| max as usize + 1);
+
+    if max <= DENSE_COUNT_LIMIT {
+        let mut counts = vec![0_u64; max];
+
+        for &id in ids {
+            counts[id as usize] += 1;
+        }
+        (0..).zip(counts).filter(|&(_, n)| n > 0).collect()
+    } else {
+        let mut counts = HashMap::<u32, u64>::new();
+
+        for &id in ids {
+            *counts.entry(id).or_default() += 1;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+
+        counts.sort_unstable();
+        counts
+    }
+}
+
+/// The codes of an `IdCodebook`: in an array indexed by id for ids that fill
+/// their range densely, and in a map for sparse ones.
+/// 
+#[derive(Clone, Debug)]
+enum IdCodes {
+    Dense(Vec<Option<Code>>),
+    Sparse(HashMap<u32, Code>),
+}
+
+impl IdCodes {
+    fn get(&self, id: u32) -> Option<Code> {
+        match self {
+            IdCodes::Dense(codes)  => codes.get(id as usize).copied().flatten(),
+            IdCodes::Sparse(codes) => codes.get(&id).copied(),
+        }
+    }
+}
+
+
+/// Canonical codes for an alphabet of `u32` ids.
+/// 
+#[derive(Clone, Debug)]
+pub struct IdCodebook {
+    codes   : IdCodes,
+    decoder : CanonicalDecoder<u32>,
+}
+
+impl IdCodebook {
+    /// Generates codes for the ids in `ids`. A lone id gets the code "0".
+    /// Fails with `HuffmanError::TooManySymbols` if there are more distinct
+    /// ids than a tree can hold.
+    /// 
+    pub fn from_ids(ids: &[u32]) -> Result<Self, HuffmanError> {
+        let counts = count_ids(ids);
+
+        if counts.len() > MAX_SYMBOLS {
+            return Err(HuffmanError::TooManySymbols {
+                symbols : counts.len(),
+                max     : MAX_SYMBOLS,
+            });
+        }
+        // The tree is built over chars, so each id stands in as the char for
+        // its index among the ids.
+        let lengths = match counts[..] {
+            []        => vec![],
+            [(id, _)] => vec![(id, 1)],
+            _         => {
+                let weights = counts.iter().enumerate().map(|(i, &(_, n))| {
+                    (id_to_char(i).unwrap(), n)
+                });
+                generate_huffman_codes_weighted(weights)
+                    .into_iter()
+                    .map(|(c, code)| (counts[char_to_id(c)].0,
+                                      code.len() as u8))
+                    .collect()
+            },
+        };
+        Self::from_lengths(&lengths)
+    }
+    /// The canonical codes with the given `(id, length)` pairs. Fails as
+    /// `CanonicalDecoder::from_lengths()` does.
+    /// 
+    pub fn from_lengths(lengths: &[(u32, u8)]) -> Result<Self, HuffmanError> {
+        let decoder = CanonicalDecoder::from_lengths(lengths)?;
+        let slots   = lengths.iter().map(|&(id, _)| id as usize + 1).max();
+
+        let codes = match slots {
+            Some(slots) if slots <= lengths.len() * DENSE_CODES_RATIO => {
+                let mut codes = vec![None; slots];
+
+                for (id, code) in decoder.codes() {
+                    codes[id as usize] = Some(code);
+                }
+                IdCodes::Dense(codes)
+            },
+            _ => IdCodes::Sparse(decoder.codes().collect()),
+        };
+        Ok(Self { codes, decoder })
+    }
+    /// The code for `id`, if it has one.
+    /// 
+    pub fn code_for(&self, id: u32) -> Option<Code> {
+        self.codes.get(id)
+    }
+    /// Whether the codes are kept in an array indexed by id, rather than a
+    /// map.
+    /// 
+    pub fn is_dense(&self) -> bool {
+        matches!(self.codes, IdCodes::Dense(_))
+    }
+    /// The number of ids with codes.
+    /// 
+    pub fn len(&self) -> usize {
+        self.decoder.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.decoder.is_empty()
+    }
+    /// Packs the codes for `ids`, most significant bit first. Fails with
+    /// `HuffmanError::IdNotInCodebook` if an id has no code.
+    /// 
+    pub fn encode(&self, ids: &[u32]) -> Result<BitBuffer, HuffmanError> {
+        let mut writer = BitWriter::new();
+
+        for (index, &id) in ids.iter().enumerate() {
+            let code = self.code_for(id).ok_or(
+                HuffmanError::IdNotInCodebook { id, index }
+            )?;
+            writer.write_code(code);
+        }
+        Ok(writer.into_buffer())
+    }
+    /// Decodes `bits` into the ids they encode. Fails as
+    /// `DecoderFsm::decode()` does.
+    /// 
+    pub fn decode(&self, bits: &BitBuffer) -> Result<Vec<u32>, HuffmanError> {
+        let mut r   = bits.reader();
+        let mut ids = vec![];
+
+        while r.remaining() > 0 {
+            let id = self.decoder.decode_symbol(&mut r).map_err(|e| match e {
+                HuffmanError::InvalidCode { bit_offset, .. } => {
+                    HuffmanError::InvalidCode {
+                        bit_offset, symbols: ids.len()
+                    }
+                },
+                HuffmanError::TruncatedBitstream { bit_offset, .. } => {
+                    HuffmanError::TruncatedBitstream {
+                        bit_offset, symbols: ids.len()
+                    }
+                },
+                e => e,
+            })?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+    /// Saves the codes as the number of ids, then each id and its code length
+    /// in order of id, with integers little-endian.
+    /// 
+    pub fn export(&self) -> Vec<u8> {
+        let mut lengths = self.decoder.codes()
+                                      .map(|(id, code)| (id, code.len))
+                                      .collect::<Vec<_>>();
+
+        lengths.sort_unstable();
+
+        let mut bytes = Vec::with_capacity(4 + 5 * lengths.len());
+
+        bytes.extend((lengths.len() as u32).to_le_bytes());
+
+        for (id, len) in lengths {
+            bytes.extend(id.to_le_bytes());
+            bytes.push(len);
+        }
+        bytes
+    }
+    /// Loads codes saved by `export()`. Fails with
+    /// `HuffmanError::MalformedData` if the blob is truncated, has trailing
+    /// bytes, or its lengths aren't those of a prefix code.
+    /// 
+    pub fn import(bytes: &[u8]) -> Result<Self, HuffmanError> {
+        let truncated = HuffmanError::MalformedData("the data is truncated");
+        let (count, entries) = bytes.split_first_chunk::<4>()
+                                    .ok_or(truncated.clone())?;
+        let count = u32::from_le_bytes(*count) as usize;
+
+        if entries.len() / 5 < count {
+            return Err(truncated);
+        }
+        if entries.len() != 5 * count {
+            return Err(HuffmanError::MalformedData("trailing bytes"));
+        }
+        let lengths = entries.chunks_exact(5).map(|entry| {
+            (u32::from_le_bytes(entry[..4].try_into().unwrap()), entry[4])
+        }).collect::<Vec<_>>();
+
+        Self::from_lengths(&lengths)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dense_and_sparse_ids() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let dense = text.bytes().map(u32::from).collect::<Vec<_>>();
+
+        // Spread the same ids thinly, all above 65,535.
+        let sparse = dense.iter()
+                          .map(|&id| id * 1000 + 70_000)
+                          .collect::<Vec<_>>();
+
+        for (ids, is_dense) in [(&dense, true), (&sparse, false)] {
+            let codebook = IdCodebook::from_ids(ids).unwrap();
+            let bits     = codebook.encode(ids).unwrap();
+
+            assert_eq!(codebook.is_dense(), is_dense);
+            assert_eq!(codebook.decode(&bits).unwrap(), *ids);
+            assert!(bits.bit_len() < ids.len() * 8);
+
+            let copy = IdCodebook::import(&codebook.export()).unwrap();
+
+            assert_eq!(copy.decode(&bits).unwrap(), *ids);
+        }
+        let dense_codes  = IdCodebook::from_ids(&dense).unwrap();
+        let sparse_codes = IdCodebook::from_ids(&sparse).unwrap();
+
+        // The mapping is one-to-one, so the codes have the same lengths.
+        for (&d, &s) in dense.iter().zip(&sparse).take(100) {
+            assert_eq!(dense_codes.code_for(d).unwrap().len,
+                       sparse_codes.code_for(s).unwrap().len);
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        let lone = IdCodebook::from_ids(&[70_000; 3]).unwrap();
+
+        let bits = BitBuffer::try_new(vec![0], 3).unwrap();
+
+        assert_eq!(lone.encode(&[70_000; 3]), Ok(bits.clone()));
+        assert_eq!(lone.decode(&bits), Ok(vec![70_000; 3]));
+        assert!(IdCodebook::from_ids(&[]).unwrap().is_empty());
+
+        let codebook = IdCodebook::from_ids(&[1, 2, 2, u32::MAX]).unwrap();
+
+        assert_eq!(codebook.encode(&[2, 3]),
+                   Err(HuffmanError::IdNotInCodebook { id: 3, index: 1 }));
+        assert_eq!(IdCodebook::import(&codebook.export()[..8]).err(),
+                   Some(HuffmanError::MalformedData("the data is truncated")));
+        assert_eq!(IdCodebook::import(&[codebook.export(), vec![0]].concat())
+                       .err(),
+                   Some(HuffmanError::MalformedData("trailing bytes")));
+    }
+}
@@ -0,0 +1,267 @@
+//! Compression that the caller drives a chunk at a time, for environments that
+//! can't block until a whole input has been processed.
+//! 
+
+use alloc::vec::Vec;
+
+use crate::bits::BitWriter;
+use crate::HuffmanError;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+
+
+/// What an `IncrementalCompressor` is doing after a call to `feed()` or
+/// `finish()`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Counting characters; `bytes_seen` bytes have been fed so far.
+    Counting { bytes_seen: u64 },
+    /// Encoding; `output_ready` bytes can be taken with `take_output()`, and
+    /// `remaining` bytes of the input have yet to be fed again.
+    Encoding { output_ready: usize, remaining: u64 },
+    /// Done; `output_ready` bytes are left to take, and the whole output is
+    /// `bit_len` bits long.
+    Done { output_ready: usize, bit_len: u64 },
+}
+
+#[derive(Debug)]
+enum Phase {
+    Counting(FrequencyTable),
+    Encoding(HuffmanCodebook),
+    /// The codes, and the length of the whole output in bits.
+    Done(HuffmanCodebook, u64),
+}
+
+/// Compresses text fed to it in chunks of any size, so the work can be spread
+/// out, e.g. one chunk per frame of a game loop.
+/// 
+/// Compression takes two passes over the input, and the caller makes both:
+/// 
+/// 1. Feed the whole input with `feed()`, then call `finish()`. This counts
+///    the characters and builds the codes.
+/// 2. Feed exactly the same bytes again, in chunks of any size, taking the
+///    output with `take_output()` whenever some is ready. Then call `finish()`
+///    again to flush the last partial byte.
+/// 
+/// The output is the packed codes, most significant bit first, padded with
+/// zeros to a whole byte. It's decoded with the `codebook()` it was made with,
+/// and `Step::Done::bit_len`.
+/// 
+#[derive(Debug)]
+pub struct IncrementalCompressor {
+    phase     : Phase,
+    pending   : Vec<u8>,
+    writer    : BitWriter,
+    total     : u64,
+    remaining : u64,
+}
+
+impl Default for IncrementalCompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncrementalCompressor {
+    pub fn new() -> Self {
+        Self {
+            phase     : Phase::Counting(FrequencyTable::default()),
+            pending   : Vec::new(),
+            writer    : BitWriter::new(),
+            total     : 0,
+            remaining : 0,
+        }
+    }
+    /// Feeds the next chunk of input. It needn't end on a character boundary.
+    /// 
+    /// Fails with `HuffmanError::InvalidUtf8` if the input isn't UTF-8, and
+    /// with `HuffmanError::InputMismatch` if the input fed while encoding
+    /// isn't what was fed while counting, or if called after encoding is done.
+    /// 
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Step, HuffmanError> {
+        if let Phase::Encoding(_) = self.phase {
+            if chunk.len() as u64 > self.remaining {
+                return Err(HuffmanError::InputMismatch);
+            }
+            self.remaining -= chunk.len() as u64;
+        } else {
+            self.total += chunk.len() as u64;
+        }
+        self.pending.extend_from_slice(chunk);
+
+        let valid = match core::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(HuffmanError::InvalidUtf8),
+        };
+        // SAFETY: `from_utf8()` validated the first `valid` bytes.
+        let text = unsafe { 
+            core::str::from_utf8_unchecked(&self.pending[..valid]) 
+        };
+
+        match &mut self.phase {
+            Phase::Counting(freqs) => {
+                for c in text.chars() {
+                    freqs.add(c, 1);
+                }
+            },
+            Phase::Encoding(codebook) => {
+                for c in text.chars() {
+                    let code = codebook.code_for(c)
+                                       .ok_or(HuffmanError::InputMismatch)?;
+                    self.writer.write_code(code);
+                }
+            },
+            Phase::Done(..) => return Err(HuffmanError::InputMismatch),
+        }
+        self.pending.drain(..valid);
+
+        Ok(self.step())
+    }
+    /// Ends the current pass. After counting, this builds the codes and starts
+    /// encoding; after encoding, it flushes the last partial byte.
+    /// 
+    /// Fails with `HuffmanError::InvalidUtf8` if the input ended partway
+    /// through a character, with `HuffmanError::InputMismatch` if less input
+    /// was fed while encoding than while counting, and with
+    /// `HuffmanError::CodeTooLong` if a code for the counted input would be
+    /// longer than 64 bits.
+    /// 
+    pub fn finish(&mut self) -> Result<Step, HuffmanError> {
+        if !self.pending.is_empty() {
+            return Err(HuffmanError::InvalidUtf8);
+        }
+        match self.phase {
+            Phase::Encoding(_) if self.remaining > 0 => {
+                return Err(HuffmanError::InputMismatch);
+            },
+            Phase::Done(..) => {
+                return Ok(self.step());
+            },
+            _ => {},
+        }
+        let phase = core::mem::replace(&mut self.phase, 
+                                      Phase::Counting(Default::default()));
+        self.phase = match phase {
+            Phase::Counting(freqs) => {
+                self.remaining = self.total;
+                Phase::Encoding(HuffmanCodebook::try_from_frequencies(freqs)?)
+            },
+            Phase::Encoding(codebook) | 
+            Phase::Done(codebook, _) => {
+                Phase::Done(codebook, self.writer.bit_len())
+            },
+        };
+        Ok(self.step())
+    }
+    /// Removes and returns the output ready so far. Until encoding is done,
+    /// that's only complete bytes.
+    /// 
+    pub fn take_output(&mut self) -> Vec<u8> {
+        match self.phase {
+            Phase::Done(..) => self.writer.finish(),
+            _              => self.writer.take_complete(),
+        }
+    }
+    /// The codes the input is being encoded with, once counting is finished.
+    /// 
+    pub fn codebook(&self) -> Option<&HuffmanCodebook> {
+        match &self.phase {
+            Phase::Counting(_)        => None,
+            Phase::Encoding(codebook) |
+            Phase::Done(codebook, _)  => Some(codebook),
+        }
+    }
+    fn step(&self) -> Step {
+        match self.phase {
+            Phase::Counting(_) => Step::Counting { bytes_seen: self.total },
+            Phase::Encoding(_) => Step::Encoding {
+                output_ready : self.writer.complete_bytes(),
+                remaining    : self.remaining,
+            },
+            Phase::Done(_, bit_len) => Step::Done {
+                output_ready : self.writer.buffered_bytes(),
+                bit_len,
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::io::{BitBuffer, DecoderFsm};
+
+    /// Runs both passes over `input` in chunks of `size` bytes.
+    /// 
+    fn compress(input: &[u8], size: usize) 
+
+        -> Result<(Vec<u8>, u64, HuffmanCodebook), HuffmanError> 
+    {
+        let mut comp = IncrementalCompressor::new();
+
+        for chunk in input.chunks(size) {
+            comp.feed(chunk)?;
+        }
+        assert_eq!(comp.finish()?, Step::Encoding { 
+            output_ready : 0, 
+            remaining    : input.len() as u64,
+        });
+        let mut output = vec![];
+
+        for chunk in input.chunks(size) {
+            if let Step::Encoding { output_ready, .. } = comp.feed(chunk)? {
+                let taken = comp.take_output();
+
+                assert_eq!(taken.len(), output_ready);
+                output.extend(taken);
+            }
+        }
+        let Step::Done { bit_len, .. } = comp.finish()? else { 
+            panic!("not done") 
+        };
+        output.extend(comp.take_output());
+
+        Ok((output, bit_len, comp.codebook().unwrap().clone()))
+    }
+
+    #[test]
+    fn one_byte_chunks() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap()
+                 + "\u{e9}\u{20ac}\u{1f600}";
+
+        for size in [1, 3, 4096] {
+            let (output, bit_len, codebook) = compress(text.as_bytes(), size)
+                                                  .unwrap();
+            let fsm = DecoderFsm::from_codebook(&codebook).unwrap();
+
+            let bits = BitBuffer::try_new(output, bit_len as usize).unwrap();
+
+            assert_eq!(bits.as_bytes().len() as u64, bit_len.div_ceil(8));
+            assert_eq!(fsm.decode(&bits).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn refeed_contract() {
+        let mut comp = IncrementalCompressor::new();
+
+        comp.feed(b"abc").unwrap();
+        comp.finish().unwrap();
+
+        assert_eq!(comp.feed(b"abcd"), Err(HuffmanError::InputMismatch));
+        assert_eq!(comp.feed(b"ab"),   Ok(Step::Encoding { output_ready : 0, 
+                                                           remaining    : 1 }));
+        assert_eq!(comp.finish(),      Err(HuffmanError::InputMismatch));
+        assert_eq!(comp.feed(b"x"),    Err(HuffmanError::InputMismatch));
+
+        let mut comp = IncrementalCompressor::new();
+
+        assert_eq!(comp.feed(&[0xe2, 0x82]), 
+                   Ok(Step::Counting { bytes_seen: 2 }));
+        assert_eq!(comp.finish(), Err(HuffmanError::InvalidUtf8));
+        assert_eq!(comp.feed(&[0xff]), Err(HuffmanError::InvalidUtf8));
+    }
+}
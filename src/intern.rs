@@ -0,0 +1,318 @@
+//! Interning of word-level symbols, so each distinct word is stored once and
+//! referred to everywhere else by a dense `u32` id. An `InternedCodebook`
+//! builds its tree over the ids rather than the words, so no word is copied
+//! past the interner.
+//! 
+
+use alloc::vec;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::{build_huffman_tree, tree_code_bits, tree_size, HuffmanError};
+use crate::{NodeMem, MAX_SYMBOLS};
+use crate::codebook::IdCodebook;
+use crate::collections::HashMap;
+use crate::io::BitBuffer;
+
+
+/// Assigns ids to strings in the order they're first seen, and maps ids back
+/// to strings. Each distinct string is allocated once and shared between the
+/// two directions.
+/// 
+#[derive(Clone, Debug, Default)]
+pub struct SymbolInterner {
+    ids     : HashMap<Rc<str>, u32>,
+    symbols : Vec<Rc<str>>,
+}
+
+impl SymbolInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The id of `symbol`, giving it the next id if it's new.
+    /// 
+    /// # Panics
+    /// 
+    /// If more than `u32::MAX` distinct symbols are interned.
+    /// 
+    pub fn intern(&mut self, symbol: &str) -> u32 {
+        if let Some(&id) = self.ids.get(symbol) {
+            return id;
+        }
+        let id     = u32::try_from(self.symbols.len())
+                         .expect("too many symbols to intern");
+        let symbol = Rc::<str>::from(symbol);
+
+        self.ids.insert(symbol.clone(), id);
+        self.symbols.push(symbol);
+        id
+    }
+    /// The id of `symbol`, if it's been interned.
+    /// 
+    pub fn get(&self, symbol: &str) -> Option<u32> {
+        self.ids.get(symbol).copied()
+    }
+    /// The symbol with the id `id`, if there is one.
+    /// 
+    pub fn resolve(&self, id: u32) -> Option<&str> {
+        self.symbols.get(id as usize).map(|s| &**s)
+    }
+    /// The number of distinct symbols interned; ids run from 0 to one less.
+    /// 
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+    /// Splits `text` into words, runs of whitespace, and single punctuation
+    /// and other characters, and interns each of them. Concatenating the
+    /// tokens gives back `text`, so nothing is lost.
+    /// 
+    pub fn intern_text(&mut self, text: &str) -> Vec<u32> {
+        tokens(text).map(|token| self.intern(token)).collect()
+    }
+    /// Concatenates the symbols with the ids in `ids`, or gives `None` if any
+    /// of them isn't an interned id.
+    /// 
+    pub fn resolve_text(&self, ids: &[u32]) -> Option<String> {
+        ids.iter().map(|&id| self.resolve(id)).collect()
+    }
+}
+
+/// Codes for the tokens of word-level text, split as
+/// `SymbolInterner::intern_text()` splits it. The tree is built over the
+/// tokens' ids, and the codes are kept in an `IdCodebook` indexed by id, so
+/// encoding a token is an interner lookup and an array index, and decoding
+/// one resolves its id.
+/// 
+#[derive(Clone, Debug)]
+pub struct InternedCodebook {
+    interner : SymbolInterner,
+    codebook : IdCodebook,
+}
+
+impl InternedCodebook {
+    /// Interns the tokens of `text` and generates codes for their ids from
+    /// how often each occurs. Fails with `HuffmanError::TooManySymbols` if
+    /// there are more distinct tokens than a tree can hold.
+    /// 
+    pub fn from_text(text: &str) -> Result<Self, HuffmanError> {
+        let mut interner = SymbolInterner::new();
+        let mut counts   = vec![];
+
+        for token in tokens(text) {
+            let id = interner.intern(token) as usize;
+
+            if id == counts.len() {
+                counts.push(0);
+            }
+            counts[id] += 1;
+        }
+        if counts.len() > MAX_SYMBOLS {
+            return Err(HuffmanError::TooManySymbols {
+                symbols : counts.len(),
+                max     : MAX_SYMBOLS,
+            });
+        }
+        let codebook = IdCodebook::from_lengths(&id_code_lengths(&counts))?;
+
+        Ok(Self { interner, codebook })
+    }
+    /// The tokens and their ids.
+    /// 
+    pub fn interner(&self) -> &SymbolInterner {
+        &self.interner
+    }
+    /// The codes, by id.
+    /// 
+    pub fn codebook(&self) -> &IdCodebook {
+        &self.codebook
+    }
+    /// Packs the codes for the tokens of `text`. Fails with
+    /// `HuffmanError::SymbolNotInCodebook`, for the first character of the
+    /// token, if a token has no code.
+    /// 
+    pub fn encode(&self, text: &str) -> Result<BitBuffer, HuffmanError> {
+        let mut ids        = vec![];
+        let mut char_index = 0;
+        let mut offset     = 0;
+
+        for token in tokens(text) {
+            let id = self.interner.get(token).ok_or_else(|| {
+                HuffmanError::SymbolNotInCodebook {
+                    symbol      : token.chars().next().unwrap(),
+                    char_index,
+                    byte_offset : offset,
+                }
+            })?;
+            ids.push(id);
+            char_index += token.chars().count();
+            offset     += token.len();
+        }
+        self.codebook.encode(&ids)
+    }
+    /// Decodes `bits` into the text whose tokens they encode. Fails as
+    /// `IdCodebook::decode()` does.
+    /// 
+    pub fn decode(&self, bits: &BitBuffer) -> Result<String, HuffmanError> {
+        let ids = self.codebook.decode(bits)?;
+
+        Ok(self.interner.resolve_text(&ids)
+                        .expect("every id with a code is interned"))
+    }
+}
+
+/// The length of the code for each id, counted as often as `counts` says,
+/// from a tree built over the ids themselves.
+/// 
+fn id_code_lengths(counts: &[u64]) -> Vec<(u32, u8)> {
+    let mut ranked = (0..).zip(counts.iter().copied()).collect::<Vec<_>>();
+    let mut nodes  = NodeMem::<u64, u32>::new();
+
+    // Most frequent first, then in order of id, as `create_freq_nodes()`
+    // orders chars.
+    ranked.sort_unstable_by_key(|&(id, count)| (Reverse(count), id));
+    nodes.reserve(tree_size(ranked.len()));
+
+    for (id, count) in ranked {
+        nodes.new_leaf(id, count);
+    }
+    // A code over 64 bits needs a count past the 65th Fibonacci number,
+    // which no text held in memory has.
+    let root = build_huffman_tree(&mut nodes);
+
+    tree_code_bits(root, &nodes).expect("no code is over 64 bits")
+                                .into_iter()
+                                .map(|(id, code)| (id, code.len))
+                                .collect()
+}
+
+/// The kind of token a character starts or continues.
+/// 
+#[derive(PartialEq)]
+enum TokenKind { Word, Space, Other }
+
+impl TokenKind {
+    fn of(c: char) -> Self {
+        if c.is_alphanumeric() || c == '\'' {
+            TokenKind::Word
+        } else if c.is_whitespace() {
+            TokenKind::Space
+        } else {
+            TokenKind::Other
+        }
+    }
+}
+
+/// Splits `text` into maximal runs of word characters or of whitespace, with
+/// every other character a token of its own.
+/// 
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+
+    core::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let kind  = TokenKind::of(first);
+        let end   = match kind {
+            TokenKind::Other => first.len_utf8(),
+            _ => rest.find(|c| TokenKind::of(c) != kind)
+                     .unwrap_or(rest.len()),
+        };
+        let (token, tail) = rest.split_at(end);
+
+        rest = tail;
+        Some(token)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut interner = SymbolInterner::new();
+        let ids = interner.intern_text(&text);
+
+        assert_eq!(interner.resolve_text(&ids).unwrap(), text);
+        assert!(interner.len() < ids.len() / 4);
+
+        let ids = interner.intern_text("Call me  Ishmael.\r\n«Ahab»—whale's");
+
+        assert_eq!(ids.iter().map(|&id| interner.resolve(id).unwrap())
+                      .collect::<Vec<_>>(),
+                   ["Call", " ", "me", "  ", "Ishmael", ".", "\r\n", "«",
+                    "Ahab", "»", "—", "whale's"]);
+        assert_eq!(interner.resolve_text(&[ids[0], u32::MAX]), None);
+    }
+
+    #[test]
+    fn interned_codebook() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = InternedCodebook::from_text(&text).unwrap();
+        let bits     = codebook.encode(&text).unwrap();
+
+        assert_eq!(codebook.decode(&bits).unwrap(), text);
+        assert!(codebook.codebook().is_dense());
+        assert_eq!(codebook.codebook().len(), codebook.interner().len());
+
+        // The tree over ids is as good as one over the ids stood in for by
+        // chars.
+        let mut interner = SymbolInterner::new();
+        let ids          = interner.intern_text(&text);
+        let by_chars     = IdCodebook::from_ids(&ids).unwrap();
+
+        assert_eq!(bits.bit_len(), by_chars.encode(&ids).unwrap().bit_len());
+
+        // Whitespace and punctuation are tokens too.
+        let text     = "Call me  Ishmael.\r\n«Ahab»—whale's\t";
+        let codebook = InternedCodebook::from_text(text).unwrap();
+        let some     = "me  Ahab\r\nwhale's—Ishmael.";
+        let bits     = codebook.encode(some).unwrap();
+
+        assert_eq!(codebook.decode(&bits).unwrap(), some);
+        assert_eq!(codebook.encode("Call me Ahab!").err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '!', char_index: 12, byte_offset: 12
+                   }));
+        assert_eq!(codebook.encode("Call them").err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: 't', char_index: 5, byte_offset: 5
+                   }));
+
+        // One token has the code "0", and none have none.
+        let codebook = InternedCodebook::from_text("ahoy").unwrap();
+        let bits     = codebook.encode("ahoyahoy").err();
+
+        assert_eq!(bits, Some(HuffmanError::SymbolNotInCodebook {
+            symbol: 'a', char_index: 0, byte_offset: 0
+        }));
+        assert_eq!(codebook.codebook().code_for(0).map(|code| code.len),
+                   Some(1));
+
+        let codebook = InternedCodebook::from_text("").unwrap();
+
+        assert!(codebook.codebook().is_empty());
+        assert_eq!(codebook.decode(&codebook.encode("").unwrap()).unwrap(),
+                   "");
+    }
+
+    #[test]
+    fn ids_are_dense_and_stable() {
+        let mut interner = SymbolInterner::new();
+
+        assert_eq!(interner.intern("the"), 0);
+        assert_eq!(interner.intern("whale"), 1);
+        assert_eq!(interner.intern("the"), 0);
+        assert_eq!(interner.get("whale"), Some(1));
+        assert_eq!(interner.get("ship"), None);
+        assert_eq!(interner.resolve(1), Some("whale"));
+        assert_eq!(interner.resolve(2), None);
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+//! Reading and writing codes: packing them into bits and back, encoding
+//! text, and decoding it again, all at once, a piece at a time, or from a
+//! reader to a writer.
+//! 
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+#[cfg(feature = "std")]
+use crate::HuffmanError;
+
+#[cfg(feature = "std")]
+pub use crate::access::{DecodeSession, RandomAccessReader};
+pub use crate::adaptive::{AdaptiveDecoder, AdaptiveEncoder};
+pub use crate::bits::{BitBuffer, BitReader, BitWriter};
+pub use crate::encode::{decode_annotated, encode, encode_annotated};
+pub use crate::encode::{AnnotatedStep, EncodeIter};
+pub use crate::fsm::{DecodeIter, DecodeSummary, DecoderFsm, Transition};
+pub use crate::incremental::{IncrementalCompressor, Step};
+#[cfg(feature = "std")]
+pub use crate::lines::LineReader;
+pub use crate::lookup::DecodeTable;
+#[cfg(feature = "parallel")]
+pub use crate::par::encode_par;
+#[cfg(feature = "std")]
+pub use crate::pipeline::PipelineCompressor;
+pub use crate::push::PushDecoder;
+#[cfg(feature = "std")]
+pub use crate::reader::HuffmanReader;
+pub use crate::utf16::{decode_utf16, encode_utf16};
+#[cfg(feature = "std")]
+pub use crate::writer::HuffmanWriter;
+
+
+/// A flag for asking a long-running operation to stop early. Clones share the
+/// flag, so one can be handed to another thread to cancel from there.
+/// 
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Asks operations watching this token to stop.
+    /// 
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The size of the buffer used when reading text from a stream.
+/// 
+#[cfg(feature = "std")]
+pub(crate) const READ_BUF_SIZE: usize = 8192;
+
+/// Reads UTF-8 text from `r` a buffer at a time, passing each decoded chunk to
+/// `f`, and stopping at the first error `f` returns. A character split across
+/// two reads is carried over to the next chunk. Invalid UTF-8 is reported as
+/// `io::ErrorKind::InvalidData` wrapping `HuffmanError::InvalidUtf8`. `cancel`
+/// is checked before each read.
+/// 
+#[cfg(feature = "std")]
+pub(crate) fn for_each_str_chunk(mut r  : impl Read, 
+                                 cancel : Option<&CancelToken>,
+                                 mut f  : impl FnMut(&str) -> io::Result<()>) 
+
+    -> io::Result<()> 
+{
+    let mut buf   = [0u8; READ_BUF_SIZE];
+    let mut carry = 0;
+
+    loop {
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            return Err(io::Error::other(HuffmanError::Cancelled));
+        }
+        let n = match r.read(&mut buf[carry..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let end = carry + n;
+
+        let valid = match core::str::from_utf8(&buf[..end]) {
+            Ok(chunk) => chunk.len(),
+            Err(e) if e.error_len().is_none() && n > 0 => e.valid_up_to(),
+            Err(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, 
+                                          HuffmanError::InvalidUtf8));
+            }
+        };
+        // SAFETY: `from_utf8()` validated the first `valid` bytes.
+        f(unsafe { core::str::from_utf8_unchecked(&buf[..valid]) })?;
+
+        if n == 0 {
+            return Ok(());
+        }
+        buf.copy_within(valid..end, 0);
+        carry = end - valid;
+    }
+}
@@ -7,15 +7,121 @@
 //! the character frquencies, and another pass to convert characters to Huffman
 //! codes.
 //! 
-//! How decompression could be imlemented isn't addressed by the code. A state
-//! machine may be the most efficient way to convert a stream of binary values
-//! back to characters.
+//! Decompression is done by `DecoderFsm`, a state machine that converts a
+//! stream of binary values back to characters.
+//! 
+//! The crate is laid out in modules: `codebook` for counting text and
+//! generating codes, `tree` and `canonical` for the trees and canonical codes
+//! behind them, `io` for writing codes as bits and reading them back,
+//! `container` for whole compressed streams, and `heapq` for the heaps trees
+//! are built with. `prelude` re-exports the handful of items most uses need,
+//! starting from a `HuffmanBuilder`.
+//! The items all used to be in the root, and are still there, deprecated, for
+//! one release.
+//! 
+//! Whatever reads or writes through `std::io`, or runs on threads, is only
+//! built with the `std` feature, which is on by default: the streams in
+//! `container`, the readers and writers in `io`, and the `huff` command.
+//! Without it, the crate is `no_std`, needing only `alloc`, and still counts
+//! text, builds trees, and generates, encodes, and decodes codes. The maps it
+//! gives codes in are then `BTreeMap`s rather than `HashMap`s, so symbols of
+//! other types than `char` must be `Ord`; `codebook::MapKey` names what they
+//! must be either way.
 //! 
 
-use std::collections::HashMap;
-use std::cmp::Ordering;
-
-use heapq::{heapify_with_aux, heap_pop_with_aux, heap_push_with_aux};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::collections::{HashMap, MapKey};
+use crate::summary::BuildProbe;
+
+#[cfg(feature = "std")]
+mod accel;
+#[cfg(feature = "std")]
+mod access;
+mod adaptive;
+mod analysis;
+mod approx;
+mod bits;
+#[cfg(feature = "std")]
+mod blocks;
+mod builder;
+mod codec;
+mod collections;
+#[cfg(feature = "std")]
+mod csv;
+mod deprecated;
+mod encode;
+mod english;
+mod error;
+mod escape;
+mod export;
+#[cfg(feature = "std")]
+mod frame;
+mod fsm;
+mod heap;
+mod ids;
+mod incremental;
+mod intern;
+mod limit;
+#[cfg(feature = "std")]
+mod lines;
+mod lookup;
+#[cfg(feature = "std")]
+mod measure;
+mod median;
+mod merge;
+#[cfg(feature = "std")]
+mod mux;
+mod names;
+#[cfg(all(feature = "std", any(test, feature = "test-support")))]
+mod mutate;
+#[cfg(feature = "std")]
+mod pipe;
+#[cfg(feature = "parallel")]
+mod par;
+#[cfg(feature = "std")]
+mod pipeline;
+mod probability;
+mod push;
+#[cfg(feature = "std")]
+mod reader;
+mod reserved;
+mod sample;
+#[cfg(feature = "std")]
+mod selfcheck;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shannon;
+mod stable;
+mod summary;
+mod table;
+mod trie;
+mod utf16;
+mod validate;
+mod varint;
+mod weight;
+#[cfg(feature = "std")]
+mod writer;
+
+pub mod canonical;
+pub mod codebook;
+pub mod codegen;
+pub mod container;
+pub mod heapq;
+pub mod io;
+pub mod prelude;
+pub mod tree;
+
+pub use deprecated::*;
+pub use error::HuffmanError;
 
 
 /// A handle to a `Node`. It holds an index in to the vector that holds the 
@@ -23,9 +129,7 @@ use heapq::{heapify_with_aux, heap_pop_with_aux, heap_push_with_aux};
 /// 
 #[derive(Clone, Copy, PartialEq)]
 #[repr(transparent)]
-struct Handle(u16);
-
-const HNONE: Handle = Handle(u16::MAX);
+struct Handle(u32);
 
 impl Handle {
     fn idx(&self) -> usize {
@@ -35,21 +139,22 @@ impl Handle {
 
 
 /// Represents the nodes of the Huffman tree used to generate the codes for
-/// characters.
+/// symbols, which are characters unless some other type is given. Frequencies
+/// are counts unless some other `Weight` is given.
 /// 
-enum Node {
-    Leaf   { char_: char, freq: usize },
-    Branch { freq: usize, left: Handle, right: Handle }
+enum Node<W = usize, S = char> {
+    Leaf   { symbol: S, freq: W },
+    Branch { freq: W, left: Handle, right: Handle }
 }
 
-impl Node {
-    fn new_leaf(char_: char, freq: usize) -> Self {
-        Node::Leaf{ char_, freq }
+impl<W: Weight, S> Node<W, S> {
+    fn new_leaf(symbol: S, freq: W) -> Self {
+        Node::Leaf{ symbol, freq }
     }
-    fn new_branch(freq: usize, left: Handle, right: Handle) -> Self {
+    fn new_branch(freq: W, left: Handle, right: Handle) -> Self {
         Node::Branch { freq, left, right }
     }
-    fn freq(&self) -> usize {
+    fn freq(&self) -> W {
         match self {
             Node::Leaf   { freq, .. } |
             Node::Branch { freq, .. } => *freq,
@@ -61,134 +166,367 @@ impl Node {
 /// Holds all the nodes of the Huffman tree in continguous memory. This is a
 /// cache efficent way to process them.
 /// 
-struct NodeMem {
-    nodes: Vec<Node>,
+struct NodeMem<W = usize, S = char> {
+    nodes: Vec<Node<W, S>>,
 }
 
-impl NodeMem {
+impl<W: Weight, S> NodeMem<W, S> {
     fn new() -> Self {
         Self { nodes: Vec::new() }
     }
     fn len(&self) -> usize {
         self.nodes.len()
     }
-    fn new_leaf(&mut self, char_: char, freq: usize) -> Handle {
-        self.nodes.push(Node::new_leaf(char_, freq));
-        Handle(self.nodes.len() as u16 - 1)
+    fn clear(&mut self) {
+        self.nodes.clear();
+    }
+    fn reserve(&mut self, len: usize) {
+        self.nodes.reserve_exact(len);
     }
-    fn new_branch(&mut self, freq: usize, left: Handle, right: Handle) 
+    fn new_leaf(&mut self, symbol: S, freq: W) -> Handle {
+        self.push(Node::new_leaf(symbol, freq))
+    }
+    fn new_branch(&mut self, freq: W, left: Handle, right: Handle) 
 
         -> Handle 
     {
-        self.nodes.push(Node::new_branch(freq, left, right));
-        Handle(self.nodes.len() as u16 - 1)
+        self.push(Node::new_branch(freq, left, right))
+    }
+    /// Adds `node`, giving its handle.
+    /// 
+    /// # Panics
+    /// 
+    /// If the arena already holds as many nodes as `u32` handles can index,
+    /// rather than wrapping around to the handle of a node already in it.
+    /// Trees of up to `MAX_SYMBOLS` leaves fit.
+    /// 
+    fn push(&mut self, node: Node<W, S>) -> Handle {
+        let handle = u32::try_from(self.nodes.len())
+                         .expect("too many nodes for u32 handles");
+
+        self.nodes.push(node);
+        Handle(handle)
     }
-    fn h2node(&self, handle: Handle) -> &Node {
+    fn h2node(&self, handle: Handle) -> &Node<W, S> {
         &self.nodes[handle.idx()]
     }
     #[allow(dead_code)]
-    fn h2node_mut(&mut self, handle: Handle) -> &mut Node {
+    fn h2node_mut(&mut self, handle: Handle) -> &mut Node<W, S> {
         &mut self.nodes[handle.idx()]
     }
 }
 
 
-/// Create the initial leaf nodes that have the frequencies of each character.
+/// The average length of `codes` weighted by the counts in `freqs`.
 /// 
-fn create_freq_nodes(data: &str) -> NodeMem {
-    let mut freqs = HashMap::new();
-    let mut nodes = NodeMem::new();
+fn average_code_length(codes : &HashMap<char, String>, 
+                       freqs : &codebook::FrequencyTable) 
 
-    for b in data.chars() {
-        *freqs.entry(b).or_insert(0) += 1;
+    -> f64 
+{
+    match (codebook::predicted_bits(codes, freqs), freqs.total()) {
+        (Some(bits), total) if total > 0 => bits as f64 / total as f64,
+        _ => 0.0,
     }
-    for (c, f) in freqs {
+}
+
+
+/// The number of nodes in a Huffman tree with `symbols` leaves.
+/// 
+fn tree_size(symbols: usize) -> usize {
+    (2 * symbols).saturating_sub(1)
+}
+
+/// The most symbols a tree can have, since its nodes are indexed by `u32`
+/// handles. That's more than there are characters, so only alphabets of
+/// other symbols can reach it.
+/// 
+const MAX_SYMBOLS: usize = (u32::MAX as usize).div_ceil(2);
+
+/// Create the initial leaf nodes that have the frequencies of each character,
+/// most frequent first and then in order of character, so the leaves don't
+/// depend on hash order. Any nodes already in `nodes` are discarded, which
+/// lets callers that build many trees reuse one arena.
+/// 
+fn create_freq_nodes(freqs: &codebook::FrequencyTable, nodes: &mut NodeMem) {
+    nodes.clear();
+    nodes.reserve(tree_size(freqs.len()));
+
+    for (c, f) in freqs.ranked() {
         nodes.new_leaf(c, f);
     }
-    nodes
 }
 
-/// Constructs the tree used to produce Huffman codes.
+/// Builds the tree for `freqs` in `nodes`, returning its root, or `None` if
+/// `freqs` is empty. This is the one path by which trees for frequency tables
+/// are built.
 /// 
-fn build_huffman_tree(nodes: &mut NodeMem) -> Handle {
+fn grow_tree(freqs: &codebook::FrequencyTable, nodes: &mut NodeMem) 
+
+    -> Option<Handle> 
+{
+    grow_tree_probed(freqs, nodes, &())
+}
+
+/// Like `grow_tree()`, telling `probe` about the work done.
+/// 
+fn grow_tree_probed(freqs : &codebook::FrequencyTable,
+                    nodes : &mut NodeMem,
+                    probe : &impl BuildProbe) 
+
+    -> Option<Handle> 
+{
+    create_freq_nodes(freqs, nodes);
+
+    match freqs.summary().tree_build() {
+        tree::TreeBuild::Heap     => build_huffman_tree_probed(nodes, probe),
+        tree::TreeBuild::TwoQueue => summary::build_two_queue(nodes, probe),
+    }
+}
+
+/// Constructs the tree used to produce Huffman codes. The two lightest nodes
+/// are merged at each step; of nodes with equal weights, the one created first
+/// is taken first, so leaves go before branches and in the order they were
+/// created. Gives the root, or `None` if there are no nodes.
+/// 
+fn build_huffman_tree<W: Weight, S>(nodes: &mut NodeMem<W, S>) 
+
+    -> Option<Handle> 
+{
+    build_huffman_tree_probed(nodes, &())
+}
+
+/// Like `build_huffman_tree()`, telling `probe` about the work done.
+/// 
+fn build_huffman_tree_probed<W: Weight, S>(nodes : &mut NodeMem<W, S>,
+                                           probe : &impl BuildProbe) 
+
+    -> Option<Handle> 
+{
     // `heap` holds instances of `Handle`, which are basically just indexes into
     // `nodes`.
-    let mut heap = (0..nodes.len() as u16).map(Handle).collect::<Vec<_>>();
+    let handles = (0..nodes.len()).map(|i| Handle(i as u32)).collect();
 
-    fn cmp(a: &Handle, b: &Handle, nodes: &NodeMem) -> Ordering {
-        nodes.h2node(*a).freq().cmp(&nodes.h2node(*b).freq())
-    }
+    let cmp = |a: &Handle, b: &Handle, nodes: &&mut NodeMem<W, S>| {
+        probe.compared();
+        nodes.h2node(*a).freq().weight_cmp(&nodes.h2node(*b).freq())
+                               .then(a.0.cmp(&b.0))
+    };
+    let mut heap = heap::Heap::from_vec(handles, cmp, nodes);
 
-    heapify_with_aux(&mut heap, cmp, nodes);
+    probe.heapified(heap.len());
 
+    // The lightest node is popped, and the next lightest, at the top, is
+    // replaced by their merge, which sifts the heap once per merge.
     loop {
-        match (heap_pop_with_aux(&mut heap, cmp, nodes), 
-               heap_pop_with_aux(&mut heap, cmp, nodes)) {
+        let left = heap.pop()?;
 
-            (Some(left), Some(right)) => {
-                let freq = nodes.h2node(left).freq() 
-                            + nodes.h2node(right).freq();
-                let merged = nodes.new_branch(freq, left, right);
+        probe.heap_op();
+
+        let Some(&right) = heap.peek() else {
+            return Some(left);
+        };
+        let nodes  = heap.aux_mut();
+        let freq   = nodes.h2node(left).freq()
+                          .weight_add(nodes.h2node(right).freq());
+        let merged = nodes.new_branch(freq, left, right);
+
+        heap.replace(merged);
+        probe.heap_op();
+    }
+}
 
-                heap_push_with_aux(&mut heap, merged, cmp, nodes);    
+/// Generates the Huffman codes for the leaves of the tree rooted at `root`,
+/// adding them to `huff`. Fails with the symbol of the first leaf, from the
+/// left, whose code would be longer than 64 bits.
+/// 
+/// The tree is walked with a stack of the nodes on the path to the current
+/// one, each with the number of its children visited, rather than by
+/// recursion, since a skewed tree is as deep as it has leaves. A bit is
+/// appended to the code on the way down to a child, and dropped on the way
+/// back up.
+/// 
+fn generate_huffman_codes_walk<W, S>(root  : Handle,
+                                     huff  : &mut HashMap<S, codebook::Code>,
+                                     nodes : &NodeMem<W, S>) 
+
+    -> Result<(), S> 
+where
+    W: Weight,
+    S: MapKey + Clone,
+{
+    let mut stack = vec![(root, 0_usize)];
+    let mut bits  = 0_u64;
+
+    // The code's length is the depth of the node on top of the stack. Past
+    // 64 bits, bits shift out of `bits`, but a leaf is reached below the
+    // first node that deep before the walk comes back up.
+    while let Some(len) = stack.len().checked_sub(1) {
+        let (node, visited) = &mut stack[len];
+
+        match (nodes.h2node(*node), *visited) {
+            (Node::Leaf { symbol, .. }, _) if len > 64 => {
+                return Err(symbol.clone());
+            },
+            (Node::Leaf { symbol, .. }, _) => {
+                let code = codebook::Code { bits, len: len as u8 };
+
+                huff.insert(symbol.clone(), code);
             },
-            (Some(left), None) => {
-                return left;
+            (Node::Branch { left, .. }, 0) => {
+                *visited = 1;
+                stack.push((*left, 0));
+                bits <<= 1;
+                continue;
             },
-            _ => { 
-                return HNONE; 
-            }
+            (Node::Branch { right, .. }, 1) => {
+                *visited = 2;
+                stack.push((*right, 0));
+                bits = bits << 1 | 1;
+                continue;
+            },
+            (Node::Branch { .. }, _) => {},
         }
+        stack.pop();
+        bits >>= 1;
     }
+    Ok(())
 }
 
-/// Generates human-readable binary strings representing Huffman codes. The
-/// dictionary passed to `huff` will be updated with these codes. The dictionary
-/// can then be printed and examined.
+/// Generates binary strings for the Huffman codes of the tree rooted at
+/// `root`, as `generate_huffman_codes_walk()` does in integers and the same
+/// way, for trees too deep for those.
 /// 
-fn generate_huffman_codes_recurs(node  : Handle, 
-                                 code  : &mut String,
-                                 huff  : &mut HashMap<char, String>,
-                                 nodes : &NodeMem) 
+fn long_codes_walk<W, S>(root  : Handle,
+                         huff  : &mut HashMap<S, String>,
+                         nodes : &NodeMem<W, S>) 
+where
+    W: Weight,
+    S: MapKey + Clone,
 {
-    if node != HNONE { 
-        match nodes.h2node(node) {
-            Node::Leaf { char_, .. } => {
-                huff.insert(*char_, code.clone());
+    let mut stack = vec![(root, 0_usize)];
+    let mut code  = String::new();
+
+    while let Some((node, visited)) = stack.last_mut() {
+        match (nodes.h2node(*node), *visited) {
+            (Node::Leaf { symbol, .. }, _) => {
+                huff.insert(symbol.clone(), code.clone());
             },
-            Node::Branch { left, right, .. } => {
+            (Node::Branch { left, .. }, 0) => {
+                *visited = 1;
+                stack.push((*left, 0));
                 code.push('0');
-                generate_huffman_codes_recurs(*left, code, huff, nodes);
-                code.pop();
-
+                continue;
+            },
+            (Node::Branch { right, .. }, 1) => {
+                *visited = 2;
+                stack.push((*right, 0));
                 code.push('1');
-                generate_huffman_codes_recurs(*right, code, huff, nodes);
-                code.pop();
-            }
+                continue;
+            },
+            (Node::Branch { .. }, _) => {},
         }
+        stack.pop();
+        code.pop();
     }
 }
 
-/// Generates a mapping of characters to string representations of their Huffman
-/// codes.
+/// Generates the code strings for the characters counted in `freqs`.
+/// 
+fn codes_from_table(freqs: &codebook::FrequencyTable) -> HashMap<char, String> {
+    codes_with_arena(freqs, &mut NodeMem::new())
+}
+
+/// Generates the codes for the characters counted in `freqs`. Fails with
+/// `HuffmanError::MalformedCode` if one would be longer than 64 bits.
+/// 
+fn code_bits_from_table(freqs: &codebook::FrequencyTable) 
+
+    -> Result<HashMap<char, codebook::Code>, HuffmanError> 
+{
+    let mut nodes = NodeMem::new();
+    let tree      = grow_tree(freqs, &mut nodes);
+
+    tree_code_bits(tree, &nodes).map_err(HuffmanError::MalformedCode)
+}
+
+/// Generates the code strings for the characters counted in `freqs`, building
+/// the tree in `nodes`.
 /// 
-pub fn generate_huffman_codes(data: &str) -> HashMap<char, String> {
-    let mut nodes = create_freq_nodes(data);
-    let     tree  = build_huffman_tree(&mut nodes);
+fn codes_with_arena(freqs: &codebook::FrequencyTable, nodes: &mut NodeMem) 
 
+    -> HashMap<char, String> 
+{
+    let tree = grow_tree(freqs, nodes);
+
+    tree_codes(tree, nodes)
+}
+
+/// The codes for the leaves of the tree rooted at `tree`. A tree with one leaf
+/// gives it the code "0", as though it had an empty sibling, since the empty
+/// path to the root would take no bits and couldn't be decoded. An empty tree,
+/// with no root, gives no codes. Fails with the symbol of a leaf whose code
+/// would be longer than 64 bits.
+/// 
+fn tree_code_bits<W, S>(tree: Option<Handle>, nodes: &NodeMem<W, S>) 
+
+    -> Result<HashMap<S, codebook::Code>, S> 
+where
+    W: Weight,
+    S: MapKey + Clone,
+{
     let mut huff = HashMap::new();
-    let mut code = String::new();
 
-    generate_huffman_codes_recurs(tree, &mut code, &mut huff, &nodes);
+    match tree.map(|root| (root, nodes.h2node(root))) {
+        Some((_, Node::Leaf { symbol, .. })) => {
+            huff.insert(symbol.clone(), codebook::Code { bits: 0, len: 1 });
+        },
+        Some((root, _)) => {
+            generate_huffman_codes_walk(root, &mut huff, nodes)?;
+        },
+        None => {},
+    }
+    Ok(huff)
+}
 
-    huff
+/// The code strings for the leaves of the tree rooted at `tree`, as
+/// `tree_code_bits()` gives them. Codes longer than 64 bits, which weights
+/// such as probabilities can skew a tree far enough for, are spelled out
+/// along the tree instead.
+/// 
+fn tree_codes<W, S>(tree: Option<Handle>, nodes: &NodeMem<W, S>) 
+
+    -> HashMap<S, String> 
+where
+    W: Weight,
+    S: MapKey + Clone,
+{
+    match tree_code_bits(tree, nodes) {
+        Ok(codes) => {
+            codes.into_iter()
+                 .map(|(s, code)| (s, code.to_code_string()))
+                 .collect()
+        },
+        Err(_) => {
+            let mut huff = HashMap::new();
+
+            if let Some(root) = tree {
+                long_codes_walk(root, &mut huff, nodes);
+            }
+            huff
+        },
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
     use std::fs::read_to_string;
+    use std::println;
     use super::*;
+    use crate::codebook::{code_stats, generate_huffman_codes, Code};
+    use crate::codebook::{generate_huffman_codes_weighted};
+    use crate::io::{encode, DecoderFsm};
 
     #[test]
     fn melville() {
@@ -196,19 +534,198 @@ mod tests {
         let huff = generate_huffman_codes(&text);
 
         println!("\nHUFFMAN CODE: {:?}\n", huff);
+        println!("\nCOMPRESSION RATIO: {}\n", 
+                 code_stats(&text).compression_ratio().unwrap());
+    }
+
+    #[test]
+    fn large_alphabets() {
+        // More symbols than 16-bit handles could index the tree of.
+        let text = (0x100..).filter_map(char::from_u32)
+                            .take(40_000)
+                            .enumerate()
+                            .flat_map(|(i, c)| {
+                                std::iter::repeat_n(c, i % 7 + 1)
+                            })
+                            .collect::<String>();
+        let codes = generate_huffman_codes(&text);
+
+        assert_eq!(codes.len(), 40_000);
+        assert!(DecoderFsm::from_code_map(&codes).is_ok());
+
+        // The codes fill the code space exactly: their Kraft sum is 1.
+        let longest = codes.values().map(String::len).max().unwrap();
+        let kraft   = codes.values()
+                           .map(|code| 1_u128 << (longest - code.len()))
+                           .sum::<u128>();
+
+        assert_eq!(kraft, 1 << longest);
+
+        let bits = encode(&text);
+
+        assert_eq!(DecoderFsm::from_code_map(&codes).unwrap().decode(&bits),
+                   Ok(text));
+    }
 
-        let mut freq = HashMap::new();
-        let mut compressed_size = 0;
+    /// The recursive code generation the walks replaced, to check them
+    /// against.
+    /// 
+    fn recursive_code_bits(node  : Handle,
+                           bits  : u64,
+                           depth : u32,
+                           huff  : &mut HashMap<char, Code>,
+                           nodes : &NodeMem<f64>) 
 
-        for ch in text.chars() {
-            *freq.entry(ch).or_insert(0) += 1;
+        -> Result<(), char> 
+    {
+        match nodes.h2node(node) {
+            Node::Leaf { symbol, .. } if depth > 64 => return Err(*symbol),
+            Node::Leaf { symbol, .. } => {
+                huff.insert(*symbol, Code { bits, len: depth as u8 });
+            },
+            Node::Branch { left, right, .. } => {
+                let (bits, depth) = (bits << 1, depth + 1);
+
+                recursive_code_bits(*left, bits, depth, huff, nodes)?;
+                recursive_code_bits(*right, bits | 1, depth, huff, nodes)?;
+            },
         }
+        Ok(())
+    }
 
-        for (c, f) in freq {
-            compressed_size += huff[&c].len() * f;
+    fn recursive_long_codes(node  : Handle,
+                            code  : &mut String,
+                            huff  : &mut HashMap<char, String>,
+                            nodes : &NodeMem<f64>) 
+    {
+        match nodes.h2node(node) {
+            Node::Leaf { symbol, .. } => {
+                huff.insert(*symbol, code.clone());
+            },
+            Node::Branch { left, right, .. } => {
+                for (child, bit) in [(left, '0'), (right, '1')] {
+                    code.push(bit);
+                    recursive_long_codes(*child, code, huff, nodes);
+                    code.pop();
+                }
+            },
         }
+    }
 
-        println!("\nCOMPRESSION RATIO: {}\n", 
-                 text.len() as f32 * 8.0 / compressed_size as f32);
+    /// The tree for `weights`, and its root.
+    /// 
+    fn weighted_tree(weights: &[(char, f64)]) -> (NodeMem<f64>, Handle) {
+        let mut nodes = NodeMem::new();
+
+        for &(c, w) in weights {
+            nodes.new_leaf(c, w);
+        }
+        let root = build_huffman_tree(&mut nodes).unwrap();
+
+        (nodes, root)
+    }
+
+    /// Fibonacci weights for `n` characters, which skew the tree into a
+    /// chain as deep as it has leaves.
+    /// 
+    fn fibonacci_weights(n: usize) -> Vec<(char, f64)> {
+        let (mut a, mut b) = (1.0, 1.0);
+
+        (0x100..).filter_map(char::from_u32).take(n).map(|c| {
+            (a, b) = (b, a + b);
+            (c, a)
+        }).collect()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn walks_match_recursion() {
+        let mut rng   = sample::SplitMix64(270);
+        let mut cases = vec![fibonacci_weights(40), fibonacci_weights(90)];
+
+        for _ in 0..50 {
+            let n = 1 + rng.below(300);
+
+            cases.push(('a'..).take(n)
+                              .map(|c| (c, 1.0 + rng.below(1000) as f64))
+                              .collect());
+        }
+        for weights in &cases {
+            let (nodes, root) = weighted_tree(weights);
+
+            let (mut walked, mut recursed) = (HashMap::new(), HashMap::new());
+            let walked   = generate_huffman_codes_walk(root, &mut walked,
+                                                      &nodes)
+                               .map(|_| walked);
+            let recursed = recursive_code_bits(root, 0, 0, &mut recursed,
+                                               &nodes)
+                               .map(|_| recursed);
+
+            assert_eq!(walked, recursed);
+
+            let (mut walked, mut recursed) = (HashMap::new(), HashMap::new());
+
+            long_codes_walk(root, &mut walked, &nodes);
+            recursive_long_codes(root, &mut String::new(), &mut recursed,
+                                 &nodes);
+
+            assert_eq!(walked, recursed);
+        }
+        // Codes over 64 bits fail with the same leaf.
+        let (nodes, root) = weighted_tree(&fibonacci_weights(90));
+
+        assert!(generate_huffman_codes_walk(root, &mut HashMap::new(), &nodes)
+                    .is_err());
+    }
+
+    #[test]
+    fn deep_trees() {
+        // Powers of 2 skew the tree as Fibonacci weights do, but an f64
+        // holds them exactly, so none round into ties, up to 2^1023.
+        let weights       = (0x100..).filter_map(char::from_u32)
+                                     .zip((0..1000).map(|i| 2_f64.powi(i)))
+                                     .collect::<Vec<_>>();
+        let (nodes, root) = weighted_tree(&weights);
+        let mut expected  = HashMap::new();
+
+        recursive_long_codes(root, &mut String::new(), &mut expected, &nodes);
+
+        // A stack this small overflows with a frame per level of the tree.
+        let codes = std::thread::Builder::new()
+                        .stack_size(64 * 1024)
+                        .spawn(move || generate_huffman_codes_weighted(weights))
+                        .unwrap()
+                        .join()
+                        .unwrap();
+
+        assert_eq!(codes, expected);
+        assert_eq!(codes.values().map(String::len).max(), Some(999));
+    }
+}
+
+/// Tests that need nothing from `std`, so they build the same with or without
+/// the feature, as code generation itself does.
+/// 
+#[cfg(test)]
+mod no_std_tests {
+    use alloc::string::ToString;
+    use crate::codebook::{generate_huffman_codes, HuffmanCodebook};
+    use crate::io::{encode, DecoderFsm};
+
+    static TEXT: &str = "abracadabra";
+
+    #[test]
+    fn codes_for_a_static_string() {
+        let codes = generate_huffman_codes(TEXT);
+        let bits  = TEXT.chars().map(|c| codes[&c].len()).sum::<usize>();
+
+        assert_eq!(codes.len(), 5);
+        assert_eq!(codes[&'a'], "0");
+        assert_eq!(bits, 23);
+
+        let codebook = HuffmanCodebook::from_text(TEXT);
+        let fsm      = DecoderFsm::from_codebook(&codebook).unwrap();
+
+        assert_eq!(codebook.code_strings(), codes);
+        assert_eq!(fsm.decode(&encode(TEXT)), Ok(TEXT.to_string()));
+    }
+}
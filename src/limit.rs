@@ -0,0 +1,362 @@
+//! Codes whose lengths are capped, overall or symbol by symbol, for decoders
+//! with a limit on the codes they can handle.
+//! 
+//! The lengths come from package-merge, which solves the coin collector's
+//! problem: each symbol has a coin of width 2^-l and the symbol's weight for
+//! each length l up to its cap, and the cheapest set of coins with a total
+//! width of n - 1 gives each symbol as many bits as it has coins in the set.
+//! A symbol with a lower cap simply has fewer coins to offer.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::canonical::CanonicalDecoder;
+use crate::codebook::{count_frequencies, Code, FrequencyTable, HuffmanCodebook};
+use crate::collections::HashMap;
+
+
+/// The longest codes a codebook may have, for `from_frequencies_limited()`.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LengthLimits {
+    pub(crate) max_len : u8,
+    caps               : HashMap<char, u8>,
+}
+
+impl Default for LengthLimits {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl LengthLimits {
+    /// Limits every code to `max_len` bits, which is clamped to 64.
+    /// 
+    pub fn new(max_len: u8) -> Self {
+        Self { max_len: max_len.min(64), caps: HashMap::new() }
+    }
+    /// Limits the codes of `symbols` to `cap` bits, on top of the overall
+    /// limit. A symbol given more than one cap keeps the lowest.
+    /// 
+    pub fn max_length_for(&mut self, symbols: &[char], cap: u8) -> &mut Self {
+        for &c in symbols {
+            let entry = self.caps.entry(c).or_insert(cap);
+
+            *entry = cap.min(*entry);
+        }
+        self
+    }
+    /// The cap on the code for `c`.
+    /// 
+    pub fn cap_for(&self, c: char) -> u8 {
+        self.caps.get(&c).map_or(self.max_len, |&cap| cap.min(self.max_len))
+    }
+}
+
+/// An item of one of package-merge's lists: a symbol's coin, or a package of
+/// two items from the list below.
+/// 
+#[derive(Clone, Copy)]
+enum Item {
+    Coin(usize),
+    Package,
+}
+
+/// The lengths of the cheapest codes for `weights` within `caps`, which are
+/// all at least 1 and at most `max_len`, and whose Kraft sum is at most 1.
+/// There must be at least two symbols.
+/// 
+fn package_merge(weights: &[u64], caps: &[u8], max_len: u8) -> Vec<u8> {
+    let mut levels = Vec::with_capacity(max_len as usize);
+    let mut below  = Vec::<(u128, Item)>::new();
+
+    for len in (1..=max_len).rev() {
+        let coins = (0..weights.len()).filter(|&i| caps[i] >= len)
+                                      .map(|i| (weights[i] as u128,
+                                                Item::Coin(i)));
+        let packages = below.chunks_exact(2)
+                            .map(|pair| (pair[0].0 + pair[1].0, Item::Package))
+                            .collect::<Vec<_>>();
+
+        // Merge by weight, coins first among equals.
+        let mut merged   = Vec::with_capacity(weights.len() + packages.len());
+        let mut packages = packages.into_iter().peekable();
+
+        for coin in coins {
+            while let Some(&package) = packages.peek() && package.0 < coin.0 {
+                merged.push(package);
+                packages.next();
+            }
+            merged.push(coin);
+        }
+        merged.extend(packages);
+        levels.push(merged.iter().map(|&(_, item)| item).collect::<Vec<_>>());
+        below = merged;
+    }
+    // The cheapest 2n - 2 items of the top list are the solution. Packages
+    // among the items chosen from a list stand for a run of the cheapest
+    // items of the list below.
+    let mut lens = vec![0; weights.len()];
+    let mut take = 2 * weights.len() - 2;
+
+    for level in levels.iter().rev() {
+        let mut packages = 0;
+
+        for &item in &level[..take] {
+            match item {
+                Item::Coin(i)  => lens[i] += 1,
+                Item::Package  => packages += 1,
+            }
+        }
+        take = 2 * packages;
+    }
+    lens
+}
+
+impl HuffmanCodebook {
+    /// Generates the codes for the characters counted in `freqs` that take
+    /// the fewest bits for the counted text without exceeding `limits`. The
+    /// codes are canonical. Characters without a cap of their own take the
+    /// shortest codes the others leave room for, so with no caps that bind,
+    /// the lengths are those of an ordinary Huffman code.
+    /// 
+    /// Fails with `HuffmanError::LengthCapInfeasible` if there aren't enough
+    /// codes within the caps to go around, naming the character that runs
+    /// out of room when they're handed out tightest cap first.
+    /// 
+    pub fn from_frequencies_limited(freqs  : FrequencyTable,
+                                    limits : &LengthLimits) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut symbols = freqs.counts()
+                               .iter()
+                               .map(|(&c, &n)| (n as u64, c))
+                               .collect::<Vec<_>>();
+
+        symbols.sort_unstable();
+
+        let caps = symbols.iter()
+                          .map(|&(_, c)| limits.cap_for(c))
+                          .collect::<Vec<_>>();
+
+        // Codes within the caps exist if the caps themselves satisfy Kraft's
+        // inequality. The sum is scaled by 2^64 to keep it whole.
+        let mut by_cap = (0..symbols.len()).collect::<Vec<_>>();
+        let mut kraft  = 0_u128;
+
+        by_cap.sort_unstable_by_key(|&i| (caps[i], symbols[i].1));
+
+        for i in by_cap {
+            let cap = caps[i];
+
+            kraft += 1 << (64 - cap as u32);
+
+            if cap == 0 || kraft > 1 << 64 {
+                return Err(HuffmanError::LengthCapInfeasible {
+                    symbol: symbols[i].1, cap
+                });
+            }
+        }
+        let codes = match symbols[..] {
+            []       => HashMap::new(),
+            [(_, c)] => HashMap::from([(c, Code { bits: 0, len: 1 })]),
+            _        => {
+                let weights = symbols.iter().map(|&(n, _)| n)
+                                            .collect::<Vec<_>>();
+                let max_len = caps.iter().copied().max().unwrap();
+                let lens    = package_merge(&weights, &caps, max_len);
+                let lengths = symbols.iter()
+                                     .zip(lens)
+                                     .map(|(&(_, c), len)| (c, len))
+                                     .collect::<Vec<_>>();
+
+                CanonicalDecoder::from_lengths(&lengths)?.codes().collect()
+            },
+        };
+        Ok(Self::from_parts(freqs, codes))
+    }
+}
+
+/// Like `generate_huffman_codes()`, but with no code longer than `max_len`
+/// bits: the canonical codes of `from_frequencies_limited()` for the text's
+/// counts. Unless the limit binds, they're as short overall as the ordinary
+/// codes. Fails with `HuffmanError::LengthCapInfeasible` if there are more
+/// distinct characters than the 2^`max_len` codes of `max_len` bits.
+/// 
+pub fn generate_huffman_codes_limited(data: &str, max_len: u8) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    let limits = LengthLimits::new(max_len);
+
+    Ok(HuffmanCodebook::from_frequencies_limited(count_frequencies(data),
+                                                 &limits)?.code_strings())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{generate_huffman_codes, predicted_bits};
+    use crate::io::DecoderFsm;
+
+    fn lengths(codebook: &HuffmanCodebook) -> Vec<(char, u8)> {
+        let mut lengths = codebook.codes()
+                                  .iter()
+                                  .map(|(&c, code)| (c, code.len))
+                                  .collect::<Vec<_>>();
+        lengths.sort_unstable();
+        lengths
+    }
+
+    fn cost(codebook: &HuffmanCodebook) -> usize {
+        codebook.codes()
+                .iter()
+                .map(|(&c, code)| codebook.frequencies().get(c)
+                                  * code.len as usize)
+                .sum()
+    }
+
+    #[test]
+    fn caps_that_bind() {
+        let freqs = [('a', 8), ('b', 4), ('c', 2), ('d', 1), ('e', 1)]
+                        .into_iter().collect::<FrequencyTable>();
+
+        let free = HuffmanCodebook::from_frequencies_limited(
+            freqs.clone(), &LengthLimits::default()
+        ).unwrap();
+
+        assert_eq!(lengths(&free), [('a', 1), ('b', 2), ('c', 3), ('d', 4),
+                                    ('e', 4)]);
+
+        // Pulling 'e' up to 2 bits costs 'b' and 'c' a bit each, but
+        // 'a', the symbol with the most to lose, keeps its 1 bit.
+        let capped = HuffmanCodebook::from_frequencies_limited(
+            freqs.clone(), LengthLimits::new(64).max_length_for(&['e'], 2)
+        ).unwrap();
+
+        assert_eq!(lengths(&capped), [('a', 1), ('b', 3), ('c', 4), ('d', 4),
+                                      ('e', 2)]);
+        assert!(capped.is_canonical());
+
+        let flat = HuffmanCodebook::from_frequencies_limited(
+            freqs, &LengthLimits::new(3)
+        ).unwrap();
+
+        assert_eq!(lengths(&flat), [('a', 1), ('b', 3), ('c', 3), ('d', 3),
+                                    ('e', 3)]);
+    }
+
+    #[test]
+    fn unbound_caps_are_optimal() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let huff  = HuffmanCodebook::from_frequencies(freqs.clone());
+        let max   = huff.codes().values().map(|code| code.len).max().unwrap();
+
+        let mut limits = LengthLimits::new(max);
+
+        limits.max_length_for(&['e', 't'], 10);
+
+        let limited = HuffmanCodebook::from_frequencies_limited(freqs.clone(),
+                                                                &limits)
+                                      .unwrap();
+
+        assert_eq!(cost(&limited), cost(&huff));
+
+        // Capping the rarest symbols at 8 bits makes them shorter than they'd
+        // be, and costs a little overall.
+        let rare = huff.codes()
+                       .iter()
+                       .filter(|(_, code)| code.len > 8)
+                       .map(|(&c, _)| c)
+                       .collect::<Vec<_>>();
+        let limited = HuffmanCodebook::from_frequencies_limited(
+            freqs, LengthLimits::new(64).max_length_for(&rare, 8)
+        ).unwrap();
+
+        assert!(!rare.is_empty());
+        assert!(rare.iter().all(|&c| limited.code_for(c).unwrap().len <= 8));
+        assert!(cost(&limited) > cost(&huff));
+    }
+
+    #[test]
+    fn infeasible_caps() {
+        let freqs = "abcde".chars().map(|c| (c, 1)).collect::<FrequencyTable>();
+        let error = |limits: &LengthLimits| {
+            HuffmanCodebook::from_frequencies_limited(freqs.clone(), limits)
+                .err()
+        };
+        assert_eq!(error(&LengthLimits::new(2)),
+                   Some(HuffmanError::LengthCapInfeasible {
+                       symbol: 'e', cap: 2
+                   }));
+        assert_eq!(error(LengthLimits::new(8).max_length_for(&['b', 'c', 'd'],
+                                                             1)),
+                   Some(HuffmanError::LengthCapInfeasible {
+                       symbol: 'd', cap: 1
+                   }));
+        assert_eq!(error(LengthLimits::new(8).max_length_for(&['c'], 0)),
+                   Some(HuffmanError::LengthCapInfeasible {
+                       symbol: 'c', cap: 0
+                   }));
+        assert!(error(&LengthLimits::new(3)).is_none());
+
+        // Even a lone symbol takes a bit.
+        let lone = HuffmanCodebook::from_frequencies_limited(
+            FrequencyTable::from_text("aa"), &LengthLimits::new(0)
+        );
+        assert_eq!(lone.err(), Some(HuffmanError::LengthCapInfeasible {
+            symbol: 'a', cap: 0
+        }));
+    }
+
+    #[test]
+    fn limited_strings() {
+        // Fibonacci counts give the most lopsided tree there is: the rarest
+        // two symbols sit at depth 24.
+        let mut fib  = (1, 1);
+        let mut text = String::new();
+
+        for c in ('a'..='y').rev() {
+            text.extend(std::iter::repeat_n(c, fib.0));
+            fib = (fib.1, fib.0 + fib.1);
+        }
+        let freqs   = count_frequencies(&text);
+        let free    = generate_huffman_codes(&text);
+        let limited = generate_huffman_codes_limited(&text, 12).unwrap();
+        let longest = |codes: &HashMap<char, String>| {
+            codes.values().map(String::len).max().unwrap()
+        };
+        let kraft   = limited.values()
+                             .map(|code| 1_u64 << (12 - code.len()))
+                             .sum::<u64>();
+        let free_bits    = predicted_bits(&free, &freqs).unwrap();
+        let limited_bits = predicted_bits(&limited, &freqs).unwrap();
+
+        assert_eq!(longest(&free), 24);
+        assert_eq!(longest(&limited), 12);
+        assert_eq!(kraft, 1 << 12);
+        assert!(DecoderFsm::from_code_map(&limited).is_ok());
+        assert!(limited_bits > free_bits);
+        assert!(limited_bits - free_bits < free_bits / 1000);
+
+        // A limit that doesn't bind changes nothing but the codes' order.
+        let loose = generate_huffman_codes_limited(&text, 24).unwrap();
+
+        assert_eq!(predicted_bits(&loose, &freqs), Some(free_bits));
+
+        // Eight codes of 3 bits can't go around nine symbols.
+        assert_eq!(generate_huffman_codes_limited("abcdefghi", 3),
+                   Err(HuffmanError::LengthCapInfeasible {
+                       symbol: 'i', cap: 3
+                   }));
+        assert_eq!(generate_huffman_codes_limited("abcdefgh", 3).unwrap()
+                                                               .len(), 8);
+        assert!(generate_huffman_codes_limited("", 0).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Compressing text a line at a time, for logs and other records that are
+//! searched more often than they're read whole. One table is trained on all
+//! the text and carried once, and each line is coded as a record of its own,
+//! so a reader can decode the lines one by one and stop at any of them.
+//! 
+//! The stream starts with the header `pipe_compress()` writes, with bits 0
+//! and 7 of the flags set, and the table. Each line follows as a varint, in
+//! the LEB128 form `MuxWriter` uses, of its number of bits of codes, then the
+//! codes, packed most significant bit first and padded to a whole byte. A
+//! line's record includes its ending, `\n` or `\r\n`, if it has one, so the
+//! text comes back exactly; only the last line can lack one. The stream ends
+//! after the last record.
+//! 
+
+use std::io::{BufRead, Read, Write};
+use std::time::Instant;
+
+use crate::bits::BitWriter;
+use crate::encode::encode_into;
+use crate::mux::read_varint;
+use crate::pipe::{header, read_flags, read_table, FLAG_LINES, FLAG_TABLE};
+use crate::pipe::MAGIC;
+use crate::varint::write_varint;
+use crate::HuffmanError;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+use crate::container::CompressionStats;
+use crate::io::{DecodeTable, READ_BUF_SIZE};
+
+
+/// Compresses the text read from `r` a line at a time, writing the stream to
+/// `w`. The table is trained on all the text, which is buffered in memory to
+/// do so. Fails with `HuffmanError::InvalidUtf8` if the text isn't UTF-8, and
+/// as `HuffmanCodebook::try_from_frequencies()` does. The stream is read by a
+/// `LineReader`.
+/// 
+pub fn compress_lines(mut r : impl BufRead,
+                      mut w : impl Write) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let start     = Instant::now();
+    let mut input = vec![];
+
+    r.read_to_end(&mut input)?;
+
+    let text = std::str::from_utf8(&input)
+                   .map_err(|_| HuffmanError::InvalidUtf8)?;
+    let codebook = HuffmanCodebook::try_from_frequencies(
+                       FrequencyTable::from_text(text))?;
+    let header   = header(&codebook, FLAG_TABLE | FLAG_LINES);
+
+    let mut stats = CompressionStats {
+        table_bytes  : (header.len() - MAGIC.len() - 2) as u64,
+        stream_bytes : header.len() as u64,
+        tree_build   : Some(codebook.frequencies().summary().tree_build()),
+        count_time   : start.elapsed(),
+        ..Default::default()
+    };
+    let start      = Instant::now();
+    let mut record = vec![];
+
+    w.write_all(&header)?;
+
+    for line in text.split_inclusive('\n') {
+        let mut writer = BitWriter::new();
+
+        encode_into(&codebook, line, &mut writer, None)?;
+
+        let bits = writer.bit_len();
+
+        record.clear();
+        write_varint(&mut record, bits);
+        record.extend(writer.finish());
+        w.write_all(&record)?;
+
+        stats.symbols        += line.chars().count() as u64;
+        stats.text_bytes     += line.len() as u64;
+        stats.payload_bits   += bits;
+        stats.predicted_bits += bits;
+        stats.stream_bytes   += record.len() as u64;
+    }
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+
+/// Reads the lines of a stream written by `compress_lines()`, in order, each
+/// with its ending, if it has one. Iteration stops after the last line, or
+/// after the first error.
+/// 
+#[derive(Debug)]
+pub struct LineReader<R: Read> {
+    inner : R,
+    /// The table to decode the lines with, or `None` if the text was empty.
+    table : Option<DecodeTable>,
+    codes : Vec<u8>,
+    done  : bool,
+}
+
+impl<R: Read> LineReader<R> {
+    /// Reads the stream's header and table. Fails as `pipe_decompress()`
+    /// does for a bad header, with `HuffmanError::MalformedData` for a stream
+    /// that isn't in lines, and as `HuffmanCodebook::import()` does for the
+    /// table.
+    /// 
+    pub fn new(mut inner: R) -> Result<Self, HuffmanError> {
+        if read_flags(&mut inner)? != FLAG_TABLE | FLAG_LINES {
+            return Err(HuffmanError::MalformedData("the stream isn't in \
+                                                    lines"));
+        }
+        let codebook = HuffmanCodebook::import(&read_table(&mut inner)?)?;
+        let table    = match codebook.codes().is_empty() {
+            true  => None,
+            false => Some(DecodeTable::from_codes(&codebook.code_strings())?),
+        };
+        Ok(Self { inner, table, codes: vec![], done: false })
+    }
+    /// Reads the next line, or gives `None` at the end of the stream. Fails
+    /// with `HuffmanError::MalformedData` if the line's record is cut short,
+    /// and as `DecodeTable::decode()` does on its codes.
+    /// 
+    pub fn read_line(&mut self) -> Result<Option<String>, HuffmanError> {
+        let truncated = HuffmanError::MalformedData("the data is truncated");
+
+        let Some(bits) = read_varint(&mut self.inner)? else {
+            return Ok(None);
+        };
+        let bits  = usize::try_from(bits).map_err(|_| truncated.clone())?;
+        let bytes = bits.div_ceil(8);
+
+        self.codes.clear();
+        self.codes.reserve(bytes.min(READ_BUF_SIZE));
+        (&mut self.inner).take(bytes as u64).read_to_end(&mut self.codes)?;
+
+        if self.codes.len() < bytes {
+            return Err(truncated);
+        }
+        match &self.table {
+            Some(table)        => table.decode(&self.codes, bits).map(Some),
+            None if bits == 0  => Ok(Some(String::new())),
+            None               => Err(HuffmanError::MalformedData(
+                                      "a line has codes but there's no \
+                                       table")),
+        }
+    }
+}
+
+impl<R: Read> Iterator for LineReader<R> {
+    type Item = Result<String, HuffmanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let line = self.read_line().transpose();
+
+        self.done = !matches!(line, Some(Ok(_)));
+        line
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::{compress_auto, pipe_decompress};
+
+    fn compress(text: &str) -> Vec<u8> {
+        let mut stream = vec![];
+
+        compress_lines(text.as_bytes(), &mut stream).unwrap();
+        stream
+    }
+
+    fn decode_lines(stream: &[u8]) -> Vec<String> {
+        LineReader::new(stream).unwrap()
+                               .collect::<Result<_, _>>()
+                               .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let long = "a rather long line of the log ".repeat(2000);
+        let text = format!("GET /index.html 200\n\n\r\n{long}\r\nPOST /form \
+                            302\r\n\n\nlast line, unended");
+
+        let stream = compress(&text);
+        let lines  = decode_lines(&stream);
+
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[1], "\n");
+        assert_eq!(lines[2], "\r\n");
+        assert_eq!(lines[3], format!("{long}\r\n"));
+        assert_eq!(lines.concat(), text);
+
+        // The lines share the one table, so the text still compresses.
+        assert!(stream.len() < text.len() / 2);
+
+        for text in ["", "\n", "\r\n", "x", "x\n", "\n\n\n", "one\r\ntwo"] {
+            assert_eq!(decode_lines(&compress(text)).concat(), text);
+        }
+        assert!(decode_lines(&compress("")).is_empty());
+        assert_eq!(decode_lines(&compress("\n\n")), ["\n", "\n"]);
+    }
+
+    #[test]
+    fn stops_early() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let stream = compress(&text);
+
+        let found = LineReader::new(&stream[..]).unwrap()
+                                                .map(Result::unwrap)
+                                                .position(|line| {
+                                                    line.contains("Ishmael")
+                                                });
+        let expected = text.split_inclusive('\n')
+                           .position(|line| line.contains("Ishmael"));
+
+        assert_eq!(found, expected);
+        assert_eq!(decode_lines(&stream).concat(), text);
+    }
+
+    #[test]
+    fn bad_streams() {
+        let stream = compress("first\nsecond\n");
+
+        let mut reader = LineReader::new(&stream[..stream.len() - 1]).unwrap();
+
+        assert_eq!(reader.next(), Some(Ok("first\n".to_string())));
+        assert_eq!(reader.next(),
+                   Some(Err(HuffmanError::MalformedData(
+                                "the data is truncated"))));
+        assert_eq!(reader.next(), None);
+
+        assert_eq!(LineReader::new(&compress_auto("text")[..]).err(),
+                   Some(HuffmanError::MalformedData(
+                            "the stream isn't in lines")));
+        assert_eq!(compress_lines(&b"\xff\n"[..], vec![]).err(),
+                   Some(HuffmanError::InvalidUtf8));
+
+        let mut decompressed = vec![];
+
+        assert_eq!(pipe_decompress(&stream[..], &mut decompressed, None, None)
+                       .err(),
+                   Some(HuffmanError::MalformedData(
+                            "the stream is in lines")));
+    }
+}
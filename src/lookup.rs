@@ -0,0 +1,377 @@
+//! Decoding a whole code per lookup, rather than a bit at a time: a table
+//! indexed by the next bits of the stream gives the symbol they start with and
+//! the length of its code.
+//! 
+//! Codes longer than the first table's width carry on in further tables, each
+//! linked from the entry for the bits they start with and indexed by the bits
+//! after those, as zlib's inflate does. The tables are built by walking the
+//! states of a `DecoderFsm` for every index, so they decode exactly what it
+//! does, and fail where it does.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::collections::HashMap;
+use crate::io::{DecoderFsm, Transition};
+
+
+/// The width of the first table `DecodeTable::from_codes()` builds.
+/// 
+const DEFAULT_WIDTH: u8 = 10;
+
+/// The widths a first table may be built with.
+/// 
+const WIDTHS: core::ops::RangeInclusive<u8> = 8..=12;
+
+
+/// What the bits indexing an entry decode to.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entry {
+    /// A code ends within the bits: its symbol, and how many of them it takes.
+    Symbol(char, u8),
+    /// This many of the bits aren't the start of any code.
+    Invalid(u8),
+    /// Every code starting with the bits carries on in the table at this
+    /// offset, of this width.
+    Link(u32, u8),
+}
+
+/// Why a lookup found no code.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Miss {
+    /// The bits aren't the start of any code.
+    Invalid,
+    /// The bits end partway through a code.
+    Truncated,
+}
+
+impl Miss {
+    /// The error for a miss on the code starting at `bit_offset`, after
+    /// `symbols` were decoded.
+    /// 
+    pub(crate) fn error(self, bit_offset: usize, symbols: usize) 
+
+        -> HuffmanError 
+    {
+        match self {
+            Miss::Invalid => {
+                HuffmanError::InvalidCode { bit_offset, symbols }
+            },
+            Miss::Truncated => {
+                HuffmanError::TruncatedBitstream { bit_offset, symbols }
+            },
+        }
+    }
+}
+
+
+/// A decoder that looks up whole codes in tables indexed by the next bits of
+/// the stream, rather than walking a tree a bit at a time. Codes no longer
+/// than the first table's width take a single lookup.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeTable {
+    /// The tables one after another, the first at offset 0.
+    entries : Vec<Entry>,
+    /// The width of the first table.
+    width   : u8,
+}
+
+impl DecodeTable {
+    /// The tables for a map of code strings, such as the one
+    /// `generate_huffman_codes()` returns, with a first table 10 bits wide, or
+    /// as wide as the longest code if that's shorter. Fails as
+    /// `DecoderFsm::from_code_map()` does.
+    /// 
+    pub fn from_codes(codes: &HashMap<char, String>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        Self::with_width(codes, DEFAULT_WIDTH)
+    }
+    /// Like `from_codes()`, with a first table `width` bits wide, which must
+    /// be 8 to 12. Wider tables take fewer lookups for long codes, but take
+    /// more memory and build more slowly. Fails with
+    /// `HuffmanError::MalformedData` for any other width.
+    /// 
+    pub fn with_width(codes: &HashMap<char, String>, width: u8) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        if !WIDTHS.contains(&width) {
+            return Err(HuffmanError::MalformedData("the table width is out of \
+                                                    range"));
+        }
+        let fsm     = DecoderFsm::from_code_map(codes)?;
+        let heights = heights(&fsm);
+
+        let mut table = Self { entries: vec![], width: 0 };
+
+        table.width = heights[0].min(width);
+        table.build(&fsm, &heights, 0, table.width, width);
+
+        Ok(table)
+    }
+    /// The width of the first table, in bits.
+    /// 
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+    /// The number of entries in all the tables.
+    /// 
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Decodes the first `nbits` bits of `bytes`, packed most significant bit
+    /// first. Fails with `HuffmanError::InvalidCode` on bits that aren't the
+    /// start of any code, and `HuffmanError::TruncatedBitstream` if the bits
+    /// end partway through one, counting bits past the end of `bytes` as
+    /// missing. Errors give the offset where the code started.
+    /// 
+    pub fn decode(&self, bytes: &[u8], nbits: usize) 
+
+        -> Result<String, HuffmanError> 
+    {
+        let total       = nbits.min(bytes.len() * 8);
+        let mut text    = String::new();
+        let mut window  = 0_u128;
+        let mut held    = 0;
+        let mut next    = 0;
+        let mut pos     = 0;
+        let mut symbols = 0;
+
+        while pos < nbits {
+            while held <= 120 && next < bytes.len() {
+                window |= (bytes[next] as u128) << (120 - held);
+                held   += 8;
+                next   += 1;
+            }
+            let avail = (total - pos).min(held as usize) as u32;
+
+            let (c, len) = self.lookup(window, avail)
+                               .map_err(|miss| miss.error(pos, symbols))?;
+            text.push(c);
+            window <<= len;
+            held     -= len;
+            pos      += len as usize;
+            symbols  += 1;
+        }
+        Ok(text)
+    }
+    /// Looks up the code that `window` starts with, most significant bit
+    /// first, of which only the first `avail` bits are the stream's. Gives its
+    /// symbol and length.
+    /// 
+    pub(crate) fn lookup(&self, window: u128, avail: u32) 
+
+        -> Result<(char, u32), Miss> 
+    {
+        let mut offset = 0;
+        let mut width  = self.width as u32;
+        let mut used   = 0;
+
+        loop {
+            let index = (window << used >> (128 - width)) as usize;
+
+            match self.entries[offset + index] {
+                Entry::Symbol(c, len) if used + len as u32 <= avail => {
+                    return Ok((c, used + len as u32));
+                },
+                Entry::Invalid(len) if used + len as u32 <= avail => {
+                    return Err(Miss::Invalid);
+                },
+                Entry::Link(next, next_width) if used + width <= avail => {
+                    offset = next as usize;
+                    used  += width;
+                    width  = next_width as u32;
+                },
+                _ => return Err(Miss::Truncated),
+            }
+        }
+    }
+    /// Adds the table of `width` bits for the codes carrying on from `state`,
+    /// and those it links to, with none wider than `max_width`. Gives its
+    /// offset.
+    /// 
+    fn build(&mut self,
+             fsm       : &DecoderFsm,
+             heights   : &[u8],
+             state     : u32,
+             width     : u8,
+             max_width : u8) 
+
+        -> u32 
+    {
+        let offset    = self.entries.len();
+        let mut links = vec![];
+
+        for index in 0..1_usize << width {
+            let mut entry = None;
+            let mut next  = state;
+
+            for depth in 1..=width {
+                let bit = index >> (width - depth) & 1 == 1;
+
+                match fsm.step(next, bit) {
+                    Transition::Next(s) => next = s,
+                    Transition::Emit(c) => {
+                        entry = Some(Entry::Symbol(c, depth));
+                        break;
+                    },
+                    Transition::Invalid => {
+                        entry = Some(Entry::Invalid(depth));
+                        break;
+                    },
+                }
+            }
+            if entry.is_none() {
+                links.push((offset + index, next));
+            }
+            self.entries.push(entry.unwrap_or(Entry::Invalid(0)));
+        }
+        for (at, next) in links {
+            let width = heights[next as usize].min(max_width);
+            let table = self.build(fsm, heights, next, width, max_width);
+
+            self.entries[at] = Entry::Link(table, width);
+        }
+        offset as u32
+    }
+}
+
+/// The length of the longest code carrying on from each state of `fsm`, or 1
+/// for a start state with no codes at all.
+/// 
+fn heights(fsm: &DecoderFsm) -> Vec<u8> {
+    fn height(fsm: &DecoderFsm, state: u32, memo: &mut [u8]) -> u8 {
+        if memo[state as usize] == 0 {
+            memo[state as usize] = 1 + fsm.states()[state as usize]
+                                          .iter()
+                                          .map(|&t| match t {
+                                              Transition::Next(s) => {
+                                                  height(fsm, s, memo)
+                                              },
+                                              _ => 0,
+                                          })
+                                          .max()
+                                          .unwrap_or(0);
+        }
+        memo[state as usize]
+    }
+    let mut memo = vec![0; fsm.states().len()];
+
+    height(fsm, 0, &mut memo);
+    memo
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use crate::codebook::{count_frequencies, FrequencyTable};
+    use crate::io::{encode, BitBuffer};
+    use crate::tree::{build_tree, HuffmanCodec};
+
+    #[test]
+    fn matches_tree_walking() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "é€🦀";
+        let tree  = build_tree(&count_frequencies(&text)).unwrap();
+        let bits  = encode(&text);
+        let table = DecodeTable::from_codes(&tree.codes()).unwrap();
+
+        assert_eq!(table.width(), 10);
+        assert_eq!(table.decode(bits.as_bytes(), bits.bit_len()),
+                   Ok(text.clone()));
+        assert_eq!(tree.decode(&bits), Ok(text));
+
+        // Every cut fails as the tree walker does.
+        for nbits in (0..200).chain(bits.bit_len() - 40..bits.bit_len()) {
+            let cut = BitBuffer::try_new_lenient(bits.as_bytes().to_vec(),
+                                                 nbits)
+                                .unwrap();
+
+            assert_eq!(table.decode(bits.as_bytes(), nbits), tree.decode(&cut),
+                       "{} bits", nbits);
+        }
+        // Bits past the end of the bytes are missing.
+        assert!(matches!(table.decode(&bits.as_bytes()[..4], 40),
+                         Err(HuffmanError::TruncatedBitstream { .. })));
+    }
+
+    #[test]
+    fn codes_longer_than_the_first_table() {
+        // Fibonacci counts give codes of every length up to 39 bits.
+        let (mut a, mut b) = (1_usize, 1_usize);
+        let freqs = ('a'..).take(40).map(|c| {
+            (a, b) = (b, a + b);
+            (c, a)
+        }).collect::<FrequencyTable>();
+
+        let codec = HuffmanCodec::from_frequencies(&freqs).unwrap();
+        let tree  = codec.tree();
+        let codes = codec.codes();
+
+        assert_eq!(codes.values().map(String::len).max(), Some(39));
+
+        let text  = codes.keys().cycle().take(1000).collect::<String>();
+        let bits  = codec.encode(&text).unwrap();
+        let table = DecodeTable::with_width(&codes, 8).unwrap();
+
+        assert_eq!(table.width(), 8);
+        assert_eq!(table.decode(bits.as_bytes(), bits.bit_len()), Ok(text));
+
+        for nbits in 0..bits.bit_len().min(600) {
+            let cut = BitBuffer::try_new_lenient(bits.as_bytes().to_vec(),
+                                                 nbits)
+                                .unwrap();
+
+            assert_eq!(table.decode(bits.as_bytes(), nbits), tree.decode(&cut));
+        }
+    }
+
+    #[test]
+    fn incomplete_codes() {
+        let codes = [('a', "0"), ('b', "10")].into_iter()
+                                            .map(|(c, s)| (c, s.to_string()))
+                                            .collect();
+        let table = DecodeTable::from_codes(&codes).unwrap();
+        let fsm   = DecoderFsm::from_code_map(&codes).unwrap();
+
+        // Small code sets get a first table only as wide as their codes.
+        assert_eq!((table.width(), table.len()), (2, 4));
+
+        for (bytes, nbits) in [(&[0b0100_0000][..], 3), (&[0b0111_0000], 3),
+                               (&[0b0110_0000], 2), (&[0b1000_0000], 1),
+                               (&[0b1100_0000], 1)] {
+            let bits = BitBuffer::try_new_lenient(bytes.to_vec(), nbits)
+                                 .unwrap();
+
+            assert_eq!(table.decode(bytes, nbits), fsm.decode(&bits),
+                       "{:?}", bytes);
+        }
+        assert_eq!(DecodeTable::with_width(&codes, 13),
+                   Err(HuffmanError::MalformedData("the table width is out \
+                                                    of range")));
+        assert_eq!(table.decode(&[], 1),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 0, symbols: 0
+                   }));
+
+        let empty = DecodeTable::from_codes(&HashMap::new()).unwrap();
+
+        assert_eq!(empty.decode(&[0], 1),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+    }
+}
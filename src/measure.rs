@@ -0,0 +1,205 @@
+//! Timing the phases of coding a text, for throughput numbers without a
+//! benchmark crate. Each phase is run back to back over the same buffers, so
+//! after the warmup rounds the timings are of steady-state work rather than of
+//! allocation.
+//! 
+
+use std::time::{Duration, Instant};
+
+use crate::encode::encode_into;
+use crate::{grow_tree, tree_code_bits, NodeMem};
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+use crate::io::{BitBuffer, DecoderFsm};
+
+
+/// How `measure()` runs.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeasureConfig {
+    /// The number of timed rounds. At least one is run.
+    pub iterations : u32,
+    /// The number of untimed rounds run first, to fill buffers and caches.
+    pub warmup     : u32,
+}
+
+impl Default for MeasureConfig {
+    fn default() -> Self {
+        Self { iterations: 10, warmup: 2 }
+    }
+}
+
+/// The time one phase took over the timed rounds of `measure()`.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseTiming {
+    /// The time over all the rounds.
+    pub total      : Duration,
+    /// The time per round.
+    pub mean       : Duration,
+    /// Megabytes (10^6 bytes) of input per second.
+    pub mb_per_sec : f64,
+}
+
+impl PhaseTiming {
+    /// The timing of `rounds` rounds over `bytes` bytes of input that took
+    /// `total`.
+    /// 
+    fn new(total: Duration, rounds: u32, bytes: usize) -> Self {
+        let secs = total.as_secs_f64();
+
+        Self {
+            total,
+            mean       : total / rounds,
+            mb_per_sec : match secs > 0.0 {
+                true  => bytes as f64 * rounds as f64 / secs / 1e6,
+                false => f64::INFINITY,
+            },
+        }
+    }
+}
+
+/// The timings of each phase of coding a text, from `measure()`.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeasureReport {
+    /// The size of the text in bytes.
+    pub bytes         : usize,
+    /// The number of characters in the text.
+    pub chars         : usize,
+    /// The number of timed rounds.
+    pub iterations    : u32,
+    /// Counting the characters.
+    pub counting      : PhaseTiming,
+    /// Building the tree, the codes, and the decoder for them.
+    pub table_build   : PhaseTiming,
+    /// Encoding the text.
+    pub encode        : PhaseTiming,
+    /// Decoding the encoded text.
+    pub decode        : PhaseTiming,
+    /// The size of the encoded text, in bits per character.
+    pub bits_per_char : f64,
+}
+
+/// Times counting the characters of `data`, building its tree, codes, and
+/// decoder, encoding it, and decoding it again, over `config.warmup` untimed
+/// and then `config.iterations` timed rounds. The frequency table, tree
+/// arena, and encoding and decoding buffers are reused from round to round.
+/// 
+/// # Panics
+/// 
+/// If the text decodes to something other than itself.
+/// 
+pub fn measure(data: &str, config: MeasureConfig) -> MeasureReport {
+    let rounds     = config.iterations.max(1);
+    let mut freqs  = FrequencyTable::default();
+    let mut nodes  = NodeMem::new();
+    let mut buffer = BitBuffer::default();
+    let mut text   = String::new();
+    let mut times  = [Duration::ZERO; 4];
+
+    for round in 0..config.warmup + rounds {
+        let start = Instant::now();
+
+        freqs.counts.clear();
+
+        for c in data.chars() {
+            *freqs.counts.entry(c).or_insert(0) += 1;
+        }
+        let counted = Instant::now();
+        let tree    = grow_tree(&freqs, &mut nodes);
+        let codes   = tree_code_bits(tree, &nodes).unwrap_or_else(|_| {
+            HuffmanCodebook::from_frequencies_capped(freqs.clone()).codes
+        });
+        let book    = HuffmanCodebook::from_parts(FrequencyTable::default(),
+                                                  codes);
+        let fsm     = DecoderFsm::from_codebook(&book)
+                          .expect("Huffman codes are prefix-free");
+        let built   = Instant::now();
+
+        let mut writer = std::mem::take(&mut buffer).into_writer();
+
+        encode_into(&book, data, &mut writer, None)
+            .expect("every character of the text has a code");
+
+        buffer = writer.into_buffer();
+
+        let encoded = Instant::now();
+
+        fsm.decode_into(&buffer, &mut text).expect("the codes decode");
+
+        let decoded = Instant::now();
+
+        assert_eq!(text, data, "the text didn't round-trip");
+
+        if round >= config.warmup {
+            let phases = [counted - start, built - counted, encoded - built,
+                          decoded - encoded];
+
+            for (time, phase) in times.iter_mut().zip(phases) {
+                *time += phase;
+            }
+        }
+    }
+    let chars  = freqs.total();
+    let timing = |total| PhaseTiming::new(total, rounds, data.len());
+
+    MeasureReport {
+        bytes         : data.len(),
+        chars,
+        iterations    : rounds,
+        counting      : timing(times[0]),
+        table_build   : timing(times[1]),
+        encode        : timing(times[2]),
+        decode        : timing(times[3]),
+        bits_per_char : match chars {
+            0 => 0.0,
+            n => buffer.bit_len() as f64 / n as f64,
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moby_dick() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let config = MeasureConfig { iterations: 3, warmup: 1 };
+        let report = measure(&text, config);
+
+        assert_eq!((report.bytes, report.iterations), (text.len(), 3));
+        assert_eq!(report.chars, text.chars().count());
+
+        for phase in [report.counting, report.table_build, report.encode,
+                      report.decode] {
+            assert!(phase.total > Duration::ZERO);
+            assert_eq!(phase.mean, phase.total / 3);
+
+            let rate = text.len() as f64 * 3.0 / phase.total.as_secs_f64() 
+                       / 1e6;
+
+            assert!((phase.mb_per_sec - rate).abs() <= rate * 1e-9);
+        }
+        let stats = crate::codebook::code_stats(&text);
+
+        assert_eq!(report.bits_per_char, stats.average_code_length);
+        assert!(report.bits_per_char > 4.0 && report.bits_per_char < 5.0);
+    }
+
+    #[test]
+    fn small_texts() {
+        let config = MeasureConfig { iterations: 0, warmup: 0 };
+
+        for text in ["", "a", "aab"] {
+            let report = measure(text, config);
+
+            assert_eq!(report.iterations, 1);
+            assert_eq!(report.chars, text.len());
+        }
+        assert_eq!(measure("", config).bits_per_char, 0.0);
+        assert_eq!(measure("aaaa", config).bits_per_char, 1.0);
+        assert_eq!(measure("aaabbc", config).bits_per_char, 1.5);
+    }
+}
@@ -0,0 +1,191 @@
+//! The running median of a stream of whole numbers, such as code lengths or
+//! symbol counts, kept on two heaps: a max-heap of the lower half of the
+//! values and a min-heap of the upper half, whose tops are the middle values.
+//! 
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use heapq::heap_push_with_aux;
+#[cfg(feature = "std")]
+use crate::codebook::HuffmanCodebook;
+use crate::heap::heap_pushpop_with_aux;
+
+
+/// The lower and upper medians of some values, if there are any.
+/// 
+type Bounds = Option<(u64, u64)>;
+
+/// Orders the min-heap of the upper half.
+/// 
+fn min_first(a: &u64, b: &u64, _: &()) -> Ordering {
+    a.cmp(b)
+}
+
+/// Orders the max-heap of the lower half.
+/// 
+fn max_first(a: &u64, b: &u64, _: &()) -> Ordering {
+    b.cmp(a)
+}
+
+
+/// The median of the values inserted so far, updated in O(log n) time per
+/// value. The lower half never has fewer values than the upper half, nor more
+/// than one more.
+/// 
+#[derive(Clone, Debug, Default)]
+pub struct RunningMedian {
+    lower : Vec<u64>,
+    upper : Vec<u64>,
+}
+
+impl RunningMedian {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds `x` to the values.
+    /// 
+    pub fn insert(&mut self, x: u64) {
+        // The lower half is as long as the upper, or one longer. `x` goes
+        // through the half that is to lose out, and whichever value of that
+        // half and `x` falls nearest the middle moves across to the other.
+        if self.lower.len() == self.upper.len() {
+            let x = heap_pushpop_with_aux(&mut self.upper, x, min_first, &());
+
+            heap_push_with_aux(&mut self.lower, x, max_first, &());
+        } else {
+            let x = heap_pushpop_with_aux(&mut self.lower, x, max_first, &());
+
+            heap_push_with_aux(&mut self.upper, x, min_first, &());
+        }
+    }
+    /// The number of values inserted.
+    /// 
+    pub fn len(&self) -> usize {
+        self.lower.len() + self.upper.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lower.is_empty()
+    }
+    /// The lower and upper medians: the middle two values in order, or the
+    /// middle value twice for an odd number of values. Every median of the
+    /// values lies between them. `None` if there are no values.
+    /// 
+    pub fn quantile_bounds(&self) -> Bounds {
+        let low = *self.lower.first()?;
+
+        match self.lower.len() > self.upper.len() {
+            true  => Some((low, low)),
+            false => Some((low, self.upper[0])),
+        }
+    }
+    /// The median: the middle value, or the mean of the middle two for an
+    /// even number of values. `None` if there are no values.
+    /// 
+    pub fn median(&self) -> Option<f64> {
+        let (low, high) = self.quantile_bounds()?;
+
+        Some(low as f64 + (high - low) as f64 / 2.0)
+    }
+}
+
+impl Extend<u64> for RunningMedian {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        for x in iter {
+            self.insert(x);
+        }
+    }
+}
+
+/// The quantile bounds of the lengths of `codebook`'s codes, and of the
+/// counts of its symbols, each taken over its symbols, for
+/// `CompressionStats`.
+/// 
+#[cfg(feature = "std")]
+pub(crate) fn codebook_medians(codebook: &HuffmanCodebook) -> (Bounds, Bounds) {
+    let mut lengths = RunningMedian::new();
+    let mut counts  = RunningMedian::new();
+
+    lengths.extend(codebook.codes().values().map(|code| code.len as u64));
+    counts.extend(codebook.frequencies()
+                          .counts()
+                          .values()
+                          .map(|&n| n as u64));
+
+    (lengths.quantile_bounds(), counts.quantile_bounds())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SplitMix64;
+
+    #[test]
+    fn agrees_with_sorting() {
+        let mut rng = SplitMix64(0x5eed);
+
+        for round in 0..50 {
+            // Small ranges make for plenty of duplicates.
+            let len   = 1 + rng.below(200) + round % 2;
+            let range = [3, 50, 1 << 40][round % 3];
+            let mut values = (0..len).map(|_| rng.next_u64() % range)
+                                     .collect::<Vec<_>>();
+
+            let mut running = RunningMedian::new();
+
+            for (i, &x) in values.iter().enumerate() {
+                running.insert(x);
+
+                let mut sorted = values[..=i].to_vec();
+
+                sorted.sort_unstable();
+
+                let n = sorted.len();
+
+                assert_eq!(running.len(), n);
+                assert_eq!(running.quantile_bounds(),
+                           Some((sorted[(n - 1) / 2], sorted[n / 2])));
+            }
+            values.sort_unstable();
+
+            let n      = values.len();
+            let median = (values[(n - 1) / 2] as f64 + values[n / 2] as f64)
+                         / 2.0;
+
+            assert_eq!(running.median(), Some(median));
+        }
+    }
+
+    #[test]
+    fn small_cases() {
+        let mut running = RunningMedian::new();
+
+        assert_eq!((running.median(), running.quantile_bounds()), (None, None));
+        assert!(running.is_empty());
+
+        running.extend([7, 7]);
+
+        assert_eq!(running.median(), Some(7.0));
+
+        running.extend([1, 2]);
+
+        assert_eq!(running.quantile_bounds(), Some((2, 7)));
+        assert_eq!(running.median(), Some(4.5));
+
+        running.insert(u64::MAX);
+
+        assert_eq!(running.quantile_bounds(), Some((7, 7)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn codebook_stats() {
+        let codebook = HuffmanCodebook::from_text("aaaabbc");
+
+        // Code lengths 1, 2, 2, and counts 4, 2, 1.
+        assert_eq!(codebook_medians(&codebook), (Some((2, 2)), Some((2, 2))));
+        assert_eq!(codebook_medians(&HuffmanCodebook::from_text("")),
+                   (None, None));
+    }
+}
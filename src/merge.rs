@@ -0,0 +1,226 @@
+//! Merging many frequency tables without building one giant map from them
+//! all at once.
+//! 
+//! Each table is turned into a run of its `(symbol, count)` pairs, sorted by
+//! symbol, and runs are merged k at a time through a heap of their heads, so
+//! the symbols come out in order and counts for the same symbol arrive one
+//! after another to be summed. Only the merged run and the next batch of
+//! tables are held at a time.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use crate::codebook::FrequencyTable;
+use crate::heap::merge_with_aux;
+
+
+/// The number of runs `FrequencyTable::merge_streaming()` merges at a time.
+/// 
+const FAN_IN: usize = 64;
+
+
+/// A run of `(symbol, count)` pairs, sorted by symbol.
+/// 
+type Run = Vec<(char, usize)>;
+
+impl FrequencyTable {
+    /// The sum of `tables`, as if every count of each were added to one
+    /// table. The tables are merged in batches by a k-way merge over their
+    /// sorted pairs, so only one batch of tables is held at a time, along
+    /// with the merged pairs so far, rather than every table at once. Counts
+    /// saturate as they do for `merge()`.
+    /// 
+    pub fn merge_streaming(tables: impl IntoIterator<Item = FrequencyTable>) 
+
+        -> FrequencyTable 
+    {
+        Self::merge_streaming_with_fan_in(tables, FAN_IN)
+    }
+    /// Like `merge_streaming()`, merging `fan_in` runs at a time, counting
+    /// the merged run so far. Larger batches take fewer passes over the
+    /// merged pairs, but hold more tables at once. A `fan_in` under 2 is
+    /// taken as 2.
+    /// 
+    pub fn merge_streaming_with_fan_in<I>(tables: I, fan_in: usize) 
+
+        -> FrequencyTable 
+    where
+        I: IntoIterator<Item = FrequencyTable>,
+    {
+        let mut tables = tables.into_iter();
+        let mut merged = Run::new();
+
+        loop {
+            let mut runs = vec![core::mem::take(&mut merged)];
+
+            runs.extend(tables.by_ref()
+                              .take(fan_in.max(2) - 1)
+                              .map(FrequencyTable::into_run));
+
+            if runs.len() == 1 {
+                return Self { counts: runs.remove(0).into_iter().collect() };
+            }
+            merged = merge_runs(runs);
+        }
+    }
+    /// Adds the counts of `other` to this table's, as if the text it counted
+    /// had been counted here too, so a corpus can be counted in chunks, in
+    /// parallel, and the counts merged. A count too large for a `usize`
+    /// saturates at `usize::MAX` rather than wrapping.
+    /// 
+    pub fn merge(&mut self, other: FrequencyTable) {
+        for (c, count) in other.counts {
+            let total = self.counts.entry(c).or_insert(0);
+
+            *total = total.saturating_add(count);
+        }
+    }
+    /// The table's pairs, sorted by symbol.
+    /// 
+    fn into_run(self) -> Run {
+        let mut run = self.counts.into_iter().collect::<Run>();
+
+        run.sort_unstable_by_key(|&(c, _)| c);
+        run
+    }
+}
+
+/// Merges sorted runs into one, summing the counts for each symbol.
+/// 
+fn merge_runs(runs: Vec<Run>) -> Run {
+    fn by_symbol(a: &(char, usize), b: &(char, usize), _: &()) -> Ordering {
+        a.0.cmp(&b.0)
+    }
+    let capacity   = runs.iter().map(Vec::len).max().unwrap_or(0);
+    let runs       = runs.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Run::with_capacity(capacity);
+
+    for (c, count) in merge_with_aux(runs, &by_symbol, &()) {
+        match merged.last_mut() {
+            Some((last, total)) if *last == c => {
+                *total = total.saturating_add(count);
+            },
+            _ => merged.push((c, count)),
+        }
+    }
+    merged
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::generate_huffman_codes;
+    use crate::sample::SplitMix64;
+
+    /// Adds the tables one into another.
+    /// 
+    fn merge_pairwise(tables: &[FrequencyTable]) -> FrequencyTable {
+        let mut merged = FrequencyTable::default();
+
+        for table in tables {
+            for (&c, &count) in table.counts() {
+                merged.add(c, count);
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn matches_pairwise_merging() {
+        let mut rng = SplitMix64(268);
+
+        // A thousand tables over overlapping alphabets of varied sizes.
+        let tables = (0..1000).map(|seed| {
+            let base = 0x20 + rng.below(2000) as u32;
+            let freqs = (0..1 + rng.below(200)).filter_map(|_| {
+                let c = char::from_u32(base + rng.below(500) as u32)?;
+
+                Some((c, 1 + rng.below(100)))
+            }).collect::<FrequencyTable>();
+
+            FrequencyTable::from_text(&freqs.sample(rng.below(3000), seed))
+        }).collect::<Vec<_>>();
+
+        let expected = merge_pairwise(&tables);
+
+        assert_eq!(FrequencyTable::merge_streaming(tables.clone()), expected);
+
+        for fan_in in [0, 2, 3, 1000, 2000] {
+            assert_eq!(FrequencyTable::merge_streaming_with_fan_in(
+                           tables.clone(), fan_in
+                       ),
+                       expected);
+        }
+        assert_eq!(expected.total(),
+                   tables.iter().map(FrequencyTable::total).sum::<usize>());
+    }
+
+    #[test]
+    fn small_merges() {
+        let mut zero = FrequencyTable::from_text("ab");
+
+        zero.add('z', 0);
+
+        let tables = [zero, FrequencyTable::default(),
+                      FrequencyTable::from_text("bcc🦀")];
+
+        assert_eq!(FrequencyTable::merge_streaming(tables.clone()),
+                   merge_pairwise(&tables));
+        assert_eq!(FrequencyTable::merge_streaming([]),
+                   FrequencyTable::default());
+        assert_eq!(merge_runs(vec![vec![('a', 1), ('c', 2)],
+                                   vec![('b', 3), ('c', 4)], vec![]]),
+                   [('a', 1), ('b', 3), ('c', 6)]);
+    }
+
+    #[test]
+    fn chunked_counting() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut at = 0;
+        let mut merged = FrequencyTable::new();
+
+        // Four chunks, each cut at a character boundary.
+        for i in 1..=4 {
+            let mut end = text.len() * i / 4;
+
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            let mut chunk = FrequencyTable::new();
+
+            chunk.update(&text[at..end]);
+            merged.merge(chunk);
+            at = end;
+        }
+        assert_eq!(merged, FrequencyTable::from_text(&text));
+        assert_eq!(merged.build_codes(), generate_huffman_codes(&text));
+        assert!(FrequencyTable::new().build_codes().is_empty());
+    }
+
+    #[test]
+    fn merges_saturate() {
+        let mut huge = FrequencyTable::new();
+
+        huge.add('a', usize::MAX - 1);
+        huge.add('b', 1);
+        huge.merge(FrequencyTable::from_text("aaab"));
+
+        assert_eq!((huge.get('a'), huge.get('b')), (usize::MAX, 2));
+        assert_eq!(huge.total(), usize::MAX);
+
+        // The saturated table still builds codes, though its branches' counts
+        // saturate too.
+        let codes = huge.build_codes();
+
+        assert_eq!((codes[&'a'].as_str(), codes[&'b'].as_str()), ("1", "0"));
+
+        huge.add('b', usize::MAX);
+
+        assert_eq!(huge.get('b'), usize::MAX);
+        assert_eq!(merge_runs(vec![vec![('a', usize::MAX)], vec![('a', 9)]]),
+                   [('a', usize::MAX)]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Random containers, and mutations of them that a decoder has to reject or
+//! survive, for fuzzing code that embeds the stream format. The mutations
+//! know where the fields of a stream are, so they follow the format as it
+//! changes. They're only built with the `test-support` feature.
+//! 
+//! A container here is a stream of text that carries its own code table, as
+//! `pipe_compress()` writes with `TableSource::Train`, strict or not.
+//! 
+
+use std::ops::Range;
+
+use crate::pipe::{FLAG_IDS, FLAG_STORED, FLAG_STRICT, FLAG_TABLE, MAGIC};
+use crate::sample::SplitMix64;
+use crate::codebook::{Code, FrequencyTable, HuffmanCodebook};
+use crate::container::{pipe_compress, pipe_compress_strict, TableSource};
+
+
+/// The first characters of the blocks `arbitrary_container()` draws its
+/// alphabets from: ASCII, Latin-1, Greek, CJK and emoji.
+/// 
+const BLOCKS: &[char] = &[' ', 'à', 'α', '一', '😀'];
+
+
+/// A way to damage a container, as `mutate_container()` does.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mutation {
+    /// Cut the container off within its table, or the table's length. It
+    /// fails to decompress with `HuffmanError::MalformedData`.
+    TruncateTable,
+    /// Give a new symbol a proper prefix of one of the table's codes, so the
+    /// code lengths oversubscribe the code space: their Kraft sum is over 1.
+    /// It fails to decompress with `HuffmanError::NotPrefixFree`.
+    CorruptKraft,
+    /// Flip one of the bits of the packed codes, not counting the padding. It
+    /// decompresses to other text, or fails to decompress.
+    FlipPayloadBit,
+    /// Declare a table longer than the rest of the container. It fails to
+    /// decompress with `HuffmanError::MalformedData`, without allocating the
+    /// declared length.
+    OversizeDeclaredLength,
+}
+
+impl Mutation {
+    /// Every mutation.
+    /// 
+    pub const ALL: [Mutation; 4] = [Mutation::TruncateTable,
+                                    Mutation::CorruptKraft,
+                                    Mutation::FlipPayloadBit,
+                                    Mutation::OversizeDeclaredLength];
+}
+
+/// Where the fields of a container are.
+/// 
+struct Layout {
+    /// The table, after its 4-byte length.
+    table   : Range<usize>,
+    /// The packed codes, up to the padding count.
+    payload : Range<usize>,
+    /// The number of padding bits at the end of the payload.
+    padding : u8,
+}
+
+impl Layout {
+    /// The layout of `bytes`, or `None` if they aren't a container.
+    /// 
+    fn of(bytes: &[u8]) -> Option<Self> {
+        let flags = *bytes.get(5)?;
+
+        if !bytes.starts_with(MAGIC)
+            || flags & FLAG_TABLE == 0
+            || flags & (FLAG_IDS | FLAG_STORED) != 0
+        {
+            return None;
+        }
+        let len     = bytes.get(6..10)?.try_into().map(u32::from_le_bytes);
+        let table   = 10..10 + len.ok()? as usize;
+        let counts  = if flags & FLAG_STRICT != 0 { 12 } else { 0 };
+        let payload = table.end + counts..bytes.len() - 1;
+        let padding = bytes[bytes.len() - 1];
+
+        match payload.start <= payload.end && padding < 8 {
+            true  => Some(Self { table, payload, padding }),
+            false => None,
+        }
+    }
+}
+
+
+/// A structurally valid container of random text, drawn over a random
+/// alphabet with skewed counts. The same `seed` always gives the same
+/// container.
+/// 
+pub fn arbitrary_container(seed: u64) -> Vec<u8> {
+    let mut rng = SplitMix64(seed);
+    let symbols = 1 + rng.below(60);
+    let freqs   = (0..symbols).map(|_| {
+        let block = BLOCKS[rng.below(BLOCKS.len())] as u32;
+        let c     = char::from_u32(block + rng.below(64) as u32).unwrap();
+        let scale = 1 << rng.below(16);
+
+        (c, 1 + rng.below(scale))
+    }).collect::<FrequencyTable>();
+
+    let text       = freqs.sample(rng.below(1000), rng.next_u64());
+    let mut stream = vec![];
+    let compressed = match rng.below(2) {
+        0 => pipe_compress(text.as_bytes(), &mut stream, TableSource::Train,
+                           None),
+        _ => pipe_compress_strict(text.as_bytes(), &mut stream,
+                                  TableSource::Train, None),
+    };
+    compressed.expect("compressing text to memory can't fail");
+    stream
+}
+
+/// `bytes` damaged by `strategy`, at a place drawn with `seed`. The same
+/// arguments always give the same bytes. Bytes that aren't a container, and
+/// containers with no payload to flip a bit of, come back unchanged.
+/// 
+pub fn mutate_container(bytes: &[u8], strategy: Mutation, seed: u64) 
+
+    -> Vec<u8> 
+{
+    let mut rng   = SplitMix64(seed);
+    let mut bytes = bytes.to_vec();
+
+    let Some(layout) = Layout::of(&bytes) else {
+        return bytes;
+    };
+    match strategy {
+        Mutation::TruncateTable => {
+            let cut = 6 + rng.below(layout.table.end.min(bytes.len()) - 6);
+
+            bytes.truncate(cut);
+        },
+        Mutation::CorruptKraft => {
+            let Some(table) = bytes.get(layout.table.clone())
+                                   .and_then(|t| {
+                                       HuffmanCodebook::import(t).ok()
+                                   })
+            else {
+                return bytes;
+            };
+            let table = oversubscribe(table, &mut rng).export();
+
+            bytes.splice(6..layout.table.end,
+                         (table.len() as u32).to_le_bytes()
+                                             .into_iter()
+                                             .chain(table));
+        },
+        Mutation::FlipPayloadBit => {
+            let nbits = layout.payload.len() * 8 - layout.padding as usize;
+
+            if nbits > 0 {
+                let bit = rng.below(nbits);
+
+                bytes[layout.payload.start + bit / 8] ^= 0x80 >> (bit % 8);
+            }
+        },
+        Mutation::OversizeDeclaredLength => {
+            let rest = (bytes.len() - 10) as u64;
+            let len  = rest + 1 + rng.next_u64() % (u32::MAX as u64 - rest);
+
+            bytes[6..10].copy_from_slice(&(len as u32).to_le_bytes());
+        },
+    }
+    bytes
+}
+
+/// `codebook` with a new symbol whose code is a proper prefix of one of its
+/// codes, or two new symbols with the empty code if it has none.
+/// 
+fn oversubscribe(mut codebook: HuffmanCodebook, rng: &mut SplitMix64) 
+
+    -> HuffmanCodebook 
+{
+    let mut sorted = codebook.codes.values().copied().collect::<Vec<_>>();
+
+    sorted.sort_unstable();
+
+    let prefix = match sorted.is_empty() {
+        true  => vec![Code::default(); 2],
+        false => {
+            let code = sorted[rng.below(sorted.len())];
+            let len  = rng.below(code.len as usize) as u8;
+            let bits = code.bits.checked_shr((code.len - len) as u32);
+
+            vec![Code { bits: bits.unwrap_or(0), len }]
+        },
+    };
+    let fresh = ('\u{e000}'..).filter(|c| !codebook.codes.contains_key(c))
+                              .take(prefix.len())
+                              .collect::<Vec<_>>();
+
+    codebook.codes.extend(fresh.into_iter().zip(prefix));
+    codebook
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HuffmanError;
+    use crate::container::pipe_decompress;
+
+    #[test]
+    fn arbitrary_containers() {
+        for seed in 0..50 {
+            let stream = arbitrary_container(seed);
+            let layout = Layout::of(&stream).unwrap();
+
+            assert_eq!(arbitrary_container(seed), stream);
+            assert!(layout.table.end <= layout.payload.start);
+            assert!(pipe_decompress(&stream[..], std::io::sink(), None, None)
+                        .is_ok());
+
+            for strategy in Mutation::ALL {
+                assert_eq!(mutate_container(&stream, strategy, seed),
+                           mutate_container(&stream, strategy, seed));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_other_bytes_alone() {
+        let mut stored = vec![];
+
+        pipe_compress(&b"abc"[..], &mut stored,
+                      TableSource::Static(&HuffmanCodebook::from_text("abc")),
+                      None).unwrap();
+
+        for bytes in [&b""[..], b"HUFF\x01", b"PK\x03\x04\x01\x01", &stored] {
+            for strategy in Mutation::ALL {
+                assert_eq!(mutate_container(bytes, strategy, 1), bytes);
+            }
+        }
+        // An empty text has no payload to flip a bit of, but a table.
+        let mut empty = vec![];
+
+        pipe_compress(&b""[..], &mut empty, TableSource::Train, None).unwrap();
+
+        assert_eq!(mutate_container(&empty, Mutation::FlipPayloadBit, 1),
+                   empty);
+        assert!(matches!(pipe_decompress(
+                             &mutate_container(&empty, Mutation::CorruptKraft,
+                                               1)[..],
+                             std::io::sink(), None, None),
+                         Err(HuffmanError::NotPrefixFree(..))));
+    }
+}
@@ -0,0 +1,392 @@
+//! Several channels of text, each coded with its own codebook, multiplexed
+//! into one stream in the order their records arrive.
+//! 
+//! The stream is laid out as follows. Varints are LEB128: seven bits to a
+//! byte, least significant first, with the high bit set on every byte but the
+//! last.
+//! 
+//! | bytes        | contents                                              |
+//! |--------------|-------------------------------------------------------|
+//! | 4            | the magic bytes "HMUX"                                |
+//! | 1            | format version, currently 1                           |
+//! | varint       | the number of channels                                |
+//! | varint + ... | per channel: the length of its codebook's `export()`  |
+//! |              | blob, then the blob                                   |
+//! | ...          | the records                                           |
+//! 
+//! A record is its channel's index and its number of characters, as varints,
+//! then the characters' codes, packed most significant bit first and padded
+//! with zeros to a whole byte. A record of no characters has no code bytes.
+//! The stream ends after its last record.
+//! 
+
+use std::io::{self, Read, Write};
+
+use crate::encode::encode_into;
+use crate::HuffmanError;
+use crate::codebook::HuffmanCodebook;
+use crate::io::{BitWriter, DecoderFsm, Transition};
+use crate::varint::{cut_short, read_varint_with, write_varint};
+
+
+/// The bytes every multiplexed stream starts with.
+/// 
+const MAGIC: &[u8; 4] = b"HMUX";
+
+/// The format version written by `MuxWriter`.
+/// 
+const VERSION: u8 = 1;
+
+
+/// The next byte of `r`, or `None` at the end of the stream.
+/// 
+fn read_byte<R: Read>(r: &mut R) -> Result<Option<u8>, HuffmanError> {
+    let mut byte = [0];
+
+    match r.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0])),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The next varint of `r`, or `None` if the stream ends before it starts.
+/// 
+pub(crate) fn read_varint<R: Read>(r: &mut R) 
+
+    -> Result<Option<u64>, HuffmanError> 
+{
+    read_varint_with(|| read_byte(r))
+}
+
+
+/// Writes records of text on any of several channels to one stream, each
+/// coded with its channel's codebook. See the module docs for the layout.
+/// 
+#[derive(Debug)]
+pub struct MuxWriter<W: Write> {
+    inner    : W,
+    channels : Vec<HuffmanCodebook>,
+}
+
+impl<W: Write> MuxWriter<W> {
+    /// Writes the stream's header, with a channel for each codebook in
+    /// `channel_tables`, numbered from 0 in order.
+    /// 
+    pub fn new(mut inner: W, channel_tables: Vec<HuffmanCodebook>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut header = MAGIC.to_vec();
+
+        header.push(VERSION);
+        write_varint(&mut header, channel_tables.len() as u64);
+
+        for codebook in &channel_tables {
+            let blob = codebook.export();
+
+            write_varint(&mut header, blob.len() as u64);
+            header.extend(blob);
+        }
+        inner.write_all(&header)?;
+
+        Ok(Self { inner, channels: channel_tables })
+    }
+    /// Writes `text` as a record on `channel`. Nothing is written if it
+    /// fails: with `HuffmanError::UnknownChannel` for a channel the stream
+    /// doesn't have, and `HuffmanError::SymbolNotInCodebook` for a character
+    /// the channel's codes don't cover.
+    /// 
+    pub fn write_record(&mut self, channel: usize, text: &str) 
+
+        -> Result<(), HuffmanError> 
+    {
+        let codebook = self.channels.get(channel).ok_or(
+            HuffmanError::UnknownChannel {
+                channel, channels: self.channels.len()
+            }
+        )?;
+        let mut writer = BitWriter::new();
+
+        encode_into(codebook, text, &mut writer, None)?;
+
+        let mut record = Vec::with_capacity(writer.bit_len() as usize / 8 + 4);
+
+        write_varint(&mut record, channel as u64);
+        write_varint(&mut record, text.chars().count() as u64);
+        record.extend(writer.finish());
+
+        self.inner.write_all(&record)?;
+        Ok(())
+    }
+    /// The number of channels.
+    /// 
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+    /// Flushes the inner writer and returns it.
+    /// 
+    pub fn finish(mut self) -> Result<W, HuffmanError> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+
+/// Reads the records of a stream written by a `MuxWriter`, in the order they
+/// were written, as `(channel, text)` pairs. Iteration stops after the last
+/// record, or after the first error.
+/// 
+#[derive(Debug)]
+pub struct MuxReader<R: Read> {
+    inner    : R,
+    channels : Vec<DecoderFsm>,
+    done     : bool,
+}
+
+impl<R: Read> MuxReader<R> {
+    /// Reads the stream's header. Fails with
+    /// `HuffmanError::UnsupportedVersion` for streams from another version of
+    /// the format, with `HuffmanError::MalformedData` if the header is
+    /// truncated or doesn't start with the magic bytes, and as
+    /// `HuffmanCodebook::import()` does for the channels' codebooks.
+    /// 
+    pub fn new(mut inner: R) -> Result<Self, HuffmanError> {
+        let mut magic = [0; 5];
+
+        inner.read_exact(&mut magic).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => cut_short(),
+            _                            => e.into(),
+        })?;
+        if magic[..4] != MAGIC[..] {
+            return Err(HuffmanError::MalformedData("not a multiplexed stream"));
+        }
+        if magic[4] != VERSION {
+            return Err(HuffmanError::UnsupportedVersion(magic[4]));
+        }
+        let count        = read_varint(&mut inner)?.ok_or_else(cut_short)?;
+        let mut channels = vec![];
+
+        for _ in 0..count {
+            let len      = read_varint(&mut inner)?.ok_or_else(cut_short)?;
+            let mut blob = vec![];
+
+            (&mut inner).take(len).read_to_end(&mut blob)?;
+
+            if blob.len() as u64 != len {
+                return Err(cut_short());
+            }
+            let codebook = HuffmanCodebook::import(&blob)?;
+
+            channels.push(DecoderFsm::from_codebook(&codebook)?);
+        }
+        Ok(Self { inner, channels, done: false })
+    }
+    /// The number of channels.
+    /// 
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+    /// Reads the next record, or gives `None` at the end of the stream. Fails
+    /// with `HuffmanError::MalformedData` if the record is cut short, names a
+    /// channel the stream doesn't have, or has padding that isn't zeros, and
+    /// with `HuffmanError::InvalidCode` on bits that aren't any code of the
+    /// channel's, at an offset from the start of the record's codes.
+    /// 
+    pub fn read_record(&mut self) 
+
+        -> Result<Option<(usize, String)>, HuffmanError> 
+    {
+        let Some(channel) = read_varint(&mut self.inner)? else {
+            return Ok(None);
+        };
+        let count = read_varint(&mut self.inner)?.ok_or_else(cut_short)?;
+        let fsm   = usize::try_from(channel).ok()
+                                            .and_then(|c| self.channels.get(c))
+                                            .ok_or(HuffmanError::MalformedData(
+                                                "a record names an unknown \
+                                                 channel"
+                                            ))?;
+        let mut text    = String::new();
+        let mut symbols = 0;
+        let mut state   = 0;
+        let mut start   = 0;
+        let mut pos     = 0;
+
+        while symbols < count {
+            let byte = read_byte(&mut self.inner)?.ok_or_else(cut_short)?;
+
+            for i in 0..8 {
+                let bit = byte >> (7 - i) & 1 == 1;
+
+                pos += 1;
+
+                if symbols == count {
+                    if bit {
+                        return Err(HuffmanError::MalformedData(
+                            "the padding isn't zeros"
+                        ));
+                    }
+                    continue;
+                }
+                match fsm.step(state, bit) {
+                    Transition::Next(next) => state = next,
+                    Transition::Emit(c)    => {
+                        text.push(c);
+                        state    = 0;
+                        start    = pos;
+                        symbols += 1;
+                    },
+                    Transition::Invalid    => {
+                        return Err(HuffmanError::InvalidCode {
+                            bit_offset : start,
+                            symbols    : symbols as usize,
+                        });
+                    },
+                }
+            }
+        }
+        Ok(Some((channel as usize, text)))
+    }
+}
+
+impl<R: Read> Iterator for MuxReader<R> {
+    type Item = Result<(usize, String), HuffmanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.read_record().transpose();
+
+        self.done = !matches!(record, Some(Ok(_)));
+        record
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDS: &[(usize, &str)] = &[
+        (0, "temp=21.5"), (1, "ok"), (0, "temp=21.7"), (2, ""),
+        (1, "ok"), (2, "fan 1200rpm"), (0, ""), (1, "warn: door open"),
+        (2, "fan 1180rpm"), (0, "temp=22.0"),
+    ];
+
+    fn tables() -> Vec<HuffmanCodebook> {
+        (0..3).map(|channel| {
+            let text = RECORDS.iter()
+                              .filter(|&&(c, _)| c == channel)
+                              .map(|&(_, text)| text)
+                              .collect::<String>();
+            HuffmanCodebook::from_text(&text)
+        }).collect()
+    }
+
+    fn mux(records: &[(usize, &str)]) -> Vec<u8> {
+        let mut writer = MuxWriter::new(vec![], tables()).unwrap();
+
+        for &(channel, text) in records {
+            writer.write_record(channel, text).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn varints() {
+        for n in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes = vec![];
+
+            write_varint(&mut bytes, n);
+
+            assert_eq!(read_varint(&mut &bytes[..]), Ok(Some(n)));
+        }
+        let mut bytes = vec![];
+
+        write_varint(&mut bytes, 300);
+
+        assert_eq!(bytes, [0xac, 0x02]);
+        assert_eq!(read_varint(&mut &[][..]), Ok(None));
+        assert_eq!(read_varint(&mut &[0x80][..]), Err(cut_short()));
+        assert_eq!(read_varint(&mut &[0xff; 11][..]),
+                   Err(HuffmanError::MalformedData("a varint is too long")));
+    }
+
+    #[test]
+    fn round_trip() {
+        let stream = mux(RECORDS);
+        let reader = MuxReader::new(&stream[..]).unwrap();
+
+        assert_eq!(reader.channels(), 3);
+        assert_eq!(reader.collect::<Result<Vec<_>, _>>().unwrap(),
+                   RECORDS.iter()
+                          .map(|&(c, text)| (c, text.to_string()))
+                          .collect::<Vec<_>>());
+
+        // A stream with no records, and one with a lone-symbol channel.
+        let stream = MuxWriter::new(vec![], vec![]).unwrap().finish().unwrap();
+
+        assert_eq!(MuxReader::new(&stream[..]).unwrap().count(), 0);
+
+        let lone       = vec![HuffmanCodebook::from_text("zz")];
+        let mut writer = MuxWriter::new(vec![], lone).unwrap();
+
+        writer.write_record(0, "zzzzzzzzz").unwrap();
+
+        let stream = writer.finish().unwrap();
+
+        assert_eq!(MuxReader::new(&stream[..]).unwrap().next(),
+                   Some(Ok((0, "zzzzzzzzz".to_string()))));
+    }
+
+    #[test]
+    fn bad_records() {
+        let mut writer = MuxWriter::new(vec![], tables()).unwrap();
+
+        assert_eq!(writer.write_record(3, "ok"),
+                   Err(HuffmanError::UnknownChannel { channel: 3,
+                                                      channels: 3 }));
+        assert_eq!(writer.write_record(1, "ok!").err().map(|e| {
+                       matches!(e, HuffmanError::SymbolNotInCodebook {
+                           symbol: '!', char_index: 2, ..
+                       })
+                   }),
+                   Some(true));
+
+        // Neither failure wrote anything.
+        assert_eq!(writer.finish().unwrap(), mux(&[]));
+
+        let stream = mux(RECORDS);
+        let header = mux(&[]).len();
+
+        // Cut partway through the last record.
+        let mut reader = MuxReader::new(&stream[..stream.len() - 1]).unwrap();
+
+        assert_eq!(reader.by_ref().last(), Some(Err(cut_short())));
+        assert_eq!(reader.next(), None);
+
+        // A record on channel 9.
+        let mut bad = stream[..header].to_vec();
+
+        bad.extend([9, 0]);
+
+        assert_eq!(MuxReader::new(&bad[..]).unwrap().next(),
+                   Some(Err(HuffmanError::MalformedData(
+                       "a record names an unknown channel"
+                   ))));
+
+        let mut bad = stream.clone();
+
+        bad[4] = 2;
+
+        assert_eq!(MuxReader::new(&bad[..]).err(),
+                   Some(HuffmanError::UnsupportedVersion(2)));
+        assert_eq!(MuxReader::new(&stream[..header - 1]).err(),
+                   Some(cut_short()));
+        assert_eq!(MuxReader::new(&b"HUFF\x01"[..]).err(),
+                   Some(HuffmanError::MalformedData("not a multiplexed \
+                                                     stream")));
+    }
+}
@@ -0,0 +1,208 @@
+//! Naming the ids of a stream of ids, for ids such as enum discriminants
+//! whose meaning changes between versions of a schema. A stream written by
+//! `compress_ids_named()` carries the names, so it describes itself.
+//! 
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+
+
+/// A mapping from ids to the names of the symbols they stand for.
+/// 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    names: BTreeMap<u32, String>,
+}
+
+impl SymbolMap {
+    /// An empty map.
+    /// 
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A map of the `(id, name)` pairs of `pairs`. Fails with
+    /// `HuffmanError::DuplicateId` if an id is named more than once.
+    /// 
+    pub fn from_pairs<I, S>(pairs: I) 
+
+        -> Result<Self, HuffmanError> 
+    where
+        I: IntoIterator<Item = (u32, S)>,
+        S: Into<String>,
+    {
+        let mut map = Self::new();
+
+        for (id, name) in pairs {
+            map.insert(id, name)?;
+        }
+        Ok(map)
+    }
+    /// Names `id`. Fails with `HuffmanError::DuplicateId` if it's named
+    /// already, leaving the map as it was.
+    /// 
+    pub fn insert(&mut self, id: u32, name: impl Into<String>) 
+
+        -> Result<(), HuffmanError> 
+    {
+        if self.names.contains_key(&id) {
+            return Err(HuffmanError::DuplicateId(id));
+        }
+        self.names.insert(id, name.into());
+        Ok(())
+    }
+    /// The name of `id`, if it has one.
+    /// 
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+    /// The ids and their names, in order of id.
+    /// 
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.names.iter().map(|(&id, name)| (id, name.as_str()))
+    }
+    /// The number of ids named.
+    /// 
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    /// Whether no ids are named.
+    /// 
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+    /// Saves the map as the number of ids, then each id, the length of its
+    /// name, and the name as UTF-8, in order of id, with integers
+    /// little-endian.
+    /// 
+    pub fn export(&self) -> Vec<u8> {
+        let mut bytes = (self.names.len() as u32).to_le_bytes().to_vec();
+
+        for (id, name) in self.iter() {
+            bytes.extend(id.to_le_bytes());
+            bytes.extend((name.len() as u32).to_le_bytes());
+            bytes.extend(name.as_bytes());
+        }
+        bytes
+    }
+    /// Loads a map saved by `export()`. Fails with `HuffmanError::DuplicateId`
+    /// if an id is named twice, `HuffmanError::InvalidUtf8` if a name isn't
+    /// UTF-8, and `HuffmanError::MalformedData` if the blob is truncated or
+    /// has trailing bytes.
+    /// 
+    pub fn import(mut bytes: &[u8]) -> Result<Self, HuffmanError> {
+        let count   = take_u32(&mut bytes)?;
+        let mut map = Self::new();
+
+        for _ in 0..count {
+            let id   = take_u32(&mut bytes)?;
+            let len  = take_u32(&mut bytes)? as usize;
+            let name = core::str::from_utf8(take(&mut bytes, len)?)
+                           .map_err(|_| HuffmanError::InvalidUtf8)?;
+
+            map.insert(id, name)?;
+        }
+        if !bytes.is_empty() {
+            return Err(HuffmanError::MalformedData("trailing bytes"));
+        }
+        Ok(map)
+    }
+}
+
+/// Takes the first `len` of `bytes`.
+/// 
+fn take<'a>(bytes: &mut &'a [u8], len: usize) 
+
+    -> Result<&'a [u8], HuffmanError> 
+{
+    let (taken, rest) = bytes.split_at_checked(len).ok_or(
+        HuffmanError::MalformedData("the data is truncated")
+    )?;
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// Takes a little-endian `u32` from the front of `bytes`.
+/// 
+fn take_u32(bytes: &mut &[u8]) -> Result<u32, HuffmanError> {
+    take(bytes, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+/// The ids decoded from a stream by `decompress_ids_named()`, with the names
+/// the stream gave them, if any.
+/// 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodedIds {
+    /// The ids, in order.
+    pub ids   : Vec<u32>,
+    /// The names the stream carried, if it carried any.
+    pub names : Option<SymbolMap>,
+}
+
+impl DecodedIds {
+    /// The name the stream gave `id`, if it gave it one.
+    /// 
+    pub fn decoded_symbol_name(&self, id: u32) -> Option<&str> {
+        self.names.as_ref()?.name(id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn export_and_import() {
+        let map = SymbolMap::from_pairs([(7, "Seven"), (0, ""), (3, "Ünï")])
+                      .unwrap();
+        let blob = map.export();
+
+        assert_eq!(map.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+                   [0, 3, 7]);
+        assert_eq!(SymbolMap::import(&blob), Ok(map.clone()));
+        assert_eq!(SymbolMap::import(&SymbolMap::new().export()),
+                   Ok(SymbolMap::new()));
+
+        let truncated = HuffmanError::MalformedData("the data is truncated");
+
+        for len in 0..blob.len() {
+            assert_eq!(SymbolMap::import(&blob[..len]),
+                       Err(truncated.clone()));
+        }
+        let mut trailing = blob.clone();
+        trailing.push(0);
+
+        assert_eq!(SymbolMap::import(&trailing),
+                   Err(HuffmanError::MalformedData("trailing bytes")));
+
+        // Renumber id 0 as 3, which is named after it.
+        let mut twice = blob.clone();
+        twice[4..8].copy_from_slice(&3_u32.to_le_bytes());
+
+        let mut bad = blob.clone();
+        let at      = bad.len() - "Seven".len();
+        bad[at] = 0xff;
+
+        assert_eq!(SymbolMap::import(&twice),
+                   Err(HuffmanError::DuplicateId(3)));
+        assert_eq!(SymbolMap::import(&bad), Err(HuffmanError::InvalidUtf8));
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let mut map = SymbolMap::new();
+
+        map.insert(1, "One").unwrap();
+
+        assert_eq!(map.insert(1, "Uno"), Err(HuffmanError::DuplicateId(1)));
+        assert_eq!(map.name(1), Some("One"));
+        assert_eq!(SymbolMap::from_pairs([(2, "a"), (2, "b")]),
+                   Err(HuffmanError::DuplicateId(2)));
+        assert_eq!(HuffmanError::DuplicateId(2).to_string(),
+                   "id 2 is named more than once");
+    }
+}
@@ -0,0 +1,195 @@
+//! Counting and encoding text on several threads, for large corpora where a
+//! single pass dominates. The text is cut into a chunk per thread, at
+//! character boundaries, and each thread counts or encodes its own chunk.
+//! The counts are summed before the tree is built, and the chunks' codes are
+//! joined in order at whatever bit each one ends on, so the results are
+//! exactly those of the single-threaded functions. They're only built with
+//! the `parallel` feature.
+//! 
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::thread;
+
+use crate::encode::encode_into;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+use crate::io::{BitBuffer, BitWriter};
+
+
+/// The number of chunk tables above which `count_par()` merges them with
+/// `FrequencyTable::merge_streaming()` rather than one into the next.
+/// 
+const STREAMING_MERGE_ABOVE: usize = 64;
+
+
+/// Like `generate_huffman_codes()`, but counts the characters on a thread
+/// per available core.
+/// 
+pub fn generate_huffman_codes_par(data: &str) -> HashMap<char, String> {
+    count_par(data, workers()).build_codes()
+}
+
+/// Like `encode()`, but counts the characters and encodes them on a thread
+/// per available core. The output is the same, bit for bit.
+/// 
+pub fn encode_par(data: &str) -> BitBuffer {
+    encode_with_workers(data, workers())
+}
+
+/// The number of threads to use.
+/// 
+fn workers() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Encodes `data` as `encode()` does, on `workers` threads.
+/// 
+fn encode_with_workers(data: &str, workers: usize) -> BitBuffer {
+    let codebook = HuffmanCodebook::from_frequencies_capped(count_par(data, 
+                                                                  workers));
+    let codebook = &codebook;
+
+    let chunks = on_threads(data, workers, |chunk| {
+        let mut writer = BitWriter::new();
+
+        encode_into(codebook, chunk, &mut writer, None)
+            .expect("every character of the text has a code");
+        writer
+    });
+    let mut writer = BitWriter::new();
+
+    for mut chunk in chunks {
+        let bits = chunk.bit_len();
+
+        writer.write_packed(&chunk.finish(), bits);
+    }
+    writer.into_buffer()
+}
+
+/// Counts the characters of `data` on `workers` threads.
+/// 
+fn count_par(data: &str, workers: usize) -> FrequencyTable {
+    count_par_merging(data, workers, STREAMING_MERGE_ABOVE)
+}
+
+/// Counts the characters of `data` on `workers` threads. The chunks' tables
+/// are summed one into the next, or, if there are more than `streaming_above`
+/// of them, by `FrequencyTable::merge_streaming()`, which doesn't grow one
+/// map from them all. The counts are the same either way.
+/// 
+fn count_par_merging(data: &str, workers: usize, streaming_above: usize) 
+
+    -> FrequencyTable 
+{
+    let tables = on_threads(data, workers, FrequencyTable::from_text);
+
+    if tables.len() > streaming_above {
+        return FrequencyTable::merge_streaming(tables);
+    }
+    let mut freqs = FrequencyTable::new();
+
+    for table in tables {
+        freqs.merge(table);
+    }
+    freqs
+}
+
+/// Runs `f` on each of `workers` chunks of `text` on a thread of its own,
+/// returning the results in order. A panic in `f` is passed on.
+/// 
+fn on_threads<T, F>(text: &str, workers: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&str) -> T + Sync,
+{
+    let f = &f;
+
+    thread::scope(|s| {
+        let handles = split_chunks(text, workers).into_iter().map(|chunk| {
+            s.spawn(move || f(chunk))
+        }).collect::<Vec<_>>();
+
+        handles.into_iter().map(|h| {
+            h.join().unwrap_or_else(|e| panic::resume_unwind(e))
+        }).collect()
+    })
+}
+
+/// Cuts `text` into `n` chunks of about the same number of bytes, each
+/// extended to end on a character boundary, so some may be empty.
+/// 
+fn split_chunks(text: &str, n: usize) -> Vec<&str> {
+    let n          = n.max(1);
+    let mut chunks = Vec::with_capacity(n);
+    let mut start  = 0;
+
+    for i in 1..=n {
+        let mut end = (text.len() * i / n).max(start);
+
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        chunks.push(&text[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::generate_huffman_codes;
+    use crate::io::encode;
+
+    #[test]
+    fn same_as_sequential() {
+        let moby = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        assert_eq!(generate_huffman_codes_par(&moby),
+                   generate_huffman_codes(&moby));
+        assert_eq!(encode_par(&moby), encode(&moby));
+
+        // The chunks' codes mostly end mid-byte, and the wide characters
+        // straddle the even cuts.
+        let text = "é€🦀 mixed widths ✓ ".repeat(1000);
+
+        for workers in [1, 2, 3, 7, 16] {
+            assert_eq!(count_par(&moby, workers),
+                       FrequencyTable::from_text(&moby));
+            assert_eq!(encode_with_workers(&moby, workers), encode(&moby));
+            assert_eq!(encode_with_workers(&text, workers), encode(&text));
+        }
+        for text in ["", "a", "ab", "🦀"] {
+            assert_eq!(encode_with_workers(text, 4), encode(text));
+        }
+    }
+
+    #[test]
+    fn merging_counts() {
+        let moby = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text     = &moby[..moby.len() / 8];
+        let expected = FrequencyTable::from_text(text);
+
+        for workers in [1, 3, 65, 200] {
+            for streaming_above in [0, 64, usize::MAX] {
+                assert_eq!(count_par_merging(text, workers, streaming_above),
+                           expected);
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_end_on_characters() {
+        let text = "a🦀é€".repeat(10);
+
+        for n in 0..=20 {
+            let chunks = split_chunks(&text, n);
+
+            assert_eq!(chunks.len(), n.max(1));
+            assert_eq!(chunks.concat(), text);
+        }
+        assert_eq!(split_chunks("🦀", 3), ["🦀", "", ""]);
+    }
+}
@@ -0,0 +1,1964 @@
+//! Compressing text from a reader to a writer in one call, and back again.
+//! 
+//! The compressed stream is laid out as follows, with integers little-endian:
+//! 
+//! | bytes | contents                                                      |
+//! |-------|---------------------------------------------------------------|
+//! | 4     | the magic bytes `HUFF`                                        |
+//! | 1     | format version, currently 1                                   |
+//! | 1     | flags; bit 0 is set if the stream carries its own code table, |
+//! |       | bit 1 if the text is stored as it is, and bit 2 if it's coded |
+//! |       | with `HuffmanCodebook::english()`; at most one is set. Bit 3  |
+//! |       | is set for a strict stream, which is never stored, and bit 4  |
+//! |       | if the symbols are `u32` ids rather than chars. Bit 5 is set  |
+//! |       | for a stream of blocks, written by `compress_blocks()`, bit 6 |
+//! |       | for a stream of ids that names them, and bit 7, with bit 0,   |
+//! |       | for a stream of lines, written by `compress_lines()`. Bit 7   |
+//! |       | without bit 0 is set for a stream coded adaptively, from      |
+//! |       | `TableSource::Adaptive`, in place of bits 0 to 2              |
+//! | 4 + n | if bit 0 is set, the table's length and the table, as written |
+//! |       | by `HuffmanCodebook::export()`                                |
+//! | 4 + 8 | if bit 3 is set, the number of symbols the codes were made    |
+//! |       | for, and the number of characters of text; for an adaptive    |
+//! |       | stream, the number of distinct characters in the text         |
+//! | ...   | the codes, packed most significant bit first                  |
+//! | 1     | the number of padding bits at the end of the last code byte   |
+//! 
+//! Streams of ids, written by `compress_ids()`, always carry their table, as
+//! written by `IdCodebook::export()`, and set no other flags but bit 6.
+//! Those written by `compress_ids_named()` set it, and follow the table with
+//! the length of the names and the names, as written by
+//! `SymbolMap::export()`.
+//! 
+//! Stored streams hold the text as UTF-8 in place of the codes and padding
+//! count. Adaptive streams carry no table; their codes are those of an
+//! `AdaptiveEncoder`, which a decoder rebuilds as it goes. Streams with none
+//! of the flags set are decoded with the codebook they were encoded with,
+//! which the caller supplies. The counts in a strict
+//! stream, written by `pipe_compress_strict()`, guard against the caller
+//! supplying the wrong one: a codebook of the wrong size is rejected before
+//! decoding starts, and text of the wrong length as soon as it's known.
+//! 
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+
+use crate::adaptive::{AdaptiveDecoder, AdaptiveEncoder};
+use crate::bits::{BitBuffer, BitReader, BitWriter};
+use crate::HuffmanError;
+use crate::codebook::{predicted_bits, CodeLookup, FrequencyTable};
+use crate::codebook::{HuffmanCodebook, IdCodebook};
+use crate::container::{DecodedIds, SymbolMap};
+use crate::io::{for_each_str_chunk, CancelToken, PushDecoder, READ_BUF_SIZE};
+use crate::tree::TreeBuild;
+use crate::median::codebook_medians;
+use crate::summary::Lookup;
+
+
+pub(crate) const MAGIC: &[u8; 4] = b"HUFF";
+
+/// The format version written by `pipe_compress()`.
+/// 
+pub(crate) const VERSION: u8 = 1;
+
+/// Set if the stream carries its own code table.
+/// 
+pub(crate) const FLAG_TABLE: u8 = 1;
+
+/// Set if the stream holds the text as it is.
+/// 
+pub(crate) const FLAG_STORED: u8 = 2;
+
+/// Set if the stream is coded with the built-in English table.
+/// 
+const FLAG_ENGLISH: u8 = 4;
+
+/// Set if the stream declares the size of its alphabet and text.
+/// 
+pub(crate) const FLAG_STRICT: u8 = 8;
+
+/// Set if the symbols are `u32` ids.
+/// 
+pub(crate) const FLAG_IDS: u8 = 16;
+
+/// Set if the stream is a sequence of blocks, each coded or stored.
+/// 
+pub(crate) const FLAG_BLOCKS: u8 = 32;
+
+/// Set if a stream of ids carries names for them.
+/// 
+const FLAG_NAMES: u8 = 64;
+
+/// Set if the stream is coded a line at a time.
+/// 
+pub(crate) const FLAG_LINES: u8 = 128;
+
+/// Set, without `FLAG_TABLE`, if the stream is coded adaptively.
+/// 
+const FLAG_ADAPTIVE: u8 = 128;
+
+/// The most input held in memory for two passes over it, past which it's
+/// held in a temporary file.
+/// 
+const SPOOL_LIMIT: usize = 64 << 20;
+
+
+/// Where `pipe_compress()` gets its codes from.
+/// 
+#[derive(Clone, Copy, Debug)]
+pub enum TableSource<'a> {
+    /// Train codes on the input, and store them in the stream. The input is
+    /// buffered to make the two passes, in memory up to 64 MiB of it, and
+    /// past that in a temporary file. Compressing fails with
+    /// `HuffmanError::CodeTooLong` if a code would be longer than 64 bits.
+    Train,
+    /// Use these codes, which aren't stored in the stream, so the decoder needs
+    /// them too. The input is encoded as it's read.
+    Static(&'a HuffmanCodebook),
+    /// Code the input adaptively, as an `AdaptiveEncoder` does, so no table
+    /// is stored or needed. The input is encoded as it's read, at some cost
+    /// in speed and, for a short input, in size.
+    Adaptive,
+}
+
+/// Limits on how much a stream may decode to, to stop a small stream from
+/// decoding to far more text than the caller has room for. A code can be a
+/// single bit, so a stream can decode to eight characters per byte. The
+/// default sets no limits.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The most bytes of UTF-8 text to decode, if any.
+    pub max_output_bytes   : Option<u64>,
+    /// The most characters to decode, if any.
+    pub max_output_symbols : Option<u64>,
+}
+
+impl DecodeLimits {
+    /// Fails with `HuffmanError::OutputLimitExceeded` if `symbols`
+    /// characters, or `bytes` bytes, of output are over a limit.
+    /// 
+    pub(crate) fn check(&self, symbols: u64, bytes: u64) 
+
+        -> Result<(), HuffmanError> 
+    {
+        for (produced, limit) in [(symbols, self.max_output_symbols),
+                                  (bytes,   self.max_output_bytes)] {
+            if let Some(limit) = limit && produced > limit {
+                return Err(HuffmanError::OutputLimitExceeded {
+                    produced, limit
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sizes and timings from compressing or decompressing a stream.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// The number of characters of text.
+    pub symbols            : u64,
+    /// The size of the text, in bytes of UTF-8.
+    pub text_bytes         : u64,
+    /// The number of bits of packed codes, not counting padding.
+    pub payload_bits       : u64,
+    /// The number of bits of packed codes predicted from the counts of the
+    /// characters and the lengths of their codes, worked out apart from the
+    /// packing. It's always `payload_bits` for a correct encoder, and 0 when
+    /// decompressing.
+    pub predicted_bits     : u64,
+    /// The size of the code table carried in the stream, with its length.
+    pub table_bytes        : u64,
+    /// The size of the compressed stream, including its header and table.
+    pub stream_bytes       : u64,
+    /// The time taken reading and counting the text and building the codes,
+    /// when training.
+    pub count_time         : Duration,
+    /// The time taken encoding, or decoding when decompressing.
+    pub code_time          : Duration,
+    /// How the tree was built, when training.
+    pub tree_build         : Option<TreeBuild>,
+    /// How the encoder looked up codes, when compressing.
+    pub code_lookup        : Option<CodeLookup>,
+    /// The lower and upper medians of the lengths of the codes, taken over
+    /// the symbols of the codebook, when compressing.
+    pub median_code_length : Option<(u64, u64)>,
+    /// The lower and upper medians of the counts the codebook was built
+    /// from, taken over its symbols, when compressing.
+    pub median_frequency   : Option<(u64, u64)>,
+    /// The number of characters substituted before encoding, by
+    /// `pipe_compress_redacted()`.
+    pub redactions         : u64,
+    /// The number of blocks stored as they are, by `compress_blocks()`.
+    pub stored_blocks      : u64,
+    /// The number of blocks coded with their own table, by
+    /// `compress_blocks()`.
+    pub coded_blocks       : u64,
+}
+
+#[cfg(test)]
+impl CompressionStats {
+    /// The stats without the timings, prediction, strategies, medians or
+    /// redactions, to compare the stats of runs that time differently, or of
+    /// compressing and decompressing.
+    /// 
+    pub(crate) fn sizes(self) -> Self {
+        Self {
+            predicted_bits     : 0,
+            count_time         : Duration::ZERO,
+            code_time          : Duration::ZERO,
+            tree_build         : None,
+            code_lookup        : None,
+            median_code_length : None,
+            median_frequency   : None,
+            redactions         : 0,
+            ..self
+        }
+    }
+}
+
+
+/// Compresses the UTF-8 text read from `r`, writing the compressed stream to
+/// `w`. See the module docs for the format. `cancel` is checked once per
+/// buffer of input read, and once it's cancelled, compressing stops with
+/// `HuffmanError::Cancelled`, leaving whatever was written to `w` unfinished.
+/// 
+pub fn pipe_compress(r      : impl Read,
+                     w      : impl Write,
+                     table  : TableSource,
+                     cancel : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    compress(r, w, table, false, &HashMap::new(), SPOOL_LIMIT, cancel)
+}
+
+/// Like `pipe_compress()`, but writes a strict stream, which declares the
+/// number of symbols its codes were made for and the number of characters of
+/// its text. `pipe_decompress()` checks both, so a stream paired with the
+/// wrong codebook fails instead of decoding to the wrong text. The input is
+/// buffered to count it, as for `TableSource::Train`, even for a
+/// `TableSource::Static` or `TableSource::Adaptive` source.
+/// 
+pub fn pipe_compress_strict(r      : impl Read,
+                            w      : impl Write,
+                            table  : TableSource,
+                            cancel : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    compress(r, w, table, true, &HashMap::new(), SPOOL_LIMIT, cancel)
+}
+
+/// Like `pipe_compress()`, but substitutes each character that's a key of
+/// `redact` with its value before encoding it, so the key never reaches the
+/// stream. Trained codes are counted after the substitution, so they have
+/// none of the keys. The substitutions are counted in the stats'
+/// `redactions`, and the predicted size is that of the substituted text.
+/// 
+pub fn pipe_compress_redacted(r      : impl Read,
+                              w      : impl Write,
+                              table  : TableSource,
+                              redact : &HashMap<char, char>,
+                              cancel : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    compress(r, w, table, false, redact, SPOOL_LIMIT, cancel)
+}
+
+/// Like `pipe_compress()` with `TableSource::Train`, but compresses the
+/// characters of the iterator `make` returns, without holding them: `make` is
+/// called once to count the characters and again to encode them, so both
+/// calls must give the same characters. The stream is the same as for the
+/// text they make up.
+/// 
+pub fn compress_chars<I, F>(mut make: F, w: impl Write) 
+
+    -> Result<CompressionStats, HuffmanError> 
+where
+    I: IntoIterator<Item = char>,
+    F: FnMut() -> I,
+{
+    let start    = Instant::now();
+    let freqs    = FrequencyTable::from_chars(make().into_iter());
+    let codebook = HuffmanCodebook::try_from_frequencies(freqs)?;
+
+    let count_time = start.elapsed();
+    let chars      = CharReader::new(make().into_iter());
+
+    let mut stats = compress_with(chars, w, &codebook, FLAG_TABLE, None,
+                                  &HashMap::new(), None)?;
+    stats.count_time = count_time;
+    stats.tree_build = Some(codebook.frequencies().summary().tree_build());
+    Ok(stats)
+}
+
+/// Reads characters from an iterator as UTF-8, so they can be encoded as
+/// text read from a reader is.
+/// 
+struct CharReader<I> {
+    chars   : I,
+    pending : [u8; 4],
+    start   : usize,
+    end     : usize,
+}
+
+impl<I: Iterator<Item = char>> CharReader<I> {
+    fn new(chars: I) -> Self {
+        Self { chars, pending: [0; 4], start: 0, end: 0 }
+    }
+}
+
+impl<I: Iterator<Item = char>> Read for CharReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+
+        while n < buf.len() {
+            if self.start == self.end {
+                let Some(c) = self.chars.next() else { break };
+
+                self.start = 0;
+                self.end   = c.encode_utf8(&mut self.pending).len();
+            }
+            let k    = (self.end - self.start).min(buf.len() - n);
+            let from = &self.pending[self.start..self.start + k];
+
+            buf[n..n + k].copy_from_slice(from);
+            self.start += k;
+            n          += k;
+        }
+        Ok(n)
+    }
+}
+
+/// `c`, or what `redact` substitutes for it.
+/// 
+fn redacted(redact: &HashMap<char, char>, c: char) -> char {
+    redact.get(&c).copied().unwrap_or(c)
+}
+
+/// Compresses as `pipe_compress()` does, or `pipe_compress_strict()` if
+/// `strict` is set, substituting characters as `pipe_compress_redacted()`
+/// does. Input read in two passes is held in memory up to `limit` bytes,
+/// and in a temporary file past that.
+/// 
+fn compress(r      : impl Read,
+            w      : impl Write,
+            table  : TableSource,
+            strict : bool,
+            redact : &HashMap<char, char>,
+            limit  : usize,
+            cancel : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    match (table, strict) {
+        (TableSource::Static(codebook), false) => {
+            return compress_with(r, w, codebook, 0, None, redact, cancel);
+        },
+        (TableSource::Adaptive, false) => {
+            return compress_adaptive(r, w, None, redact, cancel);
+        },
+        _ => {},
+    }
+    let start     = Instant::now();
+    let mut input = Spooled::read(r, limit, cancel)?;
+    let mut freqs = FrequencyTable::default();
+    let mut chars = 0;
+
+    for_each_str_chunk(input.reader()?, cancel, |chunk| {
+        for c in chunk.chars() {
+            freqs.add(redacted(redact, c), 1);
+            chars += 1;
+        }
+        Ok(())
+    })?;
+    let trained;
+    let (codebook, flags) = match table {
+        TableSource::Static(codebook) => (codebook, 0),
+        TableSource::Adaptive         => {
+            let counts     = (freqs.len() as u32, chars);
+            let count_time = start.elapsed();
+
+            let mut stats = compress_adaptive(input.reader()?, w,
+                                              Some(counts), redact, cancel)?;
+            stats.count_time = count_time;
+            return Ok(stats);
+        },
+        TableSource::Train            => {
+            trained = HuffmanCodebook::try_from_frequencies(freqs)?;
+            (&trained, FLAG_TABLE)
+        },
+    };
+    let chars      = strict.then_some(chars);
+    let count_time = start.elapsed();
+
+    let mut stats = compress_with(input.reader()?, w, codebook, flags, chars,
+                                  redact, cancel)?;
+    stats.count_time = count_time;
+    stats.tree_build = (flags & FLAG_TABLE != 0).then(|| {
+        codebook.frequencies().summary().tree_build()
+    });
+    Ok(stats)
+}
+
+/// Writes the header, then encodes the text read from `r` with `codebook`,
+/// a buffer at a time, substituting characters as `redact` says, and
+/// checking `cancel` before each. If the number of characters in the text is
+/// given as `chars`, the stream is strict.
+/// 
+fn compress_with(r        : impl Read,
+                 mut w    : impl Write,
+                 codebook : &HuffmanCodebook,
+                 flags    : u8,
+                 chars    : Option<u64>,
+                 redact   : &HashMap<char, char>,
+                 cancel   : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let start       = Instant::now();
+    let strict      = chars.map_or(0, |_| FLAG_STRICT);
+    let mut header  = header(codebook, flags | strict);
+    let table_bytes = header.len() - MAGIC.len() - 2;
+
+    if let Some(chars) = chars {
+        header.extend((codebook.codes().len() as u32).to_le_bytes());
+        header.extend(chars.to_le_bytes());
+    }
+    w.write_all(&header)?;
+
+    let lookup     = Lookup::new(codebook.codes());
+    let medians    = codebook_medians(codebook);
+    let mut writer = BitWriter::new();
+    let mut stats  = CompressionStats {
+        stream_bytes       : header.len() as u64,
+        table_bytes        : table_bytes as u64,
+        code_lookup        : Some(lookup.kind()),
+        median_code_length : medians.0,
+        median_frequency   : medians.1,
+        ..Default::default()
+    };
+    for_each_str_chunk(r, cancel, |chunk| {
+        for (offset, c) in chunk.char_indices() {
+            let sub = redact.get(&c).copied();
+            let c   = sub.unwrap_or(c);
+
+            stats.redactions += sub.is_some() as u64;
+
+            let code = lookup.get(c).ok_or_else(|| {
+                io::Error::other(HuffmanError::SymbolNotInCodebook {
+                    symbol      : c,
+                    char_index  : stats.symbols as usize,
+                    byte_offset : stats.text_bytes as usize + offset,
+                })
+            })?;
+            writer.write_code(code);
+            stats.symbols        += 1;
+            stats.predicted_bits += code.len as u64;
+        }
+        stats.text_bytes += chunk.len() as u64;
+
+        let bytes = writer.take_complete();
+
+        stats.stream_bytes += bytes.len() as u64;
+        w.write_all(&bytes)
+    })?;
+    stats.payload_bits = writer.bit_len();
+
+    let tail = tail(&mut writer);
+
+    w.write_all(&tail)?;
+    w.flush()?;
+
+    stats.stream_bytes += tail.len() as u64;
+    stats.code_time     = start.elapsed();
+    Ok(stats)
+}
+
+/// Writes the header, then encodes the text read from `r` adaptively, as
+/// `compress_with()` encodes it with a codebook. If the number of distinct
+/// characters in the text and the number of characters are given as
+/// `counts`, the stream is strict.
+/// 
+fn compress_adaptive(r      : impl Read,
+                     mut w  : impl Write,
+                     counts : Option<(u32, u64)>,
+                     redact : &HashMap<char, char>,
+                     cancel : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let start      = Instant::now();
+    let mut header = MAGIC.to_vec();
+
+    header.push(VERSION);
+    header.push(FLAG_ADAPTIVE | counts.map_or(0, |_| FLAG_STRICT));
+
+    if let Some((symbols, chars)) = counts {
+        header.extend(symbols.to_le_bytes());
+        header.extend(chars.to_le_bytes());
+    }
+    w.write_all(&header)?;
+
+    let mut encoder = AdaptiveEncoder::new();
+    let mut writer  = BitWriter::new();
+    let mut stats   = CompressionStats {
+        stream_bytes: header.len() as u64, ..Default::default()
+    };
+    for_each_str_chunk(r, cancel, |chunk| {
+        for c in chunk.chars() {
+            let sub = redact.get(&c).copied();
+            let len = writer.bit_len();
+
+            stats.redactions += sub.is_some() as u64;
+
+            encoder.write_symbol(sub.unwrap_or(c), &mut writer);
+            stats.symbols        += 1;
+            stats.predicted_bits += writer.bit_len() - len;
+        }
+        stats.text_bytes += chunk.len() as u64;
+
+        let bytes = writer.take_complete();
+
+        stats.stream_bytes += bytes.len() as u64;
+        w.write_all(&bytes)
+    })?;
+    stats.payload_bits = writer.bit_len();
+
+    let tail = tail(&mut writer);
+
+    w.write_all(&tail)?;
+    w.flush()?;
+
+    stats.stream_bytes += tail.len() as u64;
+    stats.code_time     = start.elapsed();
+    Ok(stats)
+}
+
+/// The stream header with `flags`, and `codebook`'s table if they include
+/// `FLAG_TABLE`.
+/// 
+pub(crate) fn header(codebook: &HuffmanCodebook, flags: u8) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+
+    header.push(VERSION);
+    header.push(flags);
+
+    if flags & FLAG_TABLE != 0 {
+        let table = codebook.export();
+
+        header.extend((table.len() as u32).to_le_bytes());
+        header.extend(table);
+    }
+    header
+}
+
+/// The last bytes of the stream: `writer`'s partial byte, if any, and the
+/// count of its padding bits.
+/// 
+pub(crate) fn tail(writer: &mut BitWriter) -> Vec<u8> {
+    let pad      = ((8 - writer.bit_len() % 8) % 8) as u8;
+    let mut tail = writer.finish();
+
+    tail.push(pad);
+    tail
+}
+
+/// Input held for a second pass over it.
+/// 
+enum Spooled {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+impl Spooled {
+    /// Reads the rest of `r` a buffer at a time, checking `cancel` before
+    /// each, into memory until there's more than `limit` bytes of it, and
+    /// then into a temporary file.
+    /// 
+    fn read(mut r: impl Read, limit: usize, cancel: Option<&CancelToken>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut buf   = vec![0; READ_BUF_SIZE];
+        let mut input = vec![];
+        let mut file  = None;
+
+        loop {
+            check_cancel(cancel)?;
+
+            let n = match r.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if file.is_none() && input.len() + n > limit {
+                let mut temp = TempFile::new()?;
+
+                temp.file.write_all(&input)?;
+                input = vec![];
+                file  = Some(temp);
+            }
+            match &mut file {
+                Some(temp) => temp.file.write_all(&buf[..n])?,
+                None       => input.extend(&buf[..n]),
+            }
+        }
+        Ok(match file {
+            Some(temp) => Self::File(temp),
+            None       => Self::Memory(input),
+        })
+    }
+    /// A reader from the start of the input.
+    /// 
+    fn reader(&mut self) -> io::Result<Box<dyn Read + '_>> {
+        match self {
+            Self::Memory(input) => Ok(Box::new(&input[..])),
+            Self::File(temp)    => {
+                temp.file.seek(SeekFrom::Start(0))?;
+                Ok(Box::new(&temp.file))
+            },
+        }
+    }
+}
+
+/// A file in the temporary directory, which is removed when it's dropped.
+/// 
+struct TempFile {
+    path : PathBuf,
+    file : File,
+}
+
+impl TempFile {
+    fn new() -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        loop {
+            let n    = NEXT.fetch_add(1, AtomicOrdering::Relaxed);
+            let path = env::temp_dir().join(format!("huffman-spool-{}-{}",
+                                                    process::id(), n));
+            let file = OpenOptions::new().read(true)
+                                         .write(true)
+                                         .create_new(true)
+                                         .open(&path);
+            match file {
+                Ok(file) => return Ok(Self { path, file }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Fails with `HuffmanError::Cancelled` if `cancel` has been cancelled.
+/// 
+fn check_cancel(cancel: Option<&CancelToken>) -> Result<(), HuffmanError> {
+    match cancel.is_some_and(CancelToken::is_cancelled) {
+        true  => Err(HuffmanError::Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// Fills `buf` from `r`, reporting a short read as truncated data.
+/// 
+pub(crate) fn read_exact(r: &mut impl Read, buf: &mut [u8]) 
+
+    -> Result<(), HuffmanError> 
+{
+    r.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => {
+            HuffmanError::MalformedData("the data is truncated")
+        },
+        _ => HuffmanError::from(e),
+    })
+}
+
+/// Reads the stream header from `r`, checking its magic bytes and version,
+/// and returns its flags.
+/// 
+pub(crate) fn read_flags(r: &mut impl Read) -> Result<u8, HuffmanError> {
+    let mut header = [0; 6];
+
+    read_exact(r, &mut header)?;
+
+    if &header[..4] != MAGIC {
+        return Err(HuffmanError::MalformedData("not a compressed stream"));
+    }
+    if header[4] != VERSION {
+        return Err(HuffmanError::UnsupportedVersion(header[4]));
+    }
+    Ok(header[5])
+}
+
+/// Reads a code table, or any other bytes, after their length, from `r`.
+/// 
+pub(crate) fn read_table(r: &mut impl Read) -> Result<Vec<u8>, HuffmanError> {
+    let mut len = [0; 4];
+    read_exact(r, &mut len)?;
+
+    let len       = u32::from_le_bytes(len) as usize;
+    let mut table = Vec::with_capacity(len.min(READ_BUF_SIZE));
+
+    r.take(len as u64).read_to_end(&mut table)?;
+
+    if table.len() < len {
+        return Err(HuffmanError::MalformedData("the data is truncated"));
+    }
+    Ok(table)
+}
+
+/// Decompresses a stream written by `pipe_compress()` from `r`, writing the
+/// text to `w` as it's decoded. `codebook` is needed for streams that don't
+/// carry their own table, and is otherwise ignored. `cancel` is checked once
+/// per buffer of the stream read, as `pipe_compress()` checks it.
+/// 
+pub fn pipe_decompress(r        : impl Read,
+                       w        : impl Write,
+                       codebook : Option<&HuffmanCodebook>,
+                       cancel   : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    pipe_decompress_limited(r, w, codebook, DecodeLimits::default(), cancel)
+}
+
+/// Like `pipe_decompress()`, but fails with
+/// `HuffmanError::OutputLimitExceeded` as soon as the text decoded so far is
+/// over one of `limits`, before any of it past the limit is written. Text is
+/// decoded a buffer of the stream at a time, so the error may count up to a
+/// buffer's worth more than the limit.
+/// 
+pub fn pipe_decompress_limited(mut r    : impl Read,
+                               mut w    : impl Write,
+                               codebook : Option<&HuffmanCodebook>,
+                               limits   : DecodeLimits,
+                               cancel   : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let flags = read_flags(&mut r)?;
+    let known = FLAG_TABLE | FLAG_STORED | FLAG_ENGLISH | FLAG_STRICT
+                | FLAG_ADAPTIVE;
+
+    if flags & FLAG_IDS != 0 {
+        return Err(HuffmanError::MalformedData("the stream holds ids, not \
+                                                text"));
+    }
+    if flags == FLAG_BLOCKS {
+        return Err(HuffmanError::MalformedData("the stream is in blocks"));
+    }
+    if flags == FLAG_TABLE | FLAG_LINES {
+        return Err(HuffmanError::MalformedData("the stream is in lines"));
+    }
+    if flags & !known != 0
+        || (flags & !FLAG_STRICT).count_ones() > 1
+        || flags & FLAG_STRICT != 0 && flags & FLAG_STORED != 0
+    {
+        return Err(HuffmanError::MalformedData("unknown option flags"));
+    }
+    let mut stats = CompressionStats {
+        stream_bytes: (MAGIC.len() + 2) as u64, ..Default::default()
+    };
+    if flags & FLAG_STORED != 0 {
+        return copy_stored(r, w, stats, limits, cancel);
+    }
+    if flags & FLAG_ADAPTIVE != 0 {
+        return decode_adaptive(r, w, flags, stats, limits, cancel);
+    }
+    let embedded;
+    let english;
+    let codebook = if flags & FLAG_TABLE != 0 {
+        let table = read_table(&mut r)?;
+
+        stats.table_bytes   = 4 + table.len() as u64;
+        stats.stream_bytes += stats.table_bytes;
+        embedded = HuffmanCodebook::import(&table)?;
+        &embedded
+    } else if flags & FLAG_ENGLISH != 0 {
+        english = HuffmanCodebook::english();
+        &english
+    } else {
+        codebook.ok_or(HuffmanError::MissingCodebook)?
+    };
+    let mut chars = None;
+
+    if flags & FLAG_STRICT != 0 {
+        let mut counts = [0; 12];
+        read_exact(&mut r, &mut counts)?;
+
+        let symbols = u32::from_le_bytes(counts[..4].try_into().unwrap());
+
+        if symbols as usize != codebook.codes().len() {
+            return Err(HuffmanError::AlphabetMismatch {
+                expected : symbols as usize,
+                found    : codebook.codes().len(),
+            });
+        }
+        chars = Some(u64::from_le_bytes(counts[4..].try_into().unwrap()));
+        stats.stream_bytes += counts.len() as u64;
+    }
+    let start       = Instant::now();
+    let mut decoder = PushDecoder::new(codebook)?;
+    let check_len   = |decoder: &PushDecoder, done| match chars {
+        Some(expected) if decoder.symbols() > expected
+                          || done && decoder.symbols() != expected => {
+            Err(HuffmanError::LengthMismatch {
+                expected, found: decoder.symbols()
+            })
+        },
+        _ => Ok(()),
+    };
+
+    // The last byte read is held back, since it says how much of the one
+    // before it, which the decoder holds back, is padding.
+    let mut buf  = vec![0; READ_BUF_SIZE];
+    let mut held = 0;
+    let mut sent = 0;
+    let mut text = String::new();
+
+    loop {
+        check_cancel(cancel)?;
+
+        let n = match r.read(&mut buf[held..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break;
+        }
+        let end = held + n;
+
+        stats.stream_bytes += n as u64;
+
+        decoder.push(&buf[..end - 1])?;
+        check_len(&decoder, false)?;
+        sent += end - 1;
+        buf.copy_within(end - 1..end, 0);
+        held = 1;
+
+        text.extend(decoder.drain_output());
+        stats.text_bytes += text.len() as u64;
+        limits.check(decoder.symbols(), stats.text_bytes)?;
+        w.write_all(text.as_bytes())?;
+        text.clear();
+    }
+    let valid_bits = match (sent, &buf[..held]) {
+        (0, [0])                => 0,
+        (0, _)                  => {
+            return Err(HuffmanError::MalformedData("the data is truncated"));
+        },
+        (_, &[pad]) if pad < 8  => 8 - pad,
+        _                       => {
+            return Err(HuffmanError::MalformedData("bad padding count"));
+        },
+    };
+    decoder.finish(valid_bits)?;
+    check_len(&decoder, true)?;
+    text.extend(decoder.drain_output());
+
+    stats.symbols      = decoder.symbols();
+    stats.payload_bits = decoder.bit_len();
+    stats.text_bytes  += text.len() as u64;
+    limits.check(stats.symbols, stats.text_bytes)?;
+    w.write_all(text.as_bytes())?;
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+
+/// Decodes the codes of an adaptive stream from `r`, after its flags, as
+/// `pipe_decompress_limited()` decodes those of a coded stream. The codes are
+/// decoded a buffer at a time, and a symbol split between two buffers is
+/// decoded once the second is read.
+/// 
+fn decode_adaptive(mut r     : impl Read,
+                   mut w     : impl Write,
+                   flags     : u8,
+                   mut stats : CompressionStats,
+                   limits    : DecodeLimits,
+                   cancel    : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let mut counts = None;
+
+    if flags & FLAG_STRICT != 0 {
+        let mut header = [0; 12];
+        read_exact(&mut r, &mut header)?;
+
+        let symbols = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let chars   = u64::from_le_bytes(header[4..].try_into().unwrap());
+
+        counts = Some((symbols as usize, chars));
+        stats.stream_bytes += header.len() as u64;
+    }
+    let start       = Instant::now();
+    let mut decoder = AdaptiveDecoder::new();
+    let check_len   = |decoder: &AdaptiveDecoder, done| match counts {
+        Some((_, expected)) if decoder.decoded() as u64 > expected
+                               || done
+                                  && decoder.decoded() as u64 != expected => {
+            Err(HuffmanError::LengthMismatch {
+                expected, found: decoder.decoded() as u64
+            })
+        },
+        _ => Ok(()),
+    };
+
+    // The last byte read is held back, as for a coded stream. Of the bytes
+    // before it, those in `pending` are yet to be decoded, but for the first
+    // `skip` bits of the first of them.
+    let mut buf     = vec![0; READ_BUF_SIZE];
+    let mut held    = 0;
+    let mut sent    = 0;
+    let mut pending = Pending::default();
+    let mut text    = String::new();
+
+    loop {
+        check_cancel(cancel)?;
+
+        let n = match r.read(&mut buf[held..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break;
+        }
+        let end = held + n;
+
+        stats.stream_bytes += n as u64;
+
+        pending.bytes.extend(&buf[..end - 1]);
+        pending.decode(&mut decoder, 0, false, &mut text)?;
+        check_len(&decoder, false)?;
+        sent += end - 1;
+        buf.copy_within(end - 1..end, 0);
+        held = 1;
+
+        stats.text_bytes += text.len() as u64;
+        limits.check(decoder.decoded() as u64, stats.text_bytes)?;
+        w.write_all(text.as_bytes())?;
+        text.clear();
+    }
+    let pad = match (sent, &buf[..held]) {
+        (0, [0])                                       => 0,
+        (0, _)                                         => {
+            return Err(HuffmanError::MalformedData("the data is truncated"));
+        },
+        (_, &[pad]) if pad < 8 && pending.has_pad(pad) => pad as usize,
+        _                                              => {
+            return Err(HuffmanError::MalformedData("bad padding count"));
+        },
+    };
+    pending.decode(&mut decoder, pad, true, &mut text)?;
+    check_len(&decoder, true)?;
+
+    if let Some((expected, _)) = counts && decoder.symbols() != expected {
+        return Err(HuffmanError::AlphabetMismatch {
+            expected, found: decoder.symbols()
+        });
+    }
+    stats.symbols      = decoder.decoded() as u64;
+    stats.payload_bits = pending.base;
+    stats.text_bytes  += text.len() as u64;
+    limits.check(stats.symbols, stats.text_bytes)?;
+    w.write_all(text.as_bytes())?;
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+/// The bytes of an adaptive stream read but not yet wholly decoded.
+/// 
+#[derive(Default)]
+struct Pending {
+    bytes : Vec<u8>,
+    /// The number of bits of the first byte already decoded.
+    skip  : usize,
+    /// The number of bits decoded before the first byte.
+    base  : u64,
+}
+
+impl Pending {
+    /// Whether `pad` bits at the end can be padding, leaving none of the
+    /// decoded bits in it.
+    /// 
+    fn has_pad(&self, pad: u8) -> bool {
+        self.bytes.len() * 8 >= self.skip + pad as usize
+    }
+    /// Decodes the whole symbols in the bytes onto `text`, and drops the
+    /// bytes they were in. If the bytes are the `last` of the stream, all
+    /// but the last `pad` bits are decoded. Otherwise, the last byte, which
+    /// may end in padding, is held back, and a symbol cut off before it is
+    /// left for when the rest of it has been read. Errors give the offset
+    /// from the start of the codes.
+    /// 
+    fn decode(&mut self,
+              decoder : &mut AdaptiveDecoder,
+              pad     : usize,
+              last    : bool,
+              text    : &mut String) 
+
+        -> Result<(), HuffmanError> 
+    {
+        let nbits = match last {
+            true  => self.bytes.len() * 8 - pad,
+            false => self.bytes.len().saturating_sub(1) * 8,
+        };
+        if nbits < self.skip {
+            return Ok(());
+        }
+        let mut r    = BitReader::new(&self.bytes, nbits);
+        let mut used = self.skip;
+
+        for _ in 0..self.skip {
+            r.read_bit();
+        }
+        while r.remaining() > 0 {
+            match decoder.decode_symbol(&mut r) {
+                Ok(c) => {
+                    text.push(c);
+                    used = r.position();
+                },
+                Err(HuffmanError::TruncatedBitstream { .. }) if !last => break,
+                Err(e) => return Err(self.rebase(e)),
+            }
+        }
+        self.bytes.drain(..used / 8);
+        self.skip  = used % 8;
+        self.base += (used / 8 * 8) as u64;
+
+        if last {
+            self.base += self.skip as u64;
+        }
+        Ok(())
+    }
+    /// `e`, with its offset counted from the start of the codes rather than
+    /// from the first byte.
+    /// 
+    fn rebase(&self, e: HuffmanError) -> HuffmanError {
+        let base = self.base as usize;
+
+        match e {
+            HuffmanError::TruncatedBitstream { bit_offset, symbols } => {
+                HuffmanError::TruncatedBitstream {
+                    bit_offset: base + bit_offset, symbols
+                }
+            },
+            HuffmanError::InvalidCode { bit_offset, symbols } => {
+                HuffmanError::InvalidCode {
+                    bit_offset: base + bit_offset, symbols
+                }
+            },
+            e => e,
+        }
+    }
+}
+
+/// Copies the text of a stored stream from `r` to `w`, checking it's UTF-8
+/// and within `limits`, and checking `cancel` before each buffer.
+/// 
+fn copy_stored(r         : impl Read,
+               mut w     : impl Write,
+               mut stats : CompressionStats,
+               limits    : DecodeLimits,
+               cancel    : Option<&CancelToken>) 
+
+    -> Result<CompressionStats, HuffmanError> 
+{
+    let start = Instant::now();
+
+    for_each_str_chunk(r, cancel, |chunk| {
+        stats.symbols      += chunk.chars().count() as u64;
+        stats.text_bytes   += chunk.len() as u64;
+        stats.stream_bytes += chunk.len() as u64;
+        limits.check(stats.symbols, stats.text_bytes)
+              .map_err(io::Error::other)?;
+        w.write_all(chunk.as_bytes())
+    })?;
+    w.flush()?;
+
+    stats.code_time = start.elapsed();
+    Ok(stats)
+}
+
+/// Compresses `data` whichever way gives the smallest stream: stored as it
+/// is, coded with `HuffmanCodebook::english()`, or coded with a table trained
+/// on it and carried in the stream. The sizes are predicted from the counts
+/// of the characters before anything is encoded, so the choice costs little.
+/// The stream is read by `pipe_decompress()`, or `decompress_auto()`.
+/// 
+pub fn compress_auto(data: &str) -> Vec<u8> {
+    let freqs   = FrequencyTable::from_text(data);
+    let english = HuffmanCodebook::english();
+    let trained = HuffmanCodebook::from_frequencies_capped(freqs.clone());
+
+    // A coded stream is its header, the codes, and the padding count.
+    let coded = |codebook: &HuffmanCodebook, flags| {
+        let bits = predicted_bits(&codebook.code_strings(), &freqs)?;
+
+        Some(header(codebook, flags).len() + bits.div_ceil(8) + 1)
+    };
+    let mut best = (MAGIC.len() + 2 + data.len(), FLAG_STORED);
+
+    for (size, flags) in [(coded(&english, FLAG_ENGLISH), FLAG_ENGLISH),
+                          (coded(&trained, FLAG_TABLE), FLAG_TABLE)] {
+        if let Some(size) = size && size < best.0 {
+            best = (size, flags);
+        }
+    }
+    let mut stream = Vec::with_capacity(best.0);
+
+    match best.1 {
+        FLAG_STORED  => {
+            stream.extend(header(&english, FLAG_STORED));
+            stream.extend(data.as_bytes());
+        },
+        flags        => {
+            let codebook = match flags {
+                FLAG_TABLE => &trained,
+                _          => &english,
+            };
+
+            compress_with(data.as_bytes(), &mut stream, codebook, flags,
+                          None, &HashMap::new(), None)
+                .expect("every character has a code");
+        },
+    }
+    stream
+}
+
+/// Decompresses a stream written by `compress_auto()`.
+/// 
+pub fn decompress_auto(stream: &[u8]) -> Result<String, HuffmanError> {
+    let mut text = vec![];
+
+    pipe_decompress(stream, &mut text, None, None)?;
+
+    String::from_utf8(text).map_err(|_| HuffmanError::InvalidUtf8)
+}
+
+/// Compresses a sequence of `u32` ids, such as a tokenizer's, with codes
+/// trained on it and carried in the stream. The stream is read by
+/// `decompress_ids()`. Fails as `IdCodebook::from_ids()` does.
+/// 
+pub fn compress_ids(ids: &[u32]) -> Result<Vec<u8>, HuffmanError> {
+    compress_ids_with(ids, None)
+}
+
+/// Like `compress_ids()`, but the stream also carries `names`, so it says
+/// what its ids stand for. The names needn't cover every id, nor only ids
+/// that occur. The stream is read by `decompress_ids_named()`, or by
+/// `decompress_ids()`, which skips the names.
+/// 
+pub fn compress_ids_named(ids: &[u32], names: &SymbolMap) 
+
+    -> Result<Vec<u8>, HuffmanError> 
+{
+    compress_ids_with(ids, Some(names))
+}
+
+/// Compresses as `compress_ids()` does, with `names` if there are any.
+/// 
+fn compress_ids_with(ids: &[u32], names: Option<&SymbolMap>) 
+
+    -> Result<Vec<u8>, HuffmanError> 
+{
+    let codebook = IdCodebook::from_ids(ids)?;
+    let table    = codebook.export();
+    let (codes, nbits) = codebook.encode(ids)?.into_parts();
+
+    let mut stream = MAGIC.to_vec();
+    let flags      = match names {
+        Some(_) => FLAG_TABLE | FLAG_IDS | FLAG_NAMES,
+        None    => FLAG_TABLE | FLAG_IDS,
+    };
+    stream.extend([VERSION, flags]);
+    stream.extend((table.len() as u32).to_le_bytes());
+    stream.extend(table);
+
+    if let Some(names) = names {
+        let names = names.export();
+
+        stream.extend((names.len() as u32).to_le_bytes());
+        stream.extend(names);
+    }
+    stream.extend(codes);
+    stream.push(((8 - nbits % 8) % 8) as u8);
+    Ok(stream)
+}
+
+/// Decompresses a stream written by `compress_ids()` or
+/// `compress_ids_named()`, without its names. Fails with
+/// `HuffmanError::MalformedData` for a stream of text, and otherwise as
+/// `pipe_decompress()` does.
+/// 
+pub fn decompress_ids(stream: &[u8]) -> Result<Vec<u32>, HuffmanError> {
+    decompress_ids_named(stream).map(|decoded| decoded.ids)
+}
+
+/// Decompresses a stream written by `compress_ids_named()`, or
+/// `compress_ids()`, with the names it carries, if any. Fails as
+/// `decompress_ids()` does, or as `SymbolMap::import()` does for the names.
+/// 
+pub fn decompress_ids_named(mut stream: &[u8]) 
+
+    -> Result<DecodedIds, HuffmanError> 
+{
+    let flags = read_flags(&mut stream)?;
+
+    if flags & !FLAG_NAMES != FLAG_TABLE | FLAG_IDS {
+        return Err(HuffmanError::MalformedData("the stream holds text, not \
+                                                ids"));
+    }
+    let codebook = IdCodebook::import(&read_table(&mut stream)?)?;
+    let names    = match flags & FLAG_NAMES {
+        0 => None,
+        _ => Some(SymbolMap::import(&read_table(&mut stream)?)?),
+    };
+
+    let Some((&pad, codes)) = stream.split_last() else {
+        return Err(HuffmanError::MalformedData("the data is truncated"));
+    };
+    if pad >= 8 || codes.is_empty() && pad > 0 {
+        return Err(HuffmanError::MalformedData("bad padding count"));
+    }
+    let nbits = codes.len() * 8 - pad as usize;
+    let bits  = BitBuffer::try_new_lenient(codes.to_vec(), nbits)?;
+
+    Ok(DecodedIds { ids: codebook.decode(&bits)?, names })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn compressing_chars() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        for text in [&text[..], "", "a", "ünïcödé 😀"] {
+            let mut expected = vec![];
+            let mut stream   = vec![];
+            let mut calls    = 0;
+
+            let train = pipe_compress(text.as_bytes(), &mut expected,
+                                      TableSource::Train, None).unwrap();
+            let stats = compress_chars(|| { calls += 1; text.chars() },
+                                       &mut stream).unwrap();
+
+            assert_eq!(calls, 2);
+            assert_eq!(stream, expected);
+            assert_eq!(stats.sizes(), train.sizes());
+            assert_eq!(stats.tree_build, train.tree_build);
+
+            let mut output = vec![];
+
+            pipe_decompress(&stream[..], &mut output, None, None).unwrap();
+            assert_eq!(output, text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn trained_round_trip() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut stream = vec![];
+        let stats = pipe_compress(text.as_bytes(), &mut stream,
+                                  TableSource::Train, None).unwrap();
+
+        assert_eq!(stats.symbols, text.chars().count() as u64);
+        assert_eq!(stats.text_bytes, text.len() as u64);
+        assert_eq!(stats.stream_bytes, stream.len() as u64);
+        assert_eq!(stats.predicted_bits, stats.payload_bits);
+        assert_eq!(stats.table_bytes,
+                   4 + u32::from_le_bytes(stream[6..10].try_into().unwrap())
+                           as u64);
+        assert_eq!(stats.tree_build, Some(TreeBuild::Heap));
+        assert_eq!(stats.code_lookup, Some(CodeLookup::Hashed));
+        assert!(stream.len() < text.len());
+
+        let mut output = vec![];
+
+        assert_eq!(pipe_decompress(&stream[..], &mut output, None, None)
+                       .map(CompressionStats::sizes),
+                   Ok(stats.sizes()));
+        assert_eq!(String::from_utf8(output).unwrap(), text);
+
+        for input in ["", "ab", "a🦀a🦀 é"] {
+            let mut stream = vec![];
+            let mut output = vec![];
+
+            pipe_compress(input.as_bytes(), &mut stream, TableSource::Train,
+                          None).unwrap();
+            pipe_decompress(&stream[..], &mut output, None, None).unwrap();
+
+            assert_eq!(output, input.as_bytes());
+        }
+    }
+
+    #[test]
+    fn static_round_trip() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let mut stream = vec![];
+        let stats = pipe_compress(io::Cursor::new(&text), &mut stream,
+                                  TableSource::Static(&codebook), None)
+                        .unwrap();
+
+        assert_eq!(stream[5], 0);
+        assert_eq!(stats.stream_bytes as usize, stream.len());
+        assert_eq!(stats.payload_bits + stream[stream.len() - 1] as u64,
+                   (stream.len() as u64 - 7) * 8);
+        assert_eq!(stats.predicted_bits, stats.payload_bits);
+        assert_eq!(stats.table_bytes, 0);
+        assert_eq!(stats.count_time, Duration::ZERO);
+        assert_eq!(stats.tree_build, None);
+
+        let mut output = vec![];
+
+        assert_eq!(pipe_decompress(io::Cursor::new(&stream), &mut output,
+                                   Some(&codebook), None)
+                       .map(CompressionStats::sizes),
+                   Ok(stats.sizes()));
+        assert_eq!(String::from_utf8(output).unwrap(), text);
+        assert_eq!(pipe_decompress(&stream[..], io::sink(), None, None),
+                   Err(HuffmanError::MissingCodebook));
+    }
+
+    #[test]
+    fn table_sources_over_cursors() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+
+        for (table, flags) in [(TableSource::Train, FLAG_TABLE),
+                               (TableSource::Static(&codebook), 0),
+                               (TableSource::Adaptive, FLAG_ADAPTIVE)] {
+            let mut stream = io::Cursor::new(vec![]);
+            let stats = pipe_compress(io::Cursor::new(&text), &mut stream,
+                                      table, None).unwrap();
+            let stream = stream.into_inner();
+
+            assert_eq!(stream[5], flags);
+            assert_eq!(stats.stream_bytes as usize, stream.len());
+            assert!(stream.len() < text.len() * 3 / 5);
+
+            let mut output = io::Cursor::new(vec![]);
+
+            assert_eq!(pipe_decompress(io::Cursor::new(&stream), &mut output,
+                                       Some(&codebook), None)
+                           .map(CompressionStats::sizes),
+                       Ok(stats.sizes()));
+            assert_eq!(output.into_inner(), text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn spooled_input() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let spool = || {
+            let prefix = format!("huffman-spool-{}-", process::id());
+
+            fs::read_dir(env::temp_dir()).unwrap().filter(|entry| {
+                entry.as_ref().unwrap().file_name().to_string_lossy()
+                     .starts_with(&prefix)
+            }).count()
+        };
+        let codebook = HuffmanCodebook::from_text(&text);
+
+        for (table, strict) in [(TableSource::Train, false),
+                                (TableSource::Static(&codebook), true),
+                                (TableSource::Adaptive, true)] {
+            let mut held    = vec![];
+            let mut spooled = vec![];
+            let redact      = HashMap::new();
+            let stats = compress(text.as_bytes(), &mut held, table, strict,
+                                 &redact, text.len(), None).unwrap();
+
+            // Held in memory up to 1000 bytes, so in a file.
+            assert!(matches!(Spooled::read(text.as_bytes(), 1000, None),
+                             Ok(Spooled::File(_))));
+            assert_eq!(compress(text.as_bytes(), &mut spooled, table, strict,
+                                &redact, 1000, None)
+                           .map(CompressionStats::sizes),
+                       Ok(stats.sizes()));
+            assert_eq!(spooled, held);
+        }
+        assert_eq!(spool(), 0);
+
+        let mut input = Spooled::read(text.as_bytes(), 0, None).unwrap();
+        let mut read  = String::new();
+
+        for _ in 0..2 {
+            read.clear();
+            input.reader().unwrap().read_to_string(&mut read).unwrap();
+            assert_eq!(read, text);
+        }
+        assert!(matches!(input, Spooled::File(_)));
+        assert_eq!(spool(), 1);
+
+        drop(input);
+
+        assert_eq!(spool(), 0);
+        assert!(matches!(Spooled::read(&b""[..], 0, None),
+                         Ok(Spooled::Memory(_))));
+        assert!(matches!(Spooled::read(text.as_bytes(), text.len(), None),
+                         Ok(Spooled::Memory(_))));
+        assert_eq!(compress(&b"\xff\xfe"[..], io::sink(), TableSource::Train,
+                            false, &HashMap::new(), 1, None).err(),
+                   Some(HuffmanError::InvalidUtf8));
+    }
+
+    /// A reader that gives a byte at a time.
+    /// 
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1);
+
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn adaptive_streams() {
+        let moby  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let short = moby.chars().take(5000).collect::<String>();
+
+        for text in ["", "a", "abracadabra", "日本語のテキスト", &short] {
+            for strict in [false, true] {
+                let mut stream = vec![];
+                let stats = compress(text.as_bytes(), &mut stream,
+                                     TableSource::Adaptive, strict,
+                                     &HashMap::new(), SPOOL_LIMIT, None)
+                                .unwrap();
+                let (flags, header) = match strict {
+                    false => (FLAG_ADAPTIVE, 6),
+                    true  => (FLAG_ADAPTIVE | FLAG_STRICT, 18),
+                };
+                assert_eq!(stream[5], flags);
+                assert_eq!(stats.stream_bytes as usize, stream.len());
+                assert_eq!(stats.payload_bits + stream[stream.len() - 1]
+                                                    as u64,
+                           (stream.len() - header - 1) as u64 * 8);
+                assert_eq!(stats.predicted_bits, stats.payload_bits);
+
+                // Read whole, and a byte at a time, splitting every symbol
+                // that spans two bytes between reads.
+                for whole in [true, false] {
+                    let mut output = vec![];
+                    let decoded    = match whole {
+                        true  => pipe_decompress(&stream[..], &mut output,
+                                                 None, None),
+                        false => pipe_decompress(Trickle(&stream),
+                                                 &mut output, None, None),
+                    };
+                    assert_eq!(decoded.map(CompressionStats::sizes),
+                               Ok(stats.sizes()));
+                    assert_eq!(output, text.as_bytes());
+                }
+            }
+        }
+        let decompress = |stream: &[u8]| {
+            pipe_decompress(stream, io::sink(), None, None).err()
+        };
+        let mut stream = vec![];
+        compress(&b"abracadabra"[..], &mut stream, TableSource::Adaptive,
+                 true, &HashMap::new(), SPOOL_LIMIT, None).unwrap();
+
+        let mut wrong = stream.clone();
+        wrong[6] = 4;
+        assert_eq!(decompress(&wrong),
+                   Some(HuffmanError::AlphabetMismatch {
+                       expected: 4, found: 5
+                   }));
+
+        let mut wrong = stream.clone();
+        wrong[10] = 10;
+        assert_eq!(decompress(&wrong),
+                   Some(HuffmanError::LengthMismatch {
+                       expected: 10, found: 11
+                   }));
+
+        let mut wrong = stream.clone();
+        *wrong.last_mut().unwrap() = 8;
+        assert_eq!(decompress(&wrong),
+                   Some(HuffmanError::MalformedData("bad padding count")));
+
+        let cut = [&stream[..stream.len() - 3], &[0]].concat();
+        assert_eq!(decompress(&cut),
+                   Some(HuffmanError::TruncatedBitstream {
+                       bit_offset: 92, symbols: 6
+                   }));
+
+        for flags in [FLAG_ENGLISH, FLAG_STORED] {
+            let mut wrong = stream.clone();
+            wrong[5] |= flags;
+            assert_eq!(decompress(&wrong),
+                       Some(HuffmanError::MalformedData("unknown option \
+                                                         flags")));
+        }
+
+        // Redacted text is counted after substituting.
+        let redact     = HashMap::from([('b', 'a'), ('r', 'a')]);
+        let mut stream = vec![];
+        let mut output = vec![];
+        let stats      = compress(&b"abracadabra"[..], &mut stream,
+                                  TableSource::Adaptive, true, &redact,
+                                  SPOOL_LIMIT, None).unwrap();
+
+        assert_eq!((stats.symbols, stats.redactions, stream[6]), (11, 4, 3));
+
+        pipe_decompress(&stream[..], &mut output, None, None).unwrap();
+
+        assert_eq!(output, b"aaaacadaaaa");
+    }
+
+    #[test]
+    fn redacted_streams() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let redact = HashMap::from([('|', '#'), ('@', '#'), ('e', 'E')]);
+        let input  = text.clone() + "id|name@host";
+        let count  = input.chars().filter(|c| redact.contains_key(c)).count();
+
+        let mut stream = vec![];
+        let stats = pipe_compress_redacted(input.as_bytes(), &mut stream,
+                                           TableSource::Train, &redact, None)
+                        .unwrap();
+
+        assert_eq!(stats.redactions, count as u64);
+        assert_eq!(stats.predicted_bits, stats.payload_bits);
+
+        let mut output = vec![];
+
+        pipe_decompress(&stream[..], &mut output, None, None).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!output.contains(['|', '@', 'e']));
+        assert_eq!(output, input.replace(['|', '@'], "#").replace('e', "E"));
+
+        // The trained codes have none for the redacted characters.
+        let table    = read_table(&mut &stream[6..]).unwrap();
+        let codebook = HuffmanCodebook::import(&table).unwrap();
+
+        assert!(redact.keys().all(|&c| codebook.code_for(c).is_none()));
+
+        // A static table is used as it is, and must cover the substitutes.
+        let codebook = HuffmanCodebook::from_text(&text);
+        let stats    = pipe_compress_redacted(&b"eel"[..], io::sink(),
+                                              TableSource::Static(&codebook),
+                                              &redact, None).unwrap();
+
+        assert_eq!((stats.symbols, stats.redactions), (3, 2));
+        assert_eq!(pipe_compress_redacted(&b"a@b"[..], io::sink(),
+                                          TableSource::Static(&codebook),
+                                          &HashMap::from([('@', '\0')]),
+                                          None).err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\0', char_index: 1, byte_offset: 1
+                   }));
+    }
+
+    #[test]
+    fn bad_streams() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0, 1), ('b', 0b10, 2)];
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+        let compress = |text: &str| {
+            let mut stream = vec![];
+            pipe_compress(text.as_bytes(), &mut stream,
+                          TableSource::Static(&codebook), None).map(|_| stream)
+        };
+        let decompress = |stream: &[u8]| {
+            pipe_decompress(stream, io::sink(), Some(&codebook), None)
+                .map(|_| ())
+        };
+        assert_eq!(compress("ab\nb").err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\n', char_index: 2, byte_offset: 2
+                   }));
+        let stream = compress("abba").unwrap();
+
+        assert_eq!(&stream[6..], [0b0101_0000, 2]);
+        assert_eq!(decompress(&[&stream[..6], &[0b0111_0000, 2]].concat()),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 1, symbols: 1
+                   }));
+        assert_eq!(decompress(&[&stream[..6], &[0b0101_0000, 6]].concat()),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 1, symbols: 1
+                   }));
+        assert_eq!(decompress(&[&stream[..6], &[0, 9]].concat()),
+                   Err(HuffmanError::MalformedData("bad padding count")));
+        assert_eq!(decompress(&stream[..7]),
+                   Err(HuffmanError::MalformedData("the data is truncated")));
+        assert_eq!(decompress(&stream[..6]),
+                   Err(HuffmanError::MalformedData("the data is truncated")));
+        assert_eq!(decompress(b"HUF"),
+                   Err(HuffmanError::MalformedData("the data is truncated")));
+        assert_eq!(decompress(b"PK\x03\x04\x01\x00\x00"),
+                   Err(HuffmanError::MalformedData("not a compressed stream")));
+        assert_eq!(decompress(b"HUFF\x02\x00\x00"),
+                   Err(HuffmanError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn mutated_streams() {
+        use crate::mutate::{arbitrary_container, mutate_container, Mutation};
+        use HuffmanError::*;
+
+        for seed in 0..200 {
+            let stream   = arbitrary_container(seed);
+            let mut text = vec![];
+
+            pipe_decompress(&stream[..], &mut text, None, None).unwrap();
+
+            for strategy in Mutation::ALL {
+                let mutated    = mutate_container(&stream, strategy, seed);
+                let mut output = vec![];
+                let result     = pipe_decompress(&mutated[..], &mut output,
+                                                 None, None);
+                match strategy {
+                    Mutation::TruncateTable 
+                    | Mutation::OversizeDeclaredLength => {
+                        assert_eq!(result.err(),
+                                   Some(MalformedData("the data is \
+                                                       truncated")));
+                    },
+                    Mutation::CorruptKraft => {
+                        assert!(matches!(result, Err(NotPrefixFree(..))));
+                    },
+                    Mutation::FlipPayloadBit if text.is_empty() => {
+                        assert_eq!(mutated, stream);
+                    },
+                    Mutation::FlipPayloadBit => {
+                        assert!(result.is_err() || output != text);
+                    },
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strict_streams() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let half     = &text[text.len() / 2..];
+        let codebook = HuffmanCodebook::from_text(half);
+        let strict   = |text: &str, table| {
+            let mut stream = vec![];
+            pipe_compress_strict(text.as_bytes(), &mut stream, table, None)
+                .unwrap();
+            stream
+        };
+        let decompress = |stream: &[u8], codebook| {
+            let mut output = vec![];
+            pipe_decompress(stream, &mut output, codebook, None)
+                .map(|_| String::from_utf8(output).unwrap())
+        };
+        let trained = strict(&text, TableSource::Train);
+        let payload = strict(half, TableSource::Static(&codebook));
+
+        assert_eq!(trained[5], FLAG_TABLE | FLAG_STRICT);
+        assert_eq!(decompress(&trained, None).unwrap(), text);
+        assert_eq!(decompress(&payload, Some(&codebook)).unwrap(), half);
+
+        // A codebook for the same alphabet with flat codes passes the first
+        // check, but its longer codes decode to too few characters.
+        let flat = codebook.codes()
+                      .keys()
+                      .map(|&c| (c, 1))
+                      .collect::<FrequencyTable>();
+        let flat = HuffmanCodebook::from_frequencies(flat);
+
+        let chars = half.chars().count() as u64;
+
+        assert!(matches!(decompress(&payload, Some(&flat)),
+                         Err(HuffmanError::LengthMismatch {
+                             expected, found
+                         }) if expected == chars && found < chars));
+
+        let small = HuffmanCodebook::from_text("abc");
+
+        assert_eq!(decompress(&payload, Some(&small)),
+                   Err(HuffmanError::AlphabetMismatch {
+                       expected : codebook.codes().len(),
+                       found    : 3,
+                   }));
+
+        // Declaring one character too many or too few.
+        let at = MAGIC.len() + 2 + 4;
+
+        for declared in [chars + 1, chars - 1] {
+            let mut stream = payload.clone();
+
+            stream[at..at + 8].copy_from_slice(&declared.to_le_bytes());
+
+            assert_eq!(decompress(&stream, Some(&codebook)).err().map(|e| {
+                           matches!(e, HuffmanError::LengthMismatch {
+                               expected, ..
+                           } if expected == declared)
+                       }),
+                       Some(true));
+        }
+        assert_eq!(decompress(b"HUFF\x01\x0a", None),
+                   Err(HuffmanError::MalformedData("unknown option flags")));
+    }
+
+    #[test]
+    fn id_streams() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let ids  = text.split_whitespace()
+                       .map(|word| word.bytes().fold(70_000_u32, |h, b| {
+                           h.wrapping_mul(31).wrapping_add(b as u32) | 1 << 20
+                       }))
+                       .collect::<Vec<_>>();
+        let stream = compress_ids(&ids).unwrap();
+
+        assert_eq!(stream[5], FLAG_TABLE | FLAG_IDS);
+        assert_eq!(decompress_ids(&stream), Ok(ids));
+        assert_eq!(decompress_ids(&compress_ids(&[]).unwrap()), Ok(vec![]));
+        assert_eq!(decompress_ids(&compress_ids(&[1 << 31; 9]).unwrap()),
+                   Ok(vec![1 << 31; 9]));
+
+        // Each kind of stream is refused by the other kind's decoder.
+        assert_eq!(pipe_decompress(&stream[..], io::sink(), None, None),
+                   Err(HuffmanError::MalformedData("the stream holds ids, \
+                                                    not text")));
+        assert_eq!(decompress_ids(&compress_auto("text")),
+                   Err(HuffmanError::MalformedData("the stream holds text, \
+                                                    not ids")));
+    }
+
+    #[test]
+    fn decompression_limits() {
+        // A megabyte of 1-bit codes decodes to eight million characters.
+        let codebook   = HuffmanCodebook::from_text("ab");
+        let mut stream = header(&codebook, FLAG_TABLE);
+
+        stream.extend(vec![0; 1 << 20]);
+        stream.push(0);
+
+        let bomb = |limits| {
+            let mut output = vec![];
+            let result     = pipe_decompress_limited(&stream[..], &mut output,
+                                                     None, limits, None);
+            (result, output.len() as u64)
+        };
+        let just_over = |produced: u64, limit: u64| {
+            produced > limit && produced <= limit + 8 * READ_BUF_SIZE as u64
+        };
+        let (result, written) = bomb(DecodeLimits {
+            max_output_bytes: Some(100_000), ..Default::default()
+        });
+        assert!(matches!(result, Err(HuffmanError::OutputLimitExceeded {
+            produced, limit: 100_000
+        }) if just_over(produced, 100_000)), "{:?}", result);
+        assert!(written <= 100_000);
+
+        let (result, written) = bomb(DecodeLimits {
+            max_output_symbols: Some(5000), ..Default::default()
+        });
+        assert!(matches!(result, Err(HuffmanError::OutputLimitExceeded {
+            produced, limit: 5000
+        }) if just_over(produced, 5000)), "{:?}", result);
+        assert!(written <= 5000);
+
+        // The whole output, and no more, is within the limits.
+        let (result, written) = bomb(DecodeLimits {
+            max_output_bytes   : Some(8 << 20),
+            max_output_symbols : Some(8 << 20),
+        });
+        assert_eq!(result.map(|stats| stats.symbols), Ok(8 << 20));
+        assert_eq!(written, 8 << 20);
+
+        // Stored text is limited too.
+        let stored = compress_auto("日本語のテキスト");
+        let limits = DecodeLimits {
+            max_output_bytes: Some(20), ..Default::default()
+        };
+        assert_eq!(stored[5], FLAG_STORED);
+        assert_eq!(pipe_decompress_limited(&stored[..], io::sink(), None,
+                                           limits, None).err(),
+                   Some(HuffmanError::OutputLimitExceeded {
+                       produced: 24, limit: 20
+                   }));
+    }
+
+    /// An endless stream of `byte`, which tells `ready` when it's first read
+    /// and then waits for `resume` before going on.
+    /// 
+    struct Endless {
+        byte   : u8,
+        ready  : Option<mpsc::Sender<()>>,
+        resume : mpsc::Receiver<()>,
+    }
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if let Some(ready) = self.ready.take() {
+                let _ = ready.send(());
+                let _ = self.resume.recv();
+            }
+            buf.fill(self.byte);
+            Ok(buf.len())
+        }
+    }
+
+    /// Runs `run` on an `Endless` stream of `byte`, cancelling it from another
+    /// thread once it's under way, and returns the error it stops with.
+    /// 
+    fn cancelled<F>(byte: u8, run: F) -> Option<HuffmanError>
+    where
+        F: FnOnce(Endless, &CancelToken)
+            -> Result<CompressionStats, HuffmanError>,
+    {
+        let token             = CancelToken::new();
+        let (ready, on_ready) = mpsc::channel();
+        let (resumed, resume) = mpsc::channel();
+        let endless           = Endless { byte, ready: Some(ready), resume };
+
+        let canceller         = token.clone();
+
+        thread::spawn(move || {
+            on_ready.recv().unwrap();
+            canceller.cancel();
+            resumed.send(()).unwrap();
+        });
+        run(endless, &token).err()
+    }
+
+    #[test]
+    fn cancelled_mid_stream() {
+        let codebook = HuffmanCodebook::from_text("ab");
+        let stored   = compress_auto("");
+        let coded    = header(&codebook, FLAG_TABLE);
+
+        assert_eq!(cancelled(b'a', |r, token| {
+            pipe_compress(r, io::sink(), TableSource::Static(&codebook),
+                          Some(token))
+        }), Some(HuffmanError::Cancelled));
+        assert_eq!(cancelled(b'a', |r, token| {
+            pipe_compress_strict(r, io::sink(), TableSource::Train,
+                                 Some(token))
+        }), Some(HuffmanError::Cancelled));
+        assert_eq!(cancelled(0, |r, token| {
+            pipe_decompress(coded[..].chain(r), io::sink(), None,
+                            Some(token))
+        }), Some(HuffmanError::Cancelled));
+        assert_eq!(cancelled(b'a', |r, token| {
+            pipe_decompress(stored[..].chain(r), io::sink(), None,
+                            Some(token))
+        }), Some(HuffmanError::Cancelled));
+    }
+
+    #[test]
+    fn named_id_streams() {
+        let ids   = [0, 2, 2, 1, 0, 2, 9];
+        let names = SymbolMap::from_pairs([(0, "Idle"), (1, "Running"),
+                                           (2, "Done"), (3, "Retired")])
+                        .unwrap();
+        let stream  = compress_ids_named(&ids, &names).unwrap();
+        let decoded = decompress_ids_named(&stream).unwrap();
+
+        assert_eq!(stream[5], FLAG_TABLE | FLAG_IDS | FLAG_NAMES);
+        assert_eq!(decoded.ids, ids);
+        assert_eq!(decoded.names.as_ref(), Some(&names));
+        assert_eq!(decoded.decoded_symbol_name(1), Some("Running"));
+        assert_eq!(decoded.decoded_symbol_name(3), Some("Retired"));
+        assert_eq!(decoded.decoded_symbol_name(9), None);
+        assert_eq!(decompress_ids(&stream).as_deref(), Ok(&ids[..]));
+        assert_eq!(pipe_decompress(&stream[..], io::sink(), None, None),
+                   Err(HuffmanError::MalformedData("the stream holds ids, \
+                                                    not text")));
+
+        // Without the section there are no names, even for the same ids.
+        let plain = decompress_ids_named(&compress_ids(&ids).unwrap())
+                        .unwrap();
+
+        assert_eq!(plain, DecodedIds { ids: ids.to_vec(), names: None });
+        assert_eq!(plain.decoded_symbol_name(0), None);
+
+        // An empty map is still a section.
+        let stream = compress_ids_named(&[], &SymbolMap::new()).unwrap();
+
+        assert_eq!(decompress_ids_named(&stream).unwrap().names,
+                   Some(SymbolMap::new()));
+
+        // A stream naming an id twice is refused.
+        let mut stream = compress_ids_named(&ids, &names).unwrap();
+        let table_len  = u32::from_le_bytes(stream[6..10].try_into().unwrap());
+        let first_id   = 10 + table_len as usize + 4 + 4;
+
+        stream[first_id..first_id + 4].copy_from_slice(&1_u32.to_le_bytes());
+
+        assert_eq!(decompress_ids_named(&stream),
+                   Err(HuffmanError::DuplicateId(1)));
+    }
+
+    #[test]
+    fn automatic_modes() {
+        let round_trip = |text: &str| {
+            let stream = compress_auto(text);
+
+            assert_eq!(decompress_auto(&stream).unwrap(), text);
+            (stream[5], stream.len())
+        };
+        assert!(round_trip("hi, bobby!").1 <= 10 + MAGIC.len() + 2);
+        assert_eq!(round_trip("\x01\x02\x03\x04\x05\x06\x07\x08\x0b\x0c"),
+                   (FLAG_STORED, 10 + MAGIC.len() + 2));
+        assert_eq!(round_trip("").0, FLAG_STORED);
+        assert_eq!(round_trip("日本語のテキスト").0, FLAG_STORED);
+        assert_eq!(round_trip(&"a".repeat(30)).0, FLAG_ENGLISH);
+        assert_eq!(round_trip("It was the best of times, it was the worst \
+                               of times, it was the age of wisdom.").0,
+                   FLAG_ENGLISH);
+
+        let freqs = FrequencyTable::from_text("abracadabra, alakazam!");
+        let large = freqs.sample(100_000, 3);
+        let (flags, len) = round_trip(&large);
+
+        assert_eq!(flags, FLAG_TABLE);
+        assert!(len < large.len() / 2);
+
+        let mut stats = CompressionStats::default();
+        let stream    = compress_auto("déjà vu");
+
+        stats.symbols      = 7;
+        stats.text_bytes   = 9;
+        stats.stream_bytes = stream.len() as u64;
+
+        assert_eq!(pipe_decompress(&stream[..], io::sink(), None, None)
+                       .map(CompressionStats::sizes),
+                   Ok(stats));
+        assert_eq!(decompress_auto(&[&stream[..], b"\xff"].concat()),
+                   Err(HuffmanError::InvalidUtf8));
+        assert_eq!(decompress_auto(b"HUFF\x01\x03"),
+                   Err(HuffmanError::MalformedData("unknown option flags")));
+    }
+}
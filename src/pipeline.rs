@@ -0,0 +1,449 @@
+//! Compressing on a pool of threads. The input is read in blocks, which worker
+//! threads encode in parallel while a writer thread joins their bits up in
+//! order, so reading, encoding, and writing all overlap. The output is the
+//! same stream `pipe_compress()` writes.
+//! 
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Instant;
+
+use crate::bits::BitWriter;
+use crate::HuffmanError;
+use crate::codebook::{FrequencyTable, HuffmanCodebook};
+use crate::container::{pipe_compress, CompressionStats, TableSource};
+use crate::io::for_each_str_chunk;
+use crate::pipe::{header, tail, FLAG_TABLE, MAGIC};
+use crate::median::codebook_medians;
+use crate::summary::Lookup;
+
+
+/// Compresses text with a pool of encoder threads and a writer thread.
+/// 
+/// At most two blocks per worker are in flight at once, counting blocks read
+/// but not yet encoded and blocks encoded but waiting on an earlier one to be
+/// written, so memory use is bounded however far the reader gets ahead.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineCompressor {
+    /// The number of encoder threads. Defaults to the available parallelism.
+    pub workers    : usize,
+    /// The size of the blocks the input is read in, in bytes. A block is
+    /// extended to end on a character boundary.
+    pub block_size : usize,
+}
+
+impl Default for PipelineCompressor {
+    fn default() -> Self {
+        Self {
+            workers    : thread::available_parallelism()
+                             .map_or(1, NonZeroUsize::get),
+            block_size : 1 << 20,
+        }
+    }
+}
+
+/// A block's packed codes.
+/// 
+struct Block {
+    bytes     : Vec<u8>,
+    bits      : u64,
+    predicted : u64,
+    symbols   : u64,
+    len       : u64,
+}
+
+type Encoded = (usize, Result<Block, HuffmanError>);
+
+impl PipelineCompressor {
+    /// Compresses the UTF-8 text read from `r`, writing the compressed stream
+    /// to `w`, as `pipe_compress()` does. Training counts the blocks in
+    /// parallel too. Adaptive codes depend on all the text before them, so
+    /// a `TableSource::Adaptive` input is compressed by `pipe_compress()` on
+    /// this thread. A panic in any thread is reported as
+    /// `HuffmanError::WorkerPanicked`.
+    /// 
+    /// # Panics
+    /// 
+    /// If `workers` or `block_size` is 0.
+    /// 
+    pub fn compress(&self,
+                    mut r : impl Read,
+                    w     : impl Write + Send,
+                    table : TableSource) 
+
+        -> Result<CompressionStats, HuffmanError> 
+    {
+        assert!(self.workers > 0, "there must be at least one worker");
+        assert!(self.block_size > 0, "blocks must have room for data");
+
+        match table {
+            TableSource::Static(codebook) => self.run(r, w, codebook, 0),
+            TableSource::Adaptive         => pipe_compress(r, w, table, None),
+            TableSource::Train => {
+                let mut input = vec![];
+                let start     = Instant::now();
+
+                r.read_to_end(&mut input)?;
+
+                let text = std::str::from_utf8(&input)
+                               .map_err(|_| HuffmanError::InvalidUtf8)?;
+                let codebook   = self.train(text)?;
+                let count_time = start.elapsed();
+
+                let mut stats = self.run(text.as_bytes(), w, &codebook,
+                                         FLAG_TABLE)?;
+                stats.count_time = count_time;
+                stats.tree_build = Some(codebook.frequencies()
+                                                .summary()
+                                                .tree_build());
+                Ok(stats)
+            },
+        }
+    }
+    /// Builds codes for `text`, counting its blocks on the worker threads.
+    /// Fails as `HuffmanCodebook::try_from_frequencies()` does.
+    /// 
+    fn train(&self, text: &str) -> Result<HuffmanCodebook, HuffmanError> {
+        let mut blocks = vec![];
+        let mut rest   = text;
+
+        while !rest.is_empty() {
+            let mut end = self.block_size.min(rest.len());
+
+            while !rest.is_char_boundary(end) {
+                end += 1;
+            }
+            let (block, tail) = rest.split_at(end);
+
+            blocks.push(block);
+            rest = tail;
+        }
+        let blocks = &blocks;
+        let counts = thread::scope(|s| {
+            let handles = (0..self.workers).map(|i| s.spawn(move || {
+                FrequencyTable::from_chars(blocks.iter()
+                                                 .skip(i)
+                                                 .step_by(self.workers)
+                                                 .flat_map(|b| b.chars()))
+            })).collect::<Vec<_>>();
+
+            handles.into_iter()
+                   .map(|h| h.join().map_err(|_| HuffmanError::WorkerPanicked))
+                   .collect::<Result<Vec<_>, _>>()
+        })?;
+        let mut freqs = FrequencyTable::default();
+
+        for table in counts {
+            for (&c, &count) in table.counts() {
+                freqs.add(c, count);
+            }
+        }
+        HuffmanCodebook::try_from_frequencies(freqs)
+    }
+    /// Writes the header, then the blocks of `r` encoded with `codebook`, then
+    /// the tail.
+    /// 
+    fn run(&self,
+           r        : impl Read,
+           mut w    : impl Write + Send,
+           codebook : &HuffmanCodebook,
+           flags    : u8) 
+
+        -> Result<CompressionStats, HuffmanError> 
+    {
+        let start  = Instant::now();
+        let header = header(codebook, flags);
+        let lookup = Lookup::new(codebook.codes());
+
+        w.write_all(&header)?;
+
+        let medians = codebook_medians(codebook);
+        let stats   = CompressionStats {
+            stream_bytes       : header.len() as u64,
+            table_bytes        : (header.len() - MAGIC.len() - 2) as u64,
+            code_lookup        : Some(lookup.kind()),
+            median_code_length : medians.0,
+            median_frequency   : medians.1,
+            ..Default::default()
+        };
+        // The reader takes a credit for each block it reads, and the writer
+        // hands it back once the block is written, which bounds the blocks in
+        // flight. The queues are as deep as there are credits, so only the
+        // reader ever waits on a full one.
+        let in_flight = self.workers * 2;
+
+        let (job_tx, job_rx)       = mpsc::sync_channel(in_flight);
+        let (done_tx, done_rx)     = mpsc::sync_channel(in_flight);
+        let (credit_tx, credit_rx) = mpsc::sync_channel(in_flight);
+
+        for _ in 0..in_flight {
+            credit_tx.send(()).unwrap();
+        }
+        let job_rx = Mutex::new(job_rx);
+
+        let (read, written) = thread::scope(|s| {
+            let workers = (0..self.workers).map(|_| {
+                let done_tx = done_tx.clone();
+                let job_rx  = &job_rx;
+                let lookup  = &lookup;
+
+                s.spawn(move || encode_blocks(lookup, job_rx, done_tx))
+            }).collect::<Vec<_>>();
+
+            drop(done_tx);
+
+            let writer = s.spawn(move || {
+                write_blocks(w, done_rx, credit_tx, stats)
+            });
+            let read = read_blocks(r, self.block_size, job_tx, credit_rx);
+
+            for worker in workers {
+                worker.join().map_err(|_| HuffmanError::WorkerPanicked)?;
+            }
+            let written = writer.join()
+                                .map_err(|_| HuffmanError::WorkerPanicked)?;
+            Ok::<_, HuffmanError>((read, written))
+        })?;
+        // An error in the writer stops the reader, so it comes first.
+        let (mut w, mut writer, mut stats) = written?;
+
+        read?;
+        stats.payload_bits = writer.bit_len();
+
+        let tail = tail(&mut writer);
+
+        w.write_all(&tail)?;
+        w.flush()?;
+
+        stats.stream_bytes += tail.len() as u64;
+        stats.code_time     = start.elapsed();
+        Ok(stats)
+    }
+}
+
+/// Reads `r` in blocks of about `block_size` bytes and queues them, taking a
+/// credit for each. Stops early, without an error, if the writer stops
+/// handing credits back.
+/// 
+fn read_blocks(r          : impl Read,
+               block_size : usize,
+               jobs       : SyncSender<(usize, String)>,
+               credits    : Receiver<()>) 
+
+    -> Result<(), HuffmanError> 
+{
+    let mut block = String::new();
+    let mut index = 0;
+
+    let mut send = |block: String| {
+        if credits.recv().is_err() || jobs.send((index, block)).is_err() {
+            return Err(io::Error::other(HuffmanError::Cancelled));
+        }
+        index += 1;
+        Ok(())
+    };
+    let result = for_each_str_chunk(r, None, |chunk| {
+        block.push_str(chunk);
+
+        if block.len() >= block_size {
+            send(std::mem::take(&mut block))?;
+        }
+        Ok(())
+    }).and_then(|()| match block.is_empty() {
+        true  => Ok(()),
+        false => send(block),
+    });
+    match result.map_err(HuffmanError::from) {
+        Err(HuffmanError::Cancelled) => Ok(()),
+        result                       => result,
+    }
+}
+
+/// Encodes blocks from `jobs` until it's empty and closed, or the writer has
+/// stopped. A panic while encoding is sent on as an error for the block.
+/// 
+fn encode_blocks(lookup : &Lookup,
+                 jobs   : &Mutex<Receiver<(usize, String)>>,
+                 done   : SyncSender<Encoded>)
+{
+    loop {
+        let job = jobs.lock().unwrap().recv();
+
+        let Ok((index, text)) = job else {
+            return;
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            encode_block(lookup, &text)
+        })).unwrap_or(Err(HuffmanError::WorkerPanicked));
+
+        if done.send((index, result)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Packs the codes for `text`. Positions in errors are from the start of the
+/// block.
+/// 
+fn encode_block(lookup: &Lookup, text: &str) 
+
+    -> Result<Block, HuffmanError> 
+{
+    let mut writer    = BitWriter::new();
+    let mut symbols   = 0;
+    let mut predicted = 0;
+
+    for (byte_offset, c) in text.char_indices() {
+        let code = lookup.get(c).ok_or(
+            HuffmanError::SymbolNotInCodebook {
+                symbol: c, char_index: symbols, byte_offset
+            }
+        )?;
+        writer.write_code(code);
+        symbols   += 1;
+        predicted += code.len as u64;
+    }
+    Ok(Block {
+        bits      : writer.bit_len(),
+        bytes     : writer.finish(),
+        predicted,
+        symbols   : symbols as u64,
+        len       : text.len() as u64,
+    })
+}
+
+/// Writes encoded blocks in order as they arrive, handing back a credit for
+/// each. Returns the writer, the bits of the last partial byte, and the
+/// stats so far.
+/// 
+fn write_blocks<W: Write>(mut w     : W,
+                          done      : Receiver<Encoded>,
+                          credits   : SyncSender<()>,
+                          mut stats : CompressionStats) 
+
+    -> Result<(W, BitWriter, CompressionStats), HuffmanError> 
+{
+    let mut pending = HashMap::new();
+    let mut writer  = BitWriter::new();
+    let mut next    = 0;
+
+    for (index, result) in done {
+        pending.insert(index, result);
+
+        while let Some(result) = pending.remove(&next) {
+            let block = result.map_err(|e| match e {
+                HuffmanError::SymbolNotInCodebook {
+                    symbol, char_index, byte_offset
+                } => HuffmanError::SymbolNotInCodebook {
+                    symbol,
+                    char_index  : stats.symbols as usize + char_index,
+                    byte_offset : stats.text_bytes as usize + byte_offset,
+                },
+                e => e,
+            })?;
+            writer.write_packed(&block.bytes, block.bits);
+            stats.symbols        += block.symbols;
+            stats.text_bytes     += block.len;
+            stats.predicted_bits += block.predicted;
+
+            let bytes = writer.take_complete();
+
+            stats.stream_bytes += bytes.len() as u64;
+            w.write_all(&bytes)?;
+
+            // The reader may be done, and have no use for more credits.
+            let _ = credits.send(());
+            next += 1;
+        }
+    }
+    Ok((w, writer, stats))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::pipe_compress;
+
+    fn corpus(len: usize) -> String {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        FrequencyTable::from_text(&format!("{text}ĳ€𝄞")).sample(len, 11)
+    }
+
+    #[test]
+    fn same_output_as_pipe_compress() {
+        let text = corpus(2_000_000);
+        let mut expected = vec![];
+        let stats = pipe_compress(text.as_bytes(), &mut expected,
+                                  TableSource::Train, None).unwrap();
+
+        for (workers, block_size) in [(1, 1 << 20), (3, 65536), (8, 4099)] {
+            let pipeline = PipelineCompressor { workers, block_size };
+            let mut output = vec![];
+
+            let piped = pipeline.compress(text.as_bytes(), &mut output,
+                                          TableSource::Train).unwrap();
+
+            assert_eq!(piped.sizes(), stats.sizes());
+            assert_eq!(piped.predicted_bits, piped.payload_bits);
+            assert_eq!(output, expected);
+        }
+        let codebook = HuffmanCodebook::from_text(&text);
+        let pipeline = PipelineCompressor { block_size: 100_000,
+                                            ..Default::default() };
+        let mut expected = vec![];
+        let mut output   = vec![];
+
+        let stats = pipe_compress(text.as_bytes(), &mut expected,
+                                  TableSource::Static(&codebook), None)
+                        .unwrap();
+
+        assert_eq!(pipeline.compress(text.as_bytes(), &mut output,
+                                     TableSource::Static(&codebook))
+                           .map(CompressionStats::sizes),
+                   Ok(stats.sizes()));
+        assert_eq!(output, expected);
+
+        let mut expected = vec![];
+        let mut output   = vec![];
+
+        let stats = pipe_compress(text.as_bytes(), &mut expected,
+                                  TableSource::Adaptive, None).unwrap();
+
+        assert_eq!(pipeline.compress(text.as_bytes(), &mut output,
+                                     TableSource::Adaptive)
+                           .map(CompressionStats::sizes),
+                   Ok(stats.sizes()));
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn errors_match_pipe_compress() {
+        let mut text = corpus(300_000);
+        let codebook = HuffmanCodebook::from_text(&text);
+        let offset   = text.char_indices().nth(250_003).unwrap().0;
+
+        text.insert(offset, '\u{1}');
+
+        let error = pipe_compress(text.as_bytes(), io::sink(),
+                                  TableSource::Static(&codebook), None).err();
+        let pipeline = PipelineCompressor { workers: 4, block_size: 5000 };
+
+        assert!(matches!(error, Some(HuffmanError::SymbolNotInCodebook {
+            symbol: '\u{1}', ..
+        })));
+        assert_eq!(pipeline.compress(text.as_bytes(), io::sink(),
+                                     TableSource::Static(&codebook)).err(),
+                   error);
+        assert_eq!(pipeline.compress(&b"ab\xffcd"[..], io::sink(),
+                                     TableSource::Static(&codebook)).err(),
+                   Some(HuffmanError::InvalidUtf8));
+    }
+}
@@ -0,0 +1,30 @@
+//! The handful of items most uses of the crate need, to import in one go:
+//! 
+//! ```
+//! use huffman::prelude::*;
+//! 
+//! let codebook = HuffmanBuilder::new().count("abracadabra").build().unwrap();
+//! let mut bits = BitWriter::new();
+//! 
+//! for c in "abracadabra".chars() {
+//!     bits.write_code(codebook.code_for(c).unwrap());
+//! }
+//! assert_eq!(bits.bit_len(), 23);
+//! ```
+//! 
+//! The prelude is versioned: `v1` is the first version, which this module
+//! re-exports. A later version may add items, or drop some, but `v1` keeps the
+//! ones it has, so code that imports `huffman::prelude::v1::*` goes on
+//! compiling.
+//! 
+
+pub use v1::*;
+
+/// The first version of the prelude.
+/// 
+pub mod v1 {
+    pub use crate::HuffmanError;
+    pub use crate::codebook::{Code, FrequencyTable, HuffmanBuilder};
+    pub use crate::codebook::HuffmanCodebook;
+    pub use crate::io::{BitBuffer, BitReader, BitWriter, PushDecoder};
+}
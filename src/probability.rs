@@ -0,0 +1,144 @@
+//! Building codes for symbols numbered 0 to n - 1 from their probabilities,
+//! such as a softmax distribution exported from a model.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{HuffmanError, MAX_SYMBOLS};
+use crate::codebook::{generate_huffman_codes_weighted, Code};
+
+/// The char standing in for symbol `id` in the char-based tree. Ids from the
+/// surrogate range on are moved past it.
+/// 
+pub(crate) fn id_to_char(id: usize) -> Option<char> {
+    match id {
+        0..0xd800 => char::from_u32(id as u32),
+        _         => char::from_u32(id as u32 + 0x800),
+    }
+}
+
+pub(crate) fn char_to_id(c: char) -> usize {
+    match c as usize {
+        n if n < 0xd800 => n,
+        n               => n - 0x800,
+    }
+}
+
+/// Generates codes for the symbols whose probabilities are `p`, indexed by
+/// symbol id. The codes come back in a `Vec` aligned with `p`; symbols with
+/// probability 0 get no code, and have the empty `Code::default()` in its
+/// place. A lone symbol with nonzero probability gets the code "0".
+/// 
+/// Fails with `HuffmanError::InvalidProbabilities` if an entry isn't finite
+/// or is negative, if the entries don't sum to 1 to within `tolerance`, if
+/// more than 32768 entries are nonzero, or if a code would be longer than 64
+/// bits.
+/// 
+pub fn codes_from_probabilities(p: &[f64], tolerance: f64) 
+
+    -> Result<Vec<Code>, HuffmanError> 
+{
+    let invalid = |index, reason| {
+        HuffmanError::InvalidProbabilities { index, reason }
+    };
+    if let Some(i) = p.iter().position(|x| !x.is_finite()) {
+        return Err(invalid(Some(i), "not a finite number"));
+    }
+    if let Some(i) = p.iter().position(|&x| x < 0.0) {
+        return Err(invalid(Some(i), "negative"));
+    }
+    if (p.iter().sum::<f64>() - 1.0).abs() > tolerance {
+        return Err(invalid(None, "they don't sum to 1"));
+    }
+    let weights = p.iter()
+                   .enumerate()
+                   .filter(|&(_, &x)| x > 0.0)
+                   .map(|(i, &x)| {
+                       id_to_char(i).map(|c| (c, x))
+                                    .ok_or(invalid(None, "too many symbols"))
+                   })
+                   .collect::<Result<Vec<_>, _>>()?;
+
+    if weights.len() > MAX_SYMBOLS {
+        return Err(invalid(None, "too many symbols"));
+    }
+    let mut codes = vec![Code::default(); p.len()];
+
+    if let [(c, _)] = weights[..] {
+        codes[char_to_id(c)] = Code { bits: 0, len: 1 };
+        return Ok(codes);
+    }
+    for (c, code) in generate_huffman_codes_weighted(weights) {
+        let id = char_to_id(c);
+
+        codes[id] = Code::from_str_binary(&code).ok_or(
+            invalid(Some(id), "the code would be longer than 64 bits")
+        )?;
+    }
+    Ok(codes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dyadic_distribution() {
+        let p     = [0.25, 0.0, 0.5, 0.0625, 0.125, 0.0625];
+        let codes = codes_from_probabilities(&p, 1e-9).unwrap();
+        let lens  = codes.iter().map(|code| code.len).collect::<Vec<_>>();
+
+        assert_eq!(lens, [2, 0, 1, 4, 3, 4]);
+
+        // The codes are prefix-free.
+        let table = codes.iter()
+                         .enumerate()
+                         .filter(|(_, code)| code.len > 0)
+                         .map(|(i, code)| {
+                             (id_to_char(i).unwrap(), code.bits, code.len)
+                         })
+                         .collect::<Vec<_>>();
+
+        assert!(crate::codebook::HuffmanCodebook::from_table(&table).is_ok());
+
+        let codes = codes_from_probabilities(&[0.0, 1.0], 0.0).unwrap();
+
+        assert_eq!(codes, [Code::default(), Code { bits: 0, len: 1 }]);
+        assert_eq!(codes_from_probabilities(&[], 1.0).unwrap(), []);
+    }
+
+    #[test]
+    fn invalid_distributions() {
+        let error = |p: &[f64], tolerance| {
+            match codes_from_probabilities(p, tolerance) {
+                Err(HuffmanError::InvalidProbabilities { index, reason }) => {
+                    (index, reason)
+                },
+                other => panic!("unexpected result {:?}", other),
+            }
+        };
+        assert_eq!(error(&[0.5, 0.4], 0.01), (None, "they don't sum to 1"));
+        assert_eq!(error(&[0.5, f64::NAN], 0.01),
+                   (Some(1), "not a finite number"));
+        assert_eq!(error(&[1.5, -0.5], 0.01), (Some(1), "negative"));
+        assert!(codes_from_probabilities(&[0.5, 0.4], 0.2).is_ok());
+    }
+
+    #[test]
+    fn large_ids() {
+        let mut p = vec![0.0; 0xe000];
+
+        p[0xd7ff] = 0.5;
+        p[0xd800] = 0.25;
+        p[0xdfff] = 0.25;
+
+        let codes = codes_from_probabilities(&p, 1e-9).unwrap();
+
+        assert_eq!(codes[0xd7ff].len, 1);
+        assert_eq!(codes[0xd800].len, 2);
+        assert_eq!(codes[0xdfff].len, 2);
+        assert_eq!(codes.iter().filter(|code| code.len > 0).count(), 3);
+    }
+}
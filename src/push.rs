@@ -0,0 +1,208 @@
+//! Decoding packed codes pushed in chunks of any size, for callers whose bytes
+//! arrive from somewhere that can't be wrapped in a `Read`.
+//! 
+
+use alloc::string::String;
+
+use crate::HuffmanError;
+use crate::codebook::HuffmanCodebook;
+use crate::io::{DecoderFsm, Transition};
+
+
+/// Decodes packed codes, most significant bit first, from bytes pushed to it.
+/// A code may straddle any number of pushes. Since only the caller knows how
+/// much of the last byte is padding, the latest byte is held back until more
+/// arrive or `finish()` is called.
+/// 
+#[derive(Clone, Debug)]
+pub struct PushDecoder {
+    fsm     : DecoderFsm,
+    state   : u32,
+    start   : u64,
+    bits    : u64,
+    symbols : u64,
+    held    : Option<u8>,
+    output  : String,
+}
+
+impl PushDecoder {
+    /// A decoder for the codes of `codebook`. Fails if the codebook has an
+    /// empty code.
+    /// 
+    pub fn new(codebook: &HuffmanCodebook) -> Result<Self, HuffmanError> {
+        Ok(Self {
+            fsm     : DecoderFsm::from_codebook(codebook)?,
+            state   : 0,
+            start   : 0,
+            bits    : 0,
+            symbols : 0,
+            held    : None,
+            output  : String::new(),
+        })
+    }
+    /// Decodes `bytes`, after any held back from before. Fails with
+    /// `HuffmanError::InvalidCode` on bits that aren't the start of any code.
+    /// 
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), HuffmanError> {
+        for &byte in bytes {
+            if let Some(held) = self.held.replace(byte) {
+                self.decode_byte(held, 8)?;
+            }
+        }
+        Ok(())
+    }
+    /// Takes the symbols decoded so far.
+    /// 
+    pub fn drain_output(&mut self) -> impl Iterator<Item = char> + '_ {
+        self.output.drain(..)
+    }
+    /// Ends the stream, decoding the first `valid_bits_in_last_byte` bits of
+    /// the byte held back; the rest are padding. The last symbols are then
+    /// ready to drain. Fails as `skip_to_byte_boundary()` does.
+    /// 
+    pub fn finish(&mut self, valid_bits_in_last_byte: u8) 
+
+        -> Result<(), HuffmanError> 
+    {
+        self.skip_to_byte_boundary(valid_bits_in_last_byte)
+    }
+    /// Ends a record written with `BitWriter::align_to_byte()`, decoding the
+    /// first `valid_bits_in_last_byte` bits of the byte held back and skipping
+    /// the padding after them. Decoding carries on with the next byte pushed.
+    /// 
+    /// Fails with `HuffmanError::TruncatedBitstream` if the record ends
+    /// partway through a code, and `HuffmanError::MalformedData` if the count
+    /// isn't 1 to 8, or 0 when no byte is held back.
+    /// 
+    pub fn skip_to_byte_boundary(&mut self, valid_bits_in_last_byte: u8) 
+
+        -> Result<(), HuffmanError> 
+    {
+        match (self.held.take(), valid_bits_in_last_byte) {
+            (None, 0)           => {},
+            (Some(byte), 1..=8) => {
+                self.decode_byte(byte, valid_bits_in_last_byte as u32)?;
+            },
+            _ => return Err(HuffmanError::MalformedData("bad padding count")),
+        }
+        if self.state != 0 {
+            return Err(HuffmanError::TruncatedBitstream {
+                bit_offset : self.start as usize,
+                symbols    : self.symbols as usize,
+            });
+        }
+        Ok(())
+    }
+    /// The number of symbols decoded.
+    /// 
+    pub fn symbols(&self) -> u64 {
+        self.symbols
+    }
+    /// The number of bits decoded, not counting the byte held back.
+    /// 
+    pub fn bit_len(&self) -> u64 {
+        self.bits
+    }
+    /// Decodes the first `nbits` bits of `byte`.
+    /// 
+    fn decode_byte(&mut self, byte: u8, nbits: u32) 
+
+        -> Result<(), HuffmanError> 
+    {
+        for i in 0..nbits {
+            let bit = byte >> (7 - i) & 1 == 1;
+
+            self.bits += 1;
+
+            match self.fsm.step(self.state, bit) {
+                Transition::Next(next) => self.state = next,
+                Transition::Emit(c)    => {
+                    self.output.push(c);
+                    self.state    = 0;
+                    self.start    = self.bits;
+                    self.symbols += 1;
+                },
+                Transition::Invalid    => {
+                    return Err(HuffmanError::InvalidCode {
+                        bit_offset : self.start as usize,
+                        symbols    : self.symbols as usize,
+                    });
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::BitWriter;
+
+    #[test]
+    fn chunk_sizes() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let mut writer = BitWriter::new();
+
+        for c in text.chars() {
+            writer.write_code(codebook.code_for(c).unwrap());
+        }
+        let nbits = writer.bit_len();
+        let bytes = writer.finish();
+        let valid = (nbits - 1) % 8 + 1;
+
+        for size in [1, 7, 4096] {
+            let mut decoder = PushDecoder::new(&codebook).unwrap();
+            let mut decoded = String::new();
+
+            for chunk in bytes.chunks(size) {
+                decoder.push(chunk).unwrap();
+                decoded.extend(decoder.drain_output());
+            }
+            decoder.finish(valid as u8).unwrap();
+            decoded.extend(decoder.drain_output());
+
+            assert_eq!(decoded, text);
+            assert_eq!(decoder.symbols(), text.chars().count() as u64);
+            assert_eq!(decoder.bit_len(), nbits);
+        }
+    }
+
+    #[test]
+    fn bad_endings() {
+        static CODES: &[(char, u64, u8)] = &[('a', 0b0,  1), ('b', 0b10, 2),
+                                             ('c', 0b11, 2)];
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+        let finish   = |bytes: &[u8], valid| {
+            let mut decoder = PushDecoder::new(&codebook).unwrap();
+
+            decoder.push(bytes)?;
+            decoder.finish(valid)
+        };
+
+        assert_eq!(finish(&[0b0101_1000], 5), Ok(()));
+        assert_eq!(finish(&[0b0101_1000], 6), Ok(()));
+        assert_eq!(finish(&[0b0101_1000], 4),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: 3, symbols: 2
+                   }));
+        assert_eq!(finish(&[], 0), Ok(()));
+        assert_eq!(finish(&[], 8),
+                   Err(HuffmanError::MalformedData("bad padding count")));
+        assert_eq!(finish(&[0], 0),
+                   Err(HuffmanError::MalformedData("bad padding count")));
+        assert_eq!(finish(&[0], 9),
+                   Err(HuffmanError::MalformedData("bad padding count")));
+
+        let mut decoder = PushDecoder::new(&codebook).unwrap();
+
+        decoder.push(&[0b0110_0000]).unwrap();
+        decoder.skip_to_byte_boundary(3).unwrap();
+        decoder.push(&[0b1110_0000]).unwrap();
+        decoder.finish(4).unwrap();
+
+        assert_eq!(decoder.drain_output().collect::<String>(), "accb");
+    }
+}
@@ -0,0 +1,573 @@
+//! Decoding packed codes as they're read, for streams too large to hold in
+//! memory whole.
+//! 
+
+use std::io::{self, Read};
+
+use crate::{HuffmanError, Node};
+use crate::container::DecodeLimits;
+use crate::io::{DecodeTable, READ_BUF_SIZE};
+use crate::tree::HuffmanTree;
+
+
+/// What a `HuffmanReader` decodes with.
+/// 
+enum Decoder<'a> {
+    Tree(&'a HuffmanTree),
+    Table(&'a DecodeTable),
+}
+
+
+/// Decodes the packed codes read from an inner reader by walking a
+/// `HuffmanTree`, or looking them up in a `DecodeTable`, which is faster,
+/// yielding each character as its code is complete. Codes may
+/// straddle reads of any size. The stream's length in bits is given up front,
+/// so the padding after it is skipped, and no byte past the last it takes up is
+/// read, leaving the inner reader at whatever follows.
+/// 
+/// Fails with `HuffmanError::TruncatedBitstream` if the inner reader ends
+/// before the bits do, or the bits end partway through a code, and with
+/// `HuffmanError::InvalidCode` on a bit the tree has no branch for. I/O
+/// errors are passed on as `HuffmanError::Io`. Errors give the offset where
+/// the code started. Nothing more is yielded after an error.
+/// 
+/// A reader given `DecodeLimits` by `with_limits()` yields
+/// `HuffmanError::OutputLimitExceeded` in place of the first character over
+/// a limit.
+/// 
+pub struct HuffmanReader<'a, R: Read> {
+    inner   : R,
+    decoder : Decoder<'a>,
+    buf     : Box<[u8]>,
+    /// The range of `buf` yet to be decoded.
+    next    : usize,
+    end     : usize,
+    /// The bytes read from `inner`.
+    read    : u64,
+    /// The bits after `pos` taken from `buf` for a `DecodeTable`, most
+    /// significant first, and how many there are.
+    window  : u128,
+    held    : u32,
+    bit_len : u64,
+    /// The bits decoded so far.
+    pos     : u64,
+    start   : u64,
+    symbols : usize,
+    done    : bool,
+    limits  : DecodeLimits,
+    /// The bytes of UTF-8 yielded so far.
+    bytes   : u64,
+}
+
+impl<'a, R: Read> HuffmanReader<'a, R> {
+    /// A reader of the `bit_len` bits of codes at the start of `inner`, to be
+    /// decoded with `tree`.
+    /// 
+    pub fn new(inner: R, tree: &'a HuffmanTree, bit_len: u64) -> Self {
+        Self::with_decoder(inner, Decoder::Tree(tree), bit_len)
+    }
+    /// Like `new()`, but decoding with the lookup tables of `table`.
+    /// 
+    pub fn with_table(inner: R, table: &'a DecodeTable, bit_len: u64) -> Self {
+        Self::with_decoder(inner, Decoder::Table(table), bit_len)
+    }
+    fn with_decoder(inner: R, decoder: Decoder<'a>, bit_len: u64) -> Self {
+        Self {
+            inner,
+            decoder,
+            buf     : vec![0; READ_BUF_SIZE].into_boxed_slice(),
+            next    : 0,
+            end     : 0,
+            read    : 0,
+            window  : 0,
+            held    : 0,
+            bit_len,
+            pos     : 0,
+            start   : 0,
+            symbols : 0,
+            done    : false,
+            limits  : DecodeLimits::default(),
+            bytes   : 0,
+        }
+    }
+    /// Stops decoding with `HuffmanError::OutputLimitExceeded` rather than
+    /// yield output over `limits`.
+    /// 
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// The inner reader. Once every character has been yielded, it's at the
+    /// byte after the stream's last.
+    /// 
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+    /// The number of characters decoded.
+    /// 
+    pub fn symbols(&self) -> usize {
+        self.symbols
+    }
+    /// The number of bits decoded or skipped so far.
+    /// 
+    pub fn bits_read(&self) -> u64 {
+        self.pos
+    }
+    /// Skips the padding after a record written with
+    /// `HuffmanWriter::align_to_byte()`: the bits up to the start of the next
+    /// byte, unless already at the start of one, and never past the end of
+    /// the stream. Returns the number of bits skipped. Decoding carries on
+    /// with the next record.
+    /// 
+    pub fn skip_to_byte_boundary(&mut self) -> u8 {
+        let skip = ((8 - self.pos % 8) % 8).min(self.bit_len - self.pos);
+
+        match self.decoder {
+            // The byte being read is `buf[next]` until its last bit is.
+            Decoder::Tree(_) => {
+                if skip > 0 && (self.pos + skip).is_multiple_of(8) {
+                    self.next += 1;
+                }
+            },
+            // The window holds the rest of the byte being read.
+            Decoder::Table(_) => {
+                self.window <<= skip;
+                self.held    -= skip as u32;
+            },
+        }
+        self.pos += skip;
+        skip as u8
+    }
+    /// Decodes the rest of the stream into `out`, which is cleared first,
+    /// returning the number of characters decoded. Its capacity is kept and
+    /// grown as needed, never shrunk, so a buffer reused across readers stops
+    /// allocating once it's big enough. On error, `out` holds the characters
+    /// decoded before it.
+    /// 
+    pub fn read_to_string_reuse(&mut self, out: &mut String) 
+
+        -> Result<usize, HuffmanError> 
+    {
+        let start = self.symbols;
+
+        out.clear();
+
+        for c in self.by_ref() {
+            out.push(c?);
+        }
+        Ok(self.symbols - start)
+    }
+    /// Reads more of the stream into `buf`, up to its last byte. Gives false
+    /// if the inner reader has ended.
+    /// 
+    fn fill(&mut self) -> Result<bool, HuffmanError> {
+        let wanted = (self.bit_len.div_ceil(8) - self.read)
+                         .min(self.buf.len() as u64) as usize;
+        loop {
+            match self.inner.read(&mut self.buf[..wanted]) {
+                Ok(n) => {
+                    (self.next, self.end) = (0, n);
+                    self.read += n as u64;
+                    return Ok(n > 0);
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    /// The next bit of the stream, or `None` if the inner reader has ended.
+    /// 
+    fn read_bit(&mut self) -> Result<Option<bool>, HuffmanError> {
+        if self.next == self.end && !self.fill()? {
+            return Ok(None);
+        }
+        let bit = self.buf[self.next] >> (7 - self.pos % 8) & 1 == 1;
+
+        self.pos += 1;
+
+        if self.pos.is_multiple_of(8) {
+            self.next += 1;
+        }
+        Ok(Some(bit))
+    }
+    /// Decodes the next code.
+    /// 
+    fn read_char(&mut self) -> Result<Option<char>, HuffmanError> {
+        match self.decoder {
+            Decoder::Tree(tree)   => self.walk_tree(tree),
+            Decoder::Table(table) => self.look_up(table),
+        }
+    }
+    /// Walks the tree from its root along the next code.
+    /// 
+    fn walk_tree(&mut self, tree: &HuffmanTree) 
+
+        -> Result<Option<char>, HuffmanError> 
+    {
+        let mut node = tree.root;
+
+        self.start = self.pos;
+
+        while self.pos < self.bit_len {
+            let truncated = HuffmanError::TruncatedBitstream {
+                bit_offset : self.start as usize,
+                symbols    : self.symbols,
+            };
+            let invalid   = HuffmanError::InvalidCode {
+                bit_offset : self.start as usize,
+                symbols    : self.symbols,
+            };
+            let bit = self.read_bit()?.ok_or(truncated)?;
+
+            let Some(at) = node else {
+                return Err(invalid);
+            };
+            node = match (tree.nodes.h2node(at), bit) {
+                (Node::Branch { left, .. }, false) => Some(*left),
+                (Node::Branch { right, .. }, true) => Some(*right),
+                (Node::Leaf { .. }, false) if node == tree.root => node,
+                _ => return Err(invalid),
+            };
+            if let Some(at) = node
+                && let Node::Leaf { symbol, .. } = tree.nodes.h2node(at)
+            {
+                self.symbols += 1;
+                return Ok(Some(*symbol));
+            }
+        }
+        match self.pos == self.start {
+            true  => Ok(None),
+            false => Err(HuffmanError::TruncatedBitstream {
+                bit_offset : self.start as usize,
+                symbols    : self.symbols,
+            }),
+        }
+    }
+    /// Looks the next code up in the table, after topping up the window with
+    /// as much of the stream as it holds.
+    /// 
+    fn look_up(&mut self, table: &DecodeTable) 
+
+        -> Result<Option<char>, HuffmanError> 
+    {
+        self.start = self.pos;
+
+        if self.pos == self.bit_len {
+            return Ok(None);
+        }
+        while self.held <= 120 && self.pos + (self.held as u64) < self.bit_len {
+            if self.next == self.end && !self.fill()? {
+                break;
+            }
+            self.window |= (self.buf[self.next] as u128) << (120 - self.held);
+            self.held   += 8;
+            self.next   += 1;
+        }
+        let avail = (self.bit_len - self.pos).min(self.held as u64) as u32;
+
+        let (c, len) = table.lookup(self.window, avail).map_err(|miss| {
+            miss.error(self.start as usize, self.symbols)
+        })?;
+        self.window <<= len;
+        self.held    -= len;
+        self.pos     += len as u64;
+        self.symbols += 1;
+
+        Ok(Some(c))
+    }
+}
+
+impl<R: Read> Iterator for HuffmanReader<'_, R> {
+    type Item = Result<char, HuffmanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_char().transpose().map(|c| {
+            let c = c?;
+
+            self.bytes += c.len_utf8() as u64;
+            self.limits.check(self.symbols as u64, self.bytes)?;
+            Ok(c)
+        });
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::codebook::count_frequencies;
+    use crate::io::{BitBuffer, HuffmanWriter};
+    use crate::tree::{build_tree, HuffmanCodec};
+
+    /// Passes on at most a byte per read.
+    /// 
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn decode(tree: &HuffmanTree, bits: &BitBuffer, r: impl Read) 
+
+        -> Result<String, HuffmanError> 
+    {
+        HuffmanReader::new(r, tree, bits.bit_len() as u64).collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "é€🦀";
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+        let bits  = codec.encode(&text).unwrap();
+
+        // A trailer after the stream is left unread.
+        let mut stream = bits.as_bytes().to_vec();
+
+        stream.extend(b"trailer");
+
+        let mut r = HuffmanReader::new(Trickle(Cursor::new(stream)),
+                                       codec.tree(), bits.bit_len() as u64);
+
+        assert_eq!(r.by_ref().collect::<Result<String, _>>().unwrap(), text);
+        assert_eq!(r.symbols(), text.chars().count());
+
+        let mut rest = vec![];
+
+        r.into_inner().read_to_end(&mut rest).unwrap();
+
+        assert_eq!(rest, b"trailer");
+        assert_eq!(decode(codec.tree(), &bits, bits.as_bytes()).unwrap(),
+                   text);
+
+        for text in ["", "a", "aaaa", "ab"] {
+            let codec = HuffmanCodec::from_text(text).unwrap();
+            let bits  = codec.encode(text).unwrap();
+
+            assert_eq!(decode(codec.tree(), &bits, Trickle(bits.as_bytes())),
+                       Ok(text.to_string()));
+        }
+    }
+
+    #[test]
+    fn truncated_streams() {
+        let text  = "abracadabra";
+        let tree  = build_tree(&count_frequencies(text)).unwrap();
+        let codec = HuffmanCodec::from_text(text).unwrap();
+        let bits  = codec.encode(text).unwrap();
+        let a     = codec.code_for('a').unwrap().len();
+
+        // The inner reader ends two bytes early.
+        let cut = &bits.as_bytes()[..bits.as_bytes().len() - 2];
+
+        let mut r = HuffmanReader::new(cut, &tree, bits.bit_len() as u64);
+        let mut decoded = String::new();
+        let mut error   = None;
+
+        for c in r.by_ref() {
+            match c {
+                Ok(c)  => decoded.push(c),
+                Err(e) => error = Some(e),
+            }
+        }
+        assert!(text.starts_with(&decoded));
+        assert!(matches!(error, Some(HuffmanError::TruncatedBitstream {
+            symbols, ..
+        }) if symbols == decoded.chars().count()));
+        assert_eq!(r.next(), None);
+
+        // The bit count ends partway through the second code.
+        let short = HuffmanReader::new(bits.as_bytes(), &tree, a as u64 + 1);
+
+        assert_eq!(short.collect::<Result<String, _>>(),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: a, symbols: 1
+                   }));
+
+        // A tree with no leaves has no codes at all.
+        let empty = build_tree(&count_frequencies("")).unwrap();
+
+        assert_eq!(decode(&empty, &bits, bits.as_bytes()),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+    }
+
+    #[test]
+    fn table_matches_tree() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "é€🦀";
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+        let table = DecodeTable::from_codes(&codec.codes()).unwrap();
+        let bits  = codec.encode(&text).unwrap();
+
+        let mut stream = bits.as_bytes().to_vec();
+
+        stream.extend(b"trailer");
+
+        let mut r = HuffmanReader::with_table(Trickle(Cursor::new(stream)),
+                                              &table, bits.bit_len() as u64);
+
+        assert_eq!(r.by_ref().collect::<Result<String, _>>().unwrap(), text);
+
+        let mut rest = vec![];
+
+        r.into_inner().read_to_end(&mut rest).unwrap();
+
+        assert_eq!(rest, b"trailer");
+
+        // Streams cut short, by their bytes or their bit count, fail alike.
+        let bytes = &bits.as_bytes()[..64];
+
+        for bit_len in [0, 1, 7, 100, 509, 512, 513, 600] {
+            let tree  = HuffmanReader::new(bytes, codec.tree(), bit_len);
+            let table = HuffmanReader::with_table(Trickle(bytes), &table,
+                                                  bit_len);
+
+            assert_eq!(table.collect::<Vec<_>>(), tree.collect::<Vec<_>>(),
+                       "{} bits", bit_len);
+        }
+    }
+
+    #[test]
+    fn read_to_string_reuses_the_buffer() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+        let table = DecodeTable::from_codes(&codec.codes()).unwrap();
+        let words = text.split_whitespace().take(1000).collect::<Vec<_>>();
+
+        assert_eq!(words.len(), 1000);
+
+        let mut out = String::with_capacity(4096);
+
+        for word in words {
+            let bits  = codec.encode(word).unwrap();
+            let nbits = bits.bit_len() as u64;
+            let tree  = HuffmanReader::new(bits.as_bytes(), codec.tree(),
+                                           nbits);
+            let table = HuffmanReader::with_table(bits.as_bytes(), &table,
+                                                  nbits);
+
+            for mut r in [tree, table] {
+                assert_eq!(r.read_to_string_reuse(&mut out),
+                           Ok(word.chars().count()));
+                assert_eq!(out, word);
+                assert_eq!(out.capacity(), 4096);
+            }
+        }
+        // Cut short, the characters before the error are kept.
+        let bits  = codec.encode("call me ishmael").unwrap();
+        let mut r = HuffmanReader::new(&bits.as_bytes()[..2], codec.tree(),
+                                       bits.bit_len() as u64);
+
+        assert!(r.read_to_string_reuse(&mut out).is_err());
+        assert!(!out.is_empty() && "call me ishmael".starts_with(&out));
+    }
+
+    #[test]
+    fn aligned_records() {
+        let records  = ["call me ishmael", "some years ago—never mind",
+                        "how long precisely"];
+        let text     = records.concat();
+        let codebook = crate::codebook::HuffmanCodebook::from_text(&text);
+        let codec    = HuffmanCodec::from_text(&text).unwrap();
+        let table    = DecodeTable::from_codes(&codec.codes()).unwrap();
+        let mut w    = HuffmanWriter::new(vec![], &codebook);
+        let mut index = vec![];
+
+        for record in records {
+            let start = w.bits_written();
+
+            w.write_str(record).unwrap();
+
+            let len = w.bits_written() - start;
+            let pad = w.align_to_byte().unwrap();
+
+            assert_eq!(start % 8, 0);
+            assert_eq!((len + pad as u64) % 8, 0);
+            index.push(((start / 8) as usize, len));
+        }
+        assert_eq!(w.align_to_byte().unwrap(), 0);
+
+        let (bytes, _) = w.finish().unwrap();
+
+        // Each record decodes from its own byte offset, in any order.
+        for (&(offset, len), record) in index.iter().zip(records).rev() {
+            let tree  = HuffmanReader::new(&bytes[offset..], codec.tree(), len);
+            let table = HuffmanReader::with_table(Trickle(&bytes[offset..]),
+                                                  &table, len);
+
+            assert_eq!(tree.collect::<Result<String, _>>(), Ok(record.into()));
+            assert_eq!(table.collect::<Result<String, _>>(),
+                       Ok(record.into()));
+        }
+        // Or one after another from one reader, skipping the padding.
+        let nbits = bytes.len() as u64 * 8;
+        let tree  = HuffmanReader::new(Trickle(&bytes[..]), codec.tree(),
+                                       nbits);
+        let table = HuffmanReader::with_table(Trickle(&bytes[..]), &table,
+                                              nbits);
+
+        for mut r in [tree, table] {
+            for (&(offset, _), record) in index.iter().zip(records) {
+                assert_eq!(r.bits_read(), offset as u64 * 8);
+
+                let n = record.chars().count();
+
+                assert_eq!(r.by_ref().take(n).collect::<Result<String, _>>(),
+                           Ok(record.into()));
+                r.skip_to_byte_boundary();
+            }
+            assert_eq!(r.bits_read(), nbits);
+            assert_eq!(r.skip_to_byte_boundary(), 0);
+            assert_eq!(r.next(), None);
+        }
+    }
+
+    #[test]
+    fn output_limits() {
+        let tree  = build_tree(&count_frequencies("éa")).unwrap();
+        let zero  = tree.codes()[&'é'].starts_with('0');
+        let bytes = [if zero { 0 } else { 0xff }; 4];
+        let limit = |limits| {
+            HuffmanReader::new(&bytes[..], &tree, 32).with_limits(limits)
+                                                      .collect::<Vec<_>>()
+        };
+        let decoded = limit(DecodeLimits {
+            max_output_symbols: Some(10), ..Default::default()
+        });
+
+        assert_eq!(decoded.len(), 11);
+        assert!(decoded[..10].iter().all(|c| c == &Ok('é')));
+        assert_eq!(decoded[10], Err(HuffmanError::OutputLimitExceeded {
+            produced: 11, limit: 10
+        }));
+
+        // 'é' is two bytes, so the fourth goes over 7.
+        let decoded = limit(DecodeLimits {
+            max_output_bytes: Some(7), ..Default::default()
+        });
+
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded[3], Err(HuffmanError::OutputLimitExceeded {
+            produced: 8, limit: 7
+        }));
+        assert_eq!(limit(DecodeLimits {
+                       max_output_symbols : Some(32),
+                       max_output_bytes   : Some(64),
+                   }).len(), 32);
+        assert!(limit(DecodeLimits::default()).iter().all(Result::is_ok));
+    }
+}
@@ -0,0 +1,472 @@
+//! Codes for control symbols alongside the characters of the text, such as an
+//! end-of-stream marker. Control symbols are a namespace of their own, so they
+//! can't collide with any character the text might hold.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::probability::{char_to_id, id_to_char};
+use crate::{HuffmanError, MAX_SYMBOLS};
+use crate::canonical::CanonicalDecoder;
+use crate::codebook::{generate_huffman_codes_weighted, Code, FrequencyTable};
+use crate::codebook::TailStats;
+use crate::io::{BitReader, BitWriter};
+use crate::adaptive::ESCAPE_BITS;
+
+
+/// A control symbol, coded alongside the characters of the text.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ReservedId {
+    /// The end of the stream.
+    Eos,
+    /// A point where the decoder's state starts over.
+    Reset,
+    /// Filler with no meaning.
+    Pad,
+    /// A character the codes don't cover, which follows by other means.
+    Escape,
+    /// Free for the application to use.
+    Application1,
+    Application2,
+    Application3,
+    Application4,
+}
+
+/// A coded symbol: a character of text, or a control symbol.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Token {
+    Char(char),
+    Reserved(ReservedId),
+}
+
+
+/// Canonical codes for the characters of a text together with a set of
+/// control symbols, which always have codes, however rarely they're used.
+/// 
+#[derive(Clone, Debug)]
+pub struct TokenCodebook {
+    codes   : HashMap<Token, Code>,
+    decoder : CanonicalDecoder<Token>,
+}
+
+impl TokenCodebook {
+    /// Generates codes for the characters counted in `freqs` and the control
+    /// symbols in `reserved`, each of which is weighted as if it had been
+    /// counted that many times. A control symbol with weight 0 still gets a
+    /// code, as long as any other.
+    /// 
+    /// Fails with `HuffmanError::MalformedData` if a control symbol is listed
+    /// twice, and `HuffmanError::TooManySymbols` if there are more symbols in
+    /// all than a tree can hold.
+    /// 
+    pub fn new(freqs: &FrequencyTable, reserved: &[(ReservedId, u64)]) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let mut chars = freqs.counts().iter().collect::<Vec<_>>();
+
+        chars.sort_unstable();
+
+        // The tree is built over chars, so each symbol stands in as the char
+        // for its index: control symbols first, then the text's characters.
+        let tokens = reserved.iter()
+                             .map(|&(id, _)| Token::Reserved(id))
+                             .chain(chars.iter().map(|&(&c, _)| Token::Char(c)))
+                             .collect::<Vec<_>>();
+        let weights = reserved.iter()
+                              .map(|&(_, w)| w)
+                              .chain(chars.iter().map(|&(_, &n)| n as u64));
+
+        let mut sorted = tokens.clone();
+
+        sorted.sort_unstable();
+
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(HuffmanError::MalformedData("a reserved symbol appears \
+                                                    twice"));
+        }
+        if tokens.len() > MAX_SYMBOLS {
+            return Err(HuffmanError::TooManySymbols {
+                symbols : tokens.len(),
+                max     : MAX_SYMBOLS,
+            });
+        }
+        let lengths = match tokens[..] {
+            []      => vec![],
+            [token] => vec![(token, 1)],
+            _       => {
+                let weights = weights.enumerate().map(|(i, w)| {
+                    (id_to_char(i).unwrap(), w)
+                });
+                generate_huffman_codes_weighted(weights)
+                    .into_iter()
+                    .map(|(c, code)| (tokens[char_to_id(c)], code.len() as u8))
+                    .collect()
+            },
+        };
+        let decoder = CanonicalDecoder::from_lengths(&lengths)?;
+        let codes   = decoder.codes().collect();
+
+        Ok(Self { codes, decoder })
+    }
+    /// The code for `token`, if it has one.
+    /// 
+    pub fn code_for(&self, token: Token) -> Option<Code> {
+        self.codes.get(&token).copied()
+    }
+    /// The code for the control symbol `id`, if it was reserved.
+    /// 
+    pub fn reserved_code(&self, id: ReservedId) -> Option<Code> {
+        self.code_for(Token::Reserved(id))
+    }
+    /// The codes, keyed by token.
+    /// 
+    pub fn codes(&self) -> &HashMap<Token, Code> {
+        &self.codes
+    }
+    /// Appends the codes for `tokens` to `out`. Fails with
+    /// `HuffmanError::SymbolNotInCodebook` at a character with no code, giving
+    /// its index among the tokens and its offset in the UTF-8 of the
+    /// characters before it; a control symbol that wasn't reserved is reported
+    /// as `HuffmanError::MalformedData`.
+    /// 
+    pub fn encode(&self, tokens: &[Token], out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError> 
+    {
+        let mut byte_offset = 0;
+
+        for (char_index, &token) in tokens.iter().enumerate() {
+            let code = self.code_for(token).ok_or(match token {
+                Token::Char(symbol) => HuffmanError::SymbolNotInCodebook {
+                    symbol, char_index, byte_offset
+                },
+                Token::Reserved(_)  => {
+                    HuffmanError::MalformedData("a control symbol wasn't \
+                                                 reserved")
+                },
+            })?;
+            if let Token::Char(c) = token {
+                byte_offset += c.len_utf8();
+            }
+            out.write_code(code);
+        }
+        Ok(())
+    }
+    /// Decodes the tokens coded by the rest of `r`'s bits.
+    /// 
+    pub fn decode(&self, r: &mut BitReader) 
+
+        -> Result<Vec<Token>, HuffmanError> 
+    {
+        let mut tokens = vec![];
+
+        while r.remaining() > 0 {
+            tokens.push(self.decode_token(r, tokens.len())?);
+        }
+        Ok(tokens)
+    }
+    /// Codes for the `n` most frequent characters counted in `freqs`, and
+    /// for `ReservedId::Escape` as an OTHER bucket standing for the rest,
+    /// weighted by their combined count, as
+    /// `FrequencyTable::top_n_with_other()` splits them. Returns the
+    /// characters folded into OTHER too. Text is coded with `encode_text()`.
+    /// Fails as `new()` does.
+    /// 
+    pub fn with_other(freqs: &FrequencyTable, n: usize) 
+
+        -> Result<(Self, TailStats), HuffmanError> 
+    {
+        let (top, tail) = freqs.top_n_with_other(n);
+        let codebook    = Self::new(&top, &[(ReservedId::Escape,
+                                             tail.count as u64)])?;
+        Ok((codebook, tail))
+    }
+    /// Appends the codes for the characters of `text` to `out`. A character
+    /// with no code of its own is written as the code for
+    /// `ReservedId::Escape` followed by its scalar value in 21 bits, as the
+    /// adaptive coder writes new symbols. Fails with
+    /// `HuffmanError::SymbolNotInCodebook` at such a character if
+    /// `ReservedId::Escape` wasn't reserved.
+    /// 
+    pub fn encode_text(&self, text: &str, out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError> 
+    {
+        let escape = self.reserved_code(ReservedId::Escape);
+
+        for (char_index, (byte_offset, c)) in text.char_indices().enumerate() {
+            match (self.code_for(Token::Char(c)), escape) {
+                (Some(code), _)    => out.write_code(code),
+                (None, Some(code)) => {
+                    out.write_code(code);
+                    out.write_code(Code { bits: c as u64, len: ESCAPE_BITS });
+                },
+                (None, None)       => {
+                    return Err(HuffmanError::SymbolNotInCodebook {
+                        symbol: c, char_index, byte_offset
+                    });
+                },
+            }
+        }
+        Ok(())
+    }
+    /// Decodes the text coded by `encode_text()` in the rest of `r`'s bits.
+    /// Fails as `decode()` does, with `HuffmanError::TruncatedBitstream` if
+    /// the bits end partway through an escaped scalar value, and with
+    /// `HuffmanError::InvalidCode` if it isn't a Unicode scalar value. Any
+    /// other control symbol is reported as `HuffmanError::MalformedData`.
+    /// 
+    pub fn decode_text(&self, r: &mut BitReader) 
+
+        -> Result<String, HuffmanError> 
+    {
+        let mut text    = String::new();
+        let mut symbols = 0;
+
+        while r.remaining() > 0 {
+            let start = r.position();
+
+            let c = match self.decode_token(r, symbols)? {
+                Token::Char(c)                     => c,
+                Token::Reserved(ReservedId::Escape) => {
+                    let mut value = 0;
+
+                    for _ in 0..ESCAPE_BITS {
+                        let bit = r.read_bit().ok_or(
+                            HuffmanError::TruncatedBitstream {
+                                bit_offset: start, symbols
+                            }
+                        )?;
+                        value = value << 1 | bit as u32;
+                    }
+                    char::from_u32(value).ok_or(HuffmanError::InvalidCode {
+                        bit_offset: start, symbols
+                    })?
+                },
+                Token::Reserved(_) => {
+                    return Err(HuffmanError::MalformedData("a control symbol \
+                                                            isn't text"));
+                },
+            };
+            text.push(c);
+            symbols += 1;
+        }
+        Ok(text)
+    }
+    /// Decodes one token, which has `symbols` before it.
+    /// 
+    fn decode_token(&self, r: &mut BitReader, symbols: usize) 
+
+        -> Result<Token, HuffmanError> 
+    {
+        self.decoder.decode_symbol(r).map_err(|e| match e {
+            HuffmanError::InvalidCode { bit_offset, .. } => {
+                HuffmanError::InvalidCode { bit_offset, symbols }
+            },
+            HuffmanError::TruncatedBitstream { bit_offset, .. } => {
+                HuffmanError::TruncatedBitstream { bit_offset, symbols }
+            },
+            e => e,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ReservedId::*;
+
+    static ALL: &[(ReservedId, u64)] = &[(Eos, 1), (Reset, 1), (Pad, 1),
+                                         (Escape, 1), (Application1, 1),
+                                         (Application2, 1), (Application3, 1),
+                                         (Application4, 1)];
+
+    #[test]
+    fn always_present() {
+        for text in ["", "a", "ab", "\0\0\0"] {
+            let codebook = TokenCodebook::new(&FrequencyTable::from_text(text),
+                                              ALL).unwrap();
+
+            for &(id, _) in ALL {
+                assert!(codebook.reserved_code(id).is_some());
+            }
+            for c in text.chars() {
+                assert!(codebook.code_for(Token::Char(c)).is_some());
+            }
+        }
+        let lone = TokenCodebook::new(&FrequencyTable::default(), &[(Eos, 0)])
+                                 .unwrap();
+
+        assert_eq!(lone.reserved_code(Eos), Some(Code { bits: 0, len: 1 }));
+        assert_eq!(TokenCodebook::new(&FrequencyTable::default(),
+                                      &[(Eos, 1), (Pad, 1), (Eos, 2)]).err(),
+                   Some(HuffmanError::MalformedData("a reserved symbol \
+                                                     appears twice")));
+    }
+
+    #[test]
+    fn other_bucket_round_trips() {
+        let mut rng = crate::sample::SplitMix64(207);
+
+        // A heavy tail: each of 3000 chars is as likely as the log of its
+        // rank is uniform, so a few are common and most are rare, some
+        // outside the Basic Multilingual Plane.
+        let text = (0..50_000).map(|_| {
+            let rank = (rng.below(1 << 20) as f64 / (1 << 20) as f64
+                        * 3000_f64.ln()).exp() as u32;
+            match rank % 7 {
+                0 => char::from_u32(0x1f600 + rank).unwrap(),
+                _ => char::from_u32(0x4e00 + rank).unwrap(),
+            }
+        }).collect::<String>();
+
+        let freqs = FrequencyTable::from_text(&text);
+        let (codebook, tail) = TokenCodebook::with_other(&freqs, 100).unwrap();
+
+        assert_eq!(tail.symbols.len(), freqs.len() - 100);
+        assert!(tail.count > 0);
+
+        let escape = codebook.reserved_code(Escape).unwrap();
+        let mut out = BitWriter::new();
+
+        codebook.encode_text(&text, &mut out).unwrap();
+
+        // The tail costs OTHER's code and the scalar value, and nothing is
+        // stored for it in the table.
+        let expected = text.chars().map(|c| {
+            match codebook.code_for(Token::Char(c)) {
+                Some(code) => code.len as u64,
+                None       => (escape.len + ESCAPE_BITS) as u64,
+            }
+        }).sum::<u64>();
+
+        assert_eq!(out.bit_len(), expected);
+        assert_eq!(codebook.codes().len(), 101);
+        assert!(tail.symbols.iter().all(|&c| {
+            codebook.code_for(Token::Char(c)).is_none()
+        }));
+        let nbits = out.bit_len() as usize;
+        let bytes = out.finish();
+
+        assert_eq!(codebook.decode_text(&mut BitReader::new(&bytes, nbits)),
+                   Ok(text.clone()));
+
+        // Everything in the tail, and nothing.
+        for n in [0, freqs.len()] {
+            let (codebook, _) = TokenCodebook::with_other(&freqs, n).unwrap();
+            let mut out       = BitWriter::new();
+
+            codebook.encode_text(&text, &mut out).unwrap();
+
+            let nbits = out.bit_len() as usize;
+            let bytes = out.finish();
+
+            assert_eq!(codebook.decode_text(&mut BitReader::new(&bytes,
+                                                                nbits)),
+                       Ok(text.clone()));
+        }
+    }
+
+    #[test]
+    fn other_bucket_errors() {
+        let freqs         = FrequencyTable::from_text("aaab");
+        let (codebook, _) = TokenCodebook::with_other(&freqs, 1).unwrap();
+        let escape        = codebook.reserved_code(Escape).unwrap();
+        let decode        = |codes: &[Code]| {
+            let mut out = BitWriter::new();
+
+            for &code in codes {
+                out.write_code(code);
+            }
+            let nbits = out.bit_len() as usize;
+
+            codebook.decode_text(&mut BitReader::new(&out.finish(), nbits))
+        };
+        let a = codebook.code_for(Token::Char('a')).unwrap();
+
+        assert_eq!(decode(&[a, escape, Code { bits: 'b' as u64, len: 21 }]),
+                   Ok("ab".into()));
+        assert_eq!(decode(&[a, escape, Code { bits: 1, len: 20 }]),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset: a.len as usize, symbols: 1
+                   }));
+        assert_eq!(decode(&[escape, Code { bits: 0xd800, len: 21 }]),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+
+        // Without OTHER, the tail can't be written.
+        let plain = TokenCodebook::new(&freqs, &[(Eos, 0)]).unwrap();
+
+        assert_eq!(plain.encode_text("abc", &mut BitWriter::new()),
+                   Err(HuffmanError::SymbolNotInCodebook {
+                       symbol: 'c', char_index: 2, byte_offset: 2
+                   }));
+
+        let eos     = plain.reserved_code(Eos).unwrap();
+        let mut out = BitWriter::new();
+
+        out.write_code(eos);
+
+        assert_eq!(plain.decode_text(&mut BitReader::new(&out.finish(),
+                                                         eos.len as usize)),
+                   Err(HuffmanError::MalformedData("a control symbol isn't \
+                                                    text")));
+    }
+
+    #[test]
+    fn weights_set_lengths() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = FrequencyTable::from_text(&text);
+        let len   = |codebook: &TokenCodebook, id| {
+            codebook.reserved_code(id).unwrap().len
+        };
+        let light = TokenCodebook::new(&freqs, &[(Eos, 0), (Reset, 0)])
+                                  .unwrap();
+        let heavy = TokenCodebook::new(&freqs, &[(Eos, 0),
+                                                 (Reset, freqs.total() as u64)])
+                                  .unwrap();
+
+        assert_eq!(len(&heavy, Reset), 1);
+        assert!(len(&light, Reset) > len(&heavy, Reset));
+        assert!(len(&light, Eos) >= len(&light, Reset));
+
+        let max = light.codes().values().map(|code| code.len).max().unwrap();
+
+        assert_eq!(len(&light, Eos), max);
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let text     = "abracadabra\0";
+        let codebook = TokenCodebook::new(&FrequencyTable::from_text(text),
+                                          &[(Eos, 1), (Reset, 3)]).unwrap();
+        let mut tokens = text.chars().map(Token::Char).collect::<Vec<_>>();
+
+        tokens.insert(4, Token::Reserved(Reset));
+        tokens.push(Token::Reserved(Eos));
+
+        let mut writer = BitWriter::new();
+        codebook.encode(&tokens, &mut writer).unwrap();
+
+        let nbits = writer.bit_len() as usize;
+        let bytes = writer.finish();
+
+        assert_eq!(codebook.decode(&mut BitReader::new(&bytes, nbits)),
+                   Ok(tokens));
+        assert_eq!(codebook.encode(&[Token::Char('a'), Token::Char('é'),
+                                     Token::Char('z')], &mut writer),
+                   Err(HuffmanError::SymbolNotInCodebook {
+                       symbol: 'é', char_index: 1, byte_offset: 1
+                   }));
+        assert!(codebook.encode(&[Token::Reserved(Pad)], &mut writer)
+                        .is_err());
+    }
+}
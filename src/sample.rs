@@ -0,0 +1,154 @@
+//! Generating random text with the character distribution of a frequency
+//! table, for benchmarks and tests that need large inputs.
+//! 
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::codebook::FrequencyTable;
+
+
+/// SplitMix64, a small seedable generator. It's good enough for drawing test
+/// text, and keeps the output reproducible without depending on an RNG crate.
+/// 
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    /// A uniform index below `n`.
+    /// 
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (((self.next_u64() >> 32) * n as u64) >> 32) as usize
+    }
+    /// A uniform value in [0, 1).
+    /// 
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Vose's alias table, for drawing from a discrete distribution in constant
+/// time. Column `i` is drawn with equal chance; it then gives `symbols[i]`
+/// with probability `probs[i]` and `symbols[aliases[i]]` otherwise.
+/// 
+struct AliasTable {
+    symbols : Vec<char>,
+    probs   : Vec<f64>,
+    aliases : Vec<usize>,
+}
+
+impl AliasTable {
+    fn new(freqs: &FrequencyTable) -> Self {
+        let ranked  = freqs.ranked();
+        let n       = ranked.len();
+        let total   = freqs.total() as f64;
+        let symbols = ranked.iter().map(|&(c, _)| c).collect();
+        let mut probs = ranked.iter()
+                              .map(|&(_, f)| f as f64 * n as f64 / total)
+                              .collect::<Vec<_>>();
+        let mut aliases = (0..n).collect::<Vec<_>>();
+
+        let (mut small, mut large): (Vec<_>, Vec<_>) = (0..n).partition(|&i| {
+            probs[i] < 1.0
+        });
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            aliases[s] = l;
+            probs[l]  -= 1.0 - probs[s];
+
+            if probs[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // Whatever is left is only short of 1 by rounding error.
+        for i in small.into_iter().chain(large) {
+            probs[i] = 1.0;
+        }
+        Self { symbols, probs, aliases }
+    }
+    fn draw(&self, rng: &mut SplitMix64) -> char {
+        let i = rng.below(self.symbols.len());
+
+        if rng.unit() < self.probs[i] {
+            self.symbols[i]
+        } else {
+            self.symbols[self.aliases[i]]
+        }
+    }
+}
+
+
+impl FrequencyTable {
+    /// Generates `len` characters drawn independently from the table's
+    /// distribution. The same table and `seed` always give the same text. An
+    /// empty table gives an empty string.
+    /// 
+    pub fn sample(&self, len: usize, seed: u64) -> String {
+        if self.total() == 0 {
+            return String::new();
+        }
+        let table   = AliasTable::new(self);
+        let mut rng = SplitMix64(seed);
+
+        (0..len).map(|_| table.draw(&mut rng)).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn untied() -> FrequencyTable {
+        [('a', 45), ('b', 13), ('c', 12), ('d', 16), ('e', 9), ('f', 5)]
+            .into_iter().collect()
+    }
+
+    #[test]
+    fn reproducible() {
+        let freqs = untied();
+
+        assert_eq!(freqs.sample(1000, 7), freqs.sample(1000, 7));
+        assert_ne!(freqs.sample(1000, 7), freqs.sample(1000, 8));
+        assert_eq!(freqs.sample(1000, 7).chars().count(), 1000);
+        assert_eq!(FrequencyTable::default().sample(10, 7), "");
+        assert_eq!(FrequencyTable::from_text("é").sample(3, 7), "ééé");
+    }
+
+    #[test]
+    fn matches_distribution() {
+        // With 5 degrees of freedom, chi-square exceeds 20.5 with probability
+        // 0.001.
+        let freqs  = untied();
+        let n      = 100_000;
+        let sample = FrequencyTable::from_text(&freqs.sample(n, 42));
+        let chi2   = freqs.counts().iter().map(|(&c, &f)| {
+            let expected = f as f64 * n as f64 / freqs.total() as f64;
+            let observed = sample.get(c) as f64;
+
+            (observed - expected).powi(2) / expected
+        }).sum::<f64>();
+
+        assert_eq!(sample.len(), freqs.len());
+        assert!(chi2 < 20.5, "chi-square {}", chi2);
+
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs  = FrequencyTable::from_text(&text);
+        let sample = FrequencyTable::from_text(&freqs.sample(n, 42));
+
+        for (&c, &f) in freqs.counts() {
+            let expected = f as f64 / freqs.total() as f64;
+            let observed = sample.get(c) as f64 / n as f64;
+
+            assert!((observed - expected).abs() < 0.005, "{:?}", c);
+        }
+    }
+}
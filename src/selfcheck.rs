@@ -0,0 +1,171 @@
+//! Golden vectors for the crate's coding behavior, checked at run time by
+//! `self_check()` so an embedding product can confirm that an upgrade hasn't
+//! changed the codes it assigns or the streams it writes. They double as the
+//! crate's regression tests, so changing one takes a deliberate diff here.
+//! 
+
+use std::error::Error;
+use std::fmt;
+
+use crate::codebook::{generate_huffman_codes_from_freqs, FrequencyTable};
+use crate::codebook::HuffmanCodebook;
+use crate::container::{pipe_compress, pipe_decompress, TableSource};
+
+
+/// The counts of the example in CLRS, section 16.3.
+/// 
+const FREQS: &[(char, usize)] = &[('a', 45), ('b', 13), ('c', 12), ('d', 16),
+                                  ('e', 9),  ('f', 5)];
+
+/// The codes `generate_huffman_codes_from_freqs()` gives `FREQS`, which pin
+/// down how ties are broken.
+/// 
+const TREE_CODES: &[(char, &str)] = &[('a', "0"),    ('b', "101"),
+                                      ('c', "100"),  ('d', "111"),
+                                      ('e', "1101"), ('f', "1100")];
+
+/// The canonical codes for `FREQS`, as `(symbol, bits, len)`.
+/// 
+const CANONICAL_CODES: &[(char, u64, u8)] = &[('a', 0b0,    1),
+                                              ('b', 0b100,  3),
+                                              ('c', 0b101,  3),
+                                              ('d', 0b110,  3),
+                                              ('e', 0b1110, 4),
+                                              ('f', 0b1111, 4)];
+
+/// A text compressed with a trained table by `pipe_compress()`.
+/// 
+const PAYLOAD: &str = "abracadabra, alakazam!";
+
+/// `PAYLOAD` as `pipe_compress()` writes it.
+/// 
+const PAYLOAD_STREAM: &[u8] = &[
+    0x48, 0x55, 0x46, 0x46, 0x01, 0x01, 0x4e, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00,
+    0x05, 0x1a, 0x21, 0x00, 0x00, 0x00, 0x05, 0x1b, 0x2c, 0x00,
+    0x00, 0x00, 0x05, 0x1c, 0x61, 0x00, 0x00, 0x00, 0x01, 0x00,
+    0x62, 0x00, 0x00, 0x00, 0x04, 0x0b, 0x63, 0x00, 0x00, 0x00,
+    0x05, 0x1d, 0x64, 0x00, 0x00, 0x00, 0x05, 0x1e, 0x6b, 0x00,
+    0x00, 0x00, 0x05, 0x1f, 0x6c, 0x00, 0x00, 0x00, 0x04, 0x08,
+    0x6d, 0x00, 0x00, 0x00, 0x04, 0x09, 0x72, 0x00, 0x00, 0x00,
+    0x04, 0x0c, 0x7a, 0x00, 0x00, 0x00, 0x04, 0x0a, 0x5e, 0x3a,
+    0xf2, 0xf1, 0xcd, 0x21, 0xf5, 0x27, 0x60, 0x05,
+];
+
+/// A stream coded with `HuffmanCodebook::english()`, by `compress_auto()`.
+/// 
+const FIXTURE_STREAM: &[u8] = &[
+    0x48, 0x55, 0x46, 0x46, 0x01, 0x04, 0xf6, 0xa1, 0xd2, 0x48,
+    0xa5, 0x23, 0xc9, 0x9a, 0x11, 0xa8, 0xa6, 0xdc, 0xce, 0x61,
+    0xa8, 0x74, 0x92, 0x29, 0x48, 0xea, 0x31, 0x34, 0x23, 0x51,
+    0x4d, 0xb9, 0x9f, 0x00, 0x05,
+];
+
+/// The text of `FIXTURE_STREAM`.
+/// 
+const FIXTURE: &str = "It was the best of times, it was the worst of times.";
+
+
+/// The first golden vector `self_check()` found the crate diverging from.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfCheckFailure {
+    /// The name of the vector.
+    pub vector   : &'static str,
+    /// What the vector expects, formatted for display.
+    pub expected : String,
+    /// What the crate produced instead.
+    pub found    : String,
+}
+
+impl fmt::Display for SelfCheckFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "self-check vector {:?} diverged: expected {}, found {}",
+               self.vector, self.expected, self.found)
+    }
+}
+
+impl Error for SelfCheckFailure {}
+
+/// Compares `found` to `expected` for the vector named `vector`.
+/// 
+fn check<T>(vector: &'static str, expected: T, found: T) 
+
+    -> Result<(), SelfCheckFailure> 
+where
+    T: fmt::Debug + PartialEq,
+{
+    match expected == found {
+        true  => Ok(()),
+        false => Err(SelfCheckFailure {
+            vector,
+            expected : format!("{:?}", expected),
+            found    : format!("{:?}", found),
+        }),
+    }
+}
+
+/// Runs the crate's golden vectors: the codes for a fixed frequency table,
+/// both as the tree assigns them and in canonical form; the stream a fixed
+/// text compresses to; and the text a fixed stream decompresses to. Returns
+/// the first that diverges.
+/// 
+pub fn self_check() -> Result<(), SelfCheckFailure> {
+    let freqs = FREQS.iter().copied().collect::<FrequencyTable>();
+
+    let mut tree_codes = generate_huffman_codes_from_freqs(freqs.counts())
+                             .into_iter()
+                             .collect::<Vec<_>>();
+    tree_codes.sort_unstable();
+
+    check("tree codes",
+          TREE_CODES.iter()
+                    .map(|&(c, code)| (c, code.to_string()))
+                    .collect(),
+          tree_codes)?;
+
+    let codebook = HuffmanCodebook::from_frequencies(freqs).to_canonical();
+
+    let mut canonical = codebook.codes()
+                                .iter()
+                                .map(|(&c, code)| (c, code.bits, code.len))
+                                .collect::<Vec<_>>();
+    canonical.sort_unstable();
+
+    check("canonical codes", CANONICAL_CODES.to_vec(), canonical)?;
+
+    let mut stream = vec![];
+    let compressed = pipe_compress(PAYLOAD.as_bytes(), &mut stream,
+                                   TableSource::Train, None);
+
+    check("payload stream", Ok(PAYLOAD_STREAM.to_vec()),
+          compressed.map(|_| stream))?;
+
+    let mut text     = vec![];
+    let decompressed = pipe_decompress(FIXTURE_STREAM, &mut text, None, None);
+
+    check("fixture text", Ok(FIXTURE.to_string()),
+          decompressed.map(|_| String::from_utf8_lossy(&text).into_owned()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_hold() {
+        assert_eq!(self_check(), Ok(()));
+    }
+
+    #[test]
+    fn divergence_details() {
+        assert_eq!(check("example", [1, 2], [1, 3]),
+                   Err(SelfCheckFailure {
+                       vector   : "example",
+                       expected : "[1, 2]".into(),
+                       found    : "[1, 3]".into(),
+                   }));
+        assert_eq!(check("example", "a", "a"), Ok(()));
+    }
+}
@@ -0,0 +1,202 @@
+//! Serde support, behind the `serde` feature, for handing code tables and
+//! stats to programs elsewhere, such as a decoder written in JavaScript.
+//! 
+//! A code table is serialized as a map from each symbol to its code as a
+//! string of '0's and '1's, and a frequency table as a map from each symbol
+//! to its count, both in order of symbol:
+//! 
+//! ```text
+//! {"a":"0","b":"100","c":"101","d":"11"}
+//! ```
+//! 
+//! A code table is checked by `validate_codes()` as it's deserialized, so a
+//! table with colliding codes is rejected, with a message naming them.
+//! 
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+use crate::HuffmanError;
+use crate::canonical::{validate_codes, validate_complete_codes};
+use crate::codebook::{Code, FrequencyTable, HuffmanCodebook};
+use crate::tree::HuffmanCodec;
+
+
+impl Serialize for HuffmanCodebook {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.code_strings()
+                                   .into_iter()
+                                   .collect::<BTreeMap<_, _>>())
+    }
+}
+
+/// Reads a table of code strings, as `HuffmanCodebook` serializes to. The
+/// codebook has an empty frequency table, as for `from_static()`.
+/// 
+impl<'de> Deserialize<'de> for HuffmanCodebook {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let strings = HashMap::<char, String>::deserialize(deserializer)?;
+
+        validate_codes(&strings).map_err(D::Error::custom)?;
+
+        let table = strings.iter().map(|(&c, code)| {
+            Code::from_str_binary(code).map(|code| (c, code.bits, code.len))
+                                       .ok_or(HuffmanError::CodeTooLong(c))
+        }).collect::<Result<Vec<_>, _>>().map_err(D::Error::custom)?;
+
+        Self::from_table(&table).map_err(D::Error::custom)
+    }
+}
+
+/// Serializes the codes, as `HuffmanCodebook` does.
+/// 
+impl Serialize for HuffmanCodec {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.codes().into_iter()
+                                           .collect::<BTreeMap<_, _>>())
+    }
+}
+
+/// Reads a table of code strings, rebuilding the tree whose paths they are
+/// with `HuffmanCodec::from_codes()`. The table must be complete, as a
+/// Huffman code is, so the tree has no missing branches.
+/// 
+impl<'de> Deserialize<'de> for HuffmanCodec {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let strings = HashMap::<char, String>::deserialize(deserializer)?;
+
+        match strings.len() {
+            0 | 1 => validate_codes(&strings),
+            _     => validate_complete_codes(&strings),
+        }.map_err(D::Error::custom)?;
+
+        Self::from_codes(&strings).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for FrequencyTable {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.counts.iter().collect::<BTreeMap<_, _>>())
+    }
+}
+
+impl<'de> Deserialize<'de> for FrequencyTable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let counts = HashMap::<char, usize>::deserialize(deserializer)?;
+
+        Ok(Self { counts })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{code_stats, CodeStats};
+
+    static CODES: &[(char, u64, u8)] = &[('a', 0b0,   1), ('b', 0b100, 3),
+                                         ('c', 0b101, 3), ('d', 0b11,  2)];
+
+    #[test]
+    fn json_shape() {
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+
+        assert_eq!(serde_json::to_string(&codebook).unwrap(),
+                   r#"{"a":"0","b":"100","c":"101","d":"11"}"#);
+        assert_eq!(serde_json::to_string(codebook.frequencies()).unwrap(),
+                   "{}");
+
+        let freqs = FrequencyTable::from_text("abracadabra");
+
+        assert_eq!(serde_json::to_string(&freqs).unwrap(),
+                   r#"{"a":5,"b":2,"c":1,"d":1,"r":2}"#);
+
+        let codec = HuffmanCodec::from_text("abracadabra").unwrap();
+
+        assert_eq!(serde_json::to_value(&codec).unwrap(),
+                   serde_json::to_value(codec.codes()).unwrap());
+    }
+
+    #[test]
+    fn round_trips() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codebook = HuffmanCodebook::from_text(&text);
+        let json     = serde_json::to_string(&codebook).unwrap();
+        let back     = serde_json::from_str::<HuffmanCodebook>(&json).unwrap();
+
+        assert_eq!(back.codes(), codebook.codes());
+
+        let json = serde_json::to_string(codebook.frequencies()).unwrap();
+
+        assert_eq!(&serde_json::from_str::<FrequencyTable>(&json).unwrap(),
+                   codebook.frequencies());
+
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+        let json  = serde_json::to_string(&codec).unwrap();
+        let back  = serde_json::from_str::<HuffmanCodec>(&json).unwrap();
+
+        assert_eq!(back.codes(), codec.codes());
+        assert_eq!(back.tree().decode(&codec.encode(&text).unwrap()).unwrap(),
+                   text);
+
+        let stats = code_stats(&text);
+        let json  = serde_json::to_string(&stats).unwrap();
+        let back  = serde_json::from_str::<CodeStats>(&json).unwrap();
+
+        assert_eq!(back.original_bits, stats.original_bits);
+        assert_eq!(back.compressed_bits, stats.compressed_bits);
+        assert_eq!(back.per_symbol, stats.per_symbol);
+        assert!((back.entropy - stats.entropy).abs() < 1e-12);
+    }
+
+    #[test]
+    fn invalid_tables() {
+        for (json, message) in [
+            (r#"{"a":"0","b":"01"}"#,
+             "the code for 'a' is a prefix of the code for 'b'"),
+            (r#"{"a":"10","b":"0","c":"10"}"#,
+             "'a' and 'c' have the same code"),
+            (r#"{"a":"0","b":"1x"}"#,
+             "the code for 'b' isn't made of '0's and '1's"),
+            (r#"{"a":""}"#, "the code for 'a' is empty"),
+        ] {
+            let err = serde_json::from_str::<HuffmanCodebook>(json)
+                                 .unwrap_err();
+
+            assert!(err.to_string().starts_with(message), "{}", err);
+        }
+        for (json, message) in [
+            (r#"{"a":"0","b":"10"}"#,
+             "the codes don't cover every sequence of bits"),
+            (r#"{"a":"1"}"#, "malformed data: the code table is incomplete"),
+        ] {
+            let err = serde_json::from_str::<HuffmanCodec>(json).unwrap_err();
+
+            assert!(err.to_string().starts_with(message), "{}", err);
+        }
+        let long = format!(r#"{{"a":"0","b":"1{}"}}"#, "0".repeat(64));
+        let err  = serde_json::from_str::<HuffmanCodebook>(&long).unwrap_err();
+
+        assert!(err.to_string().starts_with("the code for 'b' is too long"));
+    }
+}
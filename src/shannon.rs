@@ -0,0 +1,212 @@
+//! Shannon–Fano codes, Huffman's predecessor, as a `Codec` to compare against.
+//! 
+//! The symbols are ranked by count, and the ranking is split in two where the
+//! halves' totals are as near equal as they can be, the first half taking a 0
+//! bit and the second a 1; each half is split the same way until every part
+//! is one symbol. The codes are prefix-free but, unlike Huffman's, not always
+//! the shortest possible.
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+use crate::codebook::{Code, Codec, FrequencyTable, HuffmanCodebook};
+use crate::collections::{map_with_capacity, HashMap};
+use crate::io::{BitReader, BitWriter};
+
+
+/// Shannon–Fano codes for a text's characters. They're held in a codebook,
+/// which does the encoding and decoding.
+/// 
+#[derive(Clone, Debug)]
+pub struct ShannonFanoCodec {
+    codebook: HuffmanCodebook,
+}
+
+impl Default for ShannonFanoCodec {
+    fn default() -> Self {
+        Self { codebook: HuffmanCodebook::from_text("") }
+    }
+}
+
+impl ShannonFanoCodec {
+    /// Generates codes for the characters of `data`. Fails as
+    /// `from_frequencies()` does.
+    /// 
+    pub fn from_text(data: &str) -> Result<Self, HuffmanError> {
+        Self::from_frequencies(FrequencyTable::from_text(data))
+    }
+    /// Generates codes for the characters counted in `freqs`. Characters of
+    /// equal count are ranked in order of character, so the codes depend
+    /// only on the counts. A lone character gets the code "0", as it does
+    /// for Huffman codes. Fails with `HuffmanError::CodeTooLong` if a code
+    /// would be longer than 64 bits.
+    /// 
+    pub fn from_frequencies(freqs: FrequencyTable) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let ranked = freqs.ranked();
+        let codes  = shannon_fano_codes(&ranked)?;
+
+        Ok(Self { codebook: HuffmanCodebook::from_parts(freqs, codes) })
+    }
+    /// The codebook holding the codes.
+    /// 
+    pub fn codebook(&self) -> &HuffmanCodebook {
+        &self.codebook
+    }
+}
+
+/// The codes for `ranked`, which is sorted most frequent first. The parts
+/// still to split are kept on a stack, rather than split by recursion, since
+/// counts skewed enough make the splits as deep as there are symbols.
+/// 
+fn shannon_fano_codes(ranked: &[(char, usize)]) 
+
+    -> Result<HashMap<char, Code>, HuffmanError> 
+{
+    let mut prefix = Vec::with_capacity(ranked.len() + 1);
+    let mut sum    = 0_u128;
+
+    prefix.push(0);
+
+    for &(_, n) in ranked {
+        sum += n as u128;
+        prefix.push(sum);
+    }
+    let mut codes = map_with_capacity(ranked.len());
+    let mut parts = vec![];
+
+    match ranked {
+        []       => {},
+        [(c, _)] => { codes.insert(*c, Code { bits: 0, len: 1 }); },
+        _        => parts.push((0, ranked.len(), Code::default())),
+    }
+    while let Some((lo, hi, code)) = parts.pop() {
+        if hi - lo == 1 {
+            codes.insert(ranked[lo].0, code);
+            continue;
+        }
+        if code.len == 64 {
+            return Err(HuffmanError::CodeTooLong(ranked[lo].0));
+        }
+        let split = split_point(&prefix, lo, hi);
+
+        parts.push((split, hi, code.append(true)));
+        parts.push((lo, split, code.append(false)));
+    }
+    Ok(codes)
+}
+
+/// Where to split the symbols `lo..hi`, of which there are at least two, so
+/// the totals of the two parts are as near equal as they can be, with the
+/// first part the smaller of the two on a tie. `prefix` holds the running
+/// totals of the counts.
+/// 
+fn split_point(prefix: &[u128], lo: usize, hi: usize) -> usize {
+    let total = prefix[hi] - prefix[lo];
+    let gap   = |k: usize| (2 * (prefix[k] - prefix[lo])).abs_diff(total);
+
+    // The first split whose first part holds at least half the total, or
+    // the one before it.
+    let k = lo + 1 + prefix[lo + 1..hi].partition_point(|&p| {
+        2 * (p - prefix[lo]) < total
+    });
+    let k = k.min(hi - 1);
+
+    match k > lo + 1 && gap(k - 1) <= gap(k) {
+        true  => k - 1,
+        false => k,
+    }
+}
+
+impl Codec for ShannonFanoCodec {
+    /// Replaces the codes with ones generated for `data`.
+    /// 
+    fn train(&mut self, data: &str) -> Result<(), HuffmanError> {
+        *self = Self::from_text(data)?;
+        Ok(())
+    }
+    fn encode(&self, data: &str, out: &mut BitWriter) 
+
+        -> Result<(), HuffmanError> 
+    {
+        Codec::encode(&self.codebook, data, out)
+    }
+    fn decode(&self, r: &mut BitReader) -> Result<String, HuffmanError> {
+        Codec::decode(&self.codebook, r)
+    }
+    /// The bits for the codebook's table, as for a `HuffmanCodebook`.
+    /// 
+    fn table_overhead_bits(&self) -> u64 {
+        Codec::table_overhead_bits(&self.codebook)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn code_strings(codec: &ShannonFanoCodec) -> Vec<(char, String)> {
+        let mut codes = codec.codebook()
+                             .code_strings()
+                             .into_iter()
+                             .collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes
+    }
+
+    #[test]
+    fn textbook_codes() {
+        let freqs = [('a', 15), ('b', 7), ('c', 6), ('d', 6), ('e', 5)]
+                        .into_iter()
+                        .collect::<FrequencyTable>();
+        let codec = ShannonFanoCodec::from_frequencies(freqs).unwrap();
+
+        assert_eq!(code_strings(&codec),
+                   [('a', "00"), ('b', "01"), ('c', "10"), ('d', "110"),
+                    ('e', "111")].map(|(c, s)| (c, s.to_string())));
+
+        // Here Huffman does better, giving 'a' a single bit.
+        let huffman = HuffmanCodebook::from_frequencies(
+                          codec.codebook().frequencies().clone());
+
+        assert!(huffman.average_code_length()
+                < codec.codebook().average_code_length());
+    }
+
+    #[test]
+    fn degenerate_and_skewed() {
+        assert!(ShannonFanoCodec::default().codebook().codes().is_empty());
+        assert_eq!(code_strings(&ShannonFanoCodec::from_text("aaa").unwrap()),
+                   [('a', "0".to_string())]);
+
+        // Halving counts split one symbol off at a time.
+        let halving = |n: u32| {
+            (0..n).map(|i| {
+                (char::from_u32(0x100 + i).unwrap(), 1_usize << (n - 1 - i))
+            }).collect::<FrequencyTable>()
+        };
+        let codec = ShannonFanoCodec::from_frequencies(halving(60)).unwrap();
+
+        assert_eq!(codec.codebook().codes().values().map(|c| c.len).max(),
+                   Some(59));
+
+        // So do Fibonacci counts, and 70 of them are too many.
+        let mut fib = (1, 1);
+        let deep    = (0..70).map(|i| {
+            let count = fib.0;
+
+            fib = (fib.1, fib.0 + fib.1);
+            (char::from_u32(0x100 + i).unwrap(), count)
+        }).collect::<FrequencyTable>();
+
+        assert!(matches!(ShannonFanoCodec::from_frequencies(deep),
+                         Err(HuffmanError::CodeTooLong(_))));
+    }
+}
@@ -0,0 +1,259 @@
+//! Reassigning a codebook's codes to keep as many as possible from an earlier
+//! codebook, so retraining doesn't needlessly change them.
+//! 
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::collections::set_with_capacity;
+
+
+/// Counts of free blocks of code space or of codes to place, indexed by length.
+/// 
+type Counts = [usize; 65];
+
+
+/// The unassigned parts of the code space, as a set of disjoint blocks. A block
+/// is the set of codes that start with its bits.
+/// 
+struct FreeSpace {
+    blocks : BTreeSet<Code>,
+    counts : Counts,
+}
+
+impl FreeSpace {
+    fn new() -> Self {
+        let mut counts = [0; 65];
+        counts[0] = 1;
+
+        Self { blocks: BTreeSet::from([Code::default()]), counts }
+    }
+    /// The free block `code` lies in, if any.
+    /// 
+    fn block_of(&self, code: Code) -> Option<Code> {
+        (0..=code.len).map(|len| {
+            let bits = code.bits.checked_shr((code.len - len) as u32)
+                                .unwrap_or(0);
+            Code { bits, len }
+        }).find(|block| self.blocks.contains(block))
+    }
+    /// The smallest free block that can hold a code of length `len`.
+    /// 
+    fn best_fit(&self, len: u8) -> Option<Code> {
+        (0..=len).rev().find_map(|l| {
+            self.blocks.range(Code { bits: 0, len: l }
+                              ..=Code { bits: u64::MAX, len: l }).next()
+        }).copied()
+    }
+    /// Assigns `code` out of `block`, leaving the rest of `block` free.
+    /// 
+    fn take(&mut self, block: Code, code: Code) {
+        self.blocks.remove(&block);
+        self.counts[block.len as usize] -= 1;
+
+        for len in block.len + 1..=code.len {
+            let bits = code.bits.checked_shr((code.len - len) as u32)
+                                .unwrap_or(0);
+            self.blocks.insert(Code { bits: bits ^ 1, len });
+            self.counts[len as usize] += 1;
+        }
+    }
+    /// The block counts there'd be after `take(block, code)`.
+    /// 
+    fn counts_after(&self, block: Code, code: Code) -> Counts {
+        let mut counts = self.counts;
+        counts[block.len as usize] -= 1;
+
+        for len in block.len + 1..=code.len {
+            counts[len as usize] += 1;
+        }
+        counts
+    }
+}
+
+/// Whether codes of the lengths counted in `needed` can all be placed in free
+/// blocks of the lengths counted in `free`. Placing the shortest codes first,
+/// each in the smallest block that fits, is optimal, and comes down to carrying
+/// the unused blocks at each length down to the next as pairs of halves.
+/// 
+fn fits(free: &Counts, needed: &Counts) -> bool {
+    let total = needed.iter().sum::<usize>();
+    let mut spare = 0_usize;
+
+    for (&f, &n) in free.iter().zip(needed) {
+        let avail = spare.saturating_mul(2).saturating_add(f).min(total);
+
+        if avail < n {
+            return false;
+        }
+        spare = avail - n;
+    }
+    true
+}
+
+
+impl HuffmanCodebook {
+    /// Reassigns the codes so that as many symbols as possible keep the code
+    /// they have in `previous`, as `HuffmanBuilder::stabilize_against()`
+    /// does. Returns how many symbols in both codebooks ended up with a
+    /// different code.
+    /// 
+    #[deprecated(
+        note = "use `huffman::codebook::HuffmanBuilder::stabilize_against`"
+    )]
+    pub fn stabilize_against(&mut self, previous: &HuffmanCodebook) -> usize {
+        self.keep_codes_of(previous)
+    }
+    /// Reassigns the codes so that as many symbols as possible keep the code
+    /// they have in `previous`, such as an earlier training of the same data.
+    /// Each symbol keeps its code length, so the codes are just as compact; a
+    /// symbol keeps its old code when that has the same length and doesn't
+    /// rule out prefix-free codes for the rest. The others are given the first
+    /// free codes of their length. Returns how many symbols in both codebooks
+    /// ended up with a different code.
+    /// 
+    pub(crate) fn keep_codes_of(&mut self, previous: &HuffmanCodebook) 
+
+        -> usize 
+    {
+        let mut free   = FreeSpace::new();
+        let mut needed = [0; 65];
+
+        self.decoder.take();
+
+        for code in self.codes.values() {
+            needed[code.len as usize] += 1;
+        }
+        // Try to keep old codes shortest first, as they constrain the most.
+        let mut keep = previous.codes.iter().filter(|&(c, code)| {
+            self.codes.get(c).is_some_and(|new| new.len == code.len)
+        }).map(|(&c, &code)| (code, c)).collect::<Vec<_>>();
+
+        keep.sort_unstable();
+
+        let mut kept = set_with_capacity(keep.len());
+
+        for (code, c) in keep {
+            let Some(block) = free.block_of(code) else { continue };
+            let counts      = free.counts_after(block, code);
+
+            needed[code.len as usize] -= 1;
+
+            if fits(&counts, &needed) {
+                free.take(block, code);
+                self.codes.insert(c, code);
+                kept.insert(c);
+            } else {
+                needed[code.len as usize] += 1;
+            }
+        }
+        let mut rest = self.codes.iter().filter(|(c, _)| !kept.contains(c))
+                                        .map(|(&c, &code)| (code.len, c))
+                                        .collect::<Vec<_>>();
+        rest.sort_unstable();
+
+        for (len, c) in rest {
+            let block = free.best_fit(len)
+                            .expect("Huffman codes should fill the code space");
+            let shift = (len - block.len) as u32;
+            let code  = Code {
+                bits: block.bits.checked_shl(shift).unwrap_or(0),
+                len
+            };
+            free.take(block, code);
+            self.codes.insert(c, code);
+        }
+        self.codes.iter().filter(|&(c, code)| {
+            previous.codes.get(c).is_some_and(|old| old != code)
+        }).count()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codebook::{FrequencyTable, HuffmanBuilder};
+    use crate::io::DecoderFsm;
+
+    fn clrs() -> FrequencyTable {
+        [('a', 45), ('b', 13), ('c', 12), ('d', 16), ('e', 9), ('f', 5)]
+            .into_iter().collect()
+    }
+
+    #[test]
+    fn identical_training_keeps_codes() {
+        let previous    = HuffmanCodebook::from_frequencies(clrs());
+        let mut builder = HuffmanBuilder::new();
+
+        builder.counts(clrs()).stabilize_against(&previous);
+
+        let (next, moved) = builder.build_counting_moves().unwrap();
+
+        assert_eq!(moved, 0);
+        assert_eq!(next.codes(), previous.codes());
+
+        // Codes the lengths allow are kept even if another training assigned
+        // the patterns differently.
+        let mut swapped = next.clone();
+        let (b, c)      = (swapped.codes[&'b'], swapped.codes[&'c']);
+
+        swapped.codes.insert('b', c);
+        swapped.codes.insert('c', b);
+
+        let (next, moved) = builder.stabilize_against(&swapped)
+                                   .build_counting_moves()
+                                   .unwrap();
+
+        assert_eq!(moved, 0);
+        assert_eq!(next.codes(), swapped.codes());
+    }
+
+    #[test]
+    fn small_perturbation_moves_few_codes() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let previous = HuffmanCodebook::from_text(&text);
+        let mut more = previous.frequencies().clone();
+
+        more.add('q', 2000);
+        more.add('Z', 500);
+
+        let fresh         = HuffmanCodebook::from_frequencies(more.clone());
+        let (next, moved) = HuffmanBuilder::new().counts(more)
+                                                 .stabilize_against(&previous)
+                                                 .build_counting_moves()
+                                                 .unwrap();
+
+        let unstable = fresh.codes().iter().filter(|&(c, code)| {
+            previous.codes().get(c).is_some_and(|old| old != code)
+        }).count();
+
+        for (c, code) in next.codes() {
+            assert_eq!(code.len, fresh.codes()[c].len);
+        }
+        assert!(moved * 4 < next.codes().len());
+        assert!(moved * 2 < unstable);
+        assert!(DecoderFsm::from_codebook(&next).is_ok());
+    }
+
+    #[test]
+    fn large_alphabets() {
+        // Enough symbols that reassigning in quadratic time would show.
+        let freqs = (0x100..).filter_map(char::from_u32)
+                             .take(100_000)
+                             .enumerate()
+                             .map(|(i, c)| (c, i % 7 + 1))
+                             .collect::<FrequencyTable>();
+        let previous = HuffmanCodebook::from_frequencies(freqs.clone());
+
+        let (next, moved) = HuffmanBuilder::new().counts(freqs)
+                                                 .count("a")
+                                                 .stabilize_against(&previous)
+                                                 .build_counting_moves()
+                                                 .unwrap();
+
+        assert!(moved * 10 < next.codes().len());
+        assert!(DecoderFsm::from_codebook(&next).is_ok());
+    }
+}
@@ -0,0 +1,352 @@
+//! The facts about an alphabet that callers, and the crate's own builders,
+//! keep needing after counting, and the strategies chosen from them.
+//! 
+
+#[cfg(feature = "std")]
+use alloc::vec;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::{Handle, NodeMem};
+use crate::codebook::FrequencyTable;
+#[cfg(feature = "std")]
+use crate::codebook::Code;
+#[cfg(feature = "std")]
+use crate::collections::HashMap;
+use crate::tree::Weight;
+
+
+/// Alphabets with more symbols than this have their trees built with two
+/// queues rather than a heap.
+/// 
+const TWO_QUEUE_MIN_SYMBOLS: usize = 256;
+
+/// An alphabet is dense if it has at least one symbol for every this many
+/// slots of an array indexed by char.
+/// 
+const DENSE_RATIO: usize = 4;
+
+
+/// A summary of a frequency table, from `FrequencyTable::summary()`.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AlphabetSummary {
+    /// The number of distinct characters.
+    pub symbols    : usize,
+    /// The number of characters counted.
+    pub total      : usize,
+    /// The lowest count, or 0 for an empty table.
+    pub min_count  : usize,
+    /// The highest count, or 0 for an empty table.
+    pub max_count  : usize,
+    /// The number of characters counted once.
+    pub singletons : usize,
+    /// Whether every character is below U+0100, so fits in a `u8`.
+    pub fits_u8    : bool,
+    /// Whether every character is below U+10000, so fits in a `u16`.
+    pub fits_u16   : bool,
+    /// Whether an array indexed by char, up to the highest character, would
+    /// have at least one symbol for every 4 slots.
+    pub dense      : bool,
+}
+
+/// How a Huffman tree is built.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeBuild {
+    /// By repeatedly taking the two lightest nodes from a heap.
+    Heap,
+    /// From the leaves in order of weight and a queue of the branches made,
+    /// whose weights come out in order.
+    TwoQueue,
+}
+
+/// Counts of the work done building a tree, from
+/// `HuffmanTree::from_frequencies_with_stats()`, for tuning the builders on
+/// large alphabets.
+/// 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuildStats {
+    /// Whether the tree was built with two queues rather than a heap.
+    pub two_queue    : bool,
+    /// The number of times the heap's top was popped or replaced; none for
+    /// two queues.
+    pub heap_ops     : u64,
+    /// The most nodes the heap held; none for two queues.
+    pub max_heap_len : usize,
+    /// The number of times two nodes were compared by weight, including, for
+    /// two queues, in sorting the leaves.
+    pub comparisons  : u64,
+}
+
+/// Watches the tree builders at work. The methods do nothing by default, so
+/// building with the unit probe, as the crate does but for `BuildStats`,
+/// costs nothing.
+/// 
+pub(crate) trait BuildProbe {
+    /// Two nodes were compared by weight.
+    fn compared(&self) {}
+    /// The heap was built with `len` nodes.
+    fn heapified(&self, _len: usize) {}
+    /// The heap's top was popped or replaced.
+    fn heap_op(&self) {}
+    /// The tree is being built with two queues.
+    fn two_queue(&self) {}
+}
+
+impl BuildProbe for () {}
+
+impl BuildProbe for Cell<BuildStats> {
+    fn compared(&self) {
+        self.set(BuildStats { comparisons: self.get().comparisons + 1,
+                              ..self.get() });
+    }
+    fn heapified(&self, len: usize) {
+        self.set(BuildStats { max_heap_len: len, ..self.get() });
+    }
+    fn heap_op(&self) {
+        self.set(BuildStats { heap_ops: self.get().heap_ops + 1,
+                              ..self.get() });
+    }
+    fn two_queue(&self) {
+        self.set(BuildStats { two_queue: true, ..self.get() });
+    }
+}
+
+/// How an encoder looks up the codes for characters.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeLookup {
+    /// In an array indexed by char.
+    Dense,
+    /// In the codebook's `HashMap`.
+    Hashed,
+}
+
+impl AlphabetSummary {
+    /// Two queues for alphabets of more than 256 symbols, and a heap
+    /// otherwise. Both build the same tree.
+    /// 
+    pub fn tree_build(&self) -> TreeBuild {
+        match self.symbols > TWO_QUEUE_MIN_SYMBOLS {
+            true  => TreeBuild::TwoQueue,
+            false => TreeBuild::Heap,
+        }
+    }
+    /// An array for dense alphabets, and the `HashMap` otherwise.
+    /// 
+    pub fn code_lookup(&self) -> CodeLookup {
+        match self.dense {
+            true  => CodeLookup::Dense,
+            false => CodeLookup::Hashed,
+        }
+    }
+}
+
+impl FrequencyTable {
+    /// Summarizes the table.
+    /// 
+    pub fn summary(&self) -> AlphabetSummary {
+        let counts  = self.counts();
+        let highest = counts.keys().max().map_or(0, |&c| c as usize + 1);
+
+        AlphabetSummary {
+            symbols    : counts.len(),
+            total      : self.total(),
+            min_count  : counts.values().copied().min().unwrap_or(0),
+            max_count  : counts.values().copied().max().unwrap_or(0),
+            singletons : counts.values().filter(|&&n| n == 1).count(),
+            fits_u8    : highest <= 0x100,
+            fits_u16   : highest <= 0x1_0000,
+            dense      : !counts.is_empty()
+                         && highest <= counts.len() * DENSE_RATIO,
+        }
+    }
+}
+
+
+/// Builds a Huffman tree over the leaves in `nodes` with two queues: the
+/// leaves, lightest first, and the branches, which are made in order of
+/// weight. Each step merges the lightest two nodes at the queues' fronts. Of
+/// equal weights, leaves go first, and then the node created first, so the
+/// tree is the one `build_huffman_tree()` builds with its heap. Gives the root,
+/// or `None` if there are no nodes.
+/// 
+pub(crate) fn build_two_queue<W: Weight>(nodes : &mut NodeMem<W>,
+                                         probe : &impl BuildProbe) 
+
+    -> Option<Handle> 
+{
+    let mut leaves = (0..nodes.len()).map(|i| Handle(i as u32))
+                                     .collect::<Vec<_>>();
+
+    probe.two_queue();
+
+    leaves.sort_by(|a, b| {
+        probe.compared();
+        nodes.h2node(*a).freq().weight_cmp(&nodes.h2node(*b).freq())
+                               .then(a.0.cmp(&b.0))
+    });
+    let mut leaves   = VecDeque::from(leaves);
+    let mut branches = VecDeque::new();
+
+    fn lightest<W: Weight>(leaves   : &mut VecDeque<Handle>,
+                           branches : &mut VecDeque<Handle>,
+                           nodes    : &NodeMem<W>,
+                           probe    : &impl BuildProbe) 
+
+        -> Option<Handle> 
+    {
+        match (leaves.front(), branches.front()) {
+            (Some(&leaf), Some(&branch)) => {
+                let leaf_w   = nodes.h2node(leaf).freq();
+                let branch_w = nodes.h2node(branch).freq();
+
+                probe.compared();
+
+                match branch_w.weight_cmp(&leaf_w).is_lt() {
+                    true  => branches.pop_front(),
+                    false => leaves.pop_front(),
+                }
+            },
+            (Some(_), None) => leaves.pop_front(),
+            (None, _)       => branches.pop_front(),
+        }
+    }
+
+    loop {
+        let left  = lightest(&mut leaves, &mut branches, nodes, probe);
+        let right = lightest(&mut leaves, &mut branches, nodes, probe);
+
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                let freq = nodes.h2node(left).freq()
+                                .weight_add(nodes.h2node(right).freq());
+
+                branches.push_back(nodes.new_branch(freq, left, right));
+            },
+            (left, _) => return left,
+        }
+    }
+}
+
+/// Codes looked up the way `CodeLookup` says.
+/// 
+#[cfg(feature = "std")]
+pub(crate) enum Lookup<'a> {
+    Dense(Vec<Option<Code>>),
+    Hashed(&'a HashMap<char, Code>),
+}
+
+#[cfg(feature = "std")]
+impl<'a> Lookup<'a> {
+    /// A lookup for `codes`, dense if their alphabet is.
+    /// 
+    pub(crate) fn new(codes: &'a HashMap<char, Code>) -> Self {
+        let alphabet = codes.keys()
+                            .map(|&c| (c, 1))
+                            .collect::<FrequencyTable>();
+
+        match alphabet.summary().code_lookup() {
+            CodeLookup::Dense  => {
+                let len       = codes.keys().max().unwrap();
+                let mut table = vec![None; *len as usize + 1];
+
+                for (&c, &code) in codes {
+                    table[c as usize] = Some(code);
+                }
+                Lookup::Dense(table)
+            },
+            CodeLookup::Hashed => Lookup::Hashed(codes),
+        }
+    }
+    pub(crate) fn kind(&self) -> CodeLookup {
+        match self {
+            Lookup::Dense(_)  => CodeLookup::Dense,
+            Lookup::Hashed(_) => CodeLookup::Hashed,
+        }
+    }
+    pub(crate) fn get(&self, c: char) -> Option<Code> {
+        match self {
+            Lookup::Dense(table) => table.get(c as usize).copied().flatten(),
+            Lookup::Hashed(codes) => codes.get(&c).copied(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_huffman_tree, create_freq_nodes, tree_codes};
+
+    #[test]
+    fn summary_fields() {
+        let freqs = FrequencyTable::from_text("abracadabra é");
+
+        assert_eq!(freqs.summary(), AlphabetSummary {
+            symbols    : 7,
+            total      : 13,
+            min_count  : 1,
+            max_count  : 5,
+            singletons : 4,
+            fits_u8    : true,
+            fits_u16   : true,
+            dense      : false,
+        });
+        assert!(!FrequencyTable::from_text("🦀").summary().fits_u16);
+        assert!(!FrequencyTable::from_text("ā").summary().fits_u8);
+        assert_eq!(FrequencyTable::default().summary(),
+                   AlphabetSummary { fits_u8: true, fits_u16: true,
+                                     ..Default::default() });
+    }
+
+    #[test]
+    fn strategy_thresholds() {
+        let alphabet = |n: u32| {
+            (0..n).map(|i| (char::from_u32(0x100 + i).unwrap(), 1 + i as usize))
+                  .collect::<FrequencyTable>()
+        };
+        assert_eq!(alphabet(256).summary().tree_build(), TreeBuild::Heap);
+        assert_eq!(alphabet(257).summary().tree_build(), TreeBuild::TwoQueue);
+
+        // From U+007F, 43 symbols are the first to fill a quarter of the
+        // slots up to the last of them.
+        let dense = |n: u32| {
+            (0..n).map(|i| (char::from_u32(0x7f + i).unwrap(), 1))
+                  .collect::<FrequencyTable>()
+                  .summary()
+                  .code_lookup()
+        };
+        assert_eq!(dense(1), CodeLookup::Hashed);
+        assert_eq!(dense(42), CodeLookup::Hashed);
+        assert_eq!(dense(43), CodeLookup::Dense);
+    }
+
+    #[test]
+    fn two_queues_build_the_heap_tree() {
+        let text   = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let wide   = (0..1000).map(|i| (char::from_u32(0x4e00 + i).unwrap(),
+                                        1 + (i as usize * 7919) % 13))
+                              .collect::<FrequencyTable>();
+        let tables = [FrequencyTable::from_text(&text), wide,
+                      FrequencyTable::from_text("aaaa"),
+                      FrequencyTable::from_text("abcd"),
+                      FrequencyTable::default()];
+
+        for freqs in &tables {
+            let mut heap  = NodeMem::new();
+            let mut queue = NodeMem::new();
+
+            create_freq_nodes(freqs, &mut heap);
+            create_freq_nodes(freqs, &mut queue);
+
+            let heap_root  = build_huffman_tree(&mut heap);
+            let queue_root = build_two_queue(&mut queue, &());
+
+            assert_eq!(tree_codes(heap_root, &heap),
+                       tree_codes(queue_root, &queue));
+        }
+    }
+}
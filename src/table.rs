@@ -0,0 +1,203 @@
+//! A compact code table for a file header: the lengths of canonical codes,
+//! from which the codes themselves can be rebuilt, as JPEG and DEFLATE store
+//! theirs.
+//! 
+//! The table is laid out as follows. Varints are LEB128, as in `MuxWriter`
+//! streams.
+//! 
+//! | bytes      | contents                                                |
+//! |------------|---------------------------------------------------------|
+//! | 1          | format version, currently 1                             |
+//! | 1          | the length of the longest code, L, or 0 for no codes    |
+//! | varint × L | the number of codes of each length, from 1 to L         |
+//! | ...        | the symbols in UTF-8, in canonical order: shortest code |
+//! |            | first, and in order of symbol within a length           |
+//! 
+
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::varint::{take_varint, write_varint};
+use crate::HuffmanError;
+use crate::canonical::CanonicalDecoder;
+
+
+/// The format version written by `serialize_codes()`.
+/// 
+const VERSION: u8 = 1;
+
+
+/// Serializes the lengths of `codes`, such as the ones
+/// `generate_canonical_codes()` returns, as a table `deserialize_codes()`
+/// reads back. Only the lengths are kept, so the codes read back are the
+/// canonical codes for them, which are `codes` themselves if they were
+/// canonical. See the module docs for the layout.
+/// 
+/// # Panics
+/// 
+/// If a code is longer than 64 bits.
+/// 
+pub fn serialize_codes(codes: &HashMap<char, String>) -> Vec<u8> {
+    let mut sorted = codes.iter()
+                          .map(|(&c, code)| (code.len(), c))
+                          .collect::<Vec<_>>();
+    sorted.sort_unstable();
+
+    let max_len = sorted.last().map_or(0, |&(len, _)| len);
+
+    assert!(max_len <= 64, "code longer than 64 bits");
+
+    let mut bytes = vec![VERSION, max_len as u8];
+
+    for len in 1..=max_len {
+        let count = sorted.iter().filter(|&&(l, _)| l == len).count();
+
+        write_varint(&mut bytes, count as u64);
+    }
+    for (_, c) in sorted {
+        bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    bytes
+}
+
+/// Reads a table written by `serialize_codes()`, giving the canonical codes
+/// for its lengths. Fails with `HuffmanError::UnsupportedVersion` for another
+/// version of the format, and with `HuffmanError::MalformedData` if the table
+/// is truncated or has trailing bytes, a symbol isn't valid UTF-8 or appears
+/// twice, a length is over 64, or the lengths are too short for every symbol
+/// to have a code.
+/// 
+pub fn deserialize_codes(mut bytes: &[u8]) 
+
+    -> Result<HashMap<char, String>, HuffmanError> 
+{
+    let truncated = || HuffmanError::MalformedData("the table is truncated");
+
+    let [version, max_len, ref rest @ ..] = *bytes else {
+        return Err(truncated());
+    };
+    if version != VERSION {
+        return Err(HuffmanError::UnsupportedVersion(version));
+    }
+    if max_len > 64 {
+        return Err(HuffmanError::MalformedData("a code length is out of \
+                                                range"));
+    }
+    bytes = rest;
+
+    let mut counts  = vec![];
+    let mut lengths = vec![];
+
+    for _ in 0..max_len {
+        counts.push(take_varint(&mut bytes)?.ok_or_else(truncated)?);
+    }
+
+    for (len, count) in (1..=max_len).zip(counts) {
+        for _ in 0..count {
+            let (c, rest) = split_char(bytes)?;
+
+            lengths.push((c, len));
+            bytes = rest;
+        }
+    }
+    if !bytes.is_empty() {
+        return Err(HuffmanError::MalformedData("trailing bytes"));
+    }
+    Ok(CanonicalDecoder::from_lengths(&lengths)?
+           .codes()
+           .map(|(c, code)| (c, code.to_code_string()))
+           .collect())
+}
+
+/// The char `bytes` starts with in UTF-8, and the bytes after it.
+/// 
+fn split_char(bytes: &[u8]) -> Result<(char, &[u8]), HuffmanError> {
+    let width = match bytes.first() {
+        None                          => 0,
+        Some(&b) if b < 0x80          => 1,
+        Some(&b) if b & 0xe0 == 0xc0  => 2,
+        Some(&b) if b & 0xf0 == 0xe0  => 3,
+        Some(&b) if b & 0xf8 == 0xf0  => 4,
+        Some(_)                       => 5,
+    };
+    if width == 0 || width < 5 && bytes.len() < width {
+        return Err(HuffmanError::MalformedData("the table is truncated"));
+    }
+    let (head, tail) = bytes.split_at(width.min(bytes.len()));
+
+    match core::str::from_utf8(head).ok().and_then(|s| s.chars().next()) {
+        Some(c) if width < 5 => Ok((c, tail)),
+        _ => Err(HuffmanError::MalformedData("a symbol isn't valid UTF-8")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canonical::generate_canonical_codes;
+    use crate::codebook::generate_huffman_codes;
+
+    #[test]
+    fn round_trips() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "é€🦀";
+        let codes = generate_canonical_codes(&text);
+        let table = serialize_codes(&codes);
+
+        assert_eq!(deserialize_codes(&table).unwrap(), codes);
+
+        // Each symbol costs about a byte, plus a varint per length.
+        assert!(table.len() < codes.len() + 40);
+
+        // Tree codes come back canonical, with the same lengths.
+        let tree = generate_huffman_codes(&text);
+        let back = deserialize_codes(&serialize_codes(&tree)).unwrap();
+
+        assert_eq!(back, codes);
+
+        for texts in ["", "a", "ab", "aab"] {
+            let codes = generate_canonical_codes(texts);
+
+            assert_eq!(deserialize_codes(&serialize_codes(&codes)).unwrap(),
+                       codes);
+        }
+        assert_eq!(serialize_codes(&HashMap::new()), [VERSION, 0]);
+    }
+
+    #[test]
+    fn malformed_tables() {
+        use HuffmanError::*;
+
+        let codes = generate_canonical_codes("abracadabra, é€🦀");
+        let table = serialize_codes(&codes);
+
+        // Every cut is caught, whether between fields or inside a symbol.
+        for len in 0..table.len() {
+            assert_eq!(deserialize_codes(&table[..len]).err(),
+                       Some(MalformedData("the table is truncated")));
+        }
+        assert_eq!(deserialize_codes(&[&table[..], &[0]].concat()).err(),
+                   Some(MalformedData("trailing bytes")));
+        assert_eq!(deserialize_codes(&[2, 0]).err(),
+                   Some(UnsupportedVersion(2)));
+        assert_eq!(deserialize_codes(&[1, 65]).err(),
+                   Some(MalformedData("a code length is out of range")));
+
+        // Three codes of 1 bit.
+        assert_eq!(deserialize_codes(&[1, 1, 3, b'a', b'b', b'c']).err(),
+                   Some(MalformedData("the code lengths are oversubscribed")));
+        assert_eq!(deserialize_codes(&[1, 1, 2, b'a', b'a']).err(),
+                   Some(MalformedData("a symbol appears twice")));
+
+        // A stray continuation byte, a surrogate, and a bad lead byte.
+        for symbol in [&[0x80][..], &[0xed, 0xa0, 0x80], &[0xff]] {
+            let table = [&[1, 1, 1][..], symbol].concat();
+
+            assert_eq!(deserialize_codes(&table).err(),
+                       Some(MalformedData("a symbol isn't valid UTF-8")));
+        }
+    }
+}
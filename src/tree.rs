@@ -0,0 +1,1324 @@
+//! The Huffman tree itself, for callers who want to assign codes their own
+//! way from the crate's counting and tree construction.
+//! 
+//! The statistics of building a tree, and tries of codes, which are trees of
+//! their own, are here too.
+//! 
+
+use alloc::vec;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::error::Error;
+use core::fmt::{self, Write as _};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{create_freq_nodes, grow_tree, grow_tree_probed, tree_code_bits};
+use crate::{tree_codes, BuildProbe, Handle, HuffmanError, Node, NodeMem};
+use crate::MAX_SYMBOLS;
+use crate::canonical::{validate_codes, validate_complete_codes, CodeTableError};
+use crate::codebook::{escape_symbol, Code, EscapeContext, FrequencyTable};
+use crate::collections::{map_with_capacity, HashMap};
+use crate::io::{BitBuffer, BitReader, BitWriter};
+#[cfg(feature = "std")]
+use crate::io::for_each_str_chunk;
+
+pub use crate::summary::{BuildStats, TreeBuild};
+pub use crate::trie::{CodeTrie, Conflict, Walk};
+pub use crate::weight::Weight;
+
+
+/// A Huffman tree built from a frequency table by `build_tree()`.
+/// 
+pub struct HuffmanTree {
+    pub(crate) nodes : NodeMem,
+    /// The root, or `None` for a tree with no leaves.
+    pub(crate) root  : Option<Handle>,
+}
+
+/// Builds the Huffman tree for `freqs`, by repeatedly merging the two lightest
+/// nodes. This is the construction `generate_huffman_codes()` and the
+/// codebooks use, so the tree gives the same codes they do.
+/// 
+/// The result depends only on the counts in `freqs`, not on the order a
+/// `HashMap` happens to hold them in: leaves are created most frequent first
+/// and then in order of character, and of nodes with equal weights, the one
+/// created first is merged first, so leaves are merged before branches of the
+/// same weight.
+/// 
+/// Fails with `HuffmanError::TooManySymbols` if `freqs` has more characters
+/// than a tree can hold, though trees hold 2^31 symbols, more than there are
+/// characters.
+/// 
+pub fn build_tree(freqs: &FrequencyTable) -> Result<HuffmanTree, HuffmanError> {
+    build_tree_probed(freqs, &())
+}
+
+/// Like `build_tree()`, telling `probe` about the work done.
+/// 
+fn build_tree_probed(freqs: &FrequencyTable, probe: &impl BuildProbe) 
+
+    -> Result<HuffmanTree, HuffmanError> 
+{
+    if freqs.len() > MAX_SYMBOLS {
+        return Err(HuffmanError::TooManySymbols {
+            symbols: freqs.len(), max: MAX_SYMBOLS
+        });
+    }
+    let mut nodes = NodeMem::new();
+    let root      = grow_tree_probed(freqs, &mut nodes, probe);
+
+    Ok(HuffmanTree { nodes, root })
+}
+
+/// Adds the subtree for `codes`, sorted, which all start with the same
+/// `depth` bits, and gives its root. Complete codes split into two non-empty
+/// halves on the next bit until one code is left.
+/// 
+fn grow_from_codes(codes: &[(&[u8], char)], depth: usize, nodes: &mut NodeMem) 
+
+    -> Handle 
+{
+    if let [(_, c)] = codes {
+        return nodes.new_leaf(*c, 1);
+    }
+    let split = codes.partition_point(|(code, _)| code[depth] == b'0');
+    let left  = grow_from_codes(&codes[..split], depth + 1, nodes);
+    let right = grow_from_codes(&codes[split..], depth + 1, nodes);
+    let freq  = nodes.h2node(left).freq()
+                     .weight_add(nodes.h2node(right).freq());
+
+    nodes.new_branch(freq, left, right)
+}
+
+impl HuffmanTree {
+    /// Builds the tree for `freqs` as `build_tree()` does, along with counts
+    /// of the work the builder did. Fails as `build_tree()` does.
+    /// 
+    pub fn from_frequencies_with_stats(freqs: &FrequencyTable) 
+
+        -> Result<(Self, BuildStats), HuffmanError> 
+    {
+        let stats = Cell::new(BuildStats::default());
+        let tree  = build_tree_probed(freqs, &stats)?;
+
+        Ok((tree, stats.get()))
+    }
+    /// Rebuilds the tree whose paths are `codes`, as `codes()` gives them, so
+    /// a table read back from elsewhere decodes as the tree it came from did.
+    /// The counts aren't in the codes, so each leaf weighs 1. Fails as
+    /// `validate_complete_codes()` does, with its error converted, unless the
+    /// table is the one code "0" a tree of one leaf has, or empty.
+    /// 
+    pub fn from_codes(codes: &HashMap<char, String>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        match codes.iter().next() {
+            Some((_, code)) if codes.len() == 1 && code != "0" => {
+                return Err(CodeTableError::Incomplete.into());
+            },
+            Some(_) if codes.len() == 1 => validate_codes(codes)?,
+            Some(_)                     => validate_complete_codes(codes)?,
+            None                        => {},
+        }
+        let mut sorted = codes.iter().map(|(&c, code)| (code.as_bytes(), c))
+                                     .collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let mut nodes = NodeMem::new();
+
+        nodes.reserve((2 * sorted.len()).saturating_sub(1));
+
+        let root = (!sorted.is_empty()).then(|| {
+            grow_from_codes(&sorted, 0, &mut nodes)
+        });
+        Ok(HuffmanTree { nodes, root })
+    }
+    /// The code strings given by the paths to the leaves, '0' for a left
+    /// branch and '1' for a right one. A tree with one leaf gives it the code
+    /// "0".
+    /// 
+    pub fn codes(&self) -> HashMap<char, String> {
+        tree_codes(self.root, &self.nodes)
+    }
+    /// The depth of each leaf, which is the length of its code.
+    /// 
+    pub fn depths(&self) -> HashMap<char, usize> {
+        let mut depths = map_with_capacity(self.len());
+        let mut stack  = vec![];
+
+        stack.extend(self.root.map(|root| (root, 0)));
+
+        while let Some((node, depth)) = stack.pop() {
+            match self.nodes.h2node(node) {
+                Node::Leaf { symbol, .. } => {
+                    depths.insert(*symbol, depth);
+                },
+                Node::Branch { left, right, .. } => {
+                    stack.push((*left,  depth + 1));
+                    stack.push((*right, depth + 1));
+                },
+            }
+        }
+        depths
+    }
+    /// The total weight of the leaves.
+    /// 
+    pub fn weight(&self) -> usize {
+        self.root.map_or(0, |root| self.nodes.h2node(root).freq())
+    }
+    /// The number of leaves.
+    /// 
+    pub fn len(&self) -> usize {
+        self.nodes.len().div_ceil(2)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    /// Decodes `bits` by walking the tree from the root: left on a 0, right on
+    /// a 1, emitting the character at each leaf reached. A tree with one leaf,
+    /// whose code is "0", takes a 0 bit for each character.
+    /// 
+    /// Fails with `HuffmanError::InvalidCode` on a bit the tree has no branch
+    /// for, and `HuffmanError::TruncatedBitstream` if the bits end partway
+    /// through a code.
+    /// 
+    pub fn decode(&self, bits: &BitBuffer) -> Result<String, HuffmanError> {
+        let (bytes, nbits) = (bits.as_bytes(), bits.bit_len());
+
+        let Some(root) = self.root else {
+            return match nbits {
+                0 => Ok(String::new()),
+                _ => Err(HuffmanError::InvalidCode {
+                    bit_offset: 0, symbols: 0
+                }),
+            };
+        };
+        let mut text  = String::new();
+        let mut node  = root;
+        let mut start = 0;
+        let mut count = 0;
+
+        for pos in 0..nbits {
+            let bit = bytes[pos / 8] >> (7 - pos % 8) & 1 == 1;
+
+            node = match (self.nodes.h2node(node), bit) {
+                (Node::Branch { left, .. }, false) => *left,
+                (Node::Branch { right, .. }, true) => *right,
+                (Node::Leaf { .. }, false) if node == root => node,
+                _ => {
+                    return Err(HuffmanError::InvalidCode {
+                        bit_offset: start, symbols: count
+                    });
+                },
+            };
+            if let Node::Leaf { symbol, .. } = self.nodes.h2node(node) {
+                text.push(*symbol);
+                node   = root;
+                start  = pos + 1;
+                count += 1;
+            }
+        }
+        if node != root {
+            return Err(HuffmanError::TruncatedBitstream {
+                bit_offset: start, symbols: count
+            });
+        }
+        Ok(text)
+    }
+    /// The tree as a Graphviz digraph. Leaves are boxes labeled with their
+    /// character, escaped as `escape_symbol()` does for DOT, and count;
+    /// branches are circles labeled with the total count under them; and
+    /// each edge is labeled with its bit. A tree with one leaf has no edges,
+    /// and an empty tree no nodes.
+    /// 
+    pub fn to_dot(&self) -> String {
+        let mut dot   = String::from("digraph huffman {\n");
+        let mut stack = vec![];
+
+        stack.extend(self.root);
+
+        while let Some(node) = stack.pop() {
+            let id = node.0;
+
+            match self.nodes.h2node(node) {
+                Node::Leaf { symbol, freq } => {
+                    let symbol = escape_symbol(*symbol, EscapeContext::Dot);
+
+                    writeln!(dot, "    n{id} [shape=box, \
+                                   label=\"'{symbol}'\\n{freq}\"];").unwrap();
+                },
+                Node::Branch { freq, left, right } => {
+                    writeln!(dot, "    n{id} [shape=circle, \
+                                   label=\"{freq}\"];").unwrap();
+
+                    for (bit, child) in [(0, left), (1, right)] {
+                        writeln!(dot, "    n{id} -> n{} [label=\"{bit}\"];",
+                                 child.0).unwrap();
+                    }
+                    stack.push(*right);
+                    stack.push(*left);
+                },
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Draws the tree in plain ASCII, a node to a line, each child indented under
+/// its parent after the bit that leads to it. Leaves show their character,
+/// escaped as `escape_symbol()` does in plain text, and their count; branches
+/// the total count under them.
+/// 
+/// ```text
+/// 5
+/// +-0 'b' 2
+/// `-1 3
+///    +-0 'c' 1
+///    `-1 'a' 2
+/// ```
+/// 
+impl fmt::Display for HuffmanTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stack = vec![];
+        // Whether the node on the path at each depth from 1 is a left child,
+        // with its sibling still to be drawn below it.
+        let mut open  = vec![];
+
+        stack.extend(self.root.map(|root| (root, 0, 0)));
+
+        while let Some((node, depth, bit)) = stack.pop() {
+            if depth > 0 {
+                open.truncate(depth - 1);
+
+                for &open in &open {
+                    f.write_str(if open { "|  " } else { "   " })?;
+                }
+                f.write_str(if bit == 0 { "+-0 " } else { "`-1 " })?;
+                open.push(bit == 0);
+            }
+            match self.nodes.h2node(node) {
+                Node::Leaf { symbol, freq } => {
+                    writeln!(f, "'{}' {}",
+                             escape_symbol(*symbol, EscapeContext::Plain),
+                             freq)?;
+                },
+                Node::Branch { freq, left, right } => {
+                    writeln!(f, "{}", freq)?;
+                    stack.push((*right, depth + 1, 1));
+                    stack.push((*left,  depth + 1, 0));
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the tree for the characters of `data`, as `build_tree()` does, and
+/// draws it as a Graphviz digraph with `HuffmanTree::to_dot()`.
+/// 
+pub fn tree_to_dot(data: &str) -> String {
+    build_tree(&FrequencyTable::from_text(data))
+        .expect("a tree holds every character")
+        .to_dot()
+}
+
+
+/// Builds the Huffman tree for `freqs` as `build_tree()` does, along with a
+/// transcript of the merges that built it, for `replay_transcript()` to check
+/// elsewhere. Nodes are numbered by their place in the tree's arena: the
+/// leaves from 0, in the order `build_tree()` creates them, and then each
+/// branch in the order it's made. The transcript lists the two nodes merged at
+/// each step, the left child first. Fails as `build_tree()` does.
+/// 
+pub fn build_huffman_tree_transcripted(freqs: &FrequencyTable) 
+
+    -> Result<(HuffmanTree, Vec<(u32, u32)>), HuffmanError> 
+{
+    let tree = build_tree(freqs)?;
+
+    // Branches come after the leaves in the arena, in the order they're made.
+    let transcript = (tree.len()..tree.nodes.len()).map(|i| {
+        match tree.nodes.h2node(Handle(i as u32)) {
+            Node::Branch { left, right, .. } => (left.0, right.0),
+            Node::Leaf   { .. } => unreachable!("a leaf after the branches"),
+        }
+    }).collect();
+
+    Ok((tree, transcript))
+}
+
+/// Rebuilds a tree from `freqs` by the merges in `transcript`, checking each
+/// against the merge `build_tree()` makes at that step: the two lightest
+/// nodes not yet merged, and of equal weights, the one numbered first. A
+/// transcript from `build_huffman_tree_transcripted()` for the same counts
+/// replays to the same tree.
+/// 
+/// Fails with `ReplayError::WrongMerge` at the first step that differs, and
+/// with `ReplayError::ExtraMerges` or `ReplayError::MissingMerges` if the
+/// transcript doesn't end with the tree complete.
+/// 
+pub fn replay_transcript(freqs: &FrequencyTable, transcript: &[(u32, u32)]) 
+
+    -> Result<HuffmanTree, ReplayError> 
+{
+    if freqs.len() > MAX_SYMBOLS {
+        return Err(ReplayError::TooManySymbols {
+            symbols: freqs.len(), max: MAX_SYMBOLS
+        });
+    }
+    let mut nodes = NodeMem::new();
+
+    create_freq_nodes(freqs, &mut nodes);
+
+    let leaves   = nodes.len();
+    let mut live = (0..leaves).map(|i| {
+        (nodes.h2node(Handle(i as u32)).freq(), i as u32)
+    }).collect::<BTreeSet<_>>();
+
+    for (step, &(left, right)) in transcript.iter().enumerate() {
+        let (Some(a), Some(b)) = (live.pop_first(), live.pop_first()) else {
+            return Err(ReplayError::ExtraMerges { step });
+        };
+        if (a.1, b.1) != (left, right) {
+            return Err(ReplayError::WrongMerge {
+                step,
+                expected : (a.1, b.1),
+                found    : (left, right),
+            });
+        }
+        let branch = nodes.new_branch(a.0 + b.0, Handle(a.1), Handle(b.1));
+
+        live.insert((a.0 + b.0, branch.0));
+    }
+    if live.len() > 1 {
+        return Err(ReplayError::MissingMerges {
+            found: transcript.len(), needed: leaves - 1
+        });
+    }
+    let root = live.first().map(|&(_, i)| Handle(i));
+
+    Ok(HuffmanTree { nodes, root })
+}
+
+/// The ways a transcript can fail to replay, from `replay_transcript()`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The frequency table has more characters than a tree can hold.
+    TooManySymbols {
+        /// The number of characters.
+        symbols : usize,
+        /// The most a tree can hold.
+        max     : usize,
+    },
+    /// A step merges different nodes than the counts call for.
+    WrongMerge {
+        /// The step, counting from 0.
+        step     : usize,
+        /// The nodes the counts call for.
+        expected : (u32, u32),
+        /// The nodes the transcript merges.
+        found    : (u32, u32),
+    },
+    /// The transcript goes on after the tree is complete.
+    ExtraMerges {
+        /// The first step after the tree is complete.
+        step : usize,
+    },
+    /// The transcript ends before the tree is complete.
+    MissingMerges {
+        /// The number of steps in the transcript.
+        found  : usize,
+        /// The number of steps the tree takes.
+        needed : usize,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::TooManySymbols { symbols, max } => {
+                write!(f, "{} symbols, more than the {} a tree can hold",
+                       symbols, max)
+            },
+            ReplayError::WrongMerge { step, expected, found } => {
+                write!(f, "step {} merges nodes {} and {}, not {} and {}",
+                       step, found.0, found.1, expected.0, expected.1)
+            },
+            ReplayError::ExtraMerges { step } => {
+                write!(f, "the tree is complete before step {}", step)
+            },
+            ReplayError::MissingMerges { found, needed } => {
+                write!(f, "the transcript has {} steps of the {} the tree \
+                           takes", found, needed)
+            },
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+
+/// A Huffman tree kept together with its codes, for encoding any number of
+/// texts with codes trained once, say on a corpus.
+/// 
+pub struct HuffmanCodec {
+    tree      : HuffmanTree,
+    strings   : HashMap<char, String>,
+    codes     : HashMap<char, Code>,
+    /// The characters ordered by the length of their codes, and then by
+    /// character.
+    by_length : Vec<char>,
+}
+
+impl HuffmanCodec {
+    /// Builds the tree and codes for the characters of `data`. Fails as
+    /// `build_tree()` does.
+    /// 
+    pub fn from_text(data: &str) -> Result<Self, HuffmanError> {
+        Self::from_frequencies(&FrequencyTable::from_text(data))
+    }
+    /// Builds the tree and codes for the characters counted in `freqs`. Fails
+    /// as `build_tree()` does, or with `HuffmanError::CodeTooLong` for a code
+    /// longer than 64 bits.
+    /// 
+    pub fn from_frequencies(freqs: &FrequencyTable) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let tree  = build_tree(freqs)?;
+        let codes = tree_code_bits(tree.root, &tree.nodes)
+                        .map_err(HuffmanError::CodeTooLong)?;
+
+        Ok(Self::from_tree(tree, codes))
+    }
+    /// Rebuilds the tree and codes from the code strings `codes()` gives, as
+    /// `HuffmanTree::from_codes()` does, and failing as it does, or with
+    /// `HuffmanError::CodeTooLong` for a code longer than 64 bits.
+    /// 
+    pub fn from_codes(codes: &HashMap<char, String>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let tree = HuffmanTree::from_codes(codes)?;
+        let bits = tree_code_bits(tree.root, &tree.nodes)
+                       .map_err(HuffmanError::CodeTooLong)?;
+
+        Ok(Self::from_tree(tree, bits))
+    }
+    /// Trains codes on the UTF-8 text read from `r` and encodes it to `w`, in
+    /// two passes: one counting its characters, and, after seeking back to
+    /// the start, one writing their codes, packed most significant bit first
+    /// with the last byte padded with zeros. Neither pass holds more than a
+    /// fixed-size buffer of the text, so it may be larger than memory.
+    /// Returns the codec, whose tree decodes what was written, and the number
+    /// of bits written before the padding.
+    /// 
+    /// Fails as `FrequencyTable::from_reader()` does, and with
+    /// `io::ErrorKind::InvalidData` wrapping the `HuffmanError` if the tree
+    /// can't be built, or if the text changed between the passes so that a
+    /// character has no code. Only built with the `std` feature.
+    /// 
+    #[cfg(feature = "std")]
+    pub fn train_and_encode(mut r: impl Read + Seek, mut w: impl Write) 
+
+        -> io::Result<(Self, u64)> 
+    {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let freqs   = FrequencyTable::from_reader(&mut r)?;
+        let codec   = Self::from_frequencies(&freqs).map_err(invalid)?;
+        let mut bits  = BitWriter::new();
+        let mut chars = 0;
+        let mut bytes = 0;
+
+        r.seek(SeekFrom::Start(0))?;
+
+        for_each_str_chunk(&mut r, None, |chunk| {
+            for c in chunk.chars() {
+                let code = codec.codes.get(&c).ok_or_else(|| {
+                    invalid(HuffmanError::SymbolNotInCodebook {
+                        symbol: c, char_index: chars, byte_offset: bytes
+                    })
+                })?;
+                bits.write_code(*code);
+                chars += 1;
+                bytes += c.len_utf8();
+            }
+            w.write_all(&bits.take_complete())
+        })?;
+        let bit_len = bits.bit_len();
+
+        w.write_all(&bits.finish())?;
+        w.flush()?;
+        Ok((codec, bit_len))
+    }
+    fn from_tree(tree: HuffmanTree, codes: HashMap<char, Code>) -> Self {
+        let strings = codes.iter()
+                           .map(|(&c, code)| (c, code.to_code_string()))
+                           .collect();
+
+        let mut by_length = codes.keys().copied().collect::<Vec<_>>();
+
+        by_length.sort_unstable_by_key(|c| (codes[c].len, *c));
+
+        Self { tree, strings, codes, by_length }
+    }
+    /// The tree, which decodes what `encode()` writes.
+    /// 
+    pub fn tree(&self) -> &HuffmanTree {
+        &self.tree
+    }
+    /// The code strings, keyed by character, as `HuffmanTree::codes()` gives
+    /// them.
+    /// 
+    pub fn codes(&self) -> HashMap<char, String> {
+        self.strings.clone()
+    }
+    /// The code string for `char_`, if it has one.
+    /// 
+    pub fn code_for(&self, char_: char) -> Option<&str> {
+        self.strings.get(&char_).map(String::as_str)
+    }
+    /// The characters and their code strings, shortest code first, and of
+    /// codes the same length, in order of character, so the order doesn't
+    /// depend on hash order. This is the order canonical codes are assigned
+    /// in.
+    /// 
+    pub fn iter_by_length(&self) -> impl Iterator<Item = (char, &str)> + '_ {
+        self.by_length.iter().map(|&c| (c, self.strings[&c].as_str()))
+    }
+    /// The codes in the order `iter_by_length()` gives them.
+    /// 
+    pub fn codes_sorted(&self) -> Vec<(char, String)> {
+        self.iter_by_length()
+            .map(|(c, code)| (c, code.to_string()))
+            .collect()
+    }
+    /// The character with the shortest code, the first by character of those
+    /// tied, with its code. `None` if there are no codes.
+    /// 
+    pub fn shortest_code(&self) -> Option<(char, &str)> {
+        self.iter_by_length().next()
+    }
+    /// The character with the longest code, the last by character of those
+    /// tied, with its code. `None` if there are no codes.
+    /// 
+    pub fn longest_code(&self) -> Option<(char, &str)> {
+        self.by_length.last().map(|&c| (c, self.strings[&c].as_str()))
+    }
+    /// Encodes `data`, returning the codes packed most significant bit first.
+    /// Fails with `HuffmanError::SymbolNotInCodebook` at the first character
+    /// the codes weren't trained on.
+    /// 
+    pub fn encode(&self, data: &str) -> Result<BitBuffer, HuffmanError> {
+        let mut writer = BitWriter::new();
+
+        for (char_index, (byte_offset, c)) in data.char_indices().enumerate() {
+            let code = self.codes.get(&c).ok_or(
+                HuffmanError::SymbolNotInCodebook {
+                    symbol: c, char_index, byte_offset
+                }
+            )?;
+            writer.write_code(*code);
+        }
+        Ok(writer.into_buffer())
+    }
+}
+
+
+/// The first character from U+FDD0, where a block of noncharacters starts,
+/// that `freqs` doesn't count, to stand in for the escape leaf of
+/// `HuffmanTree::split_by()`.
+/// 
+fn escape_leaf_symbol(freqs: &FrequencyTable) -> char {
+    ('\u{FDD0}'..).find(|c| !freqs.counts().contains_key(c))
+                   .expect("a tree has fewer symbols than there are chars")
+}
+
+impl HuffmanTree {
+    /// The characters of the leaves, with their counts.
+    /// 
+    fn frequencies(&self) -> FrequencyTable {
+        self.nodes.nodes.iter().filter_map(|node| match node {
+            Node::Leaf   { symbol, freq } => Some((*symbol, *freq)),
+            Node::Branch { .. }           => None,
+        }).collect()
+    }
+    /// Splits the alphabet in two: a tree of the "hot" characters `pred`
+    /// accepts, plus an escape leaf standing for all the others, and a tree
+    /// of those "cold" characters. The escape leaf is weighted by the cold
+    /// characters' total count, so the first tree's weight is this tree's.
+    /// Both trees are built as `build_tree()` builds them from the counts.
+    /// 
+    /// Returns the two trees and the escape leaf's code in the first. The
+    /// escape leaf's character is the first from U+FDD0, a noncharacter,
+    /// that this tree doesn't have, and the first tree's `codes()` list it
+    /// under that character. A cold character is coded as the escape code
+    /// followed by its code in the second tree, as `SplitCodec` does. Fails
+    /// with `HuffmanError::CodeTooLong` if the escape code is longer than 64
+    /// bits.
+    /// 
+    pub fn split_by(&self, pred: impl Fn(char) -> bool) 
+
+        -> Result<(HuffmanTree, HuffmanTree, Code), HuffmanError> 
+    {
+        let freqs  = self.frequencies();
+        let escape = escape_leaf_symbol(&freqs);
+
+        let (hot, cold): (Vec<_>, Vec<_>) = freqs.counts()
+                                                 .iter()
+                                                 .map(|(&c, &n)| (c, n))
+                                                 .partition(|&(c, _)| pred(c));
+        let cold  = cold.into_iter().collect::<FrequencyTable>();
+        let hot   = hot.into_iter()
+                       .chain([(escape, cold.total())])
+                       .collect::<FrequencyTable>();
+
+        let mut hot_nodes  = NodeMem::new();
+        let mut cold_nodes = NodeMem::new();
+        let hot_root       = grow_tree(&hot, &mut hot_nodes);
+        let cold_root      = grow_tree(&cold, &mut cold_nodes);
+
+        let code = tree_code_bits(hot_root, &hot_nodes)
+                       .map_err(HuffmanError::CodeTooLong)?[&escape];
+
+        Ok((HuffmanTree { nodes: hot_nodes,  root: hot_root  },
+            HuffmanTree { nodes: cold_nodes, root: cold_root },
+            code))
+    }
+    /// Reads one code from `r` by walking the tree from the root, returning
+    /// the character of the leaf reached and the code read. A tree with one
+    /// leaf takes a 0 bit for it. Errors give `start` and `symbols` as the
+    /// position.
+    /// 
+    fn read_code(&self, r: &mut BitReader, start: usize, symbols: usize) 
+
+        -> Result<(char, Code), HuffmanError> 
+    {
+        let invalid  = HuffmanError::InvalidCode { 
+            bit_offset: start, symbols 
+        };
+        let Some(root) = self.root else {
+            return Err(invalid);
+        };
+        let mut node = root;
+        let mut code = Code::default();
+
+        loop {
+            let bit = r.read_bit().ok_or(HuffmanError::TruncatedBitstream {
+                bit_offset: start, symbols
+            })?;
+            code = code.append(bit);
+            node = match (self.nodes.h2node(node), bit) {
+                (Node::Branch { left, .. }, false) => *left,
+                (Node::Branch { right, .. }, true) => *right,
+                (Node::Leaf { .. }, false) if node == root => node,
+                _ => return Err(invalid),
+            };
+            if let Node::Leaf { symbol, .. } = self.nodes.h2node(node) {
+                return Ok((*symbol, code));
+            }
+        }
+    }
+}
+
+
+/// Two-level coding over the trees `HuffmanTree::split_by()` gives: hot
+/// characters are coded by the first tree, and cold ones by the escape code
+/// followed by their code in the second.
+/// 
+pub struct SplitCodec {
+    hot        : HuffmanTree,
+    cold       : HuffmanTree,
+    escape     : Code,
+    hot_codes  : HashMap<char, Code>,
+    cold_codes : HashMap<char, Code>,
+}
+
+impl SplitCodec {
+    /// Splits `tree` by `pred`, as `HuffmanTree::split_by()` does, and
+    /// failing as it does, or with `HuffmanError::CodeTooLong` for a code
+    /// longer than 64 bits in either tree.
+    /// 
+    pub fn new(tree: &HuffmanTree, pred: impl Fn(char) -> bool) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        let (hot, cold, escape) = tree.split_by(pred)?;
+
+        let mut hot_codes = tree_code_bits(hot.root, &hot.nodes)
+                                .map_err(HuffmanError::CodeTooLong)?;
+        let cold_codes    = tree_code_bits(cold.root, &cold.nodes)
+                                .map_err(HuffmanError::CodeTooLong)?;
+
+        hot_codes.retain(|_, &mut code| code != escape);
+
+        Ok(Self { hot, cold, escape, hot_codes, cold_codes })
+    }
+    /// The tree of the hot characters and the escape leaf.
+    /// 
+    pub fn hot(&self) -> &HuffmanTree {
+        &self.hot
+    }
+    /// The tree of the cold characters.
+    /// 
+    pub fn cold(&self) -> &HuffmanTree {
+        &self.cold
+    }
+    /// The escape leaf's code in the hot tree.
+    /// 
+    pub fn escape(&self) -> Code {
+        self.escape
+    }
+    /// The code for `char_`: its code in the hot tree, or the escape code
+    /// followed by its code in the cold tree. `None` if it has neither, or
+    /// if the two together are longer than 64 bits.
+    /// 
+    pub fn code_for(&self, char_: char) -> Option<Code> {
+        match self.hot_codes.get(&char_) {
+            Some(&code) => Some(code),
+            None        => self.escape.concat(*self.cold_codes.get(&char_)?),
+        }
+    }
+    /// Encodes `data`, returning the codes packed most significant bit first.
+    /// Fails with `HuffmanError::SymbolNotInCodebook` at the first character
+    /// neither tree has.
+    /// 
+    pub fn encode(&self, data: &str) -> Result<BitBuffer, HuffmanError> {
+        let mut writer = BitWriter::new();
+
+        for (char_index, (byte_offset, c)) in data.char_indices().enumerate() {
+            let not_found = HuffmanError::SymbolNotInCodebook {
+                symbol: c, char_index, byte_offset
+            };
+            match self.hot_codes.get(&c) {
+                Some(&code) => writer.write_code(code),
+                None => {
+                    let code = self.cold_codes.get(&c).ok_or(not_found)?;
+
+                    writer.write_code(self.escape);
+                    writer.write_code(*code);
+                },
+            }
+        }
+        Ok(writer.into_buffer())
+    }
+    /// Decodes `bits` as written by `encode()`. Fails with
+    /// `HuffmanError::InvalidCode` on a bit neither tree has a branch for,
+    /// and `HuffmanError::TruncatedBitstream` if the bits end partway through
+    /// a code. Errors give the offset where the character's code started,
+    /// counting its escape code.
+    /// 
+    pub fn decode(&self, bits: &BitBuffer) -> Result<String, HuffmanError> {
+        let mut r       = bits.reader();
+        let mut text    = String::new();
+        let mut symbols = 0;
+
+        while r.remaining() > 0 {
+            let start = r.position();
+
+            let (mut c, code) = self.hot.read_code(&mut r, start, symbols)?;
+
+            if code == self.escape {
+                c = self.cold.read_code(&mut r, start, symbols)?.0;
+            }
+            text.push(c);
+            symbols += 1;
+        }
+        Ok(text)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use crate::codebook::{count_frequencies, generate_huffman_codes};
+
+    #[test]
+    fn composes_to_generate_huffman_codes() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let freqs = count_frequencies(&text);
+        let tree  = build_tree(&freqs).unwrap();
+        let codes = tree.codes();
+
+        assert_eq!(codes, generate_huffman_codes(&text));
+        assert_eq!(tree.len(), freqs.len());
+        assert_eq!(tree.weight(), text.chars().count());
+        assert!(tree.depths().iter().all(|(c, &d)| codes[c].len() == d));
+
+        let empty = build_tree(&count_frequencies("")).unwrap();
+
+        assert!(empty.is_empty() && empty.codes().is_empty());
+        assert_eq!((empty.len(), empty.weight()), (0, 0));
+    }
+
+    #[test]
+    fn build_stats() {
+        let stats = |text: &str| {
+            let freqs         = count_frequencies(text);
+            let (tree, stats) = HuffmanTree::from_frequencies_with_stats(
+                                    &freqs).unwrap();
+
+            assert_eq!(tree.codes(), build_tree(&freqs).unwrap().codes());
+            stats
+        };
+        let heap = |heap_ops, max_heap_len, comparisons| BuildStats {
+            two_queue: false, heap_ops, max_heap_len, comparisons
+        };
+        // One leaf is popped alone. Two are put in order with a comparison,
+        // and then the lighter is popped, the heavier replaced by the branch
+        // and the branch popped, each from a heap of at most one node, so
+        // with nothing to compare.
+        assert_eq!(stats(""), heap(0, 0, 0));
+        assert_eq!(stats("a"), heap(1, 1, 0));
+        assert_eq!(stats("abb"), heap(3, 2, 1));
+
+        // Each of the n - 1 merges pops and replaces, and the root is popped
+        // last. The comparisons depend on how `heapq` sifts.
+        let three = stats("abbccc");
+
+        assert_eq!((three.heap_ops, three.max_heap_len), (5, 3));
+        assert!(three.comparisons >= 3);
+
+        let wide  = (0..300).filter_map(char::from_u32).collect::<String>();
+        let wide  = stats(&wide);
+
+        assert!(wide.two_queue);
+        assert_eq!((wide.heap_ops, wide.max_heap_len), (0, 0));
+        assert!(wide.comparisons >= 299);
+    }
+
+    #[test]
+    fn ties_are_broken_by_order() {
+        // Every count ties, so only the tie-breaking decides the shape.
+        let tree  = build_tree(&count_frequencies("dcba")).unwrap();
+        let codes = tree.codes();
+
+        assert_eq!(codes, HashMap::from([('a', "00".into()), 
+                                         ('b', "01".into()),
+                                         ('c', "10".into()), 
+                                         ('d', "11".into())]));
+
+        for _ in 0..10 {
+            assert_eq!(generate_huffman_codes("abcd"), codes);
+        }
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let tree = build_tree(&count_frequencies(&text)).unwrap();
+
+        let bits = crate::io::encode(&text);
+
+        assert_eq!(tree.decode(&bits), Ok(text.clone()));
+
+        // Dropping the last bit leaves the last code unfinished.
+        let last  = tree.codes()[&text.chars().last().unwrap()].len();
+        let nbits = bits.bit_len();
+        let cut   = BitBuffer::try_new_lenient(bits.into_parts().0, nbits - 1)
+                              .unwrap();
+
+        assert_eq!(tree.decode(&cut),
+                   Err(HuffmanError::TruncatedBitstream {
+                       bit_offset : nbits - last,
+                       symbols    : text.chars().count() - 1,
+                   }));
+
+        let lone   = build_tree(&count_frequencies("aaa")).unwrap();
+        let packed = |bytes: &[u8], nbits| {
+            BitBuffer::try_new(bytes.to_vec(), nbits).unwrap()
+        };
+        assert_eq!(lone.decode(&crate::io::encode("aaa")), Ok("aaa".into()));
+        assert_eq!(lone.decode(&packed(&[0b0010_0000], 3)),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 2, symbols: 2
+                   }));
+
+        let empty = build_tree(&count_frequencies("")).unwrap();
+
+        assert_eq!(empty.decode(&packed(&[], 0)), Ok(String::new()));
+        assert_eq!(empty.decode(&packed(&[0], 1)),
+                   Err(HuffmanError::InvalidCode {
+                       bit_offset: 0, symbols: 0
+                   }));
+    }
+
+    #[test]
+    fn codec_reuse() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+
+        assert_eq!(codec.codes(), generate_huffman_codes(&text));
+        assert_eq!(codec.code_for('e'), Some(codec.codes()[&'e'].as_str()));
+        assert_eq!(codec.code_for('\0'), None);
+        assert_eq!(codec.encode(&text).unwrap(), crate::io::encode(&text));
+
+        for message in text.split_terminator('.').take(50) {
+            let bits = codec.encode(message).unwrap();
+
+            assert_eq!(codec.tree().decode(&bits).unwrap(), message);
+        }
+        assert_eq!(codec.encode("whale\0"),
+                   Err(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\0', char_index: 5, byte_offset: 5
+                   }));
+
+        let lone = HuffmanCodec::from_text("zz").unwrap();
+
+        assert_eq!(lone.code_for('z'), Some("0"));
+        assert_eq!(lone.encode("zzz").map(BitBuffer::into_parts),
+                   Ok((vec![0], 3)));
+    }
+
+    #[test]
+    fn rebuilt_from_codes() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "é€🦀";
+        let codec = HuffmanCodec::from_text(&text).unwrap();
+        let back  = HuffmanCodec::from_codes(&codec.codes()).unwrap();
+        let bits  = codec.encode(&text).unwrap();
+
+        assert_eq!(back.codes(), codec.codes());
+        assert_eq!(back.codes_sorted(), codec.codes_sorted());
+        assert_eq!(back.encode(&text).unwrap(), bits);
+        assert_eq!(back.tree().decode(&bits).unwrap(), text);
+        assert_eq!(back.tree().len(), codec.tree().len());
+        assert_eq!(back.tree().weight(), codec.tree().len());
+
+        for text in ["zz", ""] {
+            let codec = HuffmanCodec::from_text(text).unwrap();
+
+            assert_eq!(HuffmanCodec::from_codes(&codec.codes()).unwrap()
+                                    .codes(),
+                       codec.codes());
+        }
+        let table = |codes: &[(char, &str)]| {
+            codes.iter()
+                 .map(|&(c, code)| (c, code.to_string()))
+                 .collect::<HashMap<_, _>>()
+        };
+        let incomplete = HuffmanError::from(CodeTableError::Incomplete);
+
+        assert_eq!(HuffmanTree::from_codes(&table(&[('a', "0"), ('b', "10")]))
+                       .err(),
+                   Some(incomplete.clone()));
+        assert_eq!(HuffmanTree::from_codes(&table(&[('a', "1")])).err(),
+                   Some(incomplete));
+        assert_eq!(HuffmanTree::from_codes(&table(&[('a', "0"), ('b', "01"),
+                                                    ('c', "1")])).err(),
+                   Some(HuffmanError::NotPrefixFree('a', 'b')));
+
+        let long = (0..66).map(|i| {
+            let c = char::from_u32(0x100 + i).unwrap();
+            let code = match i {
+                65 => "1".repeat(65),
+                _  => "1".repeat(i as usize) + "0",
+            };
+            (c, code)
+        }).collect::<HashMap<_, _>>();
+
+        assert!(HuffmanTree::from_codes(&long).is_ok());
+        assert!(matches!(HuffmanCodec::from_codes(&long),
+                         Err(HuffmanError::CodeTooLong(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn codes_too_long() {
+        // 70 Fibonacci counts, as a table read from CSV might have them,
+        // build a tree 69 levels deep.
+        let mut fib = (1_usize, 1_usize);
+        let mut csv = String::new();
+
+        for i in 0..70 {
+            csv += &format!("{},{}\n", char::from_u32(0x100 + i).unwrap(), 
+                            fib.0);
+            fib = (fib.1, fib.0 + fib.1);
+        }
+        let freqs = FrequencyTable::from_csv_reader(csv.as_bytes()).unwrap();
+        let tree  = build_tree(&freqs).unwrap();
+
+        assert!(matches!(HuffmanCodec::from_frequencies(&freqs),
+                         Err(HuffmanError::CodeTooLong(_))));
+        assert!(matches!(tree.split_by(|_| true),
+                         Err(HuffmanError::CodeTooLong(_))));
+        assert!(matches!(SplitCodec::new(&tree, |_| false),
+                         Err(HuffmanError::CodeTooLong(_))));
+        assert!(SplitCodec::new(&tree, |c| (c as u32) < 0x100 + 35).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trains_and_encodes_a_file() {
+        let moby = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let mut text = String::new();
+
+        // Multi-byte characters throughout, so some straddle the buffers.
+        while text.len() < 4 << 20 {
+            for line in moby.lines() {
+                text.push_str(line);
+                text.push_str("é€🦀\n");
+            }
+        }
+        assert!((1..text.len() / crate::io::READ_BUF_SIZE).any(|i| {
+            !text.is_char_boundary(i * crate::io::READ_BUF_SIZE)
+        }));
+        let path = std::env::temp_dir().join(
+            format!("huffman-train-{}.txt", std::process::id()));
+
+        std::fs::write(&path, &text).unwrap();
+
+        let file           = std::fs::File::open(&path).unwrap();
+        let mut packed     = vec![];
+        let (codec, nbits) = HuffmanCodec::train_and_encode(file, &mut packed)
+                                 .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(codec.codes(), generate_huffman_codes(&text));
+        assert_eq!(packed.len() as u64, nbits.div_ceil(8));
+
+        let bits = BitBuffer::try_new(packed, nbits as usize).unwrap();
+
+        assert_eq!(bits, codec.encode(&text).unwrap());
+        assert_eq!(codec.tree().decode(&bits).unwrap(), text);
+
+        // Empty, and not UTF-8.
+        let mut packed     = vec![];
+        let (empty, nbits) = HuffmanCodec::train_and_encode(
+            io::Cursor::new(b""), &mut packed).unwrap();
+
+        assert_eq!((empty.codes().len(), nbits, packed), (0, 0, vec![]));
+
+        let err = HuffmanCodec::train_and_encode(io::Cursor::new(b"ab\xff"),
+                                                 io::sink()).err().unwrap();
+
+        assert_eq!(HuffmanError::from(err), HuffmanError::InvalidUtf8);
+    }
+
+    #[test]
+    fn codes_by_length() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let codec = HuffmanCodec::from_text(&(text + "é€🦀")).unwrap();
+        let codes = codec.codes_sorted();
+
+        // Ordered by length and then character, covering the map exactly.
+        assert!(codes.windows(2).all(|pair| {
+            (pair[0].1.len(), pair[0].0) < (pair[1].1.len(), pair[1].0)
+        }));
+        assert_eq!(codes.iter().cloned().collect::<HashMap<_, _>>(),
+                   codec.codes());
+        assert!(codec.iter_by_length()
+                     .map(|(c, code)| (c, code.to_string()))
+                     .eq(codes.iter().cloned()));
+
+        let (first, last) = (&codes[0], &codes[codes.len() - 1]);
+
+        assert_eq!(codec.shortest_code(), Some((first.0, first.1.as_str())));
+        assert_eq!(codec.longest_code(), Some((last.0, last.1.as_str())));
+        assert_eq!(codec.shortest_code().map(|(c, _)| c), Some(' '));
+
+        // Ties go in order of character.
+        let even = HuffmanCodec::from_text("dcba").unwrap();
+
+        assert_eq!(even.codes_sorted().iter()
+                       .map(|&(c, _)| c)
+                       .collect::<String>(),
+                   "abcd");
+        assert_eq!(even.shortest_code().map(|(c, _)| c), Some('a'));
+        assert_eq!(even.longest_code().map(|(c, _)| c), Some('d'));
+
+        let empty = HuffmanCodec::from_text("").unwrap();
+
+        assert_eq!(empty.codes_sorted(), []);
+        assert_eq!((empty.shortest_code(), empty.longest_code()), (None, None));
+    }
+
+    #[test]
+    fn transcripts_replay() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let wide  = (0..1000).map(|i| (char::from_u32(0x4e00 + i).unwrap(),
+                                       1 + (i as usize * 7919) % 13))
+                             .collect::<FrequencyTable>();
+        let texts = [count_frequencies(&text), wide, count_frequencies("aaa"),
+                     count_frequencies("")];
+
+        for freqs in &texts {
+            let (tree, transcript) = build_huffman_tree_transcripted(freqs)
+                                         .unwrap();
+            let replayed = replay_transcript(freqs, &transcript).unwrap();
+
+            assert_eq!(transcript.len(), freqs.len().saturating_sub(1));
+            assert_eq!(replayed.codes(), tree.codes());
+            assert_eq!(replayed.codes(), build_tree(freqs).unwrap().codes());
+        }
+    }
+
+    #[test]
+    fn tampered_transcripts() {
+        let freqs = count_frequencies("abracadabra");
+        let (_, transcript) = build_huffman_tree_transcripted(&freqs).unwrap();
+
+        // The leaves are a, b, r, c, d; c and d, the lightest, merge first.
+        assert_eq!(transcript, [(3, 4), (1, 2), (5, 6), (0, 7)]);
+
+        let mut swapped = transcript.clone();
+
+        swapped[1] = (2, 1);
+
+        assert_eq!(replay_transcript(&freqs, &swapped).err(),
+                   Some(ReplayError::WrongMerge {
+                       step: 1, expected: (1, 2), found: (2, 1)
+                   }));
+
+        // With an 'a' made an 'r', 'b' ties with the first branch instead.
+        assert_eq!(replay_transcript(&count_frequencies("abracadabrr"),
+                                     &transcript).err(),
+                   Some(ReplayError::WrongMerge {
+                       step: 1, expected: (2, 5), found: (1, 2)
+                   }));
+        assert_eq!(replay_transcript(&freqs, &transcript[..3]).err(),
+                   Some(ReplayError::MissingMerges { found: 3, needed: 4 }));
+        assert_eq!(replay_transcript(&freqs, &[transcript.clone(),
+                                               vec![(8, 8)]].concat()).err(),
+                   Some(ReplayError::ExtraMerges { step: 4 }));
+    }
+
+    #[test]
+    fn split_round_trips() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let tree  = build_tree(&count_frequencies(&text)).unwrap();
+        let codec = SplitCodec::new(&tree, |c| c.is_ascii_lowercase())
+                        .unwrap();
+
+        assert_eq!(codec.hot().weight(), tree.weight());
+        assert_eq!(codec.hot().len() + codec.cold().len(), tree.len() + 1);
+        assert_eq!(codec.hot().codes()[&'\u{FDD0}'], 
+                   codec.escape().to_string());
+
+        let bits = codec.encode(&text).unwrap();
+
+        assert_eq!(codec.decode(&bits).unwrap(), text);
+        assert_eq!(codec.encode("a\u{0}").err(),
+                   Some(HuffmanError::SymbolNotInCodebook {
+                       symbol: '\0', char_index: 1, byte_offset: 1
+                   }));
+
+        // A dangling escape code.
+        let escape = codec.escape();
+        let mut w  = BitWriter::new();
+
+        w.write_code(codec.code_for('a').unwrap());
+        w.write_code(escape);
+
+        assert_eq!(codec.decode(&w.into_buffer()).err(),
+                   Some(HuffmanError::TruncatedBitstream {
+                       bit_offset: codec.code_for('a').unwrap().len as usize,
+                       symbols: 1
+                   }));
+
+        // Everything hot, so the escape leaf has no weight, and nothing hot,
+        // so the escape is the hot tree's only code.
+        for pred in [|_| true, |_| false] {
+            let codec = SplitCodec::new(&tree, pred).unwrap();
+
+            assert_eq!(codec.decode(&codec.encode(&text).unwrap()).unwrap(),
+                       text);
+        }
+        let codec = SplitCodec::new(&tree, |_| false).unwrap();
+
+        assert_eq!(codec.escape().to_string(), "0");
+        assert_eq!(codec.code_for('e').unwrap().to_string(),
+                   format!("0{}", tree.codes()[&'e']));
+    }
+
+    #[test]
+    fn split_costs() {
+        // A Zipf distribution over 300 characters, split after the top 16.
+        let freqs = (0..300_u32).map(|i| {
+            (char::from_u32(0x100 + i).unwrap(), 1_000_000 / (i as usize + 1))
+        }).collect::<FrequencyTable>();
+        let tree  = build_tree(&freqs).unwrap();
+        let codec = SplitCodec::new(&tree, |c| (c as u32) < 0x110).unwrap();
+        let flat  = tree.codes();
+
+        let flat_bits  = freqs.counts().iter().map(|(c, &n)| {
+            flat[c].len() * n
+        }).sum::<usize>();
+        let split_bits = freqs.counts().iter().map(|(&c, &n)| {
+            codec.code_for(c).unwrap().len as usize * n
+        }).sum::<usize>();
+
+        // Huffman codes are optimal, so the split can only cost more, but
+        // not much: the escape takes the place of a subtree of about the
+        // cold characters' weight.
+        assert!(split_bits >= flat_bits);
+        assert!((split_bits - flat_bits) as f64 <= flat_bits as f64 * 0.02,
+                "{} vs {}", split_bits, flat_bits);
+        assert_eq!(codec.hot().len(), 17);
+
+        let text = freqs.sample(20_000, 1);
+
+        assert_eq!(codec.decode(&codec.encode(&text).unwrap()).unwrap(), 
+                   text);
+    }
+
+    #[test]
+    fn dot_and_text() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let dot  = tree_to_dot(&text);
+        let tree = build_tree(&count_frequencies(&text)).unwrap();
+        let n    = tree.len();
+
+        assert!(dot.starts_with("digraph huffman {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches(" [shape=").count(), 2 * n - 1);
+        assert_eq!(dot.matches(" [shape=box").count(), n);
+        assert_eq!(dot.matches(" -> ").count(), 2 * n - 2);
+        assert_eq!(dot, tree.to_dot());
+
+        for c in tree.codes().into_keys() {
+            let label = format!("label=\"'{}'\\n",
+                                escape_symbol(c, EscapeContext::Dot));
+            assert_eq!(dot.matches(&label).count(), 1, "{:?}", c);
+        }
+        assert_eq!(tree.to_string().lines().count(), 2 * n - 1);
+
+        // Quotes and backslashes are escaped, in DOT as in plain text.
+        let tree = build_tree(&count_frequencies("aabbc")).unwrap();
+
+        assert_eq!(tree.to_string(), "5\n\
+                                      +-0 'b' 2\n\
+                                      `-1 3\n   \
+                                         +-0 'c' 1\n   \
+                                         `-1 'a' 2\n");
+        assert_eq!(tree_to_dot("\"\\\\"),
+                   "digraph huffman {\n    \
+                        n2 [shape=circle, label=\"3\"];\n    \
+                        n2 -> n1 [label=\"0\"];\n    \
+                        n2 -> n0 [label=\"1\"];\n    \
+                        n1 [shape=box, label=\"'\\\"'\\n1\"];\n    \
+                        n0 [shape=box, label=\"'\\\\'\\n2\"];\n\
+                    }\n");
+        assert!(build_tree(&count_frequencies("\\\"\"")).unwrap()
+                    .to_string().contains("'\\\\' 1"));
+
+        // A lone leaf has no edges, and an empty tree nothing at all.
+        assert_eq!(tree_to_dot("aaa"),
+                   "digraph huffman {\n    \
+                        n0 [shape=box, label=\"'a'\\n3\"];\n\
+                    }\n");
+        assert_eq!(build_tree(&count_frequencies("aaa")).unwrap().to_string(),
+                   "'a' 3\n");
+        assert_eq!(tree_to_dot(""), "digraph huffman {\n}\n");
+        assert_eq!(build_tree(&count_frequencies("")).unwrap().to_string(),
+                   "");
+    }
+}
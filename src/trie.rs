@@ -0,0 +1,330 @@
+//! A binary trie over codes, for callers who read bits from sources of their
+//! own and want to drive decoding a bit at a time.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::io::Transition;
+
+
+/// Why a code couldn't be added to a `CodeTrie`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// The symbol's code is empty, or has bits set beyond its length.
+    Malformed(char),
+    /// The first symbol's code is a prefix of the second's, or the same code.
+    NotPrefixFree(char, char),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        HuffmanError::from(*self).fmt(f)
+    }
+}
+
+impl core::error::Error for Conflict {}
+
+impl From<Conflict> for HuffmanError {
+    fn from(conflict: Conflict) -> Self {
+        match conflict {
+            Conflict::Malformed(c)        => HuffmanError::MalformedCode(c),
+            Conflict::NotPrefixFree(a, b) => HuffmanError::NotPrefixFree(a, b),
+        }
+    }
+}
+
+/// Where a bit fed to `CodeTrie::walk()` led.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Walk {
+    /// Partway through a code; more bits are needed.
+    Internal,
+    /// The end of this symbol's code.
+    Leaf(char),
+    /// The bits fed so far aren't the start of any code.
+    Dead,
+}
+
+
+/// A binary trie of prefix-free codes. Each node is a pair of transitions,
+/// one per bit, and node 0 is the root, where every code starts.
+/// 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeTrie {
+    nodes : Vec<[Transition; 2]>,
+    len   : usize,
+}
+
+impl Default for CodeTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeTrie {
+    /// An empty trie.
+    /// 
+    pub fn new() -> Self {
+        Self { nodes: vec![[Transition::Invalid; 2]], len: 0 }
+    }
+    /// A trie of the codes of `codebook`. Nodes are numbered in the order the
+    /// codes sort in, so the same codes always give the same trie. Fails if
+    /// the codebook has an empty code.
+    /// 
+    pub fn from_codebook(codebook: &HuffmanCodebook) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        Self::from_codes(codebook.codes().iter()
+                                         .map(|(&c, &code)| (code, c))
+                                         .collect())
+    }
+    /// A trie of `codes`, inserted in the order they sort in.
+    /// 
+    pub(crate) fn from_codes(mut codes: Vec<(Code, char)>) 
+
+        -> Result<Self, HuffmanError> 
+    {
+        codes.sort_unstable_by_key(|&(code, c)| (code.left_aligned(),
+                                                 code.len, c));
+
+        // A complete code over n symbols needs n - 1 nodes.
+        let mut trie = Self {
+            nodes : Vec::with_capacity(codes.len().max(2) - 1),
+            len   : 0,
+        };
+        trie.nodes.push([Transition::Invalid; 2]);
+
+        for (code, c) in codes {
+            trie.insert(code, c)?;
+        }
+        Ok(trie)
+    }
+    /// Adds the path that leads from the root to `symbol` on reading `code`.
+    /// The trie is left as it was if the code conflicts with one already in
+    /// it.
+    /// 
+    pub fn insert(&mut self, code: Code, symbol: char) -> Result<(), Conflict> {
+        if code.len == 0 || !code.is_valid() {
+            return Err(Conflict::Malformed(symbol));
+        }
+        // Find where the path leaves the trie before changing anything.
+        let mut node  = 0;
+        let mut depth = code.len;
+
+        while depth > 1 {
+            let bit = (code.bits >> (depth - 1) & 1) as usize;
+
+            match self.nodes[node][bit] {
+                Transition::Next(next) => node = next as usize,
+                Transition::Emit(d)    => {
+                    return Err(Conflict::NotPrefixFree(d, symbol));
+                },
+                Transition::Invalid    => break,
+            }
+            depth -= 1;
+        }
+        if depth == 1 {
+            match self.nodes[node][(code.bits & 1) as usize] {
+                Transition::Invalid   => {},
+                Transition::Emit(d)   => {
+                    return Err(Conflict::NotPrefixFree(d, symbol));
+                },
+                Transition::Next(next) => {
+                    return Err(Conflict::NotPrefixFree(symbol,
+                                                       self.first_leaf(next)));
+                },
+            }
+        }
+        for i in (1..depth).rev() {
+            let bit = (code.bits >> i & 1) as usize;
+
+            self.nodes.push([Transition::Invalid; 2]);
+            self.nodes[node][bit] = Transition::Next(self.nodes.len() as u32
+                                                     - 1);
+            node = self.nodes.len() - 1;
+        }
+        self.nodes[node][(code.bits & 1) as usize] = Transition::Emit(symbol);
+        self.len += 1;
+        Ok(())
+    }
+    /// Any symbol reachable from `node`.
+    /// 
+    fn first_leaf(&self, mut node: u32) -> char {
+        loop {
+            match self.nodes[node as usize] {
+                [Transition::Emit(c), _] | [_, Transition::Emit(c)] => {
+                    return c;
+                },
+                [Transition::Next(n), _] | [_, Transition::Next(n)] => {
+                    node = n;
+                },
+                _ => unreachable!("nodes always lead to a symbol"),
+            }
+        }
+    }
+    /// The symbol whose code is exactly `code`, if any.
+    /// 
+    pub fn lookup_exact(&self, code: Code) -> Option<char> {
+        if code.len == 0 || !code.is_valid() {
+            return None;
+        }
+        let mut state = 0;
+
+        for i in (0..code.len).rev() {
+            match self.walk(&mut state, code.bits >> i & 1 == 1) {
+                Walk::Internal => {},
+                Walk::Leaf(c)  => return (i == 0).then_some(c),
+                Walk::Dead     => return None,
+            }
+        }
+        None
+    }
+    /// Follows `bit` from the node `state`, which starts at 0, the root. On
+    /// reaching a leaf or a dead end, `state` goes back to the root, ready for
+    /// the next code.
+    /// 
+    pub fn walk(&self, state: &mut u32, bit: bool) -> Walk {
+        match self.nodes[*state as usize][bit as usize] {
+            Transition::Next(next) => {
+                *state = next;
+                Walk::Internal
+            },
+            Transition::Emit(c) => {
+                *state = 0;
+                Walk::Leaf(c)
+            },
+            Transition::Invalid => {
+                *state = 0;
+                Walk::Dead
+            },
+        }
+    }
+    /// The codes and their symbols, in the order the codes sort in as
+    /// strings.
+    /// 
+    pub fn iter(&self) -> impl Iterator<Item = (Code, char)> + '_ {
+        let [zero, one] = self.nodes[0];
+        let mut stack   = vec![(one,  Code { bits: 1, len: 1 }),
+                               (zero, Code { bits: 0, len: 1 })];
+
+        core::iter::from_fn(move || {
+            while let Some((transition, code)) = stack.pop() {
+                match transition {
+                    Transition::Emit(c)    => return Some((code, c)),
+                    Transition::Next(next) => {
+                        let [zero, one] = self.nodes[next as usize];
+
+                        stack.push((one,  code.append(true)));
+                        stack.push((zero, code.append(false)));
+                    },
+                    Transition::Invalid    => {},
+                }
+            }
+            None
+        })
+    }
+    /// The number of codes.
+    /// 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// The transitions, indexed by node and then by bit.
+    /// 
+    pub(crate) fn into_transitions(self) -> Vec<[Transition; 2]> {
+        self.nodes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static CODES: &[(char, u64, u8)] = &[('a', 0b0,   1), ('b', 0b100, 3),
+                                         ('c', 0b101, 3), ('d', 0b11,  2)];
+
+    fn code(s: &str) -> Code {
+        Code::from_str_binary(s).unwrap()
+    }
+
+    #[test]
+    fn walking_bit_by_bit() {
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+        let trie     = CodeTrie::from_codebook(&codebook).unwrap();
+        let mut state = 0;
+
+        let walks = "0101110100".chars()
+                                .map(|b| trie.walk(&mut state, b == '1'))
+                                .collect::<Vec<_>>();
+        use Walk::*;
+
+        assert_eq!(walks, [Leaf('a'), Internal, Internal, Leaf('c'),
+                           Internal, Leaf('d'), Leaf('a'), Internal,
+                           Internal, Leaf('b')]);
+        assert_eq!(state, 0);
+
+        static INCOMPLETE: &[(char, u64, u8)] = &[('a', 0b0, 1),
+                                                  ('b', 0b10, 2)];
+        let codebook = HuffmanCodebook::from_static(INCOMPLETE).unwrap();
+        let trie     = CodeTrie::from_codebook(&codebook).unwrap();
+
+        assert_eq!(trie.walk(&mut state, true), Internal);
+        assert_eq!(trie.walk(&mut state, true), Dead);
+        assert_eq!(state, 0);
+        assert_eq!(trie.walk(&mut state, false), Leaf('a'));
+    }
+
+    #[test]
+    fn lookups_and_iteration() {
+        let codebook = HuffmanCodebook::from_static(CODES).unwrap();
+        let trie     = CodeTrie::from_codebook(&codebook).unwrap();
+
+        assert_eq!(trie.len(), 4);
+        assert_eq!(trie.lookup_exact(code("101")), Some('c'));
+        assert_eq!(trie.lookup_exact(code("10")), None);
+        assert_eq!(trie.lookup_exact(code("01")), None);
+        assert_eq!(trie.lookup_exact(code("")), None);
+        assert_eq!(trie.iter().collect::<Vec<_>>(),
+                   [(code("0"), 'a'), (code("100"), 'b'), (code("101"), 'c'),
+                    (code("11"), 'd')]);
+        assert_eq!(CodeTrie::new().iter().next(), None);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut trie = CodeTrie::new();
+
+        trie.insert(code("10"), 'a').unwrap();
+        trie.insert(code("011"), 'b').unwrap();
+
+        let before = trie.clone();
+
+        assert_eq!(trie.insert(code("1"), 'c'),
+                   Err(Conflict::NotPrefixFree('c', 'a')));
+        assert_eq!(trie.insert(code("101"), 'c'),
+                   Err(Conflict::NotPrefixFree('a', 'c')));
+        assert_eq!(trie.insert(code("10"), 'c'),
+                   Err(Conflict::NotPrefixFree('a', 'c')));
+        assert_eq!(trie.insert(code("01"), 'c'),
+                   Err(Conflict::NotPrefixFree('c', 'b')));
+        assert_eq!(trie.insert(code(""), 'c'), Err(Conflict::Malformed('c')));
+        assert_eq!(trie.insert(Code { bits: 0b100, len: 2 }, 'c'),
+                   Err(Conflict::Malformed('c')));
+        assert_eq!(trie, before);
+
+        trie.insert(code("00"), 'c').unwrap();
+
+        assert_eq!(trie.len(), 3);
+        assert_eq!(HuffmanError::from(Conflict::NotPrefixFree('a', 'b')),
+                   HuffmanError::NotPrefixFree('a', 'b'));
+    }
+}
@@ -0,0 +1,202 @@
+//! Coding UTF-16 code units directly, for text that arrives as `u16` buffers.
+//! 
+//! The coding is unit-level rather than character-level: each `u16` is a
+//! symbol of its own, and the two halves of a surrogate pair are coded
+//! separately. Lone surrogates are fine, so any `&[u16]` round-trips exactly.
+//! 
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use crate::bits::{BitBuffer, BitWriter};
+use crate::HuffmanError;
+use crate::canonical::CanonicalDecoder;
+use crate::codebook::{Code, FrequencyTable, HuffmanCodebook};
+use crate::collections::HashMap;
+use crate::io::{BitReader, DecoderFsm};
+
+
+/// The first char that surrogate units are mapped to. Units are coded through
+/// the char-based tree, so each is stood in for by a char: units that aren't
+/// surrogates by the char with the same value, and surrogates by chars above
+/// `u16::MAX`, where no other unit lands.
+/// 
+const SURROGATE_BASE: u32 = 0x1_0000;
+
+fn unit_to_char(unit: u16) -> char {
+    char::from_u32(unit as u32).unwrap_or_else(|| {
+        char::from_u32(SURROGATE_BASE + unit as u32 - 0xd800).unwrap()
+    })
+}
+
+fn char_to_unit(c: char) -> u16 {
+    match c as u32 {
+        n if n < SURROGATE_BASE => n as u16,
+        n                       => (n - SURROGATE_BASE + 0xd800) as u16,
+    }
+}
+
+/// The codebook standing in for `codes`, keyed by the chars the units map to.
+/// 
+fn stand_in_codebook(codes: &HashMap<u16, Code>) 
+
+    -> Result<HuffmanCodebook, HuffmanError> 
+{
+    let table = codes.iter()
+                     .map(|(&u, code)| (unit_to_char(u), code.bits, code.len))
+                     .collect::<Vec<_>>();
+
+    HuffmanCodebook::from_table(&table)
+}
+
+
+/// Generates canonical codes for the UTF-16 code units in `units`, so that
+/// `decode_utf16()` can decode them from their lengths alone. See the module
+/// docs.
+/// 
+pub fn generate_huffman_codes_utf16(units: &[u16]) -> HashMap<u16, Code> {
+    let freqs = FrequencyTable::from_chars(units.iter()
+                                                .map(|&u| unit_to_char(u)));
+
+    let codes = HuffmanCodebook::from_frequencies_capped(freqs)
+                    .codes
+                    .into_iter()
+                    .map(|(c, code)| (char_to_unit(c), code))
+                    .collect::<HashMap<_, _>>();
+
+    CanonicalDecoder::from_lengths(&lengths(&codes))
+        .expect("Huffman code lengths are those of a prefix code")
+        .codes()
+        .collect()
+}
+
+/// The length of each unit's code.
+/// 
+fn lengths(codes: &HashMap<u16, Code>) -> Vec<(u16, u8)> {
+    codes.iter().map(|(&u, code)| (u, code.len)).collect()
+}
+
+/// Packs the codes for `units`, most significant bit first. Fails with
+/// `HuffmanError::UnitNotInCodebook` if a unit has no code.
+/// 
+pub fn encode_utf16(units: &[u16], codes: &HashMap<u16, Code>) 
+
+    -> Result<BitBuffer, HuffmanError> 
+{
+    let mut writer = BitWriter::new();
+
+    for (index, unit) in units.iter().enumerate() {
+        let &code = codes.get(unit).ok_or(HuffmanError::UnitNotInCodebook {
+            unit: *unit, index
+        })?;
+        writer.write_code(code);
+    }
+    Ok(writer.into_buffer())
+}
+
+/// Decodes `bits` into the code units they encode with `codes`. Fails as
+/// `DecoderFsm::decode()` does, and if `codes` isn't a valid prefix code.
+/// 
+/// Canonical codes, such as those from `generate_huffman_codes_utf16()`, are
+/// decoded with a `CanonicalDecoder`, which needs no tree. Other codes fall
+/// back to a `DecoderFsm`.
+/// 
+pub fn decode_utf16(bits: &BitBuffer, codes: &HashMap<u16, Code>) 
+
+    -> Result<Vec<u16>, HuffmanError> 
+{
+    if let Ok(decoder) = CanonicalDecoder::from_lengths(&lengths(codes))
+        && decoder.codes().all(|(u, code)| codes[&u] == code)
+    {
+        return decode_canonical(&decoder, &mut bits.reader());
+    }
+    let fsm       = DecoderFsm::from_codebook(&stand_in_codebook(codes)?)?;
+    let mut units = vec![];
+
+    fsm.decode_with(bits, |c| {
+        units.push(char_to_unit(c));
+        ControlFlow::Continue(())
+    })?;
+    Ok(units)
+}
+
+/// Decodes the rest of `r` with `decoder`, counting the units decoded before
+/// any error.
+/// 
+fn decode_canonical(decoder: &CanonicalDecoder<u16>, r: &mut BitReader) 
+
+    -> Result<Vec<u16>, HuffmanError> 
+{
+    let mut units = vec![];
+
+    while r.remaining() > 0 {
+        let unit = decoder.decode_symbol(r).map_err(|e| {
+            let symbols = units.len();
+
+            match e {
+                HuffmanError::InvalidCode { bit_offset, .. } => {
+                    HuffmanError::InvalidCode { bit_offset, symbols }
+                },
+                HuffmanError::TruncatedBitstream { bit_offset, .. } => {
+                    HuffmanError::TruncatedBitstream { bit_offset, symbols }
+                },
+                e => e,
+            }
+        })?;
+        units.push(unit);
+    }
+    Ok(units)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surrogates_round_trip() {
+        let text  = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text  = text + "🦀 crabs 𝄞 and 🐋 whales";
+        let mut units = text.encode_utf16().collect::<Vec<_>>();
+
+        // Lone and reversed surrogates aren't valid UTF-16, but still code.
+        units.extend([0xd800, 0xdfff, 0xdc00, 0xd83d, 0xffff]);
+
+        let codes = generate_huffman_codes_utf16(&units);
+
+        assert!(codes.contains_key(&0xd83e) && codes.contains_key(&0xdd80));
+        assert!(codes.contains_key(&0xffff));
+
+        let bits = encode_utf16(&units, &codes).unwrap();
+
+        assert!(bits.bit_len() < units.len() * 16);
+        assert_eq!(decode_utf16(&bits, &codes).unwrap(), units);
+    }
+
+    #[test]
+    fn non_canonical_codes() {
+        // 'b' and 'c' swapped from their canonical codes, 110 and 111.
+        let codes = HashMap::from([
+            (0x61, Code { bits: 0b0,   len: 1 }),
+            (0x62, Code { bits: 0b111, len: 3 }),
+            (0x63, Code { bits: 0b110, len: 3 }),
+            (0x64, Code { bits: 0b10,  len: 2 }),
+        ]);
+        let units = [0x62, 0x63, 0x61, 0x64];
+        let bits  = encode_utf16(&units, &codes).unwrap();
+
+        assert_eq!(bits.as_bytes(), [0b1111_1001, 0]);
+        assert_eq!(decode_utf16(&bits, &codes).unwrap(), units);
+    }
+
+    #[test]
+    fn missing_unit() {
+        let codes = generate_huffman_codes_utf16(&[0x61, 0x62, 0xd800]);
+
+        assert_eq!(encode_utf16(&[0x61, 0xd800, 0xdc00], &codes),
+                   Err(HuffmanError::UnitNotInCodebook {
+                       unit: 0xdc00, index: 2
+                   }));
+    }
+}
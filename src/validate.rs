@@ -0,0 +1,238 @@
+//! Checking code tables that come from elsewhere, such as over the wire,
+//! before decoding with them.
+//! 
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::HuffmanError;
+use crate::collections::HashMap;
+use crate::escape::SymbolDisplay;
+
+
+/// Why a table of code strings can't be decoded with, from `validate_codes()`
+/// or `validate_complete_codes()`.
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeTableError {
+    /// The symbol's code is empty.
+    EmptyCode(char),
+    /// The symbol's code has characters other than '0' and '1'.
+    NotBinary(char),
+    /// The two symbols have the same code.
+    DuplicateCode(char, char),
+    /// The first symbol's code is a prefix of the second's.
+    NotPrefixFree(char, char),
+    /// The codes are prefix-free, but leave some sequences of bits that
+    /// don't start with any of them: their Kraft sum is under 1.
+    Incomplete,
+}
+
+impl fmt::Display for CodeTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CodeTableError::*;
+
+        match *self {
+            EmptyCode(c) => {
+                write!(f, "the code for {} is empty", SymbolDisplay(c))
+            },
+            NotBinary(c) => {
+                write!(f, "the code for {} isn't made of '0's and '1's",
+                       SymbolDisplay(c))
+            },
+            DuplicateCode(a, b) => {
+                write!(f, "{} and {} have the same code", SymbolDisplay(a),
+                       SymbolDisplay(b))
+            },
+            NotPrefixFree(a, b) => {
+                write!(f, "the code for {} is a prefix of the code for {}",
+                       SymbolDisplay(a), SymbolDisplay(b))
+            },
+            Incomplete => {
+                write!(f, "the codes don't cover every sequence of bits")
+            },
+        }
+    }
+}
+
+impl Error for CodeTableError {}
+
+impl From<CodeTableError> for HuffmanError {
+    fn from(err: CodeTableError) -> Self {
+        use CodeTableError::*;
+
+        match err {
+            EmptyCode(c) | NotBinary(c) => Self::MalformedCode(c),
+            DuplicateCode(a, b)         => Self::NotPrefixFree(a, b),
+            NotPrefixFree(a, b)         => Self::NotPrefixFree(a, b),
+            Incomplete                  => {
+                Self::MalformedData("the code table is incomplete")
+            },
+        }
+    }
+}
+
+
+/// Checks that `codes` can be decoded with: every code is a non-empty string
+/// of '0's and '1's, and none is the same as or a prefix of another. Some
+/// sequences of bits may start with no code, as with a table of one code;
+/// `validate_complete_codes()` rules that out too. Errors name the first
+/// symbols, in order, whose codes fail a check.
+/// 
+pub fn validate_codes(codes: &HashMap<char, String>) 
+
+    -> Result<(), CodeTableError> 
+{
+    let mut sorted = codes.iter().map(|(&c, code)| (c, code.as_str()))
+                                 .collect::<Vec<_>>();
+    sorted.sort_unstable();
+
+    for &(c, code) in &sorted {
+        if code.is_empty() {
+            return Err(CodeTableError::EmptyCode(c));
+        }
+        if code.bytes().any(|b| b != b'0' && b != b'1') {
+            return Err(CodeTableError::NotBinary(c));
+        }
+    }
+    // A code that's the prefix of another sorts immediately before one of the
+    // codes it's a prefix of.
+    sorted.sort_unstable_by_key(|&(c, code)| (code, c));
+
+    for pair in sorted.windows(2) {
+        let ((a, a_code), (b, b_code)) = (pair[0], pair[1]);
+
+        if a_code == b_code {
+            return Err(CodeTableError::DuplicateCode(a, b));
+        }
+        if b_code.starts_with(a_code) {
+            return Err(CodeTableError::NotPrefixFree(a, b));
+        }
+    }
+    Ok(())
+}
+
+/// Checks `codes` as `validate_codes()` does, and that they're complete, as
+/// Huffman codes for two or more symbols are: every sequence of bits starts
+/// with one of them, so their Kraft sum, of 2^-length over the codes, is 1.
+/// Fails with `CodeTableError::Incomplete` if it's less.
+/// 
+pub fn validate_complete_codes(codes: &HashMap<char, String>) 
+
+    -> Result<(), CodeTableError> 
+{
+    validate_codes(codes)?;
+
+    let mut lengths = BTreeMap::<usize, u64>::new();
+
+    for code in codes.values() {
+        *lengths.entry(code.len()).or_default() += 1;
+    }
+    // The sum is counted in units of 2^-depth, from the longest codes up: two
+    // units at one depth make one at the next depth up, and a unit left over
+    // means the sum isn't a whole number of units there, so isn't 1.
+    let mut units = 0_u64;
+    let mut depth = lengths.last_key_value().map_or(0, |(&len, _)| len);
+
+    for (&len, &count) in lengths.iter().rev() {
+        while depth > len && units > 0 {
+            if !units.is_multiple_of(2) {
+                return Err(CodeTableError::Incomplete);
+            }
+            units /= 2;
+            depth -= 1;
+        }
+        units += count;
+        depth  = len;
+    }
+    while depth > 0 && units.is_multiple_of(2) && units > 1 {
+        units /= 2;
+        depth -= 1;
+    }
+    match (depth, units) {
+        (0, 1) => Ok(()),
+        _      => Err(CodeTableError::Incomplete),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use crate::codebook::generate_huffman_codes;
+    use crate::io::{DecodeTable, DecoderFsm};
+
+    fn table(codes: &[(char, &str)]) -> HashMap<char, String> {
+        codes.iter().map(|&(c, code)| (c, code.to_string())).collect()
+    }
+
+    #[test]
+    fn valid_tables() {
+        let text = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+
+        for text in [&text[..], "abracadabra", "ab", "abcd"] {
+            let codes = generate_huffman_codes(text);
+
+            assert_eq!(validate_codes(&codes), Ok(()));
+            assert_eq!(validate_complete_codes(&codes), Ok(()));
+        }
+        // Valid, but some bits start no code.
+        for codes in [&[('a', "0")][..], &[('a', "0"), ('b', "10")],
+                      &[('a', "00"), ('b', "01"), ('c', "10")]] {
+            let codes = table(codes);
+
+            assert_eq!(validate_codes(&codes), Ok(()));
+            assert_eq!(validate_complete_codes(&codes),
+                       Err(CodeTableError::Incomplete));
+        }
+        assert_eq!(validate_codes(&HashMap::new()), Ok(()));
+        assert_eq!(validate_complete_codes(&HashMap::new()),
+                   Err(CodeTableError::Incomplete));
+
+        // A skewed table, with codes up to 100 bits long.
+        let mut skewed = (0..100).map(|i| {
+            (char::from_u32('a' as u32 + i).unwrap(),
+             "1".repeat(i as usize) + "0")
+        }).collect::<HashMap<_, _>>();
+
+        skewed.insert('Z', "1".repeat(100));
+
+        assert_eq!(validate_complete_codes(&skewed), Ok(()));
+
+        skewed.remove(&'Z');
+
+        assert_eq!(validate_complete_codes(&skewed),
+                   Err(CodeTableError::Incomplete));
+    }
+
+    #[test]
+    fn invalid_tables() {
+        use CodeTableError::*;
+
+        for (codes, err) in [
+            (&[('a', "0"), ('b', ""), ('c', "1")][..], EmptyCode('b')),
+            (&[('a', "0"), ('b', "1x")],               NotBinary('b')),
+            (&[('a', "0"), ('b', "1 0")],              NotBinary('b')),
+            (&[('a', "10"), ('b', "0"), ('c', "10")],  DuplicateCode('a', 'c')),
+            (&[('a', "10"), ('b', "0"), ('c', "101")], NotPrefixFree('a', 'c')),
+            (&[('a', "1"), ('b', "0"), ('c', "10")],   NotPrefixFree('a', 'c')),
+        ] {
+            let codes = table(codes);
+
+            assert_eq!(validate_codes(&codes), Err(err));
+            assert_eq!(validate_complete_codes(&codes), Err(err));
+
+            // The decoders refuse the table too.
+            assert_eq!(DecoderFsm::from_code_map(&codes).err(),
+                       Some(err.into()));
+            assert_eq!(DecodeTable::from_codes(&codes).err(),
+                       Some(err.into()));
+        }
+        assert_eq!(NotPrefixFree('a', 'c').to_string(),
+                   "the code for 'a' is a prefix of the code for 'c'");
+    }
+}
@@ -0,0 +1,70 @@
+//! Varints, as the multiplexed streams, line streams and code tables write
+//! them: LEB128, seven bits to a byte, least significant first, with the high
+//! bit set on every byte but the last.
+//! 
+
+use alloc::vec::Vec;
+
+use crate::HuffmanError;
+
+
+/// The most bytes a varint for a `u64` takes.
+/// 
+const MAX_VARINT: usize = 10;
+
+
+/// Appends `n` to `out` as a varint.
+/// 
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// The next varint of the bytes `next_byte` gives, or `None` if they end
+/// before it starts.
+/// 
+pub(crate) fn read_varint_with<F>(mut next_byte: F) 
+
+    -> Result<Option<u64>, HuffmanError> 
+where
+    F: FnMut() -> Result<Option<u8>, HuffmanError>,
+{
+    let mut n = 0;
+
+    for i in 0..MAX_VARINT {
+        let Some(byte) = next_byte()? else {
+            return match i {
+                0 => Ok(None),
+                _ => Err(cut_short()),
+            };
+        };
+        n |= (byte as u64 & 0x7f) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+    }
+    Err(HuffmanError::MalformedData("a varint is too long"))
+}
+
+/// The varint `bytes` starts with, or `None` if it's empty, moving `bytes`
+/// past it.
+/// 
+pub(crate) fn take_varint(bytes: &mut &[u8]) 
+
+    -> Result<Option<u64>, HuffmanError> 
+{
+    read_varint_with(|| {
+        Ok(bytes.split_first().map(|(&byte, rest)| {
+            *bytes = rest;
+            byte
+        }))
+    })
+}
+
+pub(crate) fn cut_short() -> HuffmanError {
+    HuffmanError::MalformedData("the stream is cut short")
+}
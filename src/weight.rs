@@ -0,0 +1,54 @@
+//! The numeric types a Huffman tree can be built over.
+//! 
+
+use core::cmp::Ordering;
+use core::ops::Add;
+
+
+/// A symbol weight: a count or a probability. Weights only need adding and
+/// comparing, but the tree is built with a heap, which needs a total order, so
+/// floats supply one through `weight_cmp()`.
+/// 
+pub trait Weight: Copy + PartialOrd + Add<Output = Self> {
+    /// Compares two weights, totally. For integers this is the usual order;
+    /// for floats it's `total_cmp()`, which places NaNs after infinity.
+    /// 
+    fn weight_cmp(&self, other: &Self) -> Ordering;
+    /// Adds two weights, for the weight of the branch over them. For
+    /// integers this saturates at the type's maximum, so a tree over counts
+    /// that were merged up to it is still built, if with some ties; for
+    /// floats, and by default, it's `+`.
+    /// 
+    fn weight_add(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+macro_rules! integer_weights {
+    ($($t:ty),*) => {
+        $(
+            impl Weight for $t {
+                fn weight_cmp(&self, other: &Self) -> Ordering {
+                    self.cmp(other)
+                }
+                fn weight_add(self, other: Self) -> Self {
+                    self.saturating_add(other)
+                }
+            }
+        )*
+    };
+}
+
+integer_weights!(u8, u16, u32, u64, u128, usize);
+
+impl Weight for f32 {
+    fn weight_cmp(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+
+impl Weight for f64 {
+    fn weight_cmp(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
@@ -0,0 +1,316 @@
+//! Encoding text as it's written, for piping a large file through the codes
+//! without holding its compressed form in memory.
+//! 
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::HuffmanError;
+use crate::codebook::{Code, HuffmanCodebook};
+use crate::io::BitWriter;
+
+
+/// The error for bytes that aren't UTF-8.
+/// 
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, HuffmanError::InvalidUtf8)
+}
+
+
+/// Encodes the text written to it with a prebuilt codebook, writing the packed
+/// codes, most significant bit first, to an inner writer as each byte fills.
+/// The partial byte at the end is held back until `finish()`, which pads it.
+/// 
+/// Text can be written a `str` at a time with `write_str()`, or as bytes
+/// through `Write`, in which case a character's bytes may be split across
+/// writes. Bytes that aren't UTF-8, and characters the codebook has no code
+/// for, fail with `io::ErrorKind::InvalidData`, wrapping
+/// `HuffmanError::InvalidUtf8` or `HuffmanError::SymbolNotInCodebook`, whose
+/// positions count from the start of the text. The text before them is
+/// encoded, and `write()` reports it as written.
+/// 
+#[derive(Debug)]
+pub struct HuffmanWriter<W: Write> {
+    inner   : W,
+    codes   : HashMap<char, Code>,
+    bits    : BitWriter,
+    /// The first bytes of a character split across writes.
+    pending : Vec<u8>,
+    chars   : usize,
+    bytes   : usize,
+}
+
+impl<W: Write> HuffmanWriter<W> {
+    /// A writer encoding with the codes of `codebook`.
+    /// 
+    pub fn new(inner: W, codebook: &HuffmanCodebook) -> Self {
+        Self {
+            inner,
+            codes   : codebook.codes().clone(),
+            bits    : BitWriter::new(),
+            pending : Vec::new(),
+            chars   : 0,
+            bytes   : 0,
+        }
+    }
+    /// Encodes `text`, failing as described for the type.
+    /// 
+    pub fn write_str(&mut self, text: &str) -> io::Result<()> {
+        self.write_all(text.as_bytes())
+    }
+    /// The number of bits written so far, counting those held back.
+    /// 
+    pub fn bit_len(&self) -> u64 {
+        self.bits.bit_len()
+    }
+    /// The number of bits written so far, as `bit_len()` gives, counting the
+    /// padding written by `align_to_byte()`. Taken right after aligning, it
+    /// divides by 8 to give the byte offset of the next record.
+    /// 
+    pub fn bits_written(&self) -> u64 {
+        self.bits.bit_len()
+    }
+    /// Ends a record by padding the partial last byte, if there is one, with
+    /// zero bits, and writing it, so the next record starts on a byte
+    /// boundary. Returns the number of padding bits, 0 to 7. A reader skips
+    /// them with `HuffmanReader::skip_to_byte_boundary()`, or a
+    /// `PushDecoder` with its own. Fails with `io::ErrorKind::InvalidData`
+    /// if the text written ends partway through a character.
+    /// 
+    pub fn align_to_byte(&mut self) -> io::Result<u8> {
+        if !self.pending.is_empty() {
+            return Err(invalid_utf8());
+        }
+        let padding = self.bits.align_to_byte();
+
+        self.write_complete()?;
+        Ok(padding)
+    }
+    /// Writes the last partial byte, padded with zeros, flushes the inner
+    /// writer, and returns it along with the number of padding bits, 0 to 7.
+    /// A `PushDecoder` decodes the last byte with `8 - padding` valid bits.
+    /// Fails with `io::ErrorKind::InvalidData` if the text ends partway
+    /// through a character.
+    /// 
+    pub fn finish(mut self) -> io::Result<(W, u8)> {
+        if !self.pending.is_empty() {
+            return Err(invalid_utf8());
+        }
+        let padding = self.bits.align_to_byte();
+
+        self.write_complete()?;
+        self.inner.flush()?;
+        Ok((self.inner, padding))
+    }
+    /// Encodes the characters of `text` up to the first the codebook has no
+    /// code for, returning the number of bytes encoded. Fails if that's the
+    /// first.
+    /// 
+    fn encode(&mut self, text: &str) -> io::Result<usize> {
+        for (offset, c) in text.char_indices() {
+            let Some(&code) = self.codes.get(&c) else {
+                let error = HuffmanError::SymbolNotInCodebook {
+                    symbol      : c,
+                    char_index  : self.chars,
+                    byte_offset : self.bytes,
+                };
+                return match offset {
+                    0 => Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+                    n => Ok(n),
+                };
+            };
+            self.bits.write_code(code);
+            self.chars += 1;
+            self.bytes += c.len_utf8();
+        }
+        Ok(text.len())
+    }
+    /// Finishes the character split across writes with the first bytes of
+    /// `data`, returning the number of them taken. If the character can't be
+    /// encoded, none are taken, and its first bytes are kept as they were.
+    /// 
+    fn complete_char(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        let split     = bytes.len();
+
+        for (i, &b) in data.iter().enumerate() {
+            bytes.push(b);
+
+            let result = match std::str::from_utf8(&bytes) {
+                Ok(text) => self.encode(text),
+                Err(e) if e.error_len().is_none() => continue,
+                Err(_) => Err(invalid_utf8()),
+            };
+            if let Err(e) = result {
+                bytes.truncate(split);
+                self.pending = bytes;
+                return Err(e);
+            }
+            self.write_complete()?;
+            return Ok(i + 1);
+        }
+        self.pending = bytes;
+        Ok(data.len())
+    }
+    /// Writes the complete bytes of codes to the inner writer.
+    /// 
+    fn write_complete(&mut self) -> io::Result<()> {
+        if self.bits.complete_bytes() > 0 {
+            self.inner.write_all(&self.bits.take_complete())?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HuffmanWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.pending.is_empty() {
+            return self.complete_char(data);
+        }
+        let (valid, error) = match std::str::from_utf8(data) {
+            Ok(text) => (text.len(), None),
+            Err(e)   => (e.valid_up_to(), Some(e)),
+        };
+        if valid > 0 {
+            // SAFETY: `from_utf8()` validated the first `valid` bytes.
+            let text = unsafe { std::str::from_utf8_unchecked(&data[..valid]) };
+            let n    = self.encode(text)?;
+
+            self.write_complete()?;
+            return Ok(n);
+        }
+        match error {
+            None => Ok(0),
+            Some(e) if e.error_len().is_none() => {
+                self.pending.extend_from_slice(data);
+                Ok(data.len())
+            },
+            Some(_) => Err(invalid_utf8()),
+        }
+    }
+    /// Writes the complete bytes of codes and flushes the inner writer. The
+    /// partial last byte stays held back.
+    /// 
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_complete()?;
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{encode, PushDecoder};
+
+    #[test]
+    fn streams_in_chunks() {
+        let text     = std::fs::read_to_string("data/moby_dick.txt").unwrap();
+        let text     = text + "é€🦀";
+        let codebook = HuffmanCodebook::from_text(&text);
+        let expected = encode(&text);
+
+        for chunk in [4096, 4093, 1] {
+            let mut w = HuffmanWriter::new(vec![], &codebook);
+
+            // Some chunks end partway through a character.
+            for part in text.as_bytes().chunks(chunk) {
+                w.write_all(part).unwrap();
+            }
+            assert_eq!(w.bit_len(), expected.bit_len() as u64);
+
+            let (bytes, padding) = w.finish().unwrap();
+
+            assert_eq!(bytes, expected.as_bytes());
+            assert_eq!(bytes.len() * 8 - padding as usize, expected.bit_len());
+
+            let mut decoder = PushDecoder::new(&codebook).unwrap();
+
+            decoder.push(&bytes).unwrap();
+            decoder.finish(8 - padding).unwrap();
+
+            assert_eq!(decoder.drain_output().collect::<String>(), text);
+        }
+    }
+
+    #[test]
+    fn bad_input() {
+        let codebook = HuffmanCodebook::from_text("abc€");
+        let error    = |e: io::Error| {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            e.into_inner().unwrap().downcast::<HuffmanError>().unwrap()
+        };
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        w.write_str("ab€").unwrap();
+
+        // The text before a character without a code is taken.
+        assert_eq!(w.write(b"cxa").unwrap(), 1);
+        assert_eq!(*error(w.write(b"xa").unwrap_err()),
+                   HuffmanError::SymbolNotInCodebook {
+                       symbol: 'x', char_index: 4, byte_offset: 6
+                   });
+        assert_eq!(*error(w.write(&[0xff]).unwrap_err()),
+                   HuffmanError::InvalidUtf8);
+
+        // A character split across writes, and left unfinished.
+        assert_eq!(w.write(&"€".as_bytes()[..1]).unwrap(), 1);
+        assert_eq!(w.write(&"€".as_bytes()[1..]).unwrap(), 2);
+        assert_eq!(w.write(&"€".as_bytes()[..2]).unwrap(), 2);
+        assert_eq!(*error(w.finish().unwrap_err()), HuffmanError::InvalidUtf8);
+
+        let w = HuffmanWriter::new(vec![], &codebook);
+
+        assert_eq!(w.finish().unwrap(), (vec![], 0));
+    }
+
+    #[test]
+    fn unknown_symbol_positions() {
+        let codebook = HuffmanCodebook::from_text("abc€");
+        let error    = |e: io::Error| {
+            *e.into_inner().unwrap().downcast::<HuffmanError>().unwrap()
+        };
+        let unknown  = |symbol, char_index, byte_offset| {
+            HuffmanError::SymbolNotInCodebook {
+                symbol, char_index, byte_offset
+            }
+        };
+        // At the start, in the middle, and at the end of the text.
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        assert_eq!(error(w.write(b"xab").unwrap_err()), unknown('x', 0, 0));
+
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        assert_eq!(w.write("a€xb".as_bytes()).unwrap(), 4);
+        assert_eq!(error(w.write(b"xb").unwrap_err()), unknown('x', 2, 4));
+
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        assert_eq!(w.write(b"abcx").unwrap(), 3);
+        assert_eq!(error(w.write(b"x").unwrap_err()), unknown('x', 3, 3));
+
+        // Straddling two writes. Its first bytes are kept, so it fails the
+        // same way each time the rest is written.
+        let crab  = "🦀".as_bytes();
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        w.write_str("a€").unwrap();
+
+        assert_eq!(w.write(&crab[..2]).unwrap(), 2);
+
+        for _ in 0..2 {
+            assert_eq!(error(w.write(&crab[2..]).unwrap_err()),
+                       unknown('🦀', 2, 4));
+        }
+        // Completed as another character instead, it's encoded.
+        let euro = "€".as_bytes();
+        let mut w = HuffmanWriter::new(vec![], &codebook);
+
+        assert_eq!(w.write(&euro[..1]).unwrap(), 1);
+        assert_eq!(error(w.write(&[0xff]).unwrap_err()),
+                   HuffmanError::InvalidUtf8);
+        assert_eq!(w.write(&euro[1..]).unwrap(), 2);
+        assert_eq!(w.bit_len(), codebook.code_for('€').unwrap().len as u64);
+    }
+}
@@ -0,0 +1,9 @@
+// Generated by huffman::codegen; do not edit.
+pub static CODEBOOK: &[(char, u64, u8)] = &[
+    ('a', 0b0, 1),
+    ('b', 0b101, 3),
+    ('c', 0b100, 3),
+    ('d', 0b111, 3),
+    ('e', 0b1101, 4),
+    ('f', 0b1100, 4),
+];
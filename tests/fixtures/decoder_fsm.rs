@@ -0,0 +1,32 @@
+// Generated by huffman::codegen; do not edit.
+pub static HUFF_FSM: [[u32; 2]; 5] = [
+    [0x80000061, 0x00000001],
+    [0x00000002, 0x00000003],
+    [0x80000063, 0x80000062],
+    [0x00000004, 0x80000064],
+    [0x80000066, 0x80000065],
+];
+
+pub fn huff_decode(bytes: &[u8], nbits: usize) -> Option<String> {
+    if nbits > bytes.len() * 8 {
+        return None;
+    }
+    let mut text  = String::new();
+    let mut state = 0;
+
+    for i in 0..nbits {
+        let bit  = (bytes[i / 8] >> (7 - i % 8) & 1) as usize;
+        let next = HUFF_FSM[state][bit];
+
+        if next == u32::MAX {
+            return None;
+        }
+        if next & 0x8000_0000 == 0 {
+            state = next as usize;
+        } else {
+            text.push(char::from_u32(next & 0x7fff_ffff)?);
+            state = 0;
+        }
+    }
+    if state == 0 { Some(text) } else { None }
+}
@@ -0,0 +1,12 @@
+// Generated by huffman::codegen; do not edit.
+pub fn encode_char(c: char) -> Option<(u64, u8)> {
+    match c {
+        'a' => Some((0b0, 1)),
+        'b' => Some((0b101, 3)),
+        'c' => Some((0b100, 3)),
+        'd' => Some((0b111, 3)),
+        'e' => Some((0b1101, 4)),
+        'f' => Some((0b1100, 4)),
+        _ => None,
+    }
+}
@@ -0,0 +1,30 @@
+// Generated by huffman::codegen; do not edit.
+pub fn encode_nested(c: char) -> Option<(u64, u8)> {
+    match c {
+        'a'..='c' => match c {
+            'a'..='b' => match c {
+                'a' => Some((0b0, 1)),
+                'b' => Some((0b101, 3)),
+                _ => None,
+            },
+            'c'..='c' => match c {
+                'c' => Some((0b100, 3)),
+                _ => None,
+            },
+            _ => None,
+        },
+        'd'..='f' => match c {
+            'd'..='e' => match c {
+                'd' => Some((0b111, 3)),
+                'e' => Some((0b1101, 4)),
+                _ => None,
+            },
+            'f'..='f' => match c {
+                'f' => Some((0b1100, 4)),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
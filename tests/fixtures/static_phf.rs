@@ -0,0 +1,37 @@
+// Generated by huffman::codegen; do not edit.
+pub const HUFF_BUCKETS: usize = 2;
+pub const HUFF_SLOT_COUNT: usize = 8;
+
+pub static HUFF_DISPLACEMENTS: [u32; HUFF_BUCKETS] = [
+    0, 0,
+];
+
+pub static HUFF_SLOTS: [Option<(char, u64, u8)>; HUFF_SLOT_COUNT] = [
+    Some(('a', 0b0, 1)),
+    None,
+    Some(('c', 0b100, 3)),
+    Some(('b', 0b101, 3)),
+    Some(('e', 0b1101, 4)),
+    Some(('d', 0b111, 3)),
+    None,
+    Some(('f', 0b1100, 4)),
+];
+
+pub fn huff_hash(c: char, k: u64) -> u64 {
+    let mut x = c as u64 ^ k.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x ^= x >> 33;
+    x  = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x
+}
+
+pub fn huff_lookup(c: char) -> Option<(u64, u8)> {
+    let bucket = (huff_hash(c, 0) % HUFF_BUCKETS as u64) as usize;
+    let k      = HUFF_DISPLACEMENTS[bucket] as u64 + 1;
+    let slot   = (huff_hash(c, k) % HUFF_SLOT_COUNT as u64) as usize;
+
+    match HUFF_SLOTS[slot] {
+        Some((s, bits, len)) if s == c => Some((bits, len)),
+        _                              => None,
+    }
+}
@@ -0,0 +1,252 @@
+//! End-to-end runs of the `huff` binary.
+//! 
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use huffman::codebook::FrequencyTable;
+use huffman::container::{compress_ids, compress_ids_named, pipe_compress};
+use huffman::container::{SymbolMap, TableSource};
+use huffman::tree::HuffmanTree;
+
+
+/// A path in the temporary directory, unique to this test run.
+/// 
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("huff-{}-{}", std::process::id(), name))
+}
+
+fn huff(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_huff")).args(args).output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn round_trip() {
+    let packed   = temp("moby.huf");
+    let unpacked = temp("moby.txt");
+
+    let out = huff(&["compress", "data/moby_dick.txt",
+                     packed.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(String::from_utf8(out.stdout).unwrap().contains("%)"));
+
+    let out = huff(&["decompress", packed.to_str().unwrap(),
+                     unpacked.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let original = fs::read("data/moby_dick.txt").unwrap();
+
+    assert!(fs::metadata(&packed).unwrap().len() < original.len() as u64);
+    assert_eq!(fs::read(&unpacked).unwrap(), original);
+
+    fs::remove_file(packed).unwrap();
+    fs::remove_file(unpacked).unwrap();
+}
+
+#[test]
+fn failures() {
+    let output = temp("failed");
+    let output = output.to_str().unwrap();
+
+    let out = huff(&["compress"]);
+
+    assert_eq!(out.status.code(), Some(2));
+    assert!(stderr(&out).starts_with("usage: huff"));
+
+    let out = huff(&["compress", "no/such/file.txt", output]);
+
+    assert!(!out.status.success());
+    assert!(stderr(&out).starts_with("huff: can't open no/such/file.txt: "));
+
+    // Not UTF-8, so it can't be compressed, and nothing is left behind.
+    let binary = temp("binary");
+    fs::write(&binary, [0xff, 0xfe, 0x00]).unwrap();
+
+    let out = huff(&["compress", binary.to_str().unwrap(), output]);
+
+    assert!(!out.status.success());
+    assert!(stderr(&out).ends_with("the input isn't valid UTF-8\n"));
+    assert!(fs::metadata(output).is_err());
+
+    // A header that isn't one.
+    let out = huff(&["decompress", binary.to_str().unwrap(), output]);
+
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("malformed data"), "{}", stderr(&out));
+    assert!(fs::metadata(output).is_err());
+
+    fs::remove_file(binary).unwrap();
+}
+
+#[test]
+fn timeouts() {
+    let output = temp("timed-out");
+    let output = output.to_str().unwrap();
+
+    // Endless input, so only the timeout can stop it, and the partial output
+    // is removed.
+    let out = huff(&["compress", "--timeout", "0", "/dev/zero", output]);
+
+    assert!(!out.status.success());
+    assert_eq!(stderr(&out), "huff: /dev/zero: the operation was cancelled\n");
+    assert!(fs::metadata(output).is_err());
+
+    let out = huff(&["compress", "--timeout", "soon", "/dev/zero", output]);
+
+    assert_eq!(out.status.code(), Some(2));
+
+    // Plenty of time to finish.
+    let packed = temp("timed.huf");
+    let out    = huff(&["compress", "--timeout", "600", "data/moby_dick.txt",
+                        packed.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    fs::remove_file(packed).unwrap();
+}
+
+#[test]
+fn decompression_bombs() {
+    // Both codes are one bit, so each zero byte after the table decodes to
+    // eight characters: a megabyte decodes to eight.
+    let mut stream = vec![];
+    pipe_compress(&b"ab"[..], &mut stream, TableSource::Train, None).unwrap();
+    stream.truncate(stream.len() - 2);
+    stream.extend(vec![0; 1 << 20]);
+    stream.push(0);
+
+    let bomb     = temp("bomb.huf");
+    let unpacked = temp("bomb.txt");
+    let paths    = [bomb.to_str().unwrap(), unpacked.to_str().unwrap()];
+
+    fs::write(&bomb, &stream).unwrap();
+
+    let out = huff(&["decompress", "--max-size", "100000", paths[0],
+                     paths[1]]);
+
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("over the limit of 100000"),
+            "{}", stderr(&out));
+    assert!(fs::metadata(&unpacked).is_err());
+
+    let out = huff(&["decompress", "--max-size", "lots", paths[0], paths[1]]);
+
+    assert_eq!(out.status.code(), Some(2));
+
+    // Well within the default.
+    let out = huff(&["decompress", paths[0], paths[1]]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(fs::metadata(&unpacked).unwrap().len(), 8 << 20);
+
+    fs::remove_file(bomb).unwrap();
+    fs::remove_file(unpacked).unwrap();
+}
+
+#[test]
+fn bench() {
+    let out = huff(&["bench", "--iterations", "1", "data/moby_dick.txt"]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let report = String::from_utf8(out.stdout).unwrap();
+
+    assert!(report.starts_with("data/moby_dick.txt: "), "{}", report);
+    assert!(report.contains(" bits/char, 1 rounds\n"), "{}", report);
+
+    for phase in ["counting", "table build", "encode", "decode"] {
+        assert!(report.lines().any(|line| {
+            line.trim_start().starts_with(phase) && line.ends_with(" MB/s")
+        }), "{}", report);
+    }
+    let out = huff(&["bench", "--iterations", "many", "data/moby_dick.txt"]);
+
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn inspect() {
+    let ids   = [2, 0, 2, 1, 2, 0, 7];
+    let names = SymbolMap::from_pairs([(0, "Idle"), (1, "Running"),
+                                       (2, "Done")]).unwrap();
+    let named   = temp("named.huf");
+    let unnamed = temp("unnamed.huf");
+
+    fs::write(&named, compress_ids_named(&ids, &names).unwrap()).unwrap();
+    fs::write(&unnamed, compress_ids(&ids).unwrap()).unwrap();
+
+    let inspect = |path: &PathBuf| {
+        let out = huff(&["inspect", path.to_str().unwrap()]);
+
+        assert!(out.status.success(), "{}", stderr(&out));
+        String::from_utf8(out.stdout).unwrap()
+                                     .lines()
+                                     .map(|line| line.trim().to_owned())
+                                     .collect::<Vec<_>>()
+    };
+    assert_eq!(inspect(&named),
+               [format!("{}: ids, 7 of them, 4 distinct, named",
+                        named.display()),
+                "3  Done".into(), "2  Idle".into(), "1  Running".into(),
+                "1  #7".into()]);
+    assert_eq!(inspect(&unnamed),
+               [format!("{}: ids, 7 of them, 4 distinct", unnamed.display()),
+                "3  #2".into(), "2  #0".into(), "1  #1".into(),
+                "1  #7".into()]);
+
+    // A stream of text is described by its sizes.
+    let packed = temp("inspect.huf");
+    let out    = huff(&["compress", "data/moby_dick.txt",
+                        packed.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let out = huff(&["inspect", packed.to_str().unwrap()]);
+    let len = fs::metadata("data/moby_dick.txt").unwrap().len();
+
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(String::from_utf8(out.stdout).unwrap()
+                .contains(&format!(": text, 12210 chars, {} bytes\n", len)));
+
+    let out = huff(&["inspect", "--max-size", "100", packed.to_str().unwrap()]);
+
+    assert!(stderr(&out).contains("over the limit of 100"), "{}", stderr(&out));
+
+    for path in [named, unnamed, packed] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn stats() {
+    let text = temp("stats.txt");
+    fs::write(&text, "aaaabbbccd").unwrap();
+
+    let freqs      = FrequencyTable::from_text("aaaabbbccd");
+    let (_, build) = HuffmanTree::from_frequencies_with_stats(&freqs).unwrap();
+    let summary    = format!("{}: 10 chars, 4 distinct, counts 1 to 4, 1 seen \
+                              once", text.display());
+
+    let out = huff(&["stats", text.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), summary + "\n");
+
+    let out = huff(&["stats", "--build", text.to_str().unwrap()]);
+
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert!(String::from_utf8(out.stdout).unwrap().ends_with(&format!(
+        "  built with a heap, {} heap ops, largest heap 4, {} comparisons\n",
+        build.heap_ops, build.comparisons
+    )));
+    assert!(!build.two_queue && build.heap_ops > 0);
+
+    fs::remove_file(text).unwrap();
+}
@@ -0,0 +1,40 @@
+//! Using the crate through its prelude alone, as a dependent would.
+//! 
+
+use huffman::prelude::v1::*;
+
+
+#[test]
+fn round_trip_with_the_prelude_alone() {
+    let text     = "the prelude is all this needs";
+    let codebook = HuffmanBuilder::new().count(text).build().unwrap();
+    let mut bits = BitWriter::new();
+
+    for c in text.chars() {
+        bits.write_code(codebook.code_for(c).unwrap());
+    }
+    let bits: BitBuffer = bits.into_buffer();
+    let mut reader      = BitReader::new(bits.as_bytes(), bits.bit_len());
+    let mut decoder     = PushDecoder::new(&codebook).unwrap();
+
+    assert_eq!(std::iter::from_fn(|| reader.read_bit()).count(),
+               bits.bit_len());
+
+    decoder.push(bits.as_bytes()).unwrap();
+    decoder.finish(match bits.bit_len() % 8 {
+        0 => 8,
+        n => n as u8,
+    }).unwrap();
+
+    assert_eq!(decoder.drain_output().collect::<String>(), text);
+    assert_eq!(codebook.code_for('☃'), None::<Code>);
+
+    let freqs = FrequencyTable::from_text(text);
+
+    assert_eq!(codebook.codes(),
+               HuffmanCodebook::from_frequencies(freqs).codes());
+    assert_eq!(BitBuffer::try_new(vec![], 1).err(),
+               Some(HuffmanError::BitLengthOverrun {
+                   bit_len: 1, bytes: 0
+               }));
+}